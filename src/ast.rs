@@ -0,0 +1,188 @@
+//! Builds nixt programs from Rust, to generate them rather than format their source by hand.
+//!
+//! ```
+//! use nixt::ast;
+//! let program = ast::block().let_("x", ast::num(1.0)).call("print", [ast::var("x")]);
+//! assert_eq!(program.to_string(), "(let x 1)\n(print x)\n");
+//! let mut interpreter = nixt::Interpreter::builder().build()?;
+//! program.eval(&mut interpreter)?;
+//! # Ok::<(), String>(())
+//! ```
+
+use crate::core::interpreter::interpreter::Interpreter;
+use crate::core::lexer::Lexer;
+use crate::core::parser::validate_identifier;
+use crate::utils::element::Value;
+use crate::utils::node::{AssignType, Node, NodeType, OperatorType};
+use crate::utils::token::TokenType;
+
+/// An expression: a literal, a variable, or a call, an operation or a function.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr(Node);
+
+pub fn num(n: f64) -> Expr {
+  Expr(Node::new(NodeType::NodeNumber(n)))
+}
+pub fn string(s: impl Into<String>) -> Expr {
+  Expr(Node::new(NodeType::NodeStr(s.into())))
+}
+pub fn bool(b: bool) -> Expr {
+  Expr(Node::new(NodeType::NodeBool(b)))
+}
+pub fn nil() -> Expr {
+  Expr(Node::new(NodeType::None))
+}
+/// The value of the variable `name`.
+pub fn var(name: impl Into<String>) -> Expr {
+  Expr(Node::new(NodeType::NodeIdentifier(name.into())))
+}
+/// `(name args...)`.
+pub fn call(name: impl Into<String>, args: impl IntoIterator<Item = Expr>) -> Expr {
+  Expr(form(node(NodeType::FunctionCall(name.into()), args)))
+}
+/// `(+ operands...)`, or any other operator.
+pub fn op(operator: OperatorType, operands: impl IntoIterator<Item = Expr>) -> Expr {
+  Expr(form(node(NodeType::Operator(operator), operands)))
+}
+/// `(func (params...) {body})`.
+pub fn func<S: Into<String>>(params: impl IntoIterator<Item = S>, body: Block) -> Expr {
+  let mut master = Node::new(NodeType::Func);
+  master.add_children(&node(NodeType::Block, params.into_iter().map(var)));
+  master.add_children(&body.scope());
+  Expr(form(master))
+}
+
+/// A sequence of statements: a whole program, or the body of a function, a loop or a branch.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Block {
+  statements: Vec<Node>,
+}
+
+pub fn block() -> Block {
+  Block::default()
+}
+
+impl Block {
+  /// `(let name value)`.
+  pub fn let_(self, name: impl Into<String>, value: Expr) -> Self {
+    self.assign(AssignType::Let, name.into(), value)
+  }
+  /// `(const name value)`.
+  pub fn const_(self, name: impl Into<String>, value: Expr) -> Self {
+    self.assign(AssignType::Const, name.into(), value)
+  }
+  /// `(set name value)`.
+  pub fn set(self, name: impl Into<String>, value: Expr) -> Self {
+    self.assign(AssignType::Set, name.into(), value)
+  }
+  /// `(name args...)`.
+  pub fn call(self, name: impl Into<String>, args: impl IntoIterator<Item = Expr>) -> Self {
+    self.push(call(name, args).0)
+  }
+  /// `(ret value)`.
+  pub fn ret(self, value: Expr) -> Self {
+    self.push(form(node(NodeType::Return, [value])))
+  }
+  /// `(if condition (then...))`.
+  pub fn if_(self, condition: Expr, then: Block) -> Self {
+    self.condition(condition, then, Node::new(NodeType::None))
+  }
+  /// `(if condition (then...) (otherwise...))`.
+  pub fn if_else(self, condition: Expr, then: Block, otherwise: Block) -> Self {
+    self.condition(condition, then, otherwise.branch())
+  }
+  /// `(while condition {body})`.
+  pub fn while_(self, condition: Expr, body: Block) -> Self {
+    let mut master = Node::new(NodeType::Loop);
+    master.add_children(&condition.0);
+    master.add_children(&body.scope());
+    self.push(form(master))
+  }
+  /// The syntax tree of the program, as the parser would produce from its source. Fails if a
+  /// name could not be written in a program or a number is not finite.
+  pub fn build(&self) -> Result<Node, String> {
+    let program = node(NodeType::Block, self.statements.iter().cloned().map(Expr));
+    check(&program)?;
+    Ok(program)
+  }
+  /// Evaluates the program with `interpreter`, returning the value of its last expression.
+  pub fn eval(&self, interpreter: &mut Interpreter) -> Result<Value, String> {
+    interpreter.eval(&self.build()?)
+  }
+
+  fn assign(self, kind: AssignType, name: String, value: Expr) -> Self {
+    self.push(form(node(NodeType::Assignement(kind), [var(name), value])))
+  }
+  fn condition(self, condition: Expr, then: Block, otherwise: Node) -> Self {
+    let mut master = Node::new(NodeType::Condition);
+    master.add_children(&condition.0);
+    master.add_children(&then.branch());
+    master.add_children(&otherwise);
+    self.push(form(master))
+  }
+  fn push(mut self, statement: Node) -> Self {
+    self.statements.push(statement);
+    self
+  }
+  /// The statements in braces, as the body of a function or a loop.
+  fn scope(self) -> Node {
+    node(NodeType::Scope, self.statements.into_iter().map(Expr))
+  }
+  /// The statements in parentheses, as a branch of an `if`.
+  fn branch(self) -> Node {
+    node(NodeType::Block, self.statements.into_iter().map(Expr))
+  }
+}
+
+/// Writes the program as source, a statement per line.
+impl std::fmt::Display for Block {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    for statement in &self.statements {
+      writeln!(f, "{}", statement)?;
+    }
+    Ok(())
+  }
+}
+
+impl std::fmt::Display for Expr {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    write!(f, "{}", self.0)
+  }
+}
+
+fn node(node_type: NodeType, children: impl IntoIterator<Item = Expr>) -> Node {
+  let mut master = Node::new(node_type);
+  for child in children {
+    master.add_children(&child.0);
+  }
+  master
+}
+
+/// `inner` in parentheses.
+fn form(inner: Node) -> Node {
+  let mut block = Node::new(NodeType::Block);
+  block.add_children(&inner);
+  block
+}
+
+fn check(node: &Node) -> Result<(), String> {
+  match node.get_type() {
+    NodeType::NodeIdentifier(name) | NodeType::FunctionCall(name) => check_name(&name)?,
+    NodeType::NodeNumber(n) if !n.is_finite() => {
+      return Err(format!("`{}` can not be written in a program", n))
+    }
+    _ => {}
+  }
+  node.get_child().iter().try_for_each(check)
+}
+
+/// Checks `name` reads back as a single identifier.
+pub(crate) fn check_name(name: &str) -> Result<(), String> {
+  let tokens = Lexer::new(name).scan_tokens();
+  match tokens.first().map(|token| &token.typ) {
+    Some(TokenType::Identifier(read)) if read == name && tokens.len() == 2 => {
+      validate_identifier(name)
+    }
+    _ => Err(format!("`{}` is not a valid name", name)),
+  }
+}
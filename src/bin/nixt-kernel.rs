@@ -0,0 +1,92 @@
+//! `nixt-kernel <connection file>`: a Jupyter kernel running notebook cells with one interpreter.
+
+use nixt::tools::jupyter::{Connection, Kernel, Message};
+use nixt::Interpreter;
+use std::convert::TryFrom;
+use zeromq::prelude::*;
+use zeromq::{PubSocket, RepSocket, RouterSocket, ZmqMessage};
+
+fn main() -> Result<(), String> {
+    let path = std::env::args().nth(1).ok_or("Usage: nixt-kernel <connection file>")?;
+    let content = std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path, e))?;
+    let connection = Connection::parse(&content)?;
+    let interpreter = Interpreter::builder().build()?;
+    catch_interrupts(&interpreter)?;
+
+    let heartbeat = connection.endpoint(connection.hb_port);
+    std::thread::spawn(move || runtime()?.block_on(echo(&heartbeat)));
+    runtime()?.block_on(run(&connection, Kernel::new(interpreter)))
+}
+
+fn runtime() -> Result<tokio::runtime::Runtime, String> {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+/// Answers the heartbeats of the frontend, on a thread of its own so that long cells do not
+/// make the kernel look dead.
+async fn echo(endpoint: &str) -> Result<(), String> {
+    let mut socket = RepSocket::new();
+    socket.bind(endpoint).await.map_err(|e| e.to_string())?;
+    loop {
+        let ping = socket.recv().await.map_err(|e| e.to_string())?;
+        socket.send(ping).await.map_err(|e| e.to_string())?;
+    }
+}
+
+async fn run(connection: &Connection, mut kernel: Kernel) -> Result<(), String> {
+    let key = connection.key.as_bytes();
+    let failed = |e: zeromq::ZmqError| e.to_string();
+    let mut shell = RouterSocket::new();
+    shell.bind(&connection.endpoint(connection.shell_port)).await.map_err(failed)?;
+    let mut control = RouterSocket::new();
+    control.bind(&connection.endpoint(connection.control_port)).await.map_err(failed)?;
+    let mut iopub = PubSocket::new();
+    iopub.bind(&connection.endpoint(connection.iopub_port)).await.map_err(failed)?;
+
+    loop {
+        let (frames, from_control) = tokio::select! {
+            frames = control.recv() => (frames.map_err(failed)?, true),
+            frames = shell.recv() => (frames.map_err(failed)?, false),
+        };
+        let frames = frames.into_vec().into_iter().map(|f| f.to_vec()).collect::<Vec<_>>();
+        let request = match Message::decode(&frames, key) {
+            Ok(request) => request,
+            Err(e) => {
+                eprintln!("Dropped a message: {}", e);
+                continue;
+            }
+        };
+        let outgoing = kernel.handle(&request);
+        for message in &outgoing.iopub {
+            iopub.send(encode(message, key)?).await.map_err(failed)?;
+        }
+        if let Some(reply) = &outgoing.reply {
+            let socket = if from_control { &mut control } else { &mut shell };
+            socket.send(encode(reply, key)?).await.map_err(failed)?;
+        }
+        if outgoing.shutdown {
+            return kernel.interpreter().run_exit_handlers();
+        }
+    }
+}
+
+fn encode(message: &Message, key: &[u8]) -> Result<ZmqMessage, String> {
+    let frames = message.encode(key).into_iter().map(bytes::Bytes::from).collect::<Vec<_>>();
+    ZmqMessage::try_from(frames).map_err(|e| e.to_string())
+}
+
+/// Jupyter interrupts a kernel with SIGINT, which stops the running cell.
+#[cfg(feature = "signals")]
+fn catch_interrupts(interpreter: &Interpreter) -> Result<(), String> {
+    signal_hook::flag::register(signal_hook::consts::SIGINT, interpreter.interrupt_flag())
+        .map(|_| ())
+        .map_err(|e| format!("Failed to catch interrupts: {}", e))
+}
+
+#[cfg(not(feature = "signals"))]
+fn catch_interrupts(_: &Interpreter) -> Result<(), String> {
+    Ok(())
+}
@@ -0,0 +1,253 @@
+//! The prefix syntax the lexer and parser accept, as data, for the generators of
+//! `nixt grammar`. Keywords are spelled through the lexer's keyword table, so renaming one
+//! changes the generated grammars too.
+
+use crate::core::lexer::default_keywords;
+use crate::utils::token::TokenType::{self, *};
+
+/// The operators heading an operation, besides the `and` and `or` keywords.
+pub const OPERATORS: &[&str] = &["+", "-", "*", "/", "%", "<", "<=", ">", ">=", "=", "~", "??"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rule {
+    /// A token spelled exactly so: punctuation, an operator or a keyword.
+    Literal(String),
+    /// A token matching a regular expression.
+    Pattern(&'static str),
+    /// Another rule, hidden from syntax trees when its name starts with `_`.
+    Ref(&'static str),
+    Seq(Vec<Rule>),
+    Choice(Vec<Rule>),
+    Repeat(Box<Rule>),
+    Optional(Box<Rule>),
+    /// Names the part of its rule the inner rule matched, such as a definition's `value`.
+    Field(&'static str, Box<Rule>),
+    /// Takes as many elements as follow, like the parser, rather than ending the rule early.
+    Greedy(Box<Rule>),
+}
+
+pub struct Grammar {
+    pub name: &'static str,
+    /// The rules by name, the first one matching whole files.
+    pub rules: Vec<(&'static str, Rule)>,
+    /// What may appear between any two tokens.
+    pub extras: Vec<Rule>,
+    /// The rule of identifiers, which keywords are carved out of.
+    pub word: &'static str,
+}
+
+fn lit(text: &str) -> Rule {
+    Rule::Literal(text.to_owned())
+}
+
+/// The word the keyword table spells `typ` with.
+fn kw(typ: TokenType) -> Rule {
+    let keywords = default_keywords();
+    let word = keywords
+        .iter()
+        .find(|(_, keyword)| **keyword == typ)
+        .map(|(word, _)| word.to_owned())
+        .expect("every keyword token has a word in the keyword table");
+    Rule::Literal(word)
+}
+
+fn r(name: &'static str) -> Rule {
+    Rule::Ref(name)
+}
+
+fn seq(rules: Vec<Rule>) -> Rule {
+    Rule::Seq(rules)
+}
+
+fn choice(rules: Vec<Rule>) -> Rule {
+    Rule::Choice(rules)
+}
+
+fn repeat(rule: Rule) -> Rule {
+    Rule::Repeat(Box::new(rule))
+}
+
+fn optional(rule: Rule) -> Rule {
+    Rule::Optional(Box::new(rule))
+}
+
+fn field(name: &'static str, rule: Rule) -> Rule {
+    Rule::Field(name, Box::new(rule))
+}
+
+fn greedy(rule: Rule) -> Rule {
+    Rule::Greedy(Box::new(rule))
+}
+
+/// The grammar of nixt's prefix syntax. `--infix` expressions and `--lenient` commas are
+/// left out.
+pub fn grammar() -> Grammar {
+    let operators = OPERATORS
+        .iter()
+        .map(|op| lit(op))
+        .chain(vec![kw(And), kw(Or)])
+        .collect();
+    let rules = vec![
+        ("source_file", repeat(choice(vec![r("block"), r("scope")]))),
+        ("block", seq(vec![lit("("), repeat(r("_statement")), lit(")")])),
+        ("scope", seq(vec![lit("{"), repeat(r("_statement")), lit("}")])),
+        (
+            "_statement",
+            choice(vec![
+                r("definition"),
+                r("export"),
+                r("enum"),
+                r("condition"),
+                r("loop"),
+                r("return"),
+                r("function"),
+                r("do"),
+                r("cond"),
+                r("operation"),
+                r("call"),
+                r("block"),
+                r("scope"),
+            ]),
+        ),
+        (
+            "definition",
+            seq(vec![
+                field("keyword", choice(vec![kw(Let), kw(Const), kw(Set)])),
+                field("name", r("identifier")),
+                field("value", choice(vec![r("_value"), r("operation")])),
+            ]),
+        ),
+        (
+            "export",
+            seq(vec![kw(Pub), choice(vec![r("definition"), r("enum")])]),
+        ),
+        (
+            "enum",
+            seq(vec![
+                kw(Enum),
+                field("name", r("identifier")),
+                lit("("),
+                repeat(field("variant", r("identifier"))),
+                lit(")"),
+            ]),
+        ),
+        (
+            "condition",
+            greedy(seq(vec![
+                kw(If),
+                field("test", choice(vec![r("block"), r("identifier")])),
+                field("then", r("block")),
+                optional(field("else", r("block"))),
+            ])),
+        ),
+        (
+            "loop",
+            seq(vec![
+                kw(While),
+                field("test", choice(vec![r("block"), r("identifier")])),
+                field("body", r("scope")),
+            ]),
+        ),
+        (
+            "return",
+            seq(vec![kw(Return), choice(vec![r("_value"), r("function")])]),
+        ),
+        (
+            "function",
+            seq(vec![
+                kw(Func),
+                field("parameters", r("parameters")),
+                field("body", r("scope")),
+            ]),
+        ),
+        ("parameters", seq(vec![lit("("), repeat(r("identifier")), lit(")")])),
+        (
+            "do",
+            greedy(seq(vec![kw(Do), repeat(choice(vec![r("_operand"), r("scope")]))])),
+        ),
+        ("cond", greedy(seq(vec![kw(Cond), repeat(r("cond_branch"))]))),
+        (
+            "cond_branch",
+            seq(vec![
+                lit("("),
+                field(
+                    "test",
+                    choice(vec![kw(Else), r("block"), r("identifier"), r("boolean")]),
+                ),
+                repeat(choice(vec![r("_operand"), r("scope")])),
+                lit(")"),
+            ]),
+        ),
+        (
+            "operation",
+            greedy(seq(vec![
+                field("operator", choice(operators)),
+                repeat(r("_value")),
+            ])),
+        ),
+        (
+            "call",
+            greedy(seq(vec![
+                field("function", r("identifier")),
+                repeat(r("_value")),
+            ])),
+        ),
+        (
+            "_operand",
+            choice(vec![
+                r("number"),
+                r("string"),
+                r("identifier"),
+                r("boolean"),
+                r("nil"),
+                r("range"),
+                r("list"),
+                r("block"),
+            ]),
+        ),
+        // Braces read as a map where a value is expected, and as a scope where statements are.
+        ("_value", choice(vec![r("_operand"), r("map")])),
+        ("list", seq(vec![lit("["), repeat(r("_value")), lit("]")])),
+        (
+            "map",
+            seq(vec![
+                lit("{"),
+                repeat(seq(vec![
+                    field("key", choice(vec![r("identifier"), r("string")])),
+                    field("value", r("_value")),
+                ])),
+                lit("}"),
+            ]),
+        ),
+        (
+            "range",
+            seq(vec![
+                field("start", choice(vec![r("number"), r("identifier")])),
+                lit(".."),
+                field("end", choice(vec![r("number"), r("identifier")])),
+            ]),
+        ),
+        ("boolean", choice(vec![kw(True), kw(False)])),
+        ("nil", kw(Nil)),
+        ("number", Rule::Pattern(r"-?\d+(\.\d+)?")),
+        (
+            "string",
+            choice(vec![Rule::Pattern(r#""[^"]*""#), Rule::Pattern(r"'[^']*'")]),
+        ),
+        // `:` separates namespaces (`math:cos`), and `?` may end a name (`get?`).
+        ("identifier", Rule::Pattern(r"[\p{L}_][\p{L}\p{N}_:?]*")),
+        (
+            "comment",
+            choice(vec![
+                Rule::Pattern(r"#[^\n]*"),
+                Rule::Pattern(r"%%([^%]|%[^%])*%%"),
+            ]),
+        ),
+    ];
+    Grammar {
+        name: "nixt",
+        rules,
+        extras: vec![Rule::Pattern(r"\s"), r("comment")],
+        word: "identifier",
+    }
+}
@@ -1,4 +1,5 @@
 use crate::core::interpreter::interpreter::Interpreter;
+use crate::error::{raise, RuntimeErrorKind};
 use crate::utils::element::*;
 
 impl Interpreter {
@@ -29,13 +30,22 @@ impl Interpreter {
       _ => Ok(Value::Nil),
     }
   }
+  /// Adds numbers, or concatenates strings, writing a number concatenated with a string as
+  /// `print` does: `(+ "n = " 1.5)` is `"n = 1.5"`.
   pub fn add(&self, lhs: Value, rhs: Value) -> Result<Value, String> {
-    match lhs {
-      Value::Number(lh) => match rhs {
-        Value::Number(rh) => Ok(Value::Number(lh + rh)),
-        _ => Ok(Value::Nil),
-      },
-      _ => Ok(Value::Nil),
+    match (lhs, rhs) {
+      (Value::Number(lh), Value::Number(rh)) => Ok(Value::Number(lh + rh)),
+      (Value::String(lh), Value::String(rh)) => Ok(Value::String(lh + &rh)),
+      (Value::String(lh), Value::Number(rh)) => Ok(Value::String(lh + &format_number(rh))),
+      (Value::Number(lh), Value::String(rh)) => Ok(Value::String(format_number(lh) + &rh)),
+      (lhs, rhs) => {
+        let message = format!(
+          "Can not add a {} and a {}: `+` adds numbers and concatenates strings",
+          lhs.type_name(),
+          rhs.type_name()
+        );
+        Err(raise(RuntimeErrorKind::Type, message))
+      }
     }
   }
   pub fn modulo(&self, lhs: Value, rhs: Value) -> Result<Value, String> {
@@ -1,25 +1,21 @@
 use crate::core::interpreter::interpreter::*;
+use crate::utils::trace::trace_event;
 use crate::utils::{element::*, node::*};
 
 impl Interpreter {
   pub fn process_node(&mut self, node: &Node) -> Result<Option<Value>, String> {
     for instruction in node.get_child() {
       if instruction.get_type() == NodeType::Scope {
-        self.add_scope();
-        self.process_node(&instruction)?;
-        self.remove_scope();
-      } else if instruction.get_type() == NodeType::Block {
-        if self.scopes.len() == 0 {
-          return Err("No scopes available. Consider adding a scope to your program".to_owned());
+        if let Some(returned) = self.process_scope(instruction)? {
+          return Ok(Some(returned));
         }
-        let returned = self.process_node(&instruction)?;
+      } else if instruction.get_type() == NodeType::Block {
+        let returned = self.process_node(instruction).map_err(|e| self.located(instruction, e))?;
         if returned.is_some() {
           return Ok(returned);
         }
       } else {
-        if self.scopes.len() == 0 {
-          return Err("No scopes available. Consider adding a scope to your program".to_owned());
-        }
+        self.enter_statement(instruction)?;
         let t = instruction.get_type();
         let children = instruction.get_child();
 
@@ -28,17 +24,31 @@ impl Interpreter {
             self.var_edit(&children[0], &children[1])?;
           } else {
             self.var_def(
-              if a == AssignType::Const { true } else { false },
+              a == AssignType::Const,
               &children[0],
               &children[1],
             )?;
+            if let (Some(NodeType::NodeIdentifier(name)), Some(NodeType::Deprecated(hint))) = (
+              children.first().map(|c| c.get_type()),
+              children.get(2).map(|c| c.get_type()),
+            ) {
+              self.deprecated.insert(name, (self.env.depth(), hint));
+            }
           }
         } else if let NodeType::FunctionCall(_) = t {
-          self.process_func(&instruction)?;
-        } else if let NodeType::Loop = t {
-          self.process_loop(&instruction)?;
-        } else if let NodeType::Condition = t {
-          self.process_if(&instruction)?;
+          self.process_func(instruction)?;
+        } else if let NodeType::Loop | NodeType::Condition = t {
+          if let Some(returned) = self.process_control(instruction)? {
+            return Ok(Some(returned));
+          }
+        } else if let NodeType::Do = t {
+          self.process_do(instruction)?;
+        } else if let NodeType::Cond = t {
+          self.process_cond(instruction)?;
+        } else if let NodeType::Enum(name) = t {
+          self.process_enum(&name, children)?;
+        } else if let NodeType::Export = t {
+          self.process_export(instruction)?;
         } else if let NodeType::Return = t {
           // Return should have one children
           return Ok(Some(self.proc_value(&instruction.get_child()[0])?));
@@ -47,8 +57,76 @@ impl Interpreter {
     }
     Ok(None)
   }
+  /// Runs `scope` in a new scope, returning what a `ret` in it returned.
+  #[inline(never)]
+  fn process_scope(&mut self, scope: &Node) -> Result<Option<Value>, String> {
+    self.add_scope();
+    let returned = self.process_node(scope);
+    self.remove_scope();
+    returned
+  }
+  /// Runs a `while` or an `if`. Kept out of `process_node` like `enter_statement`.
+  #[inline(never)]
+  fn process_control(&mut self, instruction: &Node) -> Result<Option<Value>, String> {
+    match instruction.get_type() {
+      NodeType::Loop => self.process_loop(instruction),
+      _ => self.process_if(instruction),
+    }
+  }
+  /// Checks a statement can run, and traces it. Kept out of `process_node`, which every block
+  /// of a nixt function recurses through, to keep its stack frame small.
+  #[inline(never)]
+  fn enter_statement(&mut self, instruction: &Node) -> Result<(), String> {
+    trace_event!(node = %instruction, "eval");
+    if self.tracer.is_some() {
+      self.trace_step(instruction)?;
+    }
+    if self.verbose.is_some() {
+      self.verbose_statement(instruction);
+    }
+    Ok(())
+  }
+  /// Runs a `pub` definition, recording its names when it is at the module's top level.
+  fn process_export(&mut self, export: &Node) -> Result<(), String> {
+    self.process_node(export)?;
+    let definition = &export.get_child()[0];
+    let names = match definition.get_type() {
+      NodeType::Enum(name) => enum_bindings(&name, definition.get_child()),
+      _ => match definition.get_child().first().map(|n| n.get_type()) {
+        Some(NodeType::NodeIdentifier(name)) => vec![name],
+        _ => vec![],
+      },
+    };
+    if self.env.depth() == GLOBAL_SCOPE {
+      self.exports.extend(names);
+    }
+    Ok(())
+  }
+  /// Binds `Color:Red`, ... as constants, and `Color` as the list of its variants.
+  pub fn process_enum(&mut self, name: &str, variants: &[Node]) -> Result<(), String> {
+    let mut values = vec![];
+    for variant in variants {
+      if let NodeType::NodeIdentifier(variant) = variant.get_type() {
+        values.push(Value::Enum(name.to_owned(), variant));
+      }
+    }
+    let mut scope = self.env.bindings_mut();
+    for binding in enum_bindings(name, variants) {
+      if scope.contains_key(&binding) {
+        return Err(format!(
+          "Attempted to redefine variable `{}` that is already present in the current scope",
+          binding
+        ));
+      }
+    }
+    for value in &values {
+      scope.insert(format!("{}", value), (value.clone(), true));
+    }
+    scope.insert(name.to_owned(), (Value::from(values), true));
+    Ok(())
+  }
   pub fn process_inner_block(&mut self, val: &Node) -> Result<Value, String> {
-    if val.get_child().len() < 1 {
+    if val.get_child().is_empty() {
       return Ok(Value::Nil);
     }
 
@@ -57,7 +135,76 @@ impl Interpreter {
       NodeType::Operator(op) => self.proc_operator(op, &val.get_child()[0]),
       NodeType::FunctionCall(_) => self.process_func(&val.get_child()[0]),
       NodeType::Block => Ok(self.process_inner_block(&val.get_child()[0])?),
+      NodeType::Do => self.process_do(&val.get_child()[0]),
+      NodeType::Cond => self.process_cond(&val.get_child()[0]),
       _ => Ok(Value::Nil),
     }
   }
+  pub fn process_do(&mut self, val: &Node) -> Result<Value, String> {
+    self.add_scope();
+    let last = self.process_sequence(val);
+    self.remove_scope();
+    last
+  }
+  /// Evaluates every child of `val` in the current scope, yielding the last value.
+  pub fn process_sequence(&mut self, val: &Node) -> Result<Value, String> {
+    let mut last = Value::Nil;
+    for expr in val.get_child() {
+      last = if expr.get_type() == NodeType::Scope {
+        self.add_scope();
+        let returned = self.process_node(expr);
+        self.remove_scope();
+        no_return(returned?)?
+      } else if expr.get_type() == NodeType::Block && is_statement(expr) {
+        no_return(self.process_node(expr)?)?
+      } else {
+        self.proc_value(expr)?
+      };
+    }
+    Ok(last)
+  }
+}
+
+/// Whether `block` wraps a form that produces a value, such as a call or an operation.
+pub fn is_expression(block: &Node) -> bool {
+  matches!(
+    block.get_child().first().map(|n| n.get_type()),
+    Some(NodeType::Operator(_))
+      | Some(NodeType::FunctionCall(_))
+      | Some(NodeType::Func)
+      | Some(NodeType::Do)
+      | Some(NodeType::Cond)
+      | Some(NodeType::Block)
+  )
+}
+
+/// The names an `enum` binds: itself and each `name:variant`.
+fn enum_bindings(name: &str, variants: &[Node]) -> Vec<String> {
+  let mut names = vec![name.to_owned()];
+  for variant in variants {
+    if let NodeType::NodeIdentifier(variant) = variant.get_type() {
+      names.push(format!("{}:{}", name, variant));
+    }
+  }
+  names
+}
+
+/// `do` and `cond` are expressions, which a `ret` in them can not leave.
+fn no_return(returned: Option<Value>) -> Result<Value, String> {
+  match returned {
+    Some(_) => Err("`ret` can not return from inside a `do` or `cond` expression".to_owned()),
+    None => Ok(Value::Nil),
+  }
+}
+
+fn is_statement(block: &Node) -> bool {
+  matches!(
+    block.get_child().first().map(|n| n.get_type()),
+    Some(NodeType::Assignement(_))
+      | Some(NodeType::Enum(_))
+      | Some(NodeType::Export)
+      | Some(NodeType::Loop)
+      | Some(NodeType::Condition)
+      | Some(NodeType::Return)
+  )
 }
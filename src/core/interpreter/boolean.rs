@@ -1,4 +1,5 @@
 use crate::core::interpreter::interpreter::Interpreter;
+use crate::stdlib;
 use crate::utils::element::*;
 
 impl Interpreter {
@@ -40,6 +41,13 @@ impl Interpreter {
         Value::Nil => Ok(Value::Bool(true)),
         _ => Ok(Value::Bool(false)),
       },
+      Value::Enum(lh, lv) => match rhs {
+        Value::Enum(rh, rv) => Ok(Value::Bool(rh == lh && rv == lv)),
+        _ => Ok(Value::Bool(false)),
+      },
+      Value::Range(..) | Value::Map(_) | Value::Resource(_) => {
+        Ok(Value::Bool(stdlib::list::values_equal(&lhs, &rhs)))
+      }
       _ => Ok(Value::Bool(false)),
     }
   }
@@ -62,6 +70,13 @@ impl Interpreter {
         Value::Nil => Ok(Value::Bool(false)),
         _ => Ok(Value::Bool(true)),
       },
+      Value::Enum(lh, lv) => match rhs {
+        Value::Enum(rh, rv) => Ok(Value::Bool(rh != lh || rv != lv)),
+        _ => Ok(Value::Bool(true)),
+      },
+      Value::Range(..) | Value::Map(_) | Value::Resource(_) => {
+        Ok(Value::Bool(!stdlib::list::values_equal(&lhs, &rhs)))
+      }
       _ => Ok(Value::Bool(true)),
     }
   }
@@ -0,0 +1,367 @@
+use crate::core::interpreter::environment::{Bindings, Environment};
+use crate::core::interpreter::functions::BUILTINS;
+use crate::core::interpreter::harness::Snapshots;
+use crate::core::interpreter::interpreter::Interpreter;
+use crate::core::interpreter::plugin::{Builtins, NixtPlugin};
+use crate::core::interpreter::replay::{Trace, Tracer};
+use crate::core::interpreter::sandbox::SandboxPolicy;
+use crate::core::interpreter::shared::SharedEnv;
+use crate::core::interpreter::transfer;
+use crate::core::interpreter::verbose::{Verbose, Verbosity};
+use crate::core::lexer::default_keywords;
+use crate::core::parser::validate_identifier;
+use crate::utils::element::Value;
+use crate::utils::token::TokenType;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The standard modules evaluated into the prelude scope, with the namespace of their names.
+/// Each one is loaded the first time a name of its namespace is looked up.
+pub const PRELUDE: &[(&str, &str, &str)] = &[
+  ("math", "std/math", include_str!("../../../std/math.nxt")),
+  ("str", "std/string", include_str!("../../../std/string.nxt")),
+];
+
+/// Configures and creates an [`Interpreter`].
+///
+/// ```
+/// let mut interpreter = nixt::Interpreter::builder().prelude(false).strict(true).build()?;
+/// interpreter.eval_str("(print (+ 1 2))")?;
+/// # Ok::<(), String>(())
+/// ```
+pub struct InterpreterBuilder {
+  strict: bool,
+  infix: bool,
+  lenient: bool,
+  const_exprs: bool,
+  builtins: bool,
+  sandbox: SandboxPolicy,
+  prelude: bool,
+  stdout: Option<Box<dyn Write>>,
+  stderr: Option<Box<dyn Write>>,
+  defines: Vec<(String, Value)>,
+  keywords: BTreeMap<String, TokenType>,
+  plugins: Vec<Box<dyn NixtPlugin>>,
+  tracer: Option<Tracer>,
+  verbose: Option<Verbose>,
+  history: bool,
+  introspection: bool,
+  search_paths: Vec<std::path::PathBuf>,
+  prelude_sources: Vec<String>,
+  shared: Option<SharedEnv>,
+  snapshots: Option<Snapshots>,
+  test_timeout: Option<Duration>,
+  #[cfg(feature = "plugins")]
+  plugin_dirs: Vec<std::path::PathBuf>,
+}
+
+impl Default for InterpreterBuilder {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl InterpreterBuilder {
+  pub fn new() -> Self {
+    Self {
+      strict: false,
+      infix: false,
+      lenient: false,
+      const_exprs: false,
+      builtins: true,
+      sandbox: SandboxPolicy::AllowAll,
+      prelude: true,
+      stdout: None,
+      stderr: None,
+      defines: vec![],
+      keywords: default_keywords(),
+      plugins: vec![],
+      tracer: None,
+      verbose: None,
+      history: false,
+      introspection: false,
+      search_paths: vec![],
+      prelude_sources: vec![],
+      shared: None,
+      snapshots: None,
+      test_timeout: None,
+      #[cfg(feature = "plugins")]
+      plugin_dirs: vec![],
+    }
+  }
+  /// Enables the stricter parser diagnostics of `eval_str`.
+  pub fn strict(mut self, strict: bool) -> Self {
+    self.strict = strict;
+    self
+  }
+  /// Accepts infix expressions in `eval_str`.
+  pub fn infix(mut self, infix: bool) -> Self {
+    self.infix = infix;
+    self
+  }
+  /// Accepts case-insensitive keywords and stray commas in `eval_str`.
+  pub fn lenient(mut self, lenient: bool) -> Self {
+    self.lenient = lenient;
+    self
+  }
+  /// Requires `const` initializers to be constant expressions (literals, other constants and
+  /// operators) and folds them before evaluating.
+  pub fn const_exprs(mut self, const_exprs: bool) -> Self {
+    self.const_exprs = const_exprs;
+    self
+  }
+  /// Whether the native builtins (`print`, `list`, `math:cos`, ...) can be called.
+  pub fn builtins(mut self, builtins: bool) -> Self {
+    self.builtins = builtins;
+    self
+  }
+  /// Restricts the builtins and prelude bindings programs can use.
+  pub fn sandbox(mut self, policy: SandboxPolicy) -> Self {
+    self.sandbox = policy;
+    self
+  }
+  /// Evaluates `source` into the prelude when building, after the standard modules if
+  /// [`InterpreterBuilder::prelude`] keeps them: its bindings are available to every script,
+  /// which can not rebind them.
+  ///
+  /// ```
+  /// let mut interpreter = nixt::Interpreter::builder()
+  ///   .prelude(false)
+  ///   .prelude_source("(const GRAVITY 9.81) (let fall (func (t) {(ret (* GRAVITY t t 0.5))}))")
+  ///   .build()?;
+  /// assert_eq!(format!("{}", interpreter.eval_str("(fall 2)")?), "19.62");
+  /// assert!(interpreter.eval_str("(set GRAVITY 0)").is_err());
+  /// # Ok::<(), String>(())
+  /// ```
+  pub fn prelude_source(mut self, source: &str) -> Self {
+    self.prelude_sources.push(source.to_owned());
+    self
+  }
+  /// Adds a directory to search for the modules `import` does not find from the working
+  /// directory, after the ones added before.
+  pub fn search_path(mut self, dir: impl Into<std::path::PathBuf>) -> Self {
+    self.search_paths.push(dir.into());
+    self
+  }
+  /// Whether the standard modules are loaded before any evaluation. Defaults to `true`.
+  pub fn prelude(mut self, prelude: bool) -> Self {
+    self.prelude = prelude;
+    self
+  }
+  /// Where `print` and `puts` write to. Defaults to the process' stdout.
+  pub fn stdout(mut self, out: impl Write + 'static) -> Self {
+    self.stdout = Some(Box::new(out));
+    self
+  }
+  /// Where `eprint` writes to. Defaults to the process' stderr.
+  pub fn stderr(mut self, err: impl Write + 'static) -> Self {
+    self.stderr = Some(Box::new(err));
+    self
+  }
+  /// Binds `name` as a global constant of the built interpreter.
+  pub fn define(mut self, name: &str, value: Value) -> Self {
+    self.defines.push((name.to_owned(), value));
+    self
+  }
+  /// Makes `word` lex as the keyword `typ` in evaluated code, e.g. `var` as `TokenType::Let`.
+  ///
+  /// The original spelling keeps working; use [`InterpreterBuilder::keywords`] to replace it.
+  pub fn keyword(mut self, word: &str, typ: TokenType) -> Self {
+    self.keywords.insert(word.to_owned(), typ);
+    self
+  }
+  /// Replaces the whole keyword table. The prelude is still read with the default one.
+  pub fn keywords(mut self, keywords: BTreeMap<String, TokenType>) -> Self {
+    self.keywords = keywords;
+    self
+  }
+  /// Adds the builtins of `plugin`, which can not replace the standard ones.
+  pub fn plugin(mut self, plugin: impl NixtPlugin + 'static) -> Self {
+    self.plugins.push(Box::new(plugin));
+    self
+  }
+  /// Loads the plugin libraries of `dir` when building, see
+  /// [`NixtPlugin`](crate::core::interpreter::plugin::NixtPlugin).
+  #[cfg(feature = "plugins")]
+  pub fn plugin_dir(mut self, dir: impl Into<std::path::PathBuf>) -> Self {
+    self.plugin_dirs.push(dir.into());
+    self
+  }
+  /// Records every statement, call of a nixt function and result of a nondeterministic builtin,
+  /// for [`Interpreter::take_trace`] to return. See [`Trace`].
+  pub fn record(mut self, record: bool) -> Self {
+    self.tracer = if record { Some(Tracer::Record(Trace::default())) } else { None };
+    self
+  }
+  /// Replays `trace`: nondeterministic builtins return what they returned when it was recorded,
+  /// and evaluating anything else than what was recorded is an error.
+  pub fn replay(mut self, trace: Trace) -> Self {
+    self.tracer = Some(Tracer::Replay { trace, position: 0 });
+    self
+  }
+  /// Writes to `out`, as the interpreter evaluates, the statements, the bindings they leave and
+  /// how long they took, as `verbosity` selects. See [`Verbosity`].
+  pub fn verbose(mut self, out: impl Write + 'static, verbosity: Verbosity) -> Self {
+    self.verbose = Some(Verbose { verbosity, out: Box::new(out) });
+    self
+  }
+  /// Builds on `env`, looking its bindings up below the prelude scope. The standard modules
+  /// are not loaded again if `env` has them.
+  pub fn shared_env(mut self, env: &SharedEnv) -> Self {
+    self.shared = Some(env.clone());
+    self
+  }
+  /// Evaluates the prelude, the host prelude sources and the constants this builder sets, and
+  /// freezes them into an environment interpreters can share with
+  /// [`InterpreterBuilder::shared_env`].
+  ///
+  /// Only bindings are kept: the outputs, plugins, keywords and evaluation modes are set on the
+  /// builder of each interpreter.
+  ///
+  /// Fails if a binding can not be shared, as a function defined in a local scope can not be
+  /// transferred to another interpreter: see [`Transfer`](crate::Transfer).
+  pub fn freeze(self) -> Result<SharedEnv, String> {
+    let prelude = self.prelude || self.shared.as_ref().is_some_and(|env| env.prelude);
+    let mut interpreter = self.build()?;
+    while let Some((namespace, _, _)) = interpreter.pending.first() {
+      let name = format!("{}:", namespace);
+      interpreter.load_namespace_of(&name);
+    }
+    let mut bindings = match interpreter.shared.take() {
+      Some(env) => Arc::unwrap_or_clone(env.bindings),
+      None => BTreeMap::new(),
+    };
+    for scope in interpreter.env.chain() {
+      bindings.extend(std::mem::take(&mut *scope.bindings_mut()));
+    }
+    for (name, (value, _)) in bindings.iter() {
+      transfer::check(value).map_err(|e| format!("{}: `{}`", e, name))?;
+    }
+    Ok(SharedEnv {
+      bindings: Arc::new(bindings),
+      prelude,
+    })
+  }
+  /// Where `(assert_snapshot "name" value)` reads and writes its snapshots. Without it,
+  /// `assert_snapshot` fails.
+  pub fn snapshots(mut self, snapshots: Snapshots) -> Self {
+    self.snapshots = Some(snapshots);
+    self
+  }
+  /// Fails the tests [`Interpreter::run_test`] runs once they ran for `limit`, unless
+  /// `(timeout ms)` gave them another limit.
+  pub fn test_timeout(mut self, limit: Duration) -> Self {
+    self.test_timeout = Some(limit);
+    self
+  }
+  /// Records every definition and assignment of every binding, for
+  /// [`Interpreter::history`] to show how it evolved.
+  pub fn history(mut self, history: bool) -> Self {
+    self.history = history;
+    self
+  }
+  /// Grants the debug capability: `(introspect)` returns the scopes, the calls and the loaded
+  /// modules, which are recorded for it. See [`Interpreter::introspect`].
+  pub fn introspection(mut self, introspection: bool) -> Self {
+    self.introspection = introspection;
+    self
+  }
+  pub fn build(self) -> Result<Interpreter, String> {
+    let mut interpreter = Interpreter {
+      env: Environment::new(Bindings::new()),
+      out: self.stdout.unwrap_or_else(|| Box::new(std::io::stdout())),
+      err: self.stderr.unwrap_or_else(|| Box::new(std::io::stderr())),
+      strict: self.strict,
+      infix: self.infix,
+      lenient: self.lenient,
+      const_exprs: self.const_exprs,
+      builtins: self.builtins,
+      sandbox: SandboxPolicy::AllowAll,
+      keywords: default_keywords(),
+      deprecated: BTreeMap::new(),
+      warnings: vec![],
+      exports: vec![],
+      private: BTreeMap::new(),
+      search_paths: self.search_paths,
+      import_stack: vec![],
+      module_scopes: vec![],
+      pending: vec![],
+      uses: vec![],
+      interrupt: Default::default(),
+      exit_handlers: vec![],
+      natives: Builtins::default(),
+      tracer: self.tracer,
+      verbose: self.verbose,
+      history: if self.history { Some(Default::default()) } else { None },
+      watches: Default::default(),
+      shared: None,
+      tests: vec![],
+      pending_tags: vec![],
+      pending_timeout: None,
+      test_timeout: self.test_timeout,
+      fixtures: BTreeMap::new(),
+      macros: BTreeMap::new(),
+      snapshots: self.snapshots,
+      introspection: self.introspection,
+      calls: vec![],
+      modules: vec![],
+      error_span: None,
+      initial_globals: Bindings::new(),
+      #[cfg(feature = "plugins")]
+      libraries: vec![],
+    };
+    for plugin in &self.plugins {
+      let mut registry = Builtins::default();
+      plugin.register(&mut registry);
+      add_natives(&mut interpreter, plugin.name(), registry)?;
+    }
+    #[cfg(feature = "plugins")]
+    for dir in &self.plugin_dirs {
+      let mut registry = Builtins::default();
+      let libraries = crate::core::interpreter::plugin::load_dir(dir, &mut registry)?;
+      interpreter.libraries.extend(libraries);
+      add_natives(&mut interpreter, &dir.display().to_string(), registry)?;
+    }
+    if let Some((word, _)) = self.keywords.iter().find(|(_, typ)| !typ.is_keyword()) {
+      return Err(format!("`{}` can only be mapped to a keyword", word));
+    }
+    if self.prelude && !self.shared.as_ref().is_some_and(|env| env.prelude) {
+      interpreter.pending = PRELUDE.to_vec();
+    }
+    interpreter.shared = self.shared;
+    interpreter.sandbox = self.sandbox;
+    interpreter.keywords = self.keywords;
+    for source in &self.prelude_sources {
+      interpreter
+        .load_module(source)
+        .map_err(|e| format!("Failed to load the host prelude: {}", e))?;
+    }
+    let sandbox = &interpreter.sandbox;
+    interpreter.env.bindings_mut().retain(|name, _| sandbox.permits(name));
+    interpreter.add_scope();
+    for (name, value) in self.defines {
+      interpreter.define(&name, value)?;
+    }
+    interpreter.initial_globals = interpreter.env.bindings().clone();
+    Ok(interpreter)
+  }
+}
+
+/// Adds the builtins a plugin registered, which must be valid identifiers and not shadow the
+/// standard builtins.
+fn add_natives(
+  interpreter: &mut Interpreter,
+  plugin: &str,
+  registry: Builtins,
+) -> Result<(), String> {
+  for name in registry.names() {
+    if BUILTINS.contains(&name) {
+      return Err(format!("Plugin `{}` can not redefine builtin `{}`", plugin, name));
+    }
+    validate_identifier(name).map_err(|e| format!("Plugin `{}`: {}", plugin, e))?;
+  }
+  interpreter.natives.extend(registry);
+  Ok(())
+}
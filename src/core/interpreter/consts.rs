@@ -0,0 +1,104 @@
+use crate::core::interpreter::interpreter::Interpreter;
+use crate::utils::{element::*, node::*};
+use std::collections::BTreeMap;
+
+impl Interpreter {
+  /// Checks that every `const` of `ast` is initialized with a constant expression, made of
+  /// literals, other constants and operators, and replaces it with its folded value.
+  pub fn fold_consts(&mut self, ast: &Node) -> Result<Node, String> {
+    self.fold_node(ast, &mut BTreeMap::new())
+  }
+  fn fold_node(
+    &mut self,
+    node: &Node,
+    folded: &mut BTreeMap<String, Option<Node>>,
+  ) -> Result<Node, String> {
+    let mut toret = Node::new(node.get_type());
+    toret.set_span(node.span());
+    let children = node.get_child();
+    if node.get_type() == NodeType::Assignement(AssignType::Const) {
+      let name = match children[0].get_type() {
+        NodeType::NodeIdentifier(name) => name,
+        _ => return Ok(node.clone()),
+      };
+      let value = match self.fold_expression(&children[1], folded) {
+        Ok(value) => value,
+        Err(e) => {
+          return Err(format!(
+            "`const {}` must be initialized with a constant expression: {}",
+            name, e
+          ))
+        }
+      };
+      folded.insert(name, Some(value.clone()));
+      toret.add_children(&children[0]);
+      toret.add_children(&value);
+      for child in &children[2..] {
+        toret.add_children(child);
+      }
+      return Ok(toret);
+    }
+    if node.get_type() == NodeType::Assignement(AssignType::Let) {
+      if let NodeType::NodeIdentifier(name) = children[0].get_type() {
+        folded.insert(name, None); // Known, but not a constant
+      }
+    }
+    for child in children {
+      toret.add_children(&self.fold_node(child, folded)?);
+    }
+    Ok(toret)
+  }
+  /// Evaluates a constant expression down to a literal node.
+  fn fold_expression(
+    &mut self,
+    expr: &Node,
+    folded: &BTreeMap<String, Option<Node>>,
+  ) -> Result<Node, String> {
+    match expr.get_type() {
+      NodeType::NodeNumber(_) | NodeType::NodeStr(_) | NodeType::NodeBool(_) | NodeType::None => {
+        Ok(expr.clone())
+      }
+      NodeType::NodeIdentifier(name) => {
+        match folded.get(&name) {
+          Some(Some(value)) => return Ok(value.clone()),
+          Some(None) => return Err(format!("`{}` is not a constant", name)),
+          None => {}
+        }
+        self.load_namespace_of(&name);
+        let binding = self.env.lookup(&name).map(|(_, binding)| binding);
+        let binding = binding.or_else(|| self.shared_binding(&name).cloned());
+        match binding {
+          Some((value, true)) => literal(&value).ok_or(format!("`{}` is not a literal", name)),
+          Some(_) => Err(format!("`{}` is not a constant", name)),
+          None => Err(format!("`{}` is not defined", name)),
+        }
+      }
+      NodeType::Block if expr.get_child().len() == 1 => {
+        let inner = &expr.get_child()[0];
+        match inner.get_type() {
+          NodeType::Operator(op) => {
+            let mut operation = Node::new(NodeType::Operator(op.clone()));
+            for operand in inner.get_child() {
+              operation.add_children(&self.fold_expression(operand, folded)?);
+            }
+            let value = self.proc_operator(op, &operation)?;
+            literal(&value).ok_or_else(|| "the operation does not yield a literal".to_owned())
+          }
+          NodeType::Block => self.fold_expression(inner, folded),
+          _ => Err(format!("`{}` is not a constant expression", expr)),
+        }
+      }
+      _ => Err(format!("`{}` is not a constant expression", expr)),
+    }
+  }
+}
+
+fn literal(value: &Value) -> Option<Node> {
+  match value {
+    Value::Number(n) => Some(Node::new(NodeType::NodeNumber(*n))),
+    Value::String(s) => Some(Node::new(NodeType::NodeStr(s.to_owned()))),
+    Value::Bool(b) => Some(Node::new(NodeType::NodeBool(*b))),
+    Value::Nil => Some(Node::new(NodeType::None)),
+    _ => None,
+  }
+}
@@ -0,0 +1,202 @@
+use crate::utils::element::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// The bindings of a scope: the value of each name, and whether it is a constant.
+pub type Bindings = BTreeMap<String, (Value, bool)>;
+
+struct Scope {
+  bindings: Mutex<Bindings>,
+  parent: Option<Environment>,
+  kind: Kind,
+  /// The number of scopes enclosing this one: 0 for the prelude, 1 for the globals.
+  depth: usize,
+}
+
+#[derive(PartialEq)]
+enum Kind {
+  /// The scope of a program or of a `{}` block.
+  Block,
+  /// The scope holding the arguments of a function call.
+  Call,
+  /// The scope of an iteration of a loop, through which `set` edits the enclosing bindings.
+  Iteration,
+}
+
+/// A scope with the chain of scopes enclosing it, up to the prelude.
+///
+/// Cloning an environment shares its scopes instead of copying them: a value holding the
+/// environment it was created in sees the bindings defined in it later, and its changes are
+/// seen by the interpreter. Functions keep the scope they are defined in this way, and as
+/// they are values that can be sent to other threads, in a [`SharedEnv`] for one, scopes are
+/// behind an `Arc` and a `Mutex` rather than an `Rc` and a `RefCell`.
+///
+/// [`SharedEnv`]: crate::SharedEnv
+///
+/// ```
+/// use nixt::core::interpreter::environment::Environment;
+/// let globals = Environment::new(Default::default()).child();
+/// let local = globals.child();
+/// globals.bindings_mut().insert("x".to_owned(), (nixt::Value::from(1.), false));
+/// assert_eq!(local.get("x").unwrap().to_string(), "1");
+/// assert_eq!((local.depth(), local.scope(1).depth()), (2, 1));
+/// assert!(local.scope(1).ptr_eq(&globals));
+/// ```
+#[derive(Clone)]
+pub struct Environment(Arc<Scope>);
+
+impl Environment {
+  /// An outermost scope holding `bindings`.
+  pub fn new(bindings: Bindings) -> Self {
+    Self(Arc::new(Scope {
+      bindings: Mutex::new(bindings),
+      parent: None,
+      kind: Kind::Block,
+      depth: 0,
+    }))
+  }
+  /// A new empty scope inside this one.
+  pub fn child(&self) -> Self {
+    self.child_with(Bindings::new())
+  }
+  /// A new scope inside this one, holding `bindings`.
+  pub fn child_with(&self, bindings: Bindings) -> Self {
+    self.nested(bindings, Kind::Block)
+  }
+  /// A new scope inside this one for a function call, holding its `arguments`.
+  pub fn call(&self, arguments: Bindings) -> Self {
+    self.nested(arguments, Kind::Call)
+  }
+  /// A new empty scope inside this one for an iteration of a loop.
+  pub fn iteration(&self) -> Self {
+    self.nested(Bindings::new(), Kind::Iteration)
+  }
+  fn nested(&self, bindings: Bindings, kind: Kind) -> Self {
+    Self(Arc::new(Scope {
+      bindings: Mutex::new(bindings),
+      parent: Some(self.clone()),
+      kind,
+      depth: self.0.depth + 1,
+    }))
+  }
+  /// The scope enclosing this one, unless this is the outermost.
+  pub fn parent(&self) -> Option<&Environment> {
+    self.0.parent.as_ref()
+  }
+  /// The number of scopes enclosing this one: 0 for the prelude, 1 for the globals.
+  pub fn depth(&self) -> usize {
+    self.0.depth
+  }
+  /// Whether the scope was made by [`Environment::call`]. A call made as the prelude loads has
+  /// the depth of the globals.
+  pub fn is_call(&self) -> bool {
+    self.0.kind == Kind::Call
+  }
+  /// The scope `set` edits `name` in: this one, unless it is the scope of a loop iteration not
+  /// defining `name`, which `set` goes through to the scope enclosing the loop.
+  pub fn settable(&self, name: &str) -> &Environment {
+    let mut scope = self;
+    while scope.0.kind == Kind::Iteration && !scope.bindings().contains_key(name) {
+      match scope.parent() {
+        Some(parent) => scope = parent,
+        None => break,
+      }
+    }
+    scope
+  }
+  /// The bindings of this scope, not of the enclosing ones. They are locked until the guard is
+  /// dropped: looking a name up in the chain meanwhile waits forever.
+  pub fn bindings(&self) -> MutexGuard<'_, Bindings> {
+    self.0.bindings.lock().unwrap_or_else(PoisonError::into_inner)
+  }
+  /// [`Environment::bindings`], to change them.
+  pub fn bindings_mut(&self) -> MutexGuard<'_, Bindings> {
+    self.bindings()
+  }
+  /// The scope of the chain at `depth`: `scope(0)` is the prelude, `scope(GLOBAL_SCOPE)` the
+  /// globals.
+  ///
+  /// Panics if `depth` is deeper than this scope.
+  pub fn scope(&self, depth: usize) -> Environment {
+    assert!(depth <= self.0.depth, "No scope at depth {} below depth {}", depth, self.0.depth);
+    let mut scope = self;
+    while scope.0.depth > depth {
+      scope = scope.parent().expect("Every scope but the outermost has a parent");
+    }
+    scope.clone()
+  }
+  /// This scope and the enclosing ones, innermost first.
+  pub fn iter(&self) -> impl Iterator<Item = &Environment> {
+    std::iter::successors(Some(self), |scope| scope.parent())
+  }
+  /// This scope and the enclosing ones, outermost first: the prelude, the globals, then the
+  /// locals.
+  pub fn chain(&self) -> Vec<&Environment> {
+    let mut chain = self.iter().collect::<Vec<_>>();
+    chain.reverse();
+    chain
+  }
+  /// The value and constness of `name` in the innermost scope binding it, with that scope's
+  /// depth.
+  pub fn lookup(&self, name: &str) -> Option<(usize, (Value, bool))> {
+    self.iter().find_map(|scope| {
+      let binding = scope.bindings().get(name).cloned();
+      binding.map(|binding| (scope.depth(), binding))
+    })
+  }
+  /// The value of `name` in the innermost scope binding it.
+  pub fn get(&self, name: &str) -> Option<Value> {
+    self.lookup(name).map(|(_, (value, _))| value)
+  }
+  /// Whether a scope of the chain binds `name`.
+  pub fn contains(&self, name: &str) -> bool {
+    self.iter().any(|scope| scope.bindings().contains_key(name))
+  }
+  /// The innermost scope binding `name`.
+  pub fn find(&self, name: &str) -> Option<&Environment> {
+    self.iter().find(|scope| scope.bindings().contains_key(name))
+  }
+  /// A copy of the bindings of every scope of the chain, outermost first, for
+  /// [`Environment::restore`] to bring them back.
+  pub fn snapshot(&self) -> Vec<Bindings> {
+    self.chain().into_iter().map(|scope| scope.bindings().clone()).collect()
+  }
+  /// Puts back the bindings `snapshot` copied from this chain, in the same scopes, so that the
+  /// functions keeping them see the restored bindings.
+  pub fn restore(&self, snapshot: Vec<Bindings>) {
+    for (scope, bindings) in self.chain().into_iter().zip(snapshot) {
+      *scope.bindings_mut() = bindings;
+    }
+  }
+  /// Drops this handle on a scope being left. A function bound in the scope it was defined in
+  /// keeps it, and so itself, alive: if such functions are all that still keep the scope, its
+  /// bindings are cleared so that both are freed.
+  pub(crate) fn release(self) {
+    if Arc::strong_count(&self.0) > 1 {
+      self.release_cycles();
+    }
+  }
+  #[inline(never)]
+  fn release_cycles(self) {
+    let mut bindings = self.bindings();
+    let keeping = bindings.values().filter(|(value, _)| match value {
+      Value::Func(func) => func.env.as_ref().is_some_and(|env| env.ptr_eq(&self)),
+      _ => false,
+    });
+    if keeping.count() + 1 == Arc::strong_count(&self.0) {
+      std::mem::take(&mut *bindings);
+    }
+  }
+  /// Whether both are the same scope, rather than scopes with the same bindings.
+  pub fn ptr_eq(&self, other: &Environment) -> bool {
+    Arc::ptr_eq(&self.0, &other.0)
+  }
+}
+
+/// Only shows the depth: the bindings may hold functions keeping this very scope.
+impl fmt::Debug for Environment {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("Environment").field("depth", &self.depth()).finish()
+  }
+}
@@ -1,13 +1,16 @@
-use crate::core::interpreter::interpreter::Interpreter;
+use crate::core::interpreter::environment::{Bindings, Environment};
+use crate::core::interpreter::interpreter::{Interpreter, GLOBAL_SCOPE};
+use crate::error::{raise, RuntimeErrorKind};
 use crate::stdlib;
+use crate::utils::trace::{trace_event, trace_phase};
 use crate::utils::{element::*, node::*};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 impl Interpreter {
   pub fn proc_fun_def(&mut self, val: &Node) -> Result<Value, String> {
     let mut argstr = Vec::<String>::new();
     let args = val.get_child()[0].get_child();
-    for arg in &args {
+    for arg in args {
       if let NodeType::NodeIdentifier(s) = arg.get_type() {
         argstr.push(s);
       } else {
@@ -15,69 +18,170 @@ impl Interpreter {
         // Should never be called because parser checks before
       }
     }
-    Ok(Value::Func(Func {
+    // Functions defined in the globals find them in the interpreter calling them, unless they
+    // are the globals of an imported module, which keeps its private bindings.
+    let in_module = self.env.depth() == GLOBAL_SCOPE && !self.import_stack.is_empty();
+    let env = Some(self.env.clone())
+      .filter(|env| env.depth() > GLOBAL_SCOPE || env.is_call() || in_module);
+    Ok(Value::Func(Box::new(Func {
       args: argstr,
       body: val.get_child()[1].clone(),
-    }))
+      env,
+    })))
   }
-  pub fn process_func_call(&mut self, function: &Node, args: &Vec<Value>) -> Result<Value, String> {
-    let (func_args, body) = if let NodeType::FunctionCall(s) = function.get_type() {
+  pub fn process_func_call(&mut self, function: &Node, args: &[Value]) -> Result<Value, String> {
+    let fnc = if let NodeType::FunctionCall(s) = function.get_type() {
       if self.get_value(&s).is_some() {
         let raw_func = self.get_value(&s).unwrap();
 
         if let Value::Func(fnc) = raw_func {
-          let args = fnc.args;
-          let body = fnc.body;
-
-          (args, body)
+          fnc
         } else {
-          return Err("Attempted to call a regular variable as a function".to_owned());
+          return self.call_bound(raw_func, args);
         }
       } else {
-        return Err("Attempted to call an undefined function".to_owned());
+        let error = self.undefined(&s, "Attempted to call an undefined function".to_owned());
+        return Err(self.located(function, error));
       }
     } else {
       panic!("This should not be called, if you see this, please open an issue.");
     };
-    if args.len() != func_args.len() {
-      return Err(format!(
+    if self.tracer.is_some() {
+      self.trace_step(function)?;
+    }
+    if self.introspection {
+      return self.call_recorded(function, &fnc, args);
+    }
+    self.call_function(&fnc, args)
+  }
+  /// Calls `fnc` with its name on the call stack `(introspect)` returns.
+  #[inline(never)]
+  fn call_recorded(
+    &mut self,
+    function: &Node,
+    fnc: &Func,
+    args: &[Value],
+  ) -> Result<Value, String> {
+    let name = match function.get_type() {
+      NodeType::FunctionCall(name) => name,
+      _ => panic!("A call is always to a named function"),
+    };
+    self.calls.push(name);
+    let result = self.call_function(fnc, args);
+    self.calls.pop();
+    result
+  }
+  /// Calls a function or a builtin.
+  ///
+  /// ```
+  /// let mut interpreter = nixt::Interpreter::builder().build()?;
+  /// interpreter.eval_str("(let size len)")?;
+  /// let size = interpreter.get("size").unwrap();
+  /// assert_eq!(interpreter.call_value(&size, &[nixt::Value::list([1, 2])])?.to_string(), "2");
+  /// # Ok::<(), String>(())
+  /// ```
+  pub fn call_value(&mut self, callee: &Value, args: &[Value]) -> Result<Value, String> {
+    match callee {
+      Value::Func(fnc) => self.call_function(fnc, args),
+      Value::Native(name) => self.call_native(name, args),
+      other => {
+        let message = format!("Attempted to call a {} as a function", other.type_name());
+        Err(raise(RuntimeErrorKind::Type, message))
+      }
+    }
+  }
+  /// Calls a variable that is not bound to a nixt function: a builtin, or an error.
+  #[inline(never)]
+  fn call_bound(&mut self, value: Value, args: &[Value]) -> Result<Value, String> {
+    match value {
+      Value::Native(name) => self.call_native(&name, args),
+      _ => Err(raise(
+        RuntimeErrorKind::Type,
+        "Attempted to call a regular variable as a function",
+      )),
+    }
+  }
+  /// Calls the builtin `name` a `Value::Native` stands for, as the sandbox permits.
+  #[inline(never)]
+  fn call_native(&mut self, name: &str, args: &[Value]) -> Result<Value, String> {
+    if !self.sandbox.permits(name) {
+      self.check_sandbox(name)?;
+    }
+    let unknown = || Err(format!("`{}` is not a builtin", name));
+    self.process_builtin(name, args).unwrap_or_else(unknown)
+  }
+  /// The builtin an unbound identifier names, as a value, or the error `undefined` gives.
+  #[inline(never)]
+  pub(crate) fn builtin_value(
+    &mut self,
+    name: &str,
+    error: impl FnOnce() -> String,
+  ) -> Result<Value, String> {
+    let builtin = match self.uses.is_empty() {
+      true => name.to_owned(),
+      false => self.qualify_call(name.to_owned()),
+    };
+    match self.builtins && self.is_builtin(&builtin) {
+      true => Ok(Value::Native(builtin)),
+      false => Err(self.undefined(name, error())),
+    }
+  }
+  /// Calls a function value, binding `args` to its parameters in a new scope inside the one it
+  /// was defined in, or inside the globals.
+  pub fn call_function(&mut self, fnc: &Func, args: &[Value]) -> Result<Value, String> {
+    if args.len() != fnc.args.len() {
+      let message = format!(
         "Invalid number of arguments: expected {} got {}",
-        func_args.len(),
+        fnc.args.len(),
         args.len()
-      ));
+      );
+      return Err(raise(RuntimeErrorKind::Arity, message));
     }
-    self.add_scope();
-    for i in 0..args.len() {
-      self
-        .scopes
-        .last_mut()
-        .unwrap()
-        .insert(func_args[i].clone(), (args[i].clone(), false));
-    }
-    let toret = self.process_node(&body)?;
-    self.remove_scope();
-    Ok(toret.unwrap_or(Value::Nil))
+    self.check_interrupt()?;
+    let caller = self.enter_function(fnc, args);
+    let toret = self.process_node(&fnc.body);
+    self.leave_function(caller);
+    Ok(toret?.unwrap_or(Value::Nil))
   }
 
+  /// Opens the scope of a call of `fnc`, returning the caller's scope and `use`s to restore.
+  /// Kept out of `call_function`, which every call recurses through, like `leave_function`.
+  #[inline(never)]
+  fn enter_function(&mut self, fnc: &Func, args: &[Value]) -> (Environment, usize) {
+    let mut scope = Bindings::new();
+    for (name, arg) in fnc.args.iter().zip(args) {
+      scope.insert(name.clone(), (arg.clone(), false));
+    }
+    let parent = match &fnc.env {
+      Some(env) => env.clone(),
+      None => self.env.scope(GLOBAL_SCOPE.min(self.env.depth())),
+    };
+    let caller = std::mem::replace(&mut self.env, parent.call(scope));
+    (caller, self.uses.len())
+  }
+  /// Goes back to the scope and `use`s of the caller, dropping the deprecations the call made.
+  #[inline(never)]
+  fn leave_function(&mut self, (caller, uses): (Environment, usize)) {
+    let callee = std::mem::replace(&mut self.env, caller);
+    self.uses.truncate(uses);
+    if !self.deprecated.is_empty() {
+      self.deprecated.retain(|_, (scope, _)| *scope < callee.depth());
+    }
+    callee.release();
+  }
   pub fn process_func(&mut self, func: &Node) -> Result<Value, String> {
-    let children = &func.get_child();
+    let children = func.get_child();
     let mut as_value = vec![];
     for child in children {
       let topsh = match child.get_type() {
-        NodeType::Block => self.process_inner_block(&child)?,
+        NodeType::Block => self.process_inner_block(child)?,
         NodeType::NodeBool(b) => Value::Bool(b),
         NodeType::NodeNumber(n) => Value::Number(n),
         NodeType::NodeStr(s) => Value::String(s),
         NodeType::None => Value::Nil,
-        NodeType::NodeIdentifier(s) => {
-          if self.get_value(&s).is_some() {
-            self.get_value(&s).unwrap()
-          } else {
-            return Err(format!("Attempted to use an undefined variable: `{}`", s));
-          }
-        }
+        NodeType::NodeIdentifier(s) => self.argument(child, &s)?,
         NodeType::FunctionCall(_) => self.process_func(child)?,
-        x => return Err(format!("Unexpected value: {:?}", x)),
+        _ => return Err(format!("Unexpected value: {}", child)),
       };
       as_value.push(topsh);
     }
@@ -87,57 +191,175 @@ impl Interpreter {
     } else {
       return Err("Invalid function call".to_owned());
     };
+    let fname = if self.uses.is_empty() {
+      fname
+    } else {
+      self.qualify_call(fname)
+    };
+    trace_event!(function = %fname, args = as_value.len(), "call");
+    if !self.sandbox.permits(&fname) {
+      self.check_sandbox(&fname)?;
+    }
 
-    let processed = process_std(&fname, &as_value);
+    if self.builtins {
+      if let Some(result) = self.process_builtin(&fname, &as_value) {
+        return result;
+      }
+    }
 
-    if processed.0 {
-      return processed.1;
-    } else if &fname == &"import" {
-      return self.process_import(&as_value);
+    if fname == "import" {
+      self.process_import(&as_value)
     } else {
-      return self.process_func_call(&func, &as_value);
+      self.process_func_call(func, &as_value)
+    }
+  }
+  /// The value of the variable `name`, passed as the argument `node`.
+  #[inline(never)]
+  fn argument(&mut self, node: &Node, name: &String) -> Result<Value, String> {
+    match self.get_value(name) {
+      Some(value) => Ok(value),
+      None => {
+        let error = || format!("Attempted to use an undefined variable: `{}`", name);
+        self.builtin_value(name, error).map_err(|e| self.located(node, e))
+      }
+    }
+  }
+  /// Calls the builtin `name`, if there is one. Kept out of `process_func`, which every call
+  /// of a nixt function recurses through, to keep its stack frame small.
+  pub(crate) fn process_builtin(
+    &mut self,
+    name: &str,
+    args: &[Value],
+  ) -> Option<Result<Value, String>> {
+    if self.tracer.is_some() && self.is_nondeterministic(name) {
+      return Some(self.traced_builtin(name, args));
+    }
+    let result = match name {
+      "print" | "println" => stdlib::io::print(&mut self.out, args),
+      "puts" => stdlib::io::puts(&mut self.out, args),
+      "eprint" => stdlib::io::print(&mut self.err, args),
+      "get_in" => stdlib::list::get_in(args, self.strict),
+      "set_in" => stdlib::list::set_in(args, self.strict),
+      "use" => self.process_use(args),
+      "features" => self.process_features(args),
+      "on_exit" => self.process_on_exit(args),
+      "debug_dump" => self.process_debug_dump(args),
+      "introspect" => self.process_introspect(args),
+      "test" => self.process_test(args),
+      "test_each" => self.process_test_each(args),
+      "tag" => self.process_tag(args),
+      "timeout" => self.process_timeout(args),
+      "fixture" => self.process_fixture(args),
+      "assert_snapshot" => self.process_assert_snapshot(args),
+      "parse" => self.process_parse(args),
+      "eval" => self.process_eval(args),
+      "sort_by" => stdlib::list::sort_by(args, &mut |f, items| self.call_value(f, items)),
+      "for_lines" => stdlib::io::for_lines(args, &mut |f, items| self.call_value(f, items)),
+      "group_by" => stdlib::list::group_by(args, &mut |f, items| self.call_value(f, items)),
+      _ => {
+        let processed = process_std(name, args);
+        if !processed.0 {
+          return self.natives.get(name).map(|native| native.clone()(args));
+        }
+        processed.1
+      }
+    };
+    Some(result)
+  }
+  /// `(features)`: the list of [`Interpreter::features`].
+  fn process_features(&self, args: &[Value]) -> Result<Value, String> {
+    stdlib::args::ArgParser::new("features", args).exact(0)?;
+    Ok(Value::List(self.features().into_iter().map(Value::from).collect()))
+  }
+  /// `(on_exit f)`: runs `f` when the CLI exits, including after an error or an interrupt.
+  fn process_on_exit(&mut self, args: &[Value]) -> Result<Value, String> {
+    let handler = stdlib::args::ArgParser::new("on_exit", args).exact(1)?.get_func(0)?;
+    self.exit_handlers.push(handler.clone());
+    Ok(Value::Nil)
+  }
+  /// The native builtin an unbound function name stands for through `use`, if any.
+  fn qualify_call(&mut self, name: String) -> String {
+    let bound = self.env.contains(&name);
+    if bound || self.shared_binding(&name).is_some() {
+      return name;
+    }
+    match self.qualify(&name) {
+      Some(qualified) if self.is_builtin(&qualified) => qualified,
+      _ => name,
     }
   }
-  pub fn process_import(&mut self, to_import: &Vec<Value>) -> Result<Value, String> {
+  pub fn process_import(&mut self, to_import: &[Value]) -> Result<Value, String> {
+    let search_paths = self.search_paths.clone();
     let mut import_in_ast = |fname: &str| -> Result<(), String> {
+      trace_phase!("import", module = fname);
+      let path = std::fs::canonicalize(fname).unwrap_or_else(|_| PathBuf::from(fname));
+      if let Some(start) = self.import_stack.iter().position(|(p, _)| *p == path) {
+        let chain = self.import_stack[start..]
+          .iter()
+          .map(|(_, name)| name.as_str())
+          .chain(std::iter::once(fname))
+          .collect::<Vec<_>>();
+        let message = format!("Cyclic import: {}", chain.join(" -> "));
+        return Err(raise(RuntimeErrorKind::Import, message));
+      }
+
       let code = match std::fs::read_to_string(fname) {
         Ok(c) => c,
-        Err(_) => return Err("Failed to read module code".to_owned()),
+        Err(_) => return Err(raise(RuntimeErrorKind::Import, "Failed to read module code")),
       };
 
-      let mut lexer = crate::core::lexer::Lexer::new(&code);
+      let mut lexer = crate::core::lexer::Lexer::with_keywords(&code, self.keywords.clone());
+      lexer.set_trivia(true);
       let tokens = lexer.scan_tokens();
       if lexer.get_errors().is_some() {
-        return Err("The imported file contains lexing errors. Aborting".to_owned());
+        let message = "The imported file contains lexing errors. Aborting";
+        return Err(raise(RuntimeErrorKind::Import, message));
       }
       let mut parser = crate::core::parser::Parser::new(tokens);
       let ast = parser.parse();
       if parser.get_errors().is_some() {
-        return Err("The imported file contains parsing errors. Aborting".to_owned());
+        let message = "The imported file contains parsing errors. Aborting";
+        return Err(raise(RuntimeErrorKind::Import, message));
       }
-      self.process_ast(&ast)?;
-
-      Ok(())
+      let ast = self.expand_macros(&ast)?;
+      let ast = if self.const_exprs {
+        self.fold_consts(&ast)?
+      } else {
+        ast
+      };
+      self.import_stack.push((path, fname.to_owned()));
+      let imported = self.import_module(fname, &ast);
+      self.import_stack.pop();
+      // The spans of the module are not in the source being evaluated.
+      self.error_span = None;
+      imported
     };
     for val in to_import {
       if let Value::String(s) = val {
+        let mut found = search_paths.iter().map(|dir| dir.join(s)).filter(|path| path.exists());
         if Path::new(s).exists() {
-          import_in_ast(&s)?;
+          import_in_ast(s)?;
+        } else if let Some(path) = found.next() {
+          import_in_ast(&path.to_string_lossy())?;
         } else {
-          if s.starts_with("std/") {
+          if let Some(module) = s.strip_prefix("std/") {
             let folder = match std::env::var("NIXT_STD") {
               Ok(res) => res,
-              _ => return Err("Could not find NIXT_STD environnement variable. Consider creating it to import std modules.".to_owned()),
+              _ => {
+                let message = "Could not find NIXT_STD environnement variable. Consider creating it to import std modules.";
+                return Err(raise(RuntimeErrorKind::Import, message));
+              }
             };
 
-            let to_import = format!("{}/{}.nxt", folder, &s[4..]);
+            let to_import = format!("{}/{}.nxt", folder, module);
             if !Path::new(&to_import).exists() {
-              return Err(format!("Could not find standard module `{}`", &s[4..]));
+              let message = format!("Could not find standard module `{}`", module);
+              return Err(raise(RuntimeErrorKind::Import, message));
             }
 
             import_in_ast(&to_import)?;
           } else {
-            return Err(format!("Unresolved import: `{}`", s));
+            return Err(raise(RuntimeErrorKind::Import, format!("Unresolved import: `{}`", s)));
           }
         }
       } else {
@@ -149,16 +371,96 @@ impl Interpreter {
   }
 }
 
-fn process_std(name: &str, args: &Vec<Value>) -> (bool, Result<Value, String>) {
+impl Interpreter {
+  /// Evaluates a module with fresh globals, then binds its `pub` definitions in the current
+  /// scope. The other ones are only reachable by the functions of the module, which keep its
+  /// globals, and are remembered to tell private bindings from undefined ones.
+  fn import_module(&mut self, fname: &str, ast: &Node) -> Result<(), String> {
+    // The module shares the prelude scope, keeping the prelude namespaces it loads.
+    let module_env = self.env.scope(0).child();
+    let env = std::mem::replace(&mut self.env, module_env);
+    let exports = std::mem::take(&mut self.exports);
+    let result = self.process_ast(ast);
+    let module_exports = std::mem::replace(&mut self.exports, exports);
+    let module_env = std::mem::replace(&mut self.env, env);
+    result?;
+
+    let module_globals = module_env.bindings().clone();
+    self.module_scopes.push(module_env);
+    let mut scope = self.env.bindings_mut();
+    for (name, binding) in module_globals {
+      if !module_exports.contains(&name) {
+        self.private.insert(name, fname.to_owned());
+        continue;
+      }
+      if scope.contains_key(&name) {
+        return Err(format!(
+          "Attempted to import `{}` from `{}`, which is already defined",
+          name, fname
+        ));
+      }
+      self.private.remove(&name);
+      scope.insert(name, binding);
+    }
+    self.modules.push(fname.to_owned());
+    Ok(())
+  }
+  /// Refuses calls to the builtins the sandbox policy denies. Denied prelude bindings were
+  /// removed when building the interpreter, but are checked again here for defense in depth.
+  fn check_sandbox(&self, name: &str) -> Result<(), String> {
+    let is_builtin = name == "import" || self.is_builtin(name);
+    let in_prelude = self.env.scope(0).bindings().contains_key(name)
+      || self.shared.iter().any(|env| env.bindings.contains_key(name));
+    if is_builtin || in_prelude {
+      let message = format!("`{}` is not allowed by the sandbox policy", name);
+      return Err(raise(RuntimeErrorKind::Sandbox, message));
+    }
+    Ok(())
+  }
+  /// The error for a missing `name`, pointing out when it is private to an imported module.
+  pub(crate) fn undefined(&self, name: &str, error: String) -> String {
+    let message = match self.private.get(name) {
+      Some(module) => format!(
+        "`{}` is private to module `{}`. Mark it `pub` to import it",
+        name, module
+      ),
+      None => error,
+    };
+    raise(RuntimeErrorKind::Undefined, message)
+  }
+}
+
+/// The names the native builtins are called by, which programs can not rebind.
+pub const BUILTINS: &[&str] = &[
+  "print", "println", "puts", "eprint", "use", "get_in", "set_in", "sort_by", "group_by", "time:now", "str:cat",
+  "math:cos", "math:acos", "math:sin", "math:asin", "math:tan", "math:atan", "math:floor",
+  "math:ceil", "math:round_to", "list", "pop", "push", "at", "get?", "len", "range", "in",
+  "unique", "reverse", "assert", "assert_eq", "type", "version", "features", "on_exit",
+  "debug_dump", "test", "test_each", "tag", "timeout", "fixture", "assert_snapshot",
+  "parse", "eval", "fn_arity", "fn_params", "fn_body", "introspect", "fingerprint",
+  "num_to_str", "parse_num", "str:upper", "str:lower", "str:byte_len", "str:bytes",
+  "str:chars", "str:char_code", "str:from_code", "map", "get", "insert", "remove", "keys",
+  "contains", "sort_keys", "csv_parse", "csv_write", "arrow_read", "for_lines",
+  "value_encode", "value_decode", "close",
+];
+
+fn process_std(name: &str, args: &[Value]) -> (bool, Result<Value, String>) {
   let mut found = true;
 
   let toret = match name {
-    "print" => stdlib::io::print(args),
-    "puts" => stdlib::io::puts(&args),
     "time:now" => stdlib::time::now(),
 
     // str
     "str:cat" => stdlib::str::cat(args),
+    "num_to_str" => stdlib::str::num_to_str(args),
+    "parse_num" => stdlib::str::parse_num(args),
+    "str:upper" => stdlib::str::upper(args),
+    "str:lower" => stdlib::str::lower(args),
+    "str:byte_len" => stdlib::str::byte_len(args),
+    "str:bytes" => stdlib::str::bytes(args),
+    "str:chars" => stdlib::str::chars(args),
+    "str:char_code" => stdlib::str::char_code(args),
+    "str:from_code" => stdlib::str::from_code(args),
 
     // maths
     "math:cos" => stdlib::maths::cos(args),
@@ -169,16 +471,52 @@ fn process_std(name: &str, args: &Vec<Value>) -> (bool, Result<Value, String>) {
     "math:atan" => stdlib::maths::atan(args),
     "math:floor" => stdlib::maths::floor(args),
     "math:ceil" => stdlib::maths::ceil(args),
+    "math:round_to" => stdlib::maths::round_to(args),
 
     // list
     "list" => stdlib::list::list(args),
     "pop" => stdlib::list::pop(args),
     "push" => stdlib::list::push(args),
     "at" => stdlib::list::index(args),
+    "get?" => stdlib::list::get_nil_safe(args),
     "len" => stdlib::list::len(args),
+    "range" => stdlib::list::range(args),
+    "in" => stdlib::list::contains(args),
+    "unique" => stdlib::list::unique(args),
+    "reverse" => stdlib::list::reverse(args),
+    // map
+    "map" => stdlib::map::map(args),
+    "get" => stdlib::map::get(args),
+    "insert" => stdlib::map::insert(args),
+    "remove" => stdlib::map::remove(args),
+    "keys" => stdlib::map::keys(args),
+    "contains" => stdlib::map::contains(args),
+    "sort_keys" => stdlib::map::sort_keys(args),
+    // table
+    "csv_parse" => stdlib::table::csv_parse(args),
+    "csv_write" => stdlib::table::csv_write(args),
+    #[cfg(feature = "arrow")]
+    "arrow_read" => stdlib::table::arrow_read(args),
+    #[cfg(not(feature = "arrow"))]
+    "arrow_read" => Err("`arrow_read` needs nixt built with the `arrow` feature".to_owned()),
     // misc
     "assert" => stdlib::misc::assert(args),
+    "assert_eq" => stdlib::misc::assert_eq(args),
     "type" => stdlib::misc::type_of(args),
+    "version" => stdlib::misc::version(args),
+    "fn_arity" => stdlib::misc::fn_arity(args),
+    "fn_params" => stdlib::misc::fn_params(args),
+    "fn_body" => stdlib::misc::fn_body(args),
+    "fingerprint" => stdlib::misc::fingerprint(args),
+    "close" => stdlib::misc::close(args),
+    #[cfg(feature = "msgpack")]
+    "value_encode" => stdlib::misc::value_encode(args),
+    #[cfg(feature = "msgpack")]
+    "value_decode" => stdlib::misc::value_decode(args),
+    #[cfg(not(feature = "msgpack"))]
+    "value_encode" | "value_decode" => {
+      Err(format!("`{}` needs nixt built with the `msgpack` feature", name))
+    }
     _ => {
       found = false;
       Ok(Value::Nil)
@@ -0,0 +1,303 @@
+use crate::core::interpreter::interpreter::Interpreter;
+use crate::core::interpreter::replay::Tracer;
+use crate::stdlib::args::ArgParser;
+use crate::testing;
+use crate::utils::collections;
+use crate::utils::element::{Func, Value};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::Duration;
+
+/// A test a script registered with `(test "name" f)` or `test_each`, run by `nixt test`.
+#[derive(Debug, Clone)]
+pub struct TestCase {
+  pub name: String,
+  pub func: Func,
+  /// The arguments of a `test_each` case. The other tests take the fixtures their parameters
+  /// name.
+  pub args: Option<Vec<Value>>,
+  /// The tags prefixing the name, `slow` for `"slow: big sort"`, then those `(tag ...)` gave.
+  pub tags: Vec<String>,
+  /// The time limit `(timeout ms)` gave the test.
+  pub timeout: Option<Duration>,
+}
+
+impl TestCase {
+  fn new(name: String, func: Func, args: Option<Vec<Value>>, tags: &[String]) -> Self {
+    let mut prefix = name.as_str();
+    let mut all = vec![];
+    while let Some((tag, rest)) = prefix.split_once(": ") {
+      if tag.is_empty() || tag.contains(char::is_whitespace) {
+        break;
+      }
+      all.push(tag.to_owned());
+      prefix = rest;
+    }
+    all.extend(tags.iter().filter(|tag| !all.contains(tag)).cloned().collect::<Vec<_>>());
+    Self {
+      name,
+      func,
+      args,
+      tags: all,
+      timeout: None,
+    }
+  }
+}
+
+/// A value set up by `(fixture "name" f)` for the tests taking it as a parameter.
+#[derive(Debug, Clone)]
+pub(crate) enum Fixture {
+  /// Not set up yet: `f` is called once, when the first test needs it.
+  Setup(Func),
+  Done(Result<Value, String>),
+}
+
+/// Where `(assert_snapshot "name" value)` keeps its snapshots, set with
+/// [`InterpreterBuilder::snapshots`](crate::InterpreterBuilder::snapshots).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshots {
+  /// The directory of the snapshot files.
+  pub dir: PathBuf,
+  /// Prepended to the snapshot names, to tell apart the snapshots of different scripts.
+  pub prefix: String,
+  /// Whether snapshots that do not match are overwritten instead of failing.
+  pub update: bool,
+}
+
+impl Snapshots {
+  /// The snapshots of the script at `path`: in the `__snapshots__` directory next to it, named
+  /// after the script.
+  ///
+  /// ```
+  /// use nixt::core::interpreter::harness::Snapshots;
+  /// let snapshots = Snapshots::for_script("tests/sort_test.nxt".as_ref(), false);
+  /// assert_eq!(snapshots.path("sorts numbers"), std::path::Path::new(
+  ///   "tests/__snapshots__/sort_test__sorts_numbers.snap"
+  /// ));
+  /// ```
+  pub fn for_script(path: &Path, update: bool) -> Self {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    Self {
+      dir: path.parent().unwrap_or(Path::new(".")).join("__snapshots__"),
+      prefix: format!("{}__", stem),
+      update,
+    }
+  }
+  /// The file of the snapshot called `name`.
+  pub fn path(&self, name: &str) -> PathBuf {
+    let name = name
+      .chars()
+      .map(|c| if c.is_alphanumeric() || c == '-' { c } else { '_' })
+      .collect::<String>();
+    self.dir.join(format!("{}{}.snap", self.prefix, name))
+  }
+  /// Checks `value` against the snapshot `name`, writing the snapshot if there is none yet or
+  /// if updating.
+  fn check(&self, name: &str, value: &Value) -> Result<(), String> {
+    let path = self.path(name);
+    let written = format!("{}\n", testing::show(value));
+    match std::fs::read_to_string(&path) {
+      Ok(stored) if stored == written => Ok(()),
+      Ok(stored) if !self.update => Err(format!(
+        "Snapshot `{}` does not match {}:\n  expected {}\n  found    {}\nRun with \
+         `--update-snapshots` to accept the new value",
+        name,
+        path.display(),
+        stored.trim_end(),
+        written.trim_end()
+      )),
+      _ => {
+        std::fs::create_dir_all(&self.dir).map_err(|e| format!("{}: {}", self.dir.display(), e))?;
+        std::fs::write(&path, written).map_err(|e| format!("{}: {}", path.display(), e))
+      }
+    }
+  }
+}
+
+impl Interpreter {
+  /// Takes the tests the evaluated scripts registered, in the order they were registered.
+  pub fn take_tests(&mut self) -> Vec<TestCase> {
+    std::mem::take(&mut self.tests)
+  }
+  /// Runs `test`, with its case's arguments or the fixtures its parameters name, setting them
+  /// up if no test did yet.
+  ///
+  /// A test running longer than its `(timeout ms)`, or the limit
+  /// [`InterpreterBuilder::test_timeout`](crate::InterpreterBuilder::test_timeout) set, is
+  /// interrupted and fails with the last steps it evaluated.
+  ///
+  /// ```
+  /// use std::time::Duration;
+  /// let mut interpreter = nixt::Interpreter::builder().build()?;
+  /// interpreter.eval_str("(timeout 50) (test \"spins\" (func () {(while (= 1 1) {})}))")?;
+  /// let test = interpreter.take_tests().remove(0);
+  /// assert_eq!(test.timeout, Some(Duration::from_millis(50)));
+  /// let failure = interpreter.run_test(&test).unwrap_err();
+  /// assert!(failure.starts_with("Timed out after 50 ms"));
+  /// # Ok::<(), String>(())
+  /// ```
+  pub fn run_test(&mut self, test: &TestCase) -> Result<Value, String> {
+    let args = match &test.args {
+      Some(args) => args.clone(),
+      None => test
+        .func
+        .args
+        .iter()
+        .map(|name| self.fixture(name))
+        .collect::<Result<Vec<_>, _>>()?,
+    };
+    match test.timeout.or(self.test_timeout) {
+      Some(limit) => self.call_with_deadline(&test.func, &args, limit),
+      None => self.call_function(&test.func, &args),
+    }
+  }
+  /// Calls `func`, setting the interrupt flag from another thread once it ran for `limit`.
+  /// Unless the interpreter records or replays a trace, the last steps are kept to tell where
+  /// the call was stuck.
+  fn call_with_deadline(
+    &mut self,
+    func: &Func,
+    args: &[Value],
+    limit: Duration,
+  ) -> Result<Value, String> {
+    let (finished, done) = mpsc::channel::<()>();
+    let interrupt = self.interrupt.clone();
+    let watchdog = std::thread::spawn(move || {
+      let expired = done.recv_timeout(limit) == Err(RecvTimeoutError::Timeout);
+      if expired {
+        interrupt.store(true, Ordering::Relaxed);
+      }
+      expired
+    });
+    let tail = self.tracer.is_none();
+    if tail {
+      self.tracer = Some(Tracer::Tail(Default::default()));
+    }
+    let result = self.call_function(func, args);
+    drop(finished);
+    let expired = watchdog.join().unwrap_or(false);
+    let steps = match self.tracer.take() {
+      Some(Tracer::Tail(steps)) if tail => Vec::from(steps),
+      tracer => {
+        self.tracer = tracer;
+        vec![]
+      }
+    };
+    if !expired {
+      return result;
+    }
+    self.interrupt.store(false, Ordering::Relaxed);
+    // The test may have returned right before the deadline.
+    result.map_err(|_| {
+      let mut failure = format!("Timed out after {} ms", limit.as_millis());
+      if !steps.is_empty() {
+        failure.push_str(&format!(", last steps:\n  {}", steps.join("\n  ")));
+      }
+      failure
+    })
+  }
+  fn fixture(&mut self, name: &str) -> Result<Value, String> {
+    let setup = match self.fixtures.get(name) {
+      Some(Fixture::Done(result)) => return result.clone(),
+      Some(Fixture::Setup(setup)) => setup.clone(),
+      None => return Err(format!("`{}` is not a fixture", name)),
+    };
+    let result = self
+      .call_function(&setup, &[])
+      .map_err(|e| format!("Fixture `{}` failed: {}", name, e));
+    self.fixtures.insert(name.to_owned(), Fixture::Done(result.clone()));
+    result
+  }
+  fn add_test(&mut self, test: TestCase) -> Result<(), String> {
+    if self.tests.iter().any(|other| other.name == test.name) {
+      return Err(format!("Test `{}` is already defined", test.name));
+    }
+    self.tests.push(test);
+    Ok(())
+  }
+  /// `(test "name" f)`: registers `f` as a test, which `nixt test` calls after evaluating the
+  /// script, with the fixtures its parameters name.
+  pub(crate) fn process_test(&mut self, args: &[Value]) -> Result<Value, String> {
+    let args = ArgParser::new("test", args).exact(2)?;
+    let name = args.get_str(0)?.to_owned();
+    let func = args.get_func(1)?.clone();
+    let tags = std::mem::take(&mut self.pending_tags);
+    let mut test = TestCase::new(name, func, None, &tags);
+    test.timeout = self.pending_timeout.take();
+    self.add_test(test)?;
+    Ok(Value::Nil)
+  }
+  /// `(test_each "name" cases f)`: registers a test `name[i]` per case, calling `f` with the
+  /// elements of the case, or with the case itself if it is not a list.
+  pub(crate) fn process_test_each(&mut self, args: &[Value]) -> Result<Value, String> {
+    let args = ArgParser::new("test_each", args).exact(3)?;
+    let name = args.get_str(0)?;
+    let func = args.get_func(2)?;
+    let tags = std::mem::take(&mut self.pending_tags);
+    let timeout = self.pending_timeout.take();
+    for (i, case) in args.get_list(1)?.iter().enumerate() {
+      let args = match case {
+        Value::List(items) => collections::to_vec(items),
+        other => vec![other.clone()],
+      };
+      if args.len() != func.args.len() {
+        return Err(format!(
+          "Case {} of `{}` has {} value(s), but the test takes {} argument(s)",
+          i,
+          name,
+          args.len(),
+          func.args.len()
+        ));
+      }
+      let name = format!("{}[{}]", name, i);
+      let mut test = TestCase::new(name, func.clone(), Some(args), &tags);
+      test.timeout = timeout;
+      self.add_test(test)?;
+    }
+    Ok(Value::Nil)
+  }
+  /// `(tag "slow" ...)`: tags the next test, or every case of the next `test_each`.
+  pub(crate) fn process_tag(&mut self, args: &[Value]) -> Result<Value, String> {
+    let parser = ArgParser::new("tag", args).between(1, usize::MAX)?;
+    for i in 0..args.len() {
+      self.pending_tags.push(parser.get_str(i)?.to_owned());
+    }
+    Ok(Value::Nil)
+  }
+  /// `(timeout ms)`: fails the next test, or each case of the next `test_each`, once it ran for
+  /// `ms` milliseconds.
+  pub(crate) fn process_timeout(&mut self, args: &[Value]) -> Result<Value, String> {
+    let args = ArgParser::new("timeout", args).exact(1)?;
+    let ms = args.get_num(0)?;
+    if !(ms > 0.0 && ms.is_finite()) {
+      return Err(format!("A timeout must be a positive number of milliseconds, not {}", ms));
+    }
+    self.pending_timeout = Some(Duration::from_secs_f64(ms / 1000.0));
+    Ok(Value::Nil)
+  }
+  /// `(fixture "name" f)`: sets up the value of the test parameters called `name` by calling
+  /// `f`, once per script and only if a test needs it.
+  pub(crate) fn process_fixture(&mut self, args: &[Value]) -> Result<Value, String> {
+    let args = ArgParser::new("fixture", args).exact(2)?;
+    let name = args.get_str(0)?;
+    if self.fixtures.contains_key(name) {
+      return Err(format!("Fixture `{}` is already defined", name));
+    }
+    let setup = Fixture::Setup(args.get_func(1)?.clone());
+    self.fixtures.insert(name.to_owned(), setup);
+    Ok(Value::Nil)
+  }
+  /// `(assert_snapshot "name" value)`: fails unless `value` matches the stored snapshot `name`,
+  /// which is created on the first run.
+  pub(crate) fn process_assert_snapshot(&mut self, args: &[Value]) -> Result<Value, String> {
+    let args = ArgParser::new("assert_snapshot", args).exact(2)?;
+    let name = args.get_str(0)?;
+    let snapshots = self
+      .snapshots
+      .as_ref()
+      .ok_or("`assert_snapshot` needs a snapshot directory, as `nixt test` sets")?;
+    snapshots.check(name, args.get(1)?)?;
+    Ok(Value::Nil)
+  }
+}
@@ -0,0 +1,97 @@
+use crate::core::interpreter::interpreter::Interpreter;
+use crate::core::interpreter::watch::WatchHit;
+use crate::utils::element::Value;
+use crate::utils::node::Node;
+use std::collections::BTreeMap;
+
+/// A definition or assignment of a binding, recorded with [`InterpreterBuilder::history`].
+///
+/// [`InterpreterBuilder::history`]: crate::InterpreterBuilder::history
+#[derive(Debug, Clone)]
+pub struct Mutation {
+  /// The order of the mutation among those of every binding, from 0.
+  pub ordinal: usize,
+  /// The value the binding was given.
+  pub value: Value,
+  /// The `let`, `const` or `set` form that gave it, as `(set x (+ x 1))`.
+  pub location: String,
+  /// The depth of the scope the binding belongs to: 1 for globals.
+  pub scope: usize,
+}
+
+/// `#3 (set x (+ x 1)) => 4`
+impl std::fmt::Display for Mutation {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    write!(f, "#{} {} => {}", self.ordinal, self.location, self.value)?;
+    if self.scope > 1 {
+      write!(f, " (local, depth {})", self.scope)?;
+    }
+    Ok(())
+  }
+}
+
+/// The mutations of every binding, by name.
+#[derive(Debug, Default)]
+pub(crate) struct History {
+  bindings: BTreeMap<String, Vec<Mutation>>,
+  count: usize,
+}
+
+impl Interpreter {
+  /// How the bindings named `name` evolved, oldest first. Empty unless the interpreter was built
+  /// with [`InterpreterBuilder::history`](crate::InterpreterBuilder::history).
+  ///
+  /// ```
+  /// let mut interpreter = nixt::Interpreter::builder().history(true).build()?;
+  /// interpreter.eval_str("(let x 1) (set x (+ x 1))")?;
+  /// let history = interpreter.history("x");
+  /// assert_eq!(history[1].to_string(), "#1 (set x (+ x 1)) => 2");
+  /// # Ok::<(), String>(())
+  /// ```
+  pub fn history(&self, name: &str) -> &[Mutation] {
+    self
+      .history
+      .as_ref()
+      .and_then(|history| history.bindings.get(name))
+      .map_or(&[], |mutations| mutations.as_slice())
+  }
+  /// Records that `keyword` (`let`, `const` or `set`) is giving `name`, in the scope it edits,
+  /// the value `new` of `value`, and pauses at the watchpoints on `name`. Kept out of `var_def`
+  /// and `var_edit`, which nested calls recurse through, to keep their stack frames small.
+  #[inline(never)]
+  pub(crate) fn observe_mutation(
+    &mut self,
+    keyword: &str,
+    name: &str,
+    value: &Node,
+    new: &Value,
+  ) -> Result<(), String> {
+    let scope = if keyword == "set" { self.env.settable(name) } else { &self.env };
+    let scope = scope.depth();
+    let location = format!("({} {} {})", keyword, name, value);
+    if let Some(history) = self.history.as_mut() {
+      let mutation = Mutation {
+        ordinal: history.count,
+        value: new.clone(),
+        location: location.clone(),
+        scope,
+      };
+      history.count += 1;
+      history.bindings.entry(name.to_owned()).or_default().push(mutation);
+    }
+    if self.watches.contains(name) {
+      let old = match keyword {
+        "set" => self.env.settable(name).bindings().get(name).map(|(old, _)| old.clone()),
+        _ => None,
+      };
+      let hit = WatchHit {
+        name: name.to_owned(),
+        old,
+        new: new.clone(),
+        location,
+      };
+      self.hit_watchpoint(hit)?;
+    }
+    Ok(())
+  }
+}
@@ -1,131 +1,662 @@
+use crate::ast::check_name;
+use crate::core::interpreter::blocks::is_expression;
+use crate::core::interpreter::builder::{InterpreterBuilder, PRELUDE};
+use crate::core::interpreter::environment::{Bindings, Environment};
+use crate::core::interpreter::functions::BUILTINS;
+use crate::core::interpreter::harness::{Fixture, Snapshots, TestCase};
+use crate::core::interpreter::history::History;
+use crate::core::interpreter::macros::{uses_macros, Macro};
+use crate::core::interpreter::plugin::Builtins;
+use crate::core::interpreter::replay::Tracer;
+use crate::core::interpreter::sandbox::SandboxPolicy;
+use crate::core::interpreter::verbose::Verbose;
+use crate::core::interpreter::shared::SharedEnv;
+use crate::core::interpreter::watch::Watches;
+use crate::core::{lexer::Lexer, parser::*};
+use crate::error::{raise, take_raised, NixtError, RuntimeError, RuntimeErrorKind};
 use crate::utils::element::*;
 use crate::utils::node::*;
+use crate::utils::token::{Span, TokenType};
+use crate::utils::trace::trace_phase;
+use std::cell::RefCell;
 use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-pub fn is_defined(scope: &BTreeMap<String, (Value, bool)>, name: &String) -> bool {
+/// Whether `value` passes a condition: `nil`, `false`, `0`, NaN and `""` do not, the other
+/// booleans, numbers and strings do. Other values can not be used as conditions.
+///
+/// ```
+/// use nixt::{core::interpreter::interpreter::truthy, Value};
+/// assert_eq!(truthy(&Value::from("")), Ok(false));
+/// assert_eq!(truthy(&Value::from(2)), Ok(true));
+/// assert!(truthy(&Value::list([1])).is_err());
+/// ```
+pub fn truthy(value: &Value) -> Result<bool, String> {
+  match value {
+    Value::Nil => Ok(false),
+    Value::Bool(b) => Ok(*b),
+    Value::Number(n) => Ok(*n != 0.0 && !n.is_nan()),
+    Value::String(s) => Ok(!s.is_empty()),
+    other => {
+      let message = format!("Attempted to use a {} as a condition", other.type_name());
+      Err(raise(RuntimeErrorKind::Type, message))
+    }
+  }
+}
+
+pub fn is_defined(scope: &Bindings, name: &String) -> bool {
   if scope.contains_key(name) {
     return true;
   }
   false
 }
 
+/// Index of the scope holding the program's globals, right above the prelude.
+pub const GLOBAL_SCOPE: usize = 1;
+
+/// What [`Interpreter::eval_capture`] collected while evaluating a script.
+#[derive(Debug)]
+pub struct EvalOutput {
+  /// The script's result, or the error that stopped it.
+  pub value: Result<Value, String>,
+  /// What `print` and `puts` wrote.
+  pub stdout: String,
+  /// What `eprint` wrote.
+  pub stderr: String,
+  /// The lexer, parser and evaluation warnings.
+  pub diagnostics: Vec<String>,
+}
+
+/// A writer appending to a shared buffer, swapped in for the interpreter's outputs.
+#[derive(Clone, Default)]
+pub(crate) struct Capture(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Capture {
+  fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+    self.0.borrow_mut().write(buf)
+  }
+  fn flush(&mut self) -> std::io::Result<()> {
+    Ok(())
+  }
+}
+
+impl Capture {
+  pub(crate) fn take(&self) -> String {
+    String::from_utf8_lossy(&self.0.borrow_mut().split_off(0)).into_owned()
+  }
+  pub(crate) fn contents(&self) -> String {
+    String::from_utf8_lossy(&self.0.borrow()).into_owned()
+  }
+}
+
+/// A reusable interpreter, created with [`Interpreter::builder`].
+///
+/// Scope 0 holds the prelude, scope 1 the globals of everything evaluated so far. Interpreters
+/// built on a [`SharedEnv`] look its bindings up below scope 0.
 pub struct Interpreter {
-  pub scopes: Vec<BTreeMap<String, (Value, bool)>>,
+  /// The innermost scope being evaluated in, chained to the globals and the prelude.
+  pub env: Environment,
+  pub(crate) out: Box<dyn Write>,
+  pub(crate) err: Box<dyn Write>,
+  pub(crate) strict: bool,
+  pub(crate) infix: bool,
+  pub(crate) lenient: bool,
+  pub(crate) const_exprs: bool,
+  pub(crate) builtins: bool,
+  pub(crate) sandbox: SandboxPolicy,
+  pub(crate) keywords: BTreeMap<String, TokenType>,
+  /// Deprecated bindings, with the index of the scope declaring them and a hint.
+  pub(crate) deprecated: BTreeMap<String, (usize, String)>,
+  pub(crate) warnings: Vec<String>,
+  /// The `pub` names defined so far by the module being evaluated.
+  pub(crate) exports: Vec<String>,
+  /// Private bindings of imported modules, with the module defining them.
+  pub(crate) private: BTreeMap<String, String>,
+  /// The directories searched for the modules `import` does not find.
+  pub(crate) search_paths: Vec<PathBuf>,
+  /// The modules being imported, outermost first, to report import cycles.
+  pub(crate) import_stack: Vec<(PathBuf, String)>,
+  /// The prelude modules not loaded yet: their namespace, name and source.
+  pub(crate) pending: Vec<(&'static str, &'static str, &'static str)>,
+  /// The namespaces brought in by `use`, with the index of the scope using them.
+  pub(crate) uses: Vec<(usize, String)>,
+  /// Set from another thread or a signal handler to stop the running evaluation.
+  pub(crate) interrupt: Arc<AtomicBool>,
+  /// The functions registered with `on_exit`.
+  pub(crate) exit_handlers: Vec<Func>,
+  /// The builtins registered by plugins.
+  pub(crate) natives: Builtins,
+  /// Records the evaluation, or checks it against a recording.
+  pub(crate) tracer: Option<Tracer>,
+  /// Where and what the verbose mode writes, if it is on.
+  pub(crate) verbose: Option<Verbose>,
+  /// The mutations of every binding, when they are recorded.
+  pub(crate) history: Option<History>,
+  /// The watchpoints set on bindings.
+  pub(crate) watches: Watches,
+  /// The frozen bindings shared with other interpreters, below the prelude scope.
+  pub(crate) shared: Option<SharedEnv>,
+  /// The tests registered with `(test "name" f)`.
+  pub(crate) tests: Vec<TestCase>,
+  /// The tags `(tag ...)` gave the next test.
+  pub(crate) pending_tags: Vec<String>,
+  /// The time limit `(timeout ms)` gave the next test.
+  pub(crate) pending_timeout: Option<Duration>,
+  /// The time limit of the tests `(timeout ms)` did not give one.
+  pub(crate) test_timeout: Option<Duration>,
+  /// The fixtures of the tests, by name.
+  pub(crate) fixtures: BTreeMap<String, Fixture>,
+  /// The macros defined so far, by name.
+  pub(crate) macros: BTreeMap<String, Macro>,
+  /// Where `assert_snapshot` keeps its snapshots.
+  pub(crate) snapshots: Option<Snapshots>,
+  /// Whether `(introspect)` is allowed, and the calls are kept for it.
+  pub(crate) introspection: bool,
+  /// The names of the nixt functions being called, outermost first, kept with `introspection`.
+  pub(crate) calls: Vec<String>,
+  /// The modules loaded so far: the prelude modules by name, then the imported files as written.
+  pub(crate) modules: Vec<String>,
+  /// The globals of the imported modules, which the functions they define keep.
+  pub(crate) module_scopes: Vec<Environment>,
+  /// Where the error being raised is in the source, set by the innermost node that knows.
+  pub(crate) error_span: Option<Span>,
+  /// The globals the builder defined, restored by [`Interpreter::reset`].
+  pub(crate) initial_globals: Bindings,
+  /// The plugin libraries `natives` calls into, dropped after them.
+  #[cfg(feature = "plugins")]
+  pub(crate) libraries: Vec<libloading::Library>,
 }
 
 impl Interpreter {
-  pub fn new(ast: Option<&Node>) -> Result<Interpreter, String> {
-    let mut interpreter = Interpreter {
-      scopes: vec![BTreeMap::new()],
+  pub fn builder() -> InterpreterBuilder {
+    InterpreterBuilder::new()
+  }
+  /// Evaluates a parsed program, returning the value of its last top-level expression.
+  ///
+  /// When evaluation fails, the scopes it opened are closed again: the globals, including the
+  /// ones the program defined before failing, stay available to the next evaluation.
+  pub fn eval(&mut self, ast: &Node) -> Result<Value, String> {
+    trace_phase!("eval");
+    let depth = self.env.depth();
+    let expanded;
+    let ast = if self.macros.is_empty() && !uses_macros(ast) {
+      ast
+    } else {
+      expanded = self.expand_macros(ast)?;
+      &expanded
     };
-    if ast.is_some() {
-      interpreter.process_node(&ast.unwrap())?;
+    let result = if self.const_exprs {
+      self.fold_consts(ast).and_then(|folded| self.process_ast(&folded))
+    } else {
+      self.process_ast(ast)
+    };
+    if result.is_err() {
+      while self.env.depth() > depth {
+        self.remove_scope();
+      }
     }
-    Ok(interpreter)
+    result
   }
-  pub fn add_scope(&mut self) {
-    self.scopes.push(BTreeMap::new())
+  /// Lexes, parses and evaluates `source` with this interpreter's options.
+  pub fn eval_str(&mut self, source: &str) -> Result<Value, String> {
+    self.eval_source(source).map_err(String::from)
   }
-  pub fn remove_scope(&mut self) {
-    self.scopes.pop();
+  /// Evaluates `source` like [`Interpreter::eval_str`], telling apart the errors of lexing,
+  /// parsing and evaluation.
+  pub fn eval_source(&mut self, source: &str) -> Result<Value, NixtError> {
+    let ast = self.parse_checked(source)?;
+    self.error_span = None;
+    take_raised();
+    self.eval(&ast).map_err(|e| {
+      let mut error = RuntimeError::raised(e);
+      error.span = self.error_span.take();
+      NixtError::Runtime(error)
+    })
   }
-  pub fn var_edit(&mut self, name: &Node, new_val: &Node) -> Result<(), String> {
-    if self.scopes.len() == 0 {
-      return Err("No scopes available. Consider adding a scope to your program".to_owned());
+  /// Records that `error` was raised at `node`, unless a node inside it already did.
+  #[inline(never)]
+  pub(crate) fn located(&mut self, node: &Node, error: String) -> String {
+    if self.error_span.is_none() {
+      self.error_span = node.span();
     }
-    let scope = &self.scopes[self.scopes.len() - 1];
-    let name = if let NodeType::NodeIdentifier(s) = name.get_type() {
-      s
-    } else {
-      return Err("Found an invalid identifier in variable edition".to_owned());
-      // Should never be called because parser checks
+    error
+  }
+  /// Lexes and parses `source` with this interpreter's options, keeping the warnings.
+  pub(crate) fn parse_source(&mut self, source: &str) -> Result<Node, String> {
+    self.parse_checked(source).map_err(String::from)
+  }
+  fn parse_checked(&mut self, source: &str) -> Result<Node, NixtError> {
+    let mut lexer = Lexer::with_keywords(source, self.keywords.clone());
+    lexer.set_case_insensitive(self.lenient);
+    lexer.set_trivia(true);
+    let tokens = lexer.scan_tokens();
+    if !lexer.diagnostics().is_empty() {
+      return Err(NixtError::Lex(lexer.diagnostics().to_vec()));
+    }
+    self.warnings.extend(lexer.get_warnings().unwrap_or_default());
+    let mut parser = Parser::new(tokens);
+    parser.set_strict(self.strict);
+    parser.set_infix(self.infix || has_infix_pragma(source));
+    parser.set_lenient(self.lenient);
+    let ast = parser.parse();
+    if !parser.diagnostics().is_empty() {
+      return Err(NixtError::Parse(parser.diagnostics().to_vec()));
+    }
+    self.warnings.extend(parser.get_warnings().unwrap_or_default());
+    Ok(ast)
+  }
+  /// Evaluates `source` like [`Interpreter::eval_str`], but restores every binding to its state
+  /// before the call if evaluation fails, so a script either applies entirely or not at all.
+  /// So are the imports, `use`s, macros, `on_exit` handlers, tests, tags and fixtures it
+  /// registered.
+  ///
+  /// What the script already printed can not be taken back.
+  pub fn eval_transactional(&mut self, source: &str) -> Result<Value, String> {
+    let bindings = self.env.snapshot();
+    let deprecated = self.deprecated.clone();
+    let private = self.private.clone();
+    let pending = self.pending.clone();
+    let modules = self.modules.clone();
+    let uses = self.uses.clone();
+    let exports = self.exports.clone();
+    let macros = self.macros.clone();
+    let exit_handlers = self.exit_handlers.clone();
+    let tests = self.tests.clone();
+    let pending_tags = self.pending_tags.clone();
+    let pending_timeout = self.pending_timeout;
+    let fixtures = self.fixtures.clone();
+    let result = self.eval_str(source);
+    if result.is_err() {
+      self.env.restore(bindings);
+      self.deprecated = deprecated;
+      self.private = private;
+      self.pending = pending;
+      self.modules = modules;
+      self.uses = uses;
+      self.exports = exports;
+      self.macros = macros;
+      self.exit_handlers = exit_handlers;
+      self.tests = tests;
+      self.pending_tags = pending_tags;
+      self.pending_timeout = pending_timeout;
+      self.fixtures = fixtures;
+    }
+    result
+  }
+  /// Forgets everything evaluated since the interpreter was built: the globals go back to the
+  /// constants the builder defined, and imports, `use`, `on_exit` handlers, tests, fixtures,
+  /// warnings and the interrupt flag are cleared. The prelude, its loaded namespaces and the
+  /// shared environment are kept, as are the outputs and watchpoints.
+  ///
+  /// ```
+  /// let mut interpreter = nixt::Interpreter::builder().build()?;
+  /// interpreter.eval_str("(let x (math:abs -1))")?;
+  /// interpreter.reset();
+  /// assert!(interpreter.get("x").is_none());
+  /// assert!(interpreter.eval_str("(let x 2)").is_ok());
+  /// # Ok::<(), String>(())
+  /// ```
+  pub fn reset(&mut self) {
+    self.env = self.env.scope(0).child_with(self.initial_globals.clone());
+    self.deprecated.retain(|_, (scope, _)| *scope < GLOBAL_SCOPE);
+    self.uses.clear();
+    self.warnings.clear();
+    self.exports.clear();
+    self.private.clear();
+    self.import_stack.clear();
+    self.calls.clear();
+    self.modules.retain(|module| PRELUDE.iter().any(|(_, name, _)| name == module));
+    self.release_modules();
+    self.exit_handlers.clear();
+    self.tests.clear();
+    self.pending_tags.clear();
+    self.pending_timeout = None;
+    self.fixtures.clear();
+    self.macros.clear();
+    if self.history.is_some() {
+      self.history = Some(Default::default());
+    }
+    self.interrupt.store(false, Ordering::Relaxed);
+  }
+  /// Evaluates `source` like [`Interpreter::eval_str`], collecting what it prints and the
+  /// warnings it raises instead of writing them to the configured outputs.
+  ///
+  /// ```
+  /// let mut interpreter = nixt::Interpreter::builder().build()?;
+  /// let output = interpreter.eval_capture("(print 'hi') (eprint 'oops') (+ 40 2)");
+  /// assert_eq!(output.stdout, "hi\n");
+  /// assert_eq!(output.stderr, "oops\n");
+  /// assert_eq!(format!("{}", output.value?), "42");
+  /// # Ok::<(), String>(())
+  /// ```
+  pub fn eval_capture(&mut self, source: &str) -> EvalOutput {
+    let (out, err) = (Capture::default(), Capture::default());
+    let pending = self.take_warnings();
+    let previous_out = std::mem::replace(&mut self.out, Box::new(out.clone()));
+    let previous_err = std::mem::replace(&mut self.err, Box::new(err.clone()));
+    let value = self.eval_str(source);
+    self.out = previous_out;
+    self.err = previous_err;
+    let diagnostics = std::mem::replace(&mut self.warnings, pending);
+    EvalOutput {
+      value,
+      stdout: out.take(),
+      stderr: err.take(),
+      diagnostics,
+    }
+  }
+  /// Binds `name` as a constant of the global scope, for hosts injecting inputs. `name` must be
+  /// a valid identifier, and not that of a builtin.
+  pub fn define(&mut self, name: &str, value: Value) -> Result<(), String> {
+    check_name(name)?;
+    self.ensure_not_builtin(name)?;
+    let globals = self.env.scope(GLOBAL_SCOPE);
+    if is_defined(&globals.bindings(), &name.to_owned()) {
+      return Err(format!("Attempted to redefine variable `{}`", name));
+    }
+    globals.bindings_mut().insert(name.to_owned(), (value, true));
+    Ok(())
+  }
+  /// Looks `name` up from the innermost scope down to the prelude, loading the prelude
+  /// namespace of `name` if needed.
+  pub fn get(&mut self, name: &str) -> Option<Value> {
+    self.load_namespace_of(name);
+    self.env.get(name).or_else(|| self.shared_value(name))
+  }
+  /// Rebinds an existing variable, or creates a global one if `name` is not bound yet. Like
+  /// [`Interpreter::define`], it refuses invalid names and those of builtins.
+  pub fn set(&mut self, name: &str, value: Value) -> Result<(), String> {
+    check_name(name)?;
+    self.ensure_not_builtin(name)?;
+    match self.env.find(name) {
+      Some(scope) => {
+        let mut bindings = scope.bindings_mut();
+        let binding = bindings.get_mut(name).unwrap();
+        if binding.1 {
+          return Err(format!("Attempted to redefine constant `{}`", name));
+        }
+        binding.0 = value;
+      }
+      None => {
+        let globals = self.env.scope(GLOBAL_SCOPE);
+        globals.bindings_mut().insert(name.to_owned(), (value, false));
+      }
+    }
+    Ok(())
+  }
+  /// Exposes the Rust function `function` to programs as the builtin `name`, replacing an
+  /// earlier one of the same name. It is called like the standard builtins, with the values of
+  /// its arguments, and can be passed around as a value: `(sort_by name xs)`.
+  ///
+  /// ```
+  /// use nixt::{error::{NixtError, RuntimeError}, Value};
+  /// let mut interpreter = nixt::Interpreter::builder().build()?;
+  /// interpreter.register_native("host:env", |args| match args {
+  ///   [Value::String(key)] if key == "HOME" => Ok(Value::from("/home/nixt")),
+  ///   _ => Err(NixtError::Runtime(RuntimeError::new("`host:env` takes a known key"))),
+  /// })?;
+  /// assert_eq!(interpreter.eval_str("(host:env \"HOME\")")?.to_string(), "/home/nixt");
+  /// assert!(interpreter.eval_str("(host:env 1)").is_err());
+  /// # Ok::<(), String>(())
+  /// ```
+  pub fn register_native(
+    &mut self,
+    name: &str,
+    function: impl Fn(&[Value]) -> Result<Value, NixtError> + 'static,
+  ) -> Result<(), String> {
+    if BUILTINS.contains(&name) {
+      return Err(format!("`{}` is a builtin and can not be redefined", name));
+    }
+    check_name(name)?;
+    let mut registry = Builtins::default();
+    // The kind of a runtime error of the host is kept for the error reported
+    let failed = |error| match error {
+      NixtError::Runtime(error) => raise(error.kind, error.message),
+      other => String::from(other),
     };
-
-    if !is_defined(&scope, &name) {
-      return Err("Attempted to redefine an undefined variable".to_owned());
+    registry.register(name, move |args| function(args).map_err(failed));
+    self.natives.extend(registry);
+    Ok(())
+  }
+  /// The global bindings, sorted by name, with whether each one is constant.
+  pub fn iter_bindings(&self) -> impl Iterator<Item = (String, Value, bool)> {
+    let globals = self.env.scope(GLOBAL_SCOPE);
+    let bindings = globals.bindings();
+    let bindings = bindings.iter().map(|(name, (value, is_const))| {
+      (name.to_owned(), value.clone(), *is_const)
+    });
+    bindings.collect::<Vec<_>>().into_iter()
+  }
+  /// A flag stopping the running evaluation with an `Interrupted` error once set, for example
+  /// from a signal handler. It stays set until cleared.
+  pub fn interrupt_flag(&self) -> Arc<AtomicBool> {
+    self.interrupt.clone()
+  }
+  pub(crate) fn check_interrupt(&self) -> Result<(), String> {
+    if self.interrupt.load(Ordering::Relaxed) {
+      return Err(raise(RuntimeErrorKind::Interrupted, "Interrupted"));
     }
-
-    if scope[&name].1 {
-      return Err("Attempted to redefine a constant".to_owned());
+    Ok(())
+  }
+  /// Runs the functions registered with `(on_exit f)`, the last registered first, after
+  /// clearing the interrupt flag. Every handler runs even if one fails; the first error is
+  /// returned.
+  pub fn run_exit_handlers(&mut self) -> Result<(), String> {
+    self.interrupt.store(false, Ordering::Relaxed);
+    let mut result = Ok(());
+    while let Some(handler) = self.exit_handlers.pop() {
+      let handled = self.call_function(&handler, &[]);
+      if result.is_ok() {
+        result = handled.map(|_| ());
+      }
     }
-
+    result
+  }
+  /// The capabilities of this interpreter, as `(features)` lists them: `fs` when `import` is
+  /// allowed, the enabled evaluation modes (`strict`, `infix`, `lenient`, `const-exprs`),
+  /// `debug` when `(introspect)` is allowed and the crate features (`tracing`, `plugins`).
+  pub fn features(&self) -> Vec<&'static str> {
+    let enabled = [
+      ("fs", self.sandbox.permits("import")),
+      ("strict", self.strict),
+      ("infix", self.infix),
+      ("lenient", self.lenient),
+      ("const-exprs", self.const_exprs),
+      ("debug", self.introspection),
+      ("tracing", cfg!(feature = "tracing")),
+      ("plugins", cfg!(feature = "plugins")),
+      ("arrow", cfg!(feature = "arrow")),
+      ("msgpack", cfg!(feature = "msgpack")),
+    ];
+    enabled.iter().filter(|(_, on)| *on).map(|(feature, _)| *feature).collect()
+  }
+  /// Drains the warnings raised while evaluating, such as uses of deprecated bindings.
+  pub fn take_warnings(&mut self) -> Vec<String> {
+    std::mem::take(&mut self.warnings)
+  }
+  pub fn add_scope(&mut self) {
+    self.env = self.env.child();
+  }
+  /// Leaves the innermost scope for the one enclosing it. The prelude scope is never left.
+  pub fn remove_scope(&mut self) {
+    if let Some(parent) = self.env.parent().cloned() {
+      std::mem::replace(&mut self.env, parent).release();
+    }
+    let depth = self.env.depth();
+    if !self.deprecated.is_empty() {
+      self.deprecated.retain(|_, (scope, _)| *scope <= depth);
+    }
+    if !self.uses.is_empty() {
+      self.uses.retain(|(scope, _)| *scope <= depth);
+    }
+  }
+  pub fn var_edit(&mut self, name: &Node, new_val: &Node) -> Result<(), String> {
+    let name = self.assignable(name, true)?;
     let new_val_valued = self.proc_value(new_val)?;
+    if self.history.is_some() || !self.watches.is_empty() {
+      self.observe_mutation("set", &name, new_val, &new_val_valued)?;
+    }
 
-    if let Some(x) = self.scopes.last_mut().unwrap().get_mut(&name) {
+    if let Some(x) = self.env.settable(&name).bindings_mut().get_mut(&name) {
       *x = (new_val_valued, false)
     }
 
     Ok(())
   }
   pub fn var_def(&mut self, is_const: bool, name: &Node, value: &Node) -> Result<(), String> {
-    if self.scopes.len() == 0 {
-      return Err("No scopes available. Consider adding a scope to your program".to_owned());
+    let name = self.assignable(name, false)?;
+    let valued = self.proc_value(value)?;
+    if self.history.is_some() || !self.watches.is_empty() {
+      let keyword = if is_const { "const" } else { "let" };
+      self.observe_mutation(keyword, &name, value, &valued)?;
     }
-    let scope = &self.scopes[self.scopes.len() - 1];
+    self.env.bindings_mut().insert(name, (valued, is_const));
+    Ok(())
+  }
+  /// The name of a binding `set` (if `edit`) or defined in the innermost scope, checking it can
+  /// be. Kept out of `var_def` and `var_edit`, which nested calls recurse through, to keep their
+  /// stack frames small.
+  #[inline(never)]
+  fn assignable(&mut self, name: &Node, edit: bool) -> Result<String, String> {
     let name = if let NodeType::NodeIdentifier(s) = name.get_type() {
       s
+    } else if edit {
+      return Err("Found an invalid identifier in variable edition".to_owned());
+      // Should never be called because parser checks
     } else {
       return Err("Found an invalid identifier in variable declaration".to_owned());
-      // Should never be called because parser checks
     };
 
-    if is_defined(&scope, &name) {
+    self.ensure_not_builtin(&name)?;
+    let scope = if edit { self.env.settable(&name) } else { &self.env };
+    let scope = &*scope.bindings();
+    if edit {
+      if !is_defined(scope, &name) {
+        let message = "Attempted to redefine an undefined variable";
+        return Err(raise(RuntimeErrorKind::Undefined, message));
+      }
+      if scope[&name].1 {
+        return Err("Attempted to redefine a constant".to_owned());
+      }
+    } else if is_defined(scope, &name) {
       return Err(format!(
         "Attempted to redefine variable `{}` that is already present in the current scope",
         &name
       ));
     }
-    let value = self.proc_value(value)?;
-    self
-      .scopes
-      .last_mut()
-      .unwrap()
-      .insert(name, (value, is_const));
+    Ok(name)
+  }
+  /// The builtins and the prelude are read-only once loaded: programs can not rebind them,
+  /// even in a nested scope, as functions called from there would see the new binding.
+  fn ensure_not_builtin(&mut self, name: &str) -> Result<(), String> {
+    self.load_namespace_of(name);
+    let in_prelude = self.env.depth() > 0 && self.env.scope(0).bindings().contains_key(name);
+    let in_shared = self.shared_binding(name).is_some();
+    if in_prelude || in_shared || (self.builtins && self.is_builtin(name)) {
+      return Err(format!("Cannot modify builtin `{}`", name));
+    }
     Ok(())
   }
+  /// Whether `name` is a native builtin, standard or registered by a plugin.
+  pub(crate) fn is_builtin(&self, name: &str) -> bool {
+    BUILTINS.contains(&name) || self.natives.contains(name)
+  }
   pub fn proc_value(&mut self, val: &Node) -> Result<Value, String> {
     match val.get_type() {
-      NodeType::NodeNumber(n) => return Ok(Value::Number(n)),
-      NodeType::NodeStr(s) => return Ok(Value::String(s)),
-      NodeType::NodeBool(b) => return Ok(Value::Bool(b)),
-      NodeType::Block => return Ok(self.process_inner_block(&val)?),
+      NodeType::NodeNumber(n) => Ok(Value::Number(n)),
+      NodeType::NodeStr(s) => Ok(Value::String(s)),
+      NodeType::NodeBool(b) => Ok(Value::Bool(b)),
+      NodeType::Block => self.process_inner_block(val),
       NodeType::NodeIdentifier(s) => {
-        return if self.get_value(&s).is_some() {
+        if self.get_value(&s).is_some() {
           Ok(self.get_value(&s).unwrap())
         } else {
-          Err("Attempted to access an undefined variable".to_owned())
+          self
+            .builtin_value(&s, || "Attempted to access an undefined variable".to_owned())
+            .map_err(|e| self.located(val, e))
         }
       }
-      _ => return Ok(Value::Nil),
+      _ => Ok(Value::Nil),
     }
   }
   pub fn get_value(&mut self, value: &String) -> Option<Value> {
-    for i in (0..self.scopes.len()).rev() {
-      let scope = &self.scopes[i];
-      if scope.contains_key(value) {
-        return Some((scope[value].0).clone());
+    if !self.pending.is_empty() {
+      self.load_namespace_of(value);
+    }
+    if let Some((depth, (found, _))) = self.env.lookup(value) {
+      if let Some((_, hint)) = self.deprecated.get(value).filter(|(scope, _)| *scope == depth) {
+        let warning = format!("`{}` is deprecated: {}", value, hint);
+        if !self.warnings.contains(&warning) {
+          self.warnings.push(warning);
+        }
+      }
+      return Some(found);
+    }
+    if self.shared.is_some() {
+      if let Some(found) = self.shared_value(value) {
+        return Some(found);
+      }
+    }
+    if !self.uses.is_empty() {
+      if let Some(qualified) = self.qualify(value) {
+        return self.get_value(&qualified);
       }
     }
     None
   }
 
+  /// Evaluates the condition of an `if` or a `while`: any expression, whose value is tested
+  /// with [`truthy`].
   pub fn eval_condition(&mut self, cdn: &Node) -> Result<bool, String> {
-    let t = if let NodeType::Operator(op) = cdn.get_type() {
-      op
-    } else {
-      return Err(format!("Found invalid operator: {}", cdn));
+    let processed = match cdn.get_type() {
+      NodeType::None => Value::Nil,
+      _ => self.proc_value(cdn)?,
     };
-    let processed = self.proc_operator(t, cdn)?;
-    let you_have_been_banboolzled = if let Value::Bool(b) = processed {
-      b
-    } else {
-      return Err("Attempted to use a non-boolean value as a condition".to_owned());
-    };
-    Ok(you_have_been_banboolzled)
+    truthy(&processed)
   }
 
-  pub fn process_ast(&mut self, ast: &Node) -> Result<(), String> {
-    self.process_node(ast)?;
-    Ok(())
+  /// Runs a whole program and returns the value of its last top-level expression.
+  pub fn process_ast(&mut self, ast: &Node) -> Result<Value, String> {
+    let mut result = Value::Nil;
+    for form in ast.get_child() {
+      let started = self.verbose.as_ref().map(|_| Instant::now());
+      result = if form.get_type() == NodeType::Block && is_expression(form) {
+        if self.tracer.is_some() {
+          self.trace_step(&form.get_child()[0])?;
+        }
+        if self.verbose.is_some() {
+          self.verbose_statement(&form.get_child()[0]);
+        }
+        self.proc_value(form).map_err(|e| self.located(form, e))?
+      } else {
+        let mut single = Node::new(NodeType::Block);
+        single.add_children(form);
+        if let Some(returned) = self.process_node(&single)? {
+          return Ok(returned);
+        }
+        Value::Nil
+      };
+      if let Some(started) = started {
+        self.verbose_form(form, started);
+      }
+    }
+    Ok(result)
+  }
+  /// Clears the globals of the imported modules, whose functions keep them alive.
+  fn release_modules(&mut self) {
+    for scope in self.module_scopes.drain(..) {
+      std::mem::take(&mut *scope.bindings_mut());
+    }
+  }
+}
+
+impl Drop for Interpreter {
+  fn drop(&mut self) {
+    self.release_modules();
   }
 }
@@ -0,0 +1,281 @@
+use crate::core::interpreter::interpreter::Interpreter;
+use crate::error::{raise, RuntimeErrorKind};
+use crate::stdlib::args::ArgParser;
+use crate::utils::element::Value;
+use crate::utils::node::*;
+
+/// How many times the expansion of a macro may expand another one, to catch macros expanding
+/// to themselves.
+const MAX_EXPANSION_DEPTH: usize = 64;
+
+/// A macro defined with `(macro name (params...) (quote template))`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Macro {
+  params: Vec<String>,
+  template: Node,
+}
+
+impl Interpreter {
+  /// Expands the macros of `ast` before it is evaluated: removes the `(macro ...)` definitions
+  /// and replaces each call of a macro defined before it by its template, with every
+  /// `(unquote param)` replaced by the syntax of the argument. A template of several forms,
+  /// `((a) (b))`, expands to all of them in place. The macros stay defined for the next
+  /// evaluations.
+  ///
+  /// A `(quote form)` left outside a template evaluates to the source of `form`. Expansion is
+  /// not hygienic: the names a template binds are those of the code it is expanded into.
+  ///
+  /// ```
+  /// let mut interpreter = nixt::Interpreter::builder().build()?;
+  /// interpreter.eval_str(
+  ///   "(macro unless (test body) (quote (if (unquote test) () ((unquote body)))))",
+  /// )?;
+  /// let value = interpreter.eval_str("(let x 1) (unless (= x 2) (set x 3)) (+ x 0)")?;
+  /// assert_eq!(value.to_string(), "3");
+  /// assert_eq!(interpreter.eval_str("(quote (+ x 1))")?.to_string(), "(+ x 1)");
+  /// # Ok::<(), String>(())
+  /// ```
+  pub fn expand_macros(&mut self, ast: &Node) -> Result<Node, String> {
+    self.expand(ast, 0)
+  }
+  fn expand(&mut self, node: &Node, depth: usize) -> Result<Node, String> {
+    if let Some((name, args)) = self.macro_call(node) {
+      if depth >= MAX_EXPANSION_DEPTH {
+        return Err(format!("Macro `{}` expands too deeply; does it expand to itself?", name));
+      }
+      let expanded = self.macros[&name].substitute(&name, args)?;
+      return self.expand(&expanded, depth + 1);
+    }
+    match form_call(node).map(|call| call.get_type()) {
+      Some(NodeType::FunctionCall(name)) if name == "quote" => return quote(node),
+      Some(NodeType::FunctionCall(name)) if name == "unquote" => {
+        return Err("`unquote` can only be used in the template of a macro".to_owned())
+      }
+      _ => {}
+    }
+    let mut toret = Node::new(node.get_type());
+    toret.set_span(node.span());
+    let statements = matches!(node.get_type(), NodeType::Block | NodeType::Scope);
+    for child in node.get_child() {
+      if self.define_macro(child)? {
+        continue;
+      }
+      let called = self.macro_call(child).is_some();
+      let expanded = self.expand(child, depth)?;
+      if statements && called && is_sequence(&expanded) {
+        for statement in expanded.get_child() {
+          toret.add_children(statement);
+        }
+      } else {
+        toret.add_children(&expanded);
+      }
+    }
+    Ok(toret)
+  }
+  /// The name and arguments of `node` if it calls a macro: `(name args...)`.
+  fn macro_call<'a>(&self, node: &'a Node) -> Option<(String, &'a [Node])> {
+    if self.macros.is_empty() {
+      return None;
+    }
+    let call = form_call(node)?;
+    match call.get_type() {
+      NodeType::FunctionCall(name) if self.macros.contains_key(&name) => {
+        Some((name, call.get_child()))
+      }
+      _ => None,
+    }
+  }
+  /// Defines the macro of `node` if it is a `(macro ...)` form.
+  fn define_macro(&mut self, node: &Node) -> Result<bool, String> {
+    let definition = match form_call(node) {
+      Some(call) if call.get_type() == NodeType::FunctionCall("macro".to_owned()) => call,
+      _ => return Ok(false),
+    };
+    let invalid = || "A macro is defined as `(macro name (params...) (quote template))`";
+    let (name, params, template) = match definition.get_child() {
+      [name, params, template] => (name, params, template),
+      _ => return Err(invalid().to_owned()),
+    };
+    let name = match name.get_type() {
+      NodeType::NodeIdentifier(name) => name,
+      _ => return Err(invalid().to_owned()),
+    };
+    if self.is_builtin(&name) {
+      return Err(format!("`{}` is a builtin and can not be redefined as a macro", name));
+    }
+    let params = parameters(params).ok_or_else(invalid)?;
+    let template = match form_call(template) {
+      Some(quoted) if quoted.get_type() == NodeType::FunctionCall("quote".to_owned()) => {
+        match quoted.get_child() {
+          [template] => template.clone(),
+          _ => return Err(invalid().to_owned()),
+        }
+      }
+      _ => return Err(invalid().to_owned()),
+    };
+    self.macros.insert(name, Macro { params, template });
+    Ok(true)
+  }
+}
+
+impl Interpreter {
+  /// `(parse "source")`: the program `source` holds, for `eval` to evaluate.
+  pub(crate) fn process_parse(&mut self, args: &[Value]) -> Result<Value, String> {
+    let source = ArgParser::new("parse", args).exact(1)?.get_str(0)?;
+    Ok(Value::Ast(self.parse_source(source)?))
+  }
+  /// `(eval code)`: evaluates a program `parse` gave, or its source, in the current scope,
+  /// returning the value of its last expression. `(eval code "fresh")` evaluates it in a new
+  /// scope that only sees the prelude.
+  ///
+  /// ```
+  /// let mut interpreter = nixt::Interpreter::builder().build()?;
+  /// let code = "(let x 2) (let ast (parse \"(* x 21)\")) (eval ast)";
+  /// assert_eq!(interpreter.eval_str(code)?.to_string(), "42");
+  /// assert!(interpreter.eval_str("(eval \"(print x)\" \"fresh\")").is_err());
+  /// # Ok::<(), String>(())
+  /// ```
+  pub(crate) fn process_eval(&mut self, args: &[Value]) -> Result<Value, String> {
+    let parser = ArgParser::new("eval", args).between(1, 2)?;
+    let ast = match parser.get(0)? {
+      Value::Ast(ast) => ast.clone(),
+      Value::String(source) => self.parse_source(source)?,
+      _ => return Err(parser.expected(0, "ast or string")),
+    };
+    let result = match args.get(1) {
+      None => self.eval_in_scope(&ast),
+      Some(_) if parser.get_str(1)? == "fresh" => self.eval_fresh(&ast),
+      Some(_) => {
+        return Err(format!(
+          "`eval` evaluates in the current scope or a \"fresh\" one, not {:?}",
+          parser.get_str(1)?
+        ))
+      }
+    };
+    // The spans of `ast` are not those of the `eval` call, which is where the error is.
+    self.error_span = None;
+    result
+  }
+  fn eval_in_scope(&mut self, ast: &Node) -> Result<Value, String> {
+    if self.macros.is_empty() && !uses_macros(ast) {
+      return self.process_ast(ast);
+    }
+    let expanded = self.expand_macros(ast)?;
+    self.process_ast(&expanded)
+  }
+  /// Evaluates `ast` with the globals and locals set aside, restoring them after.
+  fn eval_fresh(&mut self, ast: &Node) -> Result<Value, String> {
+    let prelude = self.env.scope(0);
+    let outer = std::mem::replace(&mut self.env, prelude);
+    let uses = std::mem::take(&mut self.uses);
+    self.add_scope();
+    let result = self.eval_in_scope(ast);
+    self.env = outer;
+    self.uses = uses;
+    result
+  }
+}
+
+impl Macro {
+  /// The template with the `(unquote param)` forms replaced by `args`.
+  fn substitute(&self, name: &str, args: &[Node]) -> Result<Node, String> {
+    if args.len() != self.params.len() {
+      let message = format!(
+        "Macro `{}` takes {} argument(s), got {}",
+        name,
+        self.params.len(),
+        args.len()
+      );
+      return Err(raise(RuntimeErrorKind::Arity, message));
+    }
+    self.replace(&self.template, args)
+  }
+  fn replace(&self, node: &Node, args: &[Node]) -> Result<Node, String> {
+    let unquoted = form_call(node)
+      .filter(|call| call.get_type() == NodeType::FunctionCall("unquote".to_owned()));
+    if let Some(unquote) = unquoted {
+      let param = match unquote.get_child() {
+        [param] => param,
+        _ => return Err("`unquote` takes the name of a parameter of the macro".to_owned()),
+      };
+      let index = self
+        .params
+        .iter()
+        .position(|p| param.get_type() == NodeType::NodeIdentifier(p.clone()));
+      return match index {
+        Some(index) => Ok(args[index].clone()),
+        None => Err(format!("`(unquote {})` does not name a parameter of the macro", param)),
+      };
+    }
+    let mut toret = Node::new(node.get_type());
+    toret.set_span(node.span());
+    for child in node.get_child() {
+      toret.add_children(&self.replace(child, args)?);
+    }
+    Ok(toret)
+  }
+}
+
+/// Whether `ast` defines a macro or quotes a form, so that it needs expanding even if no macro
+/// is defined yet.
+pub(crate) fn uses_macros(ast: &Node) -> bool {
+  match ast.get_type() {
+    NodeType::FunctionCall(name) if ["macro", "quote", "unquote"].contains(&name.as_str()) => true,
+    _ => ast.get_child().iter().any(uses_macros),
+  }
+}
+
+/// The call `node` wraps, if it is a form of a single call: `(name args...)`.
+fn form_call(node: &Node) -> Option<&Node> {
+  match (node.get_type(), node.get_child()) {
+    (NodeType::Block, [call]) if matches!(call.get_type(), NodeType::FunctionCall(_)) => {
+      Some(call)
+    }
+    _ => None,
+  }
+}
+
+/// Whether `node` is a sequence of forms, `((a) (b))`, which a macro called as a statement
+/// expands to in place.
+fn is_sequence(node: &Node) -> bool {
+  let forms = node.get_child();
+  node.get_type() == NodeType::Block
+    && !forms.is_empty()
+    && forms.iter().all(|form| form.get_type() == NodeType::Block)
+}
+
+/// The names of a parameter list, `(a b c)` or `()`.
+fn parameters(params: &Node) -> Option<Vec<String>> {
+  if params.get_type() != NodeType::Block {
+    return None;
+  }
+  let first = match params.get_child() {
+    [] => return Some(vec![]),
+    [first] => first,
+    _ => return None,
+  };
+  let mut names = match first.get_type() {
+    NodeType::FunctionCall(name) => vec![name],
+    _ => return None,
+  };
+  for rest in first.get_child() {
+    match rest.get_type() {
+      NodeType::NodeIdentifier(name) => names.push(name),
+      _ => return None,
+    }
+  }
+  Some(names)
+}
+
+/// `(quote form)` as `(do "form")`, a form evaluating to the source of `form`.
+fn quote(node: &Node) -> Result<Node, String> {
+  let source = match node.get_child()[0].get_child() {
+    [form] => Node::new(NodeType::NodeStr(form.to_string())),
+    _ => return Err("`quote` takes a single form".to_owned()),
+  };
+  let mut block = Node::new(NodeType::Block);
+  let mut value = Node::new(NodeType::Do);
+  value.add_children(&source);
+  block.add_children(&value);
+  Ok(block)
+}
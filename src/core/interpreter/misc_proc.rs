@@ -1,73 +1,122 @@
-use crate::core::interpreter::interpreter::Interpreter;
+use crate::core::interpreter::interpreter::{truthy, Interpreter};
 use crate::utils::{element::*, node::*};
 
 impl Interpreter {
-  pub fn process_loop(&mut self, master: &Node) -> Result<(), String> {
-    let raw_condition = &master.get_child()[0].get_child()[0];
+  /// `(while (cond) {body})`. Each iteration runs in a scope of its own, which closures made
+  /// in it keep, while `set` still edits the bindings of the enclosing scope. Returns what a
+  /// `ret` in the body returned.
+  pub fn process_loop(&mut self, master: &Node) -> Result<Option<Value>, String> {
+    let raw_condition = &master.get_child()[0];
     while self.eval_condition(raw_condition)? {
-      self.process_node(&master.get_child()[1])?;
+      self.check_interrupt()?;
+      self.env = self.env.iteration();
+      let returned = self.process_node(&master.get_child()[1]);
+      self.remove_scope();
+      if let Some(returned) = returned? {
+        return Ok(Some(returned));
+      }
     }
-    Ok(())
+    Ok(None)
   }
-  pub fn process_if(&mut self, master: &Node) -> Result<(), String> {
-    let raw_condition = &master.get_child()[0].get_child()[0];
+  /// Runs the branch of an `if` its condition picks, returning what a `ret` in it returned.
+  pub fn process_if(&mut self, master: &Node) -> Result<Option<Value>, String> {
+    let raw_condition = &master.get_child()[0];
     if self.eval_condition(raw_condition)? {
-      self.process_node(&master.get_child()[1])?;
+      self.process_node(&master.get_child()[1])
+    } else if master.get_child()[2].get_type() == NodeType::None {
+      Ok(None)
     } else {
-      if &master.get_child()[2].get_type() == &NodeType::None {
-        return Ok(());
-      } else {
-        self.process_node(&master.get_child()[2])?;
+      self.process_node(&master.get_child()[2])
+    }
+  }
+  pub fn process_cond(&mut self, master: &Node) -> Result<Value, String> {
+    for clause in master.get_child() {
+      if truthy(&self.proc_value(&clause.get_child()[0])?)? {
+        return self.process_sequence(&clause.get_child()[1]);
       }
     }
-    Ok(())
+    Ok(Value::Nil)
   }
   pub fn proc_operator(&mut self, op: OperatorType, val: &Node) -> Result<Value, String> {
-    let lhs = match val.get_child()[0].get_type() {
-      NodeType::Block => self.process_inner_block(&val.get_child()[0])?,
-      NodeType::NodeNumber(n) => Value::Number(n),
-      NodeType::NodeStr(s) => Value::String(s),
-      NodeType::NodeBool(b) => Value::Bool(b),
-      NodeType::None => Value::Nil,
-      NodeType::NodeIdentifier(s) => {
-        if self.get_value(&s).is_some() {
-          self.get_value(&s).unwrap()
-        } else {
-          return Err("Attempted to access an undefined variable".to_owned());
+    let operands = val.get_child();
+    if operands.is_empty() {
+      return Ok(Value::Nil);
+    }
+    let mut lhs = self.proc_operand(&operands[0])?;
+    if is_comparison(&op) {
+      // (< a b c) holds when every adjacent pair does
+      for operand in &operands[1..] {
+        let rhs = self.proc_operand(operand)?;
+        match self.apply_operator(&op, lhs, rhs.clone())? {
+          Value::Bool(true) => lhs = rhs,
+          other => return Ok(other),
         }
       }
-      _ => return Err("Invalid element".to_owned()),
-    };
-    let rhs = match val.get_child()[1].get_type() {
-      NodeType::Block => self.process_inner_block(&val.get_child()[1])?,
-      NodeType::NodeNumber(n) => Value::Number(n),
-      NodeType::NodeStr(s) => Value::String(s),
-      NodeType::NodeBool(b) => Value::Bool(b),
-      NodeType::None => Value::Nil,
-      NodeType::NodeIdentifier(s) => {
-        if self.get_value(&s).is_some() {
-          self.get_value(&s).unwrap()
-        } else {
-          return Err("Attempted to access an undefined variable".to_owned());
+      return Ok(Value::Bool(true));
+    }
+    for operand in &operands[1..] {
+      if op == OperatorType::NilCoalesce && !matches!(lhs, Value::Nil) {
+        return Ok(lhs); // The default is only evaluated when needed
+      }
+      // The rest is only evaluated while `and` meets truthy values and `or` falsy ones: the
+      // result is the operand that decided, or the last one
+      if let OperatorType::And | OperatorType::Or = op {
+        if truthy(&lhs)? == (op == OperatorType::Or) {
+          return Ok(lhs);
         }
+        lhs = self.proc_operand(operand)?;
+        continue;
       }
-      _ => return Err("Invalid element".to_owned()),
-    };
-    let toret = match op {
-      OperatorType::Div => self.div(lhs, rhs)?,
-      OperatorType::Times => self.mul(lhs, rhs)?,
-      OperatorType::Plus => self.add(lhs, rhs)?,
-      OperatorType::Minus => self.sub(lhs, rhs)?,
-      OperatorType::Modulo => self.modulo(lhs, rhs)?,
-      OperatorType::Equal => self.eq(lhs, rhs)?,
-      OperatorType::NotEqual => self.neq(lhs, rhs)?,
-      OperatorType::LessEqual => self.leq(lhs, rhs)?,
-      OperatorType::Less => self.le(lhs, rhs)?,
-      OperatorType::Greater => self.ge(lhs, rhs)?,
-      OperatorType::GreaterEqual => self.geq(lhs, rhs)?,
-      OperatorType::And => self.and(lhs, rhs)?,
-      OperatorType::Or => self.or(lhs, rhs)?,
-    };
-    Ok(toret)
+      let rhs = self.proc_operand(operand)?;
+      lhs = self.apply_operator(&op, lhs, rhs)?;
+    }
+    Ok(lhs)
   }
+  fn proc_operand(&mut self, operand: &Node) -> Result<Value, String> {
+    match operand.get_type() {
+      NodeType::Block => self.process_inner_block(operand),
+      NodeType::NodeNumber(n) => Ok(Value::Number(n)),
+      NodeType::NodeStr(s) => Ok(Value::String(s)),
+      NodeType::NodeBool(b) => Ok(Value::Bool(b)),
+      NodeType::None => Ok(Value::Nil),
+      NodeType::NodeIdentifier(s) => match self.get_value(&s) {
+        Some(v) => Ok(v),
+        None => {
+          let error = self.undefined(&s, "Attempted to access an undefined variable".to_owned());
+          Err(self.located(operand, error))
+        }
+      },
+      _ => Err("Invalid element".to_owned()),
+    }
+  }
+  fn apply_operator(&self, op: &OperatorType, lhs: Value, rhs: Value) -> Result<Value, String> {
+    match op {
+      OperatorType::Div => self.div(lhs, rhs),
+      OperatorType::Times => self.mul(lhs, rhs),
+      OperatorType::Plus => self.add(lhs, rhs),
+      OperatorType::Minus => self.sub(lhs, rhs),
+      OperatorType::Modulo => self.modulo(lhs, rhs),
+      OperatorType::Equal => self.eq(lhs, rhs),
+      OperatorType::NotEqual => self.neq(lhs, rhs),
+      OperatorType::LessEqual => self.leq(lhs, rhs),
+      OperatorType::Less => self.le(lhs, rhs),
+      OperatorType::Greater => self.ge(lhs, rhs),
+      OperatorType::GreaterEqual => self.geq(lhs, rhs),
+      OperatorType::And => self.and(lhs, rhs),
+      OperatorType::Or => self.or(lhs, rhs),
+      OperatorType::NilCoalesce => Ok(rhs),
+    }
+  }
+}
+
+fn is_comparison(op: &OperatorType) -> bool {
+  matches!(
+    op,
+    OperatorType::Equal
+      | OperatorType::NotEqual
+      | OperatorType::Less
+      | OperatorType::LessEqual
+      | OperatorType::Greater
+      | OperatorType::GreaterEqual
+  )
 }
@@ -1,6 +1,23 @@
 pub mod arithmetic;
 pub mod blocks;
 pub mod boolean;
+pub mod builder;
+pub mod consts;
+pub mod environment;
 pub mod functions;
+pub mod harness;
+pub mod history;
+#[allow(clippy::module_inception)]
 pub mod interpreter;
+pub mod macros;
 pub mod misc_proc;
+pub mod namespaces;
+pub mod plugin;
+pub mod pool;
+pub mod replay;
+pub mod sandbox;
+pub mod shared;
+pub mod state;
+pub mod transfer;
+pub mod verbose;
+pub mod watch;
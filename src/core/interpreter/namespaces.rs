@@ -0,0 +1,99 @@
+use crate::core::interpreter::functions::BUILTINS;
+use crate::core::interpreter::interpreter::Interpreter;
+use crate::core::{lexer::Lexer, parser::Parser};
+use crate::utils::element::Value;
+use crate::utils::trace::trace_phase;
+
+impl Interpreter {
+  /// Evaluates the prelude module of the namespace `name` is qualified with (`math` for
+  /// `math:abs`), the first time one of its names is looked up.
+  pub(crate) fn load_namespace_of(&mut self, name: &str) {
+    let namespace = match name.split_once(':') {
+      Some((namespace, _)) => namespace,
+      None => return,
+    };
+    let index = match self.pending.iter().position(|(ns, _, _)| *ns == namespace) {
+      Some(index) => index,
+      None => return,
+    };
+    let (_, module, source) = self.pending.remove(index);
+    trace_phase!("load", module);
+    self.modules.push(module.to_owned());
+    if let Err(e) = self.load_module(source) {
+      self
+        .warnings
+        .push(format!("Failed to load prelude module `{}`: {}", module, e));
+    }
+    let sandbox = &self.sandbox;
+    self.env.scope(0).bindings_mut().retain(|name, _| sandbox.permits(name));
+  }
+  /// Evaluates `source` in the prelude scope, with the default syntax.
+  pub(crate) fn load_module(&mut self, source: &str) -> Result<(), String> {
+    let mut lexer = Lexer::new(source);
+    let tokens = lexer.scan_tokens();
+    if let Some(errors) = lexer.get_errors() {
+      return Err(errors.join("\n"));
+    }
+    let mut parser = Parser::new(tokens);
+    let ast = parser.parse();
+    if let Some(errors) = parser.get_errors() {
+      return Err(errors.join("\n"));
+    }
+    let prelude = self.env.scope(0);
+    let env = std::mem::replace(&mut self.env, prelude);
+    let exports = std::mem::take(&mut self.exports);
+    // The prelude is not the program the verbose mode traces.
+    let verbose = self.verbose.take();
+    let result = self.process_ast(&ast);
+    self.verbose = verbose;
+    self.exports = exports;
+    self.env = env;
+    result.map(|_| ())
+  }
+  /// `(use "math")`: makes the names of a namespace usable unqualified in the current scope,
+  /// `(cos x)` standing for `(math:cos x)` unless `cos` is bound.
+  pub fn process_use(&mut self, args: &[Value]) -> Result<Value, String> {
+    for arg in args {
+      let namespace = match arg {
+        Value::String(s) => s,
+        _ => return Err("`use` expects namespace names, such as \"math\"".to_owned()),
+      };
+      let in_namespace = |name: &str| name.split_once(':').map(|(ns, _)| ns) == Some(namespace);
+      let known = BUILTINS.iter().any(|name| in_namespace(name))
+        || self.natives.names().any(in_namespace)
+        || self.env.scope(0).bindings().keys().any(|name| in_namespace(name))
+        || self.shared.iter().flat_map(|env| env.names()).any(in_namespace)
+        || self.pending.iter().any(|(ns, _, _)| ns == namespace);
+      if !known {
+        return Err(format!("Unknown namespace `{}`", namespace));
+      }
+      self.uses.push((self.env.depth(), namespace.to_owned()));
+    }
+    Ok(Value::Nil)
+  }
+  /// The qualified name an unbound `name` stands for through the namespaces in use, innermost
+  /// `use` first.
+  pub(crate) fn qualify(&mut self, name: &str) -> Option<String> {
+    if name.contains(':') {
+      return None;
+    }
+    let namespaces = self
+      .uses
+      .iter()
+      .rev()
+      .map(|(_, ns)| ns.to_owned())
+      .collect::<Vec<_>>();
+    for namespace in namespaces {
+      let qualified = format!("{}:{}", namespace, name);
+      if self.is_builtin(&qualified) {
+        return Some(qualified);
+      }
+      self.load_namespace_of(&qualified);
+      let in_prelude = self.env.scope(0).bindings().contains_key(&qualified);
+      if in_prelude || self.shared_binding(&qualified).is_some() {
+        return Some(qualified);
+      }
+    }
+    None
+  }
+}
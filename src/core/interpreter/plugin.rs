@@ -0,0 +1,97 @@
+use crate::utils::element::Value;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+/// A builtin implemented by the host or a plugin.
+pub type NativeFn = Rc<dyn Fn(&[Value]) -> Result<Value, String>>;
+
+/// The builtins plugins register, called by programs like the standard ones.
+#[derive(Clone, Default)]
+pub struct Builtins {
+  functions: BTreeMap<String, NativeFn>,
+}
+
+impl Builtins {
+  /// Registers `function` as the builtin `name`, replacing an earlier one of the same name.
+  pub fn register(
+    &mut self,
+    name: &str,
+    function: impl Fn(&[Value]) -> Result<Value, String> + 'static,
+  ) -> &mut Self {
+    self.functions.insert(name.to_owned(), Rc::new(function));
+    self
+  }
+  pub fn get(&self, name: &str) -> Option<&NativeFn> {
+    self.functions.get(name)
+  }
+  pub fn contains(&self, name: &str) -> bool {
+    self.functions.contains_key(name)
+  }
+  pub fn names(&self) -> impl Iterator<Item = &str> {
+    self.functions.keys().map(|name| name.as_str())
+  }
+  pub(crate) fn extend(&mut self, other: Builtins) {
+    self.functions.extend(other.functions);
+  }
+}
+
+/// A pack of builtins, added with
+/// [`InterpreterBuilder::plugin`](crate::InterpreterBuilder::plugin).
+///
+/// ```
+/// use nixt::{core::interpreter::plugin::{Builtins, NixtPlugin}, Interpreter, Value};
+///
+/// struct Greeter;
+///
+/// impl NixtPlugin for Greeter {
+///   fn name(&self) -> &str {
+///     "greeter"
+///   }
+///   fn register(&self, registry: &mut Builtins) {
+///     registry.register("greet:hello", |args| {
+///       Ok(Value::String(format!("hello {}", args.first().unwrap_or(&Value::Nil))))
+///     });
+///   }
+/// }
+///
+/// let mut interpreter = Interpreter::builder().plugin(Greeter).build()?;
+/// assert_eq!(format!("{}", interpreter.eval_str("(greet:hello 'you')")?), "hello you");
+/// # Ok::<(), String>(())
+/// ```
+pub trait NixtPlugin {
+  fn name(&self) -> &str;
+  fn register(&self, registry: &mut Builtins);
+}
+
+/// Loads the plugin libraries of `dir`, registering their builtins in `registry`.
+///
+/// A plugin library exports `#[no_mangle] pub fn nixt_plugin() -> Box<dyn NixtPlugin>`, and
+/// must be built with the same compiler and nixt version as the host.
+#[cfg(feature = "plugins")]
+pub(crate) fn load_dir(
+  dir: &std::path::Path,
+  registry: &mut Builtins,
+) -> Result<Vec<libloading::Library>, String> {
+  let entries = std::fs::read_dir(dir)
+    .map_err(|e| format!("Failed to read plugin directory `{}`: {}", dir.display(), e))?;
+  let mut paths = entries
+    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+    .filter(|path| path.extension().is_some_and(|ext| ext == std::env::consts::DLL_EXTENSION))
+    .collect::<Vec<_>>();
+  paths.sort();
+  let mut libraries = vec![];
+  for path in paths {
+    let failed = |e: libloading::Error| format!("Failed to load plugin `{}`: {}", path.display(), e);
+    // SAFETY: plugin libraries are trusted to export `nixt_plugin` with the documented
+    // signature. The library is kept loaded as long as the interpreter calling its builtins.
+    unsafe {
+      let library = libloading::Library::new(&path).map_err(failed)?;
+      let constructor = library
+        .get::<fn() -> Box<dyn NixtPlugin>>(b"nixt_plugin")
+        .map_err(failed)?;
+      constructor().register(registry);
+      libraries.push(library);
+    }
+  }
+  Ok(libraries)
+}
@@ -0,0 +1,107 @@
+use crate::core::interpreter::builder::InterpreterBuilder;
+use crate::core::interpreter::interpreter::Interpreter;
+
+/// How an [`InterpreterPool`] has been used since it was created.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolStats {
+  /// The interpreters built, up front or when the pool was empty.
+  pub created: usize,
+  /// The calls to [`InterpreterPool::checkout`].
+  pub checkouts: usize,
+  /// The checkouts handing out an interpreter that was checked in before.
+  pub reused: usize,
+  /// The interpreters dropped when checked in to a full pool.
+  pub discarded: usize,
+}
+
+impl PoolStats {
+  /// The share of checkouts served by a reused interpreter, between 0 and 1.
+  pub fn reuse_rate(&self) -> f64 {
+    if self.checkouts == 0 {
+      return 0.0;
+    }
+    self.reused as f64 / self.checkouts as f64
+  }
+}
+
+/// Idle interpreters handed out to evaluate one script each, then reset and kept for the next
+/// one, so services do not pay for building an interpreter and loading its prelude per request.
+///
+/// Interpreters are built by calling the builder function again, for example on a
+/// [`SharedEnv`](crate::SharedEnv). As interpreters are not `Send`, a service keeps a pool per
+/// worker thread.
+///
+/// ```
+/// use nixt::{Interpreter, InterpreterPool};
+/// let mut pool = InterpreterPool::with_capacity(1, || Interpreter::builder().prelude(false))?;
+/// let mut interpreter = pool.checkout()?;
+/// interpreter.eval_str("(let x 1)")?;
+/// pool.checkin(interpreter);
+/// let mut interpreter = pool.checkout()?;
+/// assert!(interpreter.get("x").is_none());
+/// assert_eq!((pool.stats().created, pool.stats().reused), (1, 2));
+/// # Ok::<(), String>(())
+/// ```
+pub struct InterpreterPool {
+  builder: Box<dyn Fn() -> InterpreterBuilder>,
+  idle: Vec<Interpreter>,
+  capacity: usize,
+  stats: PoolStats,
+}
+
+impl InterpreterPool {
+  /// Builds `capacity` interpreters with the builders `builder` returns. The pool keeps at most
+  /// `capacity` idle interpreters.
+  pub fn with_capacity(
+    capacity: usize,
+    builder: impl Fn() -> InterpreterBuilder + 'static,
+  ) -> Result<Self, String> {
+    let mut pool = Self {
+      builder: Box::new(builder),
+      idle: Vec::with_capacity(capacity),
+      capacity,
+      stats: PoolStats::default(),
+    };
+    for _ in 0..capacity {
+      let interpreter = pool.build()?;
+      pool.idle.push(interpreter);
+    }
+    Ok(pool)
+  }
+  /// An idle interpreter, or a new one if there is none.
+  pub fn checkout(&mut self) -> Result<Interpreter, String> {
+    self.stats.checkouts += 1;
+    match self.idle.pop() {
+      Some(interpreter) => {
+        self.stats.reused += 1;
+        Ok(interpreter)
+      }
+      None => self.build(),
+    }
+  }
+  /// Resets `interpreter` with [`Interpreter::reset`] and keeps it for the next checkout, or
+  /// drops it if the pool is full.
+  pub fn checkin(&mut self, mut interpreter: Interpreter) {
+    if self.idle.len() >= self.capacity {
+      self.stats.discarded += 1;
+      return;
+    }
+    interpreter.reset();
+    self.idle.push(interpreter);
+  }
+  /// The number of interpreters waiting to be checked out.
+  pub fn idle(&self) -> usize {
+    self.idle.len()
+  }
+  pub fn capacity(&self) -> usize {
+    self.capacity
+  }
+  pub fn stats(&self) -> PoolStats {
+    self.stats
+  }
+  fn build(&mut self) -> Result<Interpreter, String> {
+    let interpreter = (self.builder)().build()?;
+    self.stats.created += 1;
+    Ok(interpreter)
+  }
+}
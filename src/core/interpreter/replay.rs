@@ -0,0 +1,216 @@
+use crate::core::interpreter::interpreter::Interpreter;
+use crate::utils::element::Value;
+use crate::utils::json::{self, Json};
+use crate::utils::node::Node;
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+
+/// The builtins whose results a trace records, besides the builtins of plugins.
+pub const NONDETERMINISTIC: &[&str] = &["time:now"];
+
+const MAGIC: &[u8] = b"NIXTTRACE1";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TraceEvent {
+  /// A statement ran or a nixt function was called, labelled with its node type.
+  Step(String),
+  /// A nondeterministic builtin returned.
+  Builtin {
+    name: String,
+    result: Result<Json, String>,
+  },
+}
+
+/// The evaluation of a script, recorded with [`InterpreterBuilder::record`] and replayed with
+/// [`InterpreterBuilder::replay`].
+///
+/// [`InterpreterBuilder::record`]: crate::InterpreterBuilder::record
+/// [`InterpreterBuilder::replay`]: crate::InterpreterBuilder::replay
+///
+/// ```
+/// use nixt::{core::interpreter::replay::Trace, Interpreter};
+/// let mut recording = Interpreter::builder().record(true).build()?;
+/// recording.eval_str("(let started (time:now))")?;
+/// let trace = recording.take_trace().unwrap();
+///
+/// let mut bytes = vec![];
+/// trace.write_to(&mut bytes).map_err(|e| e.to_string())?;
+/// let trace = Trace::read_from(&mut bytes.as_slice())?;
+/// let mut replaying = Interpreter::builder().replay(trace).build()?;
+/// replaying.eval_str("(let started (time:now))")?;
+/// let started = |nixt: &mut Interpreter| nixt.get("started").map(|v| v.to_string());
+/// assert_eq!(started(&mut replaying), started(&mut recording));
+/// # Ok::<(), String>(())
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Trace {
+  pub events: Vec<TraceEvent>,
+}
+
+impl Trace {
+  /// Writes the trace in its binary format: a magic number, then each event as a tag byte and
+  /// length-prefixed strings.
+  pub fn write_to(&self, out: &mut impl Write) -> std::io::Result<()> {
+    out.write_all(MAGIC)?;
+    for event in &self.events {
+      match event {
+        TraceEvent::Step(label) => {
+          out.write_all(&[0])?;
+          write_str(out, label)?;
+        }
+        TraceEvent::Builtin { name, result } => {
+          let (tag, text) = match result {
+            Ok(value) => (1, value.to_string()),
+            Err(e) => (2, e.to_owned()),
+          };
+          out.write_all(&[tag])?;
+          write_str(out, name)?;
+          write_str(out, &text)?;
+        }
+      }
+    }
+    Ok(())
+  }
+  pub fn read_from(input: &mut impl Read) -> Result<Self, String> {
+    let mut bytes = vec![];
+    input.read_to_end(&mut bytes).map_err(|e| e.to_string())?;
+    let mut rest = bytes
+      .strip_prefix(MAGIC)
+      .ok_or("Not a nixt trace: the magic number is missing")?;
+    let mut events = vec![];
+    while let Some((&tag, after)) = rest.split_first() {
+      rest = after;
+      let event = match tag {
+        0 => TraceEvent::Step(read_str(&mut rest)?),
+        1 | 2 => {
+          let name = read_str(&mut rest)?;
+          let text = read_str(&mut rest)?;
+          let result = if tag == 1 { Ok(json::parse(&text)?) } else { Err(text) };
+          TraceEvent::Builtin { name, result }
+        }
+        _ => return Err(format!("Corrupted trace: unknown event tag {}", tag)),
+      };
+      events.push(event);
+    }
+    Ok(Self { events })
+  }
+}
+
+fn write_str(out: &mut impl Write, s: &str) -> std::io::Result<()> {
+  out.write_all(&(s.len() as u32).to_le_bytes())?;
+  out.write_all(s.as_bytes())
+}
+
+fn read_str(rest: &mut &[u8]) -> Result<String, String> {
+  let truncated = || "Corrupted trace: truncated event".to_owned();
+  let (len, after) = rest.split_first_chunk::<4>().ok_or_else(truncated)?;
+  let len = u32::from_le_bytes(*len) as usize;
+  let bytes = after.get(..len).ok_or_else(truncated)?;
+  *rest = &after[len..];
+  String::from_utf8(bytes.to_vec()).map_err(|_| "Corrupted trace: invalid UTF-8".to_owned())
+}
+
+/// How many steps [`Tracer::Tail`] keeps.
+pub(crate) const TAIL_STEPS: usize = 8;
+
+/// Whether an interpreter records its evaluation or replays a recording.
+pub(crate) enum Tracer {
+  Record(Trace),
+  Replay { trace: Trace, position: usize },
+  /// Only keeps the labels of the last [`TAIL_STEPS`] steps, to tell where a test that timed
+  /// out was stuck.
+  Tail(VecDeque<String>),
+}
+
+impl Interpreter {
+  /// Stops recording, returning what was recorded since the interpreter was built.
+  pub fn take_trace(&mut self) -> Option<Trace> {
+    match self.tracer.take() {
+      Some(Tracer::Record(trace)) => Some(trace),
+      tracer => {
+        self.tracer = tracer;
+        None
+      }
+    }
+  }
+  /// Records the evaluation of `node`, or checks that the replayed script evaluates what the
+  /// recorded one did.
+  #[inline(never)]
+  pub(crate) fn trace_step(&mut self, node: &Node) -> Result<(), String> {
+    let label = format!("{:?}", node.get_type());
+    match self.tracer.as_mut() {
+      Some(Tracer::Record(trace)) => trace.events.push(TraceEvent::Step(label)),
+      Some(Tracer::Replay { trace, position }) => {
+        let step = *position;
+        *position += 1;
+        match trace.events.get(step) {
+          Some(TraceEvent::Step(recorded)) if *recorded == label => {}
+          recorded => return Err(diverged(step, recorded, &label)),
+        }
+      }
+      Some(Tracer::Tail(steps)) => {
+        if steps.len() == TAIL_STEPS {
+          steps.pop_front();
+        }
+        steps.push_back(label);
+      }
+      None => {}
+    }
+    Ok(())
+  }
+  /// Whether the results of the builtin `name` are recorded and replayed.
+  pub(crate) fn is_nondeterministic(&self, name: &str) -> bool {
+    NONDETERMINISTIC.contains(&name) || self.natives.contains(name)
+  }
+  /// Calls a nondeterministic builtin while recording, or returns its recorded result while
+  /// replaying.
+  pub(crate) fn traced_builtin(&mut self, name: &str, args: &[Value]) -> Result<Value, String> {
+    match self.tracer.take() {
+      Some(Tracer::Record(mut trace)) => {
+        let result = self.call_builtin(name, args);
+        let recorded = result.as_ref().map(json::from_value).map_err(|e| e.to_owned());
+        trace.events.push(TraceEvent::Builtin {
+          name: name.to_owned(),
+          result: recorded,
+        });
+        self.tracer = Some(Tracer::Record(trace));
+        result
+      }
+      Some(Tracer::Replay { trace, position }) => {
+        let result = match trace.events.get(position) {
+          Some(TraceEvent::Builtin { name: recorded, result }) if recorded == name => {
+            result.as_ref().map_err(|e| e.to_owned()).and_then(json::to_value)
+          }
+          recorded => Err(diverged(position, recorded, &format!("builtin `{}`", name))),
+        };
+        self.tracer = Some(Tracer::Replay {
+          trace,
+          position: position + 1,
+        });
+        result
+      }
+      tracer => {
+        // Taken out while the builtin runs, or `process_builtin` would trace it again
+        let result = self.call_builtin(name, args);
+        self.tracer = tracer;
+        result
+      }
+    }
+  }
+  fn call_builtin(&mut self, name: &str, args: &[Value]) -> Result<Value, String> {
+    let unknown = || format!("Unknown builtin `{}`", name);
+    self.process_builtin(name, args).unwrap_or_else(|| Err(unknown()))
+  }
+}
+
+fn diverged(step: usize, recorded: Option<&TraceEvent>, evaluated: &str) -> String {
+  let recorded = match recorded {
+    Some(TraceEvent::Step(label)) => label.to_owned(),
+    Some(TraceEvent::Builtin { name, .. }) => format!("builtin `{}`", name),
+    None => "the end of the trace".to_owned(),
+  };
+  format!(
+    "Replay diverged at event {}: the trace has {}, the script evaluated {}",
+    step, recorded, evaluated
+  )
+}
@@ -0,0 +1,42 @@
+/// Which builtins an interpreter lets programs call, set with
+/// [`InterpreterBuilder::sandbox`](crate::InterpreterBuilder::sandbox).
+///
+/// The policy covers the native builtins, `import` and the prelude bindings: denied prelude
+/// bindings are removed when the interpreter is built, and denied builtins fail when called.
+///
+/// ```
+/// use nixt::{Interpreter, SandboxPolicy};
+/// let mut interpreter = Interpreter::builder()
+///   .sandbox(SandboxPolicy::deny(["import", "print"]))
+///   .build()?;
+/// assert!(interpreter.eval_str("(print 1)").is_err());
+/// assert!(interpreter.eval_str("(len (list 1))").is_ok());
+/// # Ok::<(), String>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum SandboxPolicy {
+  /// Every builtin can be called.
+  #[default]
+  AllowAll,
+  /// Only the listed builtins can be called.
+  Allow(Vec<String>),
+  /// Every builtin but the listed ones can be called.
+  Deny(Vec<String>),
+}
+
+impl SandboxPolicy {
+  pub fn allow<S: AsRef<str>>(names: impl IntoIterator<Item = S>) -> Self {
+    Self::Allow(names.into_iter().map(|n| n.as_ref().to_owned()).collect())
+  }
+  pub fn deny<S: AsRef<str>>(names: impl IntoIterator<Item = S>) -> Self {
+    Self::Deny(names.into_iter().map(|n| n.as_ref().to_owned()).collect())
+  }
+  /// Whether programs may use the builtin called `name`.
+  pub fn permits(&self, name: &str) -> bool {
+    match self {
+      Self::AllowAll => true,
+      Self::Allow(names) => names.iter().any(|n| n == name),
+      Self::Deny(names) => !names.iter().any(|n| n == name),
+    }
+  }
+}
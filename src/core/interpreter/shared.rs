@@ -0,0 +1,61 @@
+use crate::core::interpreter::interpreter::Interpreter;
+use crate::utils::element::Value;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// A read-only base environment shared by many interpreters, created once with
+/// [`InterpreterBuilder::freeze`](crate::InterpreterBuilder::freeze): the whole prelude, the
+/// host prelude sources and the host constants, evaluated up front.
+///
+/// Interpreters built on it with
+/// [`InterpreterBuilder::shared_env`](crate::InterpreterBuilder::shared_env) look its bindings
+/// up below their own prelude scope instead of evaluating the prelude again. Cloning it only
+/// clones an `Arc`, and it can be sent to other threads.
+///
+/// ```
+/// use nixt::Interpreter;
+/// let env = Interpreter::builder().define("LIMIT", nixt::Value::Number(3.0)).freeze()?;
+/// let mut first = Interpreter::builder().shared_env(&env).build()?;
+/// let mut second = Interpreter::builder().shared_env(&env).build()?;
+/// first.eval_str("(let x (* LIMIT 2))")?;
+/// assert_eq!(format!("{}", second.eval_str("(math:abs (- 0 LIMIT))")?), "3");
+/// assert!(second.get("x").is_none());
+/// # Ok::<(), String>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct SharedEnv {
+  pub(crate) bindings: Arc<BTreeMap<String, (Value, bool)>>,
+  /// Whether the standard prelude modules are part of the bindings.
+  pub(crate) prelude: bool,
+}
+
+impl SharedEnv {
+  /// The value bound to `name` in the environment.
+  pub fn get(&self, name: &str) -> Option<&Value> {
+    self.bindings.get(name).map(|(value, _)| value)
+  }
+  /// The names bound in the environment, sorted.
+  pub fn names(&self) -> impl Iterator<Item = &str> {
+    self.bindings.keys().map(String::as_str)
+  }
+  pub fn len(&self) -> usize {
+    self.bindings.len()
+  }
+  pub fn is_empty(&self) -> bool {
+    self.bindings.is_empty()
+  }
+}
+
+impl Interpreter {
+  /// The binding of `name` in the shared environment, if the sandbox lets programs use it.
+  pub(crate) fn shared_binding(&self, name: &str) -> Option<&(Value, bool)> {
+    let shared = self.shared.as_ref()?;
+    shared.bindings.get(name).filter(|_| self.sandbox.permits(name))
+  }
+  /// The value of `name` in the shared environment. Kept out of `get_value`, which nested
+  /// calls recurse through, to keep its stack frame small.
+  #[inline(never)]
+  pub(crate) fn shared_value(&self, name: &str) -> Option<Value> {
+    self.shared_binding(name).map(|(value, _)| value.clone())
+  }
+}
@@ -0,0 +1,145 @@
+use crate::core::interpreter::interpreter::Interpreter;
+use crate::stdlib;
+use crate::utils::element::{Map, Value};
+use crate::utils::json::Json;
+use std::io::Write;
+
+impl Interpreter {
+  /// The scope chain as JSON, outermost first: each scope's depth, kind (`prelude`, `globals`
+  /// or `local`) and bindings, sorted by name, with whether they are constant and their value.
+  ///
+  /// Numbers, strings, booleans, nil and lists are written as JSON; functions, enum variants
+  /// and ranges as objects with a `type` member.
+  ///
+  /// ```
+  /// let mut interpreter = nixt::Interpreter::builder().prelude(false).build()?;
+  /// interpreter.eval_str("(const xs (list 1 'a'))")?;
+  /// let state = interpreter.dump_state().to_string();
+  /// assert!(state.contains(r#"{"name":"xs","const":true,"value":[1,"a"]}"#));
+  /// # Ok::<(), String>(())
+  /// ```
+  pub fn dump_state(&self) -> Json {
+    let scopes = self.env.chain().into_iter().map(|scope| {
+      let depth = scope.depth();
+      let kind = scope_kind(depth);
+      let bindings = scope.bindings();
+      let bindings = bindings.iter().map(|(name, (value, is_const))| {
+        Json::Object(vec![
+          ("name".to_owned(), Json::Str(name.to_owned())),
+          ("const".to_owned(), Json::Bool(*is_const)),
+          ("value".to_owned(), state_value(value)),
+        ])
+      });
+      Json::Object(vec![
+        ("depth".to_owned(), Json::Number(depth as f64)),
+        ("kind".to_owned(), Json::Str(kind.to_owned())),
+        ("bindings".to_owned(), Json::Array(bindings.collect())),
+      ])
+    });
+    Json::Object(vec![("scopes".to_owned(), Json::Array(scopes.collect()))])
+  }
+  /// `(debug_dump)`: prints [`Interpreter::dump_state`] on a line.
+  pub(crate) fn process_debug_dump(&mut self, args: &[Value]) -> Result<Value, String> {
+    stdlib::args::ArgParser::new("debug_dump", args).exact(0)?;
+    let state = self.dump_state();
+    writeln!(self.out, "{}", state).map_err(|e| e.to_string())?;
+    Ok(Value::Nil)
+  }
+  /// The state `(introspect)` returns, as a map: `scopes`, the scope chain outermost first,
+  /// each with its `depth`, `kind` and `bindings` by name; `calls`, the names of the nixt
+  /// functions being called, outermost first; and `modules`, the prelude modules and files
+  /// loaded so far.
+  ///
+  /// Calls are only recorded with [`InterpreterBuilder::introspection`]; calls of a function
+  /// that is not bound to a name, such as `(sort_by (func ...) xs)`, are not listed.
+  ///
+  /// ```
+  /// let mut interpreter = nixt::Interpreter::builder().introspection(true).build()?;
+  /// let code = "(let f (func () {(ret (introspect))})) (let state (f)) (at state 'calls')";
+  /// assert_eq!(interpreter.eval_str(code)?.to_string(), "[f]");
+  /// assert!(interpreter.introspect().to_string().contains("modules: []"));
+  /// # Ok::<(), String>(())
+  /// ```
+  ///
+  /// [`InterpreterBuilder::introspection`]: crate::InterpreterBuilder::introspection
+  pub fn introspect(&self) -> Value {
+    let scopes = self.env.chain().into_iter().map(|scope| {
+      let depth = scope.depth();
+      let bindings = scope.bindings();
+      let bindings = bindings.iter().map(|(name, (value, _))| (name.to_owned(), value.clone()));
+      Value::Map(Map::from([
+        ("bindings".to_owned(), Value::Map(bindings.collect())),
+        ("depth".to_owned(), Value::Number(depth as f64)),
+        ("kind".to_owned(), Value::from(scope_kind(depth))),
+      ]))
+    });
+    Value::Map(Map::from([
+      ("calls".to_owned(), Value::list(self.calls.iter().map(String::as_str))),
+      ("modules".to_owned(), Value::list(self.modules.iter().map(String::as_str))),
+      ("scopes".to_owned(), Value::List(scopes.collect())),
+    ]))
+  }
+  /// `(introspect)`: [`Interpreter::introspect`], for programs with the debug capability.
+  pub(crate) fn process_introspect(&mut self, args: &[Value]) -> Result<Value, String> {
+    stdlib::args::ArgParser::new("introspect", args).exact(0)?;
+    if !self.introspection {
+      return Err(
+        "`introspect` needs the debug capability: run with `--debug`, or build the interpreter \
+         with `InterpreterBuilder::introspection`"
+          .to_owned(),
+      );
+    }
+    Ok(self.introspect())
+  }
+}
+
+fn scope_kind(depth: usize) -> &'static str {
+  match depth {
+    0 => "prelude",
+    1 => "globals",
+    _ => "local",
+  }
+}
+
+fn state_value(value: &Value) -> Json {
+  let typed = |typ: &str, mut members: Vec<(String, Json)>| {
+    members.insert(0, ("type".to_owned(), Json::Str(typ.to_owned())));
+    Json::Object(members)
+  };
+  match value {
+    Value::Nil => Json::Null,
+    Value::Bool(b) => Json::Bool(*b),
+    Value::Number(n) => Json::Number(*n),
+    Value::String(s) => Json::Str(s.to_owned()),
+    Value::List(items) => Json::Array(items.iter().map(state_value).collect()),
+    Value::Map(m) => Json::Object(m.iter().map(|(k, v)| (k.to_owned(), state_value(v))).collect()),
+    Value::Func(func) => {
+      let params = func.args.iter().map(|arg| Json::Str(arg.to_owned())).collect();
+      typed("func", vec![("params".to_owned(), Json::Array(params))])
+    }
+    Value::Ast(_) => typed("ast", vec![("source".to_owned(), Json::Str(value.to_string()))]),
+    Value::Native(name) => typed("builtin", vec![("name".to_owned(), Json::Str(name.to_owned()))]),
+    Value::Enum(name, variant) => typed(
+      "enum",
+      vec![
+        ("enum".to_owned(), Json::Str(name.to_owned())),
+        ("variant".to_owned(), Json::Str(variant.to_owned())),
+      ],
+    ),
+    Value::Range(start, end, step) => typed(
+      "range",
+      vec![
+        ("start".to_owned(), Json::Number(*start)),
+        ("end".to_owned(), Json::Number(*end)),
+        ("step".to_owned(), Json::Number(*step)),
+      ],
+    ),
+    Value::Resource(resource) => typed(
+      "resource",
+      vec![
+        ("kind".to_owned(), Json::Str(resource.kind().to_owned())),
+        ("closed".to_owned(), Json::Bool(resource.is_closed())),
+      ],
+    ),
+  }
+}
@@ -0,0 +1,126 @@
+use crate::core::interpreter::interpreter::Interpreter;
+use crate::utils::collections::{List, Map};
+use crate::utils::element::Value;
+use crate::utils::resource::Resource;
+use std::sync::Arc;
+
+/// A value on its way from one interpreter to another: on another thread, through an
+/// [`InterpreterPool`](crate::InterpreterPool), or from a request to the interpreter serving it.
+///
+/// [`Transfer::copy`] copies the value whole, so that neither interpreter sees what the other
+/// does with it. [`Transfer::frozen`] moves it behind an `Arc` instead, so that cloning the
+/// transfer to send it to many interpreters copies nothing. Each interpreter receiving it still
+/// gets a value of its own, cloned from the shared one, which copies its lists and maps unless
+/// the `persistent` feature is enabled.
+///
+/// Only values holding nothing of the interpreter they come from can be transferred. Functions
+/// defined in a local scope or an imported module keep that scope, which their interpreter still
+/// changes: they are refused, as are the resources of the host. Other functions go as their
+/// syntax tree, and see the globals of the interpreter receiving them. Builtins go by name, so
+/// that a builtin the host registered on one interpreter only is unknown to the others.
+///
+/// ```
+/// use nixt::{Interpreter, Transfer};
+/// let mut sender = Interpreter::builder().build()?;
+/// sender.eval_str("(let config {depth 3}) (let twice (func (x) { (ret (* x 2)) }))")?;
+/// let (config, twice) = (sender.send("config")?, sender.send("twice")?);
+/// let config = Transfer::frozen(config.into_value())?;
+/// let worker = std::thread::spawn(move || {
+///   let mut receiver = Interpreter::builder().build()?;
+///   receiver.receive("config", config)?;
+///   receiver.receive("twice", twice)?;
+///   receiver.eval_str("(twice (get config \"depth\"))")
+/// });
+/// assert_eq!(worker.join().unwrap()?.to_string(), "6");
+///
+/// sender.eval_str("(let make (func (n) { (ret (func () { (ret n) })) }))")?;
+/// sender.eval_str("(let closure (make 1))")?;
+/// assert!(sender.send("closure").is_err());
+/// # Ok::<(), String>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Transfer(Payload);
+
+#[derive(Debug, Clone)]
+enum Payload {
+  Copied(Value),
+  Frozen(Arc<Value>),
+}
+
+impl Transfer {
+  /// A deep copy of `value`, sharing nothing with it.
+  pub fn copy(value: &Value) -> Result<Self, String> {
+    Ok(Self(Payload::Copied(deep_copy(value)?)))
+  }
+  /// `value` behind an `Arc`, shared rather than copied by the clones of the transfer until they
+  /// are received.
+  pub fn frozen(value: Value) -> Result<Self, String> {
+    check(&value)?;
+    Ok(Self(Payload::Frozen(Arc::new(value))))
+  }
+  /// Whether the value is shared by the clones of the transfer.
+  pub fn is_frozen(&self) -> bool {
+    matches!(self.0, Payload::Frozen(_))
+  }
+  /// The value, without taking it.
+  pub fn value(&self) -> &Value {
+    match &self.0 {
+      Payload::Copied(value) => value,
+      Payload::Frozen(value) => value,
+    }
+  }
+  /// The value, for the interpreter receiving it. A frozen value is cloned unless this is the
+  /// last transfer sharing it.
+  pub fn into_value(self) -> Value {
+    match self.0 {
+      Payload::Copied(value) => value,
+      Payload::Frozen(value) => Arc::unwrap_or_clone(value),
+    }
+  }
+}
+
+/// Fails if `value` holds something tied to the interpreter it comes from.
+pub(crate) fn check(value: &Value) -> Result<(), String> {
+  match value {
+    Value::Func(func) if func.env.is_some() => Err(local_function()),
+    Value::Resource(resource) => Err(host_resource(resource)),
+    Value::List(items) => items.iter().try_for_each(check),
+    Value::Map(entries) => entries.iter().try_for_each(|(_, value)| check(value)),
+    _ => Ok(()),
+  }
+}
+
+/// A copy of `value` sharing none of its collections, failing as [`check`] does.
+fn deep_copy(value: &Value) -> Result<Value, String> {
+  Ok(match value {
+    Value::Func(func) if func.env.is_some() => return Err(local_function()),
+    Value::Resource(resource) => return Err(host_resource(resource)),
+    Value::List(items) => Value::List(items.iter().map(deep_copy).collect::<Result<List, _>>()?),
+    Value::Map(entries) => {
+      let entries = entries.iter().map(|(key, value)| Ok((key.clone(), deep_copy(value)?)));
+      Value::Map(entries.collect::<Result<Map, String>>()?)
+    }
+    other => other.clone(),
+  })
+}
+
+fn local_function() -> String {
+  "Can not transfer a function defined in a local scope, as it keeps that scope".to_owned()
+}
+
+fn host_resource(resource: &Resource) -> String {
+  format!("Can not transfer the {} resource, which its host gave one interpreter", resource.kind())
+}
+
+impl Interpreter {
+  /// A copy of the value of `name` for another interpreter to [`Interpreter::receive`].
+  pub fn send(&mut self, name: &str) -> Result<Transfer, String> {
+    let value = self.get(name).ok_or_else(|| format!("`{}` is not defined", name))?;
+    Transfer::copy(&value).map_err(|e| format!("{}: `{}`", e, name))
+  }
+  /// Binds the transferred value to `name`, as a constant of the global scope like
+  /// [`Interpreter::define`] does.
+  pub fn receive(&mut self, name: &str, transfer: Transfer) -> Result<(), String> {
+    self.define(name, transfer.into_value())
+  }
+}
@@ -0,0 +1,69 @@
+use crate::core::interpreter::interpreter::Interpreter;
+use crate::testing::show;
+use crate::utils::node::Node;
+use std::io::Write;
+use std::time::Instant;
+
+/// What an interpreter built with [`InterpreterBuilder::verbose`] writes as it evaluates.
+///
+/// [`InterpreterBuilder::verbose`]: crate::InterpreterBuilder::verbose
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Verbosity {
+  /// Every statement and top-level expression, before it is evaluated: `eval: (print x)`.
+  pub expressions: bool,
+  /// The bindings of the innermost scope after each top-level form: `scope: x = 1, y = "a"`.
+  pub scopes: bool,
+  /// How long each top-level form took: `took 1.2ms: (fib 20)`.
+  pub timing: bool,
+}
+
+impl Verbosity {
+  /// Everything: the expressions, the scopes and the timing.
+  pub fn all() -> Self {
+    Self {
+      expressions: true,
+      scopes: true,
+      timing: true,
+    }
+  }
+}
+
+/// Where the verbose mode writes, and what.
+pub(crate) struct Verbose {
+  pub(crate) verbosity: Verbosity,
+  pub(crate) out: Box<dyn Write>,
+}
+
+impl Interpreter {
+  /// Writes `statement` before it is evaluated, if the expressions are traced. Failing to
+  /// write does not stop the program.
+  #[inline(never)]
+  pub(crate) fn verbose_statement(&mut self, statement: &Node) {
+    if let Some(verbose) = self.verbose.as_mut().filter(|v| v.verbosity.expressions) {
+      writeln!(verbose.out, "eval: ({})", statement).ok();
+    }
+  }
+  /// Writes how long the top-level `form` took since `started`, and the bindings it leaves.
+  #[inline(never)]
+  pub(crate) fn verbose_form(&mut self, form: &Node, started: Instant) {
+    let elapsed = started.elapsed();
+    let scope = match &self.verbose {
+      Some(verbose) if verbose.verbosity.scopes => {
+        let scope = self.env.bindings();
+        let bindings = scope.iter().map(|(name, (value, _))| format!("{} = {}", name, show(value)));
+        Some(bindings.collect::<Vec<_>>().join(", "))
+      }
+      _ => None,
+    };
+    let verbose = match self.verbose.as_mut() {
+      Some(verbose) => verbose,
+      None => return,
+    };
+    if verbose.verbosity.timing {
+      writeln!(verbose.out, "took {:.1?}: {}", elapsed, form).ok();
+    }
+    if let Some(scope) = scope {
+      writeln!(verbose.out, "scope: {}", scope).ok();
+    }
+  }
+}
@@ -0,0 +1,87 @@
+use crate::core::interpreter::interpreter::Interpreter;
+use crate::utils::element::Value;
+use std::collections::BTreeSet;
+use std::io::Write;
+
+/// A definition or assignment of a watched binding.
+#[derive(Debug, Clone)]
+pub struct WatchHit {
+  pub name: String,
+  /// The value before an assignment; `None` for a definition.
+  pub old: Option<Value>,
+  pub new: Value,
+  /// The `let`, `const` or `set` form, as `(set x (+ x 1))`.
+  pub location: String,
+}
+
+/// `` `x` changed from 1 to 2 at (set x (+ x 1)) ``
+impl std::fmt::Display for WatchHit {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    match &self.old {
+      Some(old) => write!(f, "`{}` changed from {} to {}", self.name, old, self.new)?,
+      None => write!(f, "`{}` defined as {}", self.name, self.new)?,
+    }
+    write!(f, " at {}", self.location)
+  }
+}
+
+type WatchHandler = Box<dyn FnMut(&WatchHit) -> Result<(), String>>;
+
+/// The watched bindings, and what runs when one of them changes.
+#[derive(Default)]
+pub(crate) struct Watches {
+  names: BTreeSet<String>,
+  handler: Option<WatchHandler>,
+}
+
+impl Watches {
+  pub(crate) fn contains(&self, name: &str) -> bool {
+    self.names.contains(name)
+  }
+  pub(crate) fn is_empty(&self) -> bool {
+    self.names.is_empty()
+  }
+}
+
+impl Interpreter {
+  /// Pauses the evaluation whenever a binding named `name` is defined or assigned, to run the
+  /// handler set with [`Interpreter::on_watch`]. Without a handler, the change is written to
+  /// stderr.
+  ///
+  /// ```
+  /// use std::{cell::RefCell, rc::Rc};
+  /// let mut interpreter = nixt::Interpreter::builder().build()?;
+  /// let hits = Rc::new(RefCell::new(vec![]));
+  /// let seen = hits.clone();
+  /// interpreter.watch("x");
+  /// interpreter.on_watch(move |hit| {
+  ///   seen.borrow_mut().push(hit.to_string());
+  ///   Ok(())
+  /// });
+  /// interpreter.eval_str("(let x 1) (let y 2) (set x (+ x y))")?;
+  /// assert_eq!(hits.borrow()[1], "`x` changed from 1 to 3 at (set x (+ x y))");
+  /// # Ok::<(), String>(())
+  /// ```
+  pub fn watch(&mut self, name: &str) {
+    self.watches.names.insert(name.to_owned());
+  }
+  /// Stops watching `name`, returning whether it was watched.
+  pub fn unwatch(&mut self, name: &str) -> bool {
+    self.watches.names.remove(name)
+  }
+  /// The names being watched, sorted.
+  pub fn watched(&self) -> impl Iterator<Item = &str> {
+    self.watches.names.iter().map(|name| name.as_str())
+  }
+  /// Sets what runs when a watched binding changes, before the evaluation goes on. Returning an
+  /// error stops the evaluation with it.
+  pub fn on_watch(&mut self, handler: impl FnMut(&WatchHit) -> Result<(), String> + 'static) {
+    self.watches.handler = Some(Box::new(handler));
+  }
+  pub(crate) fn hit_watchpoint(&mut self, hit: WatchHit) -> Result<(), String> {
+    match self.watches.handler.as_mut() {
+      Some(handler) => handler(&hit),
+      None => writeln!(self.err, "Watchpoint: {}", hit).map_err(|e| e.to_string()),
+    }
+  }
+}
@@ -1,42 +1,86 @@
+use crate::error::Diagnostic;
+use crate::utils::trace::trace_phase;
 use crate::{utils::token::TokenType::*, utils::token::*};
 use std::collections::BTreeMap;
 
 #[derive(Clone)]
 pub struct Lexer {
-    source: String,
+    source: Vec<char>,
+    /// The byte offset of each character of the source, then the length of the source.
+    offsets: Vec<usize>,
     tokens: Vec<Token>,
     start: usize,
     current: usize,
     line: usize,
-    errors: Vec<String>,
+    errors: Vec<Diagnostic>,
     keywords: BTreeMap<String, TokenType>,
+    case_insensitive: bool,
+    folded_keywords: Vec<usize>,
+    trivia: bool,
+    pending_trivia: Vec<Token>,
+    max_errors: usize,
+}
+
+/// The keyword table used by `Lexer::new`.
+pub fn default_keywords() -> BTreeMap<String, TokenType> {
+    let mut keywords = BTreeMap::new();
+    keywords.insert("func".to_owned(), Func);
+    keywords.insert("if".to_owned(), If);
+    keywords.insert("nil".to_owned(), Nil);
+    keywords.insert("or".to_owned(), Or);
+    keywords.insert("ret".to_owned(), Return);
+    keywords.insert("true".to_owned(), True);
+    keywords.insert("false".to_owned(), False);
+    keywords.insert("while".to_owned(), While);
+    keywords.insert("let".to_owned(), Let);
+    keywords.insert("const".to_owned(), Const);
+    keywords.insert("set".to_owned(), Set);
+    keywords.insert("and".to_owned(), And);
+    keywords.insert("do".to_owned(), Do);
+    keywords.insert("cond".to_owned(), Cond);
+    keywords.insert("else".to_owned(), Else);
+    keywords.insert("pub".to_owned(), Pub);
+    keywords.insert("enum".to_owned(), Enum);
+    keywords
 }
 
 impl Lexer {
     pub fn new(source: &str) -> Self {
-        let mut keywords = BTreeMap::new();
-        keywords.insert("func".to_owned(), Func);
-        keywords.insert("if".to_owned(), If);
-        keywords.insert("nil".to_owned(), Nil);
-        keywords.insert("or".to_owned(), Or);
-        keywords.insert("ret".to_owned(), Return);
-        keywords.insert("true".to_owned(), True);
-        keywords.insert("false".to_owned(), False);
-        keywords.insert("while".to_owned(), While);
-        keywords.insert("let".to_owned(), Let);
-        keywords.insert("const".to_owned(), Const);
-        keywords.insert("set".to_owned(), Set);
-        keywords.insert("and".to_owned(), And);
+        Self::with_keywords(source, default_keywords())
+    }
+    /// Lexes `source` with a custom keyword table, e.g. mapping `var` to `Let`.
+    pub fn with_keywords(source: &str, keywords: BTreeMap<String, TokenType>) -> Self {
+        let offsets = source.char_indices().map(|(i, _)| i);
         Self {
-            source: source.to_owned(),
+            source: source.chars().collect(),
+            offsets: offsets.chain(std::iter::once(source.len())).collect(),
             tokens: vec![],
             start: 0,
             current: 0,
             line: 1,
             errors: vec![],
-            keywords: keywords,
+            keywords,
+            case_insensitive: false,
+            folded_keywords: vec![],
+            trivia: false,
+            pending_trivia: vec![],
+            max_errors: usize::MAX,
         }
     }
+    /// Keeps comments as `Comment` tokens attached to the `trivia` of the token following
+    /// them, for comment-aware tooling. The parser does not see them either way.
+    pub fn set_trivia(&mut self, trivia: bool) {
+        self.trivia = trivia;
+    }
+    /// Matches keywords regardless of case (`LET`, `Func`), warning when it was needed.
+    pub fn set_case_insensitive(&mut self, case_insensitive: bool) {
+        self.case_insensitive = case_insensitive;
+    }
+    /// Keeps only the first `max_errors` errors for [`Lexer::get_errors`]; the others are
+    /// counted by [`Lexer::suppressed_errors`].
+    pub fn set_max_errors(&mut self, max_errors: usize) {
+        self.max_errors = max_errors;
+    }
     fn is_at_end(&self) -> bool {
         self.current >= self.source.len()
     }
@@ -48,10 +92,18 @@ impl Lexer {
             '}' => self.add_token(RightBrace),
             '(' => self.add_token(LeftParen),
             ')' => self.add_token(RightParen),
+            '[' => self.add_token(LeftBracket),
+            ']' => self.add_token(RightBracket),
             ',' => self.add_token(Comma),
-            '.' => self.add_token(Dot),
+            '.' => {
+                if self.match_('.') {
+                    self.add_token(DotDot);
+                } else {
+                    self.add_token(Dot);
+                }
+            }
             '-' => {
-                if self.peek().is_digit(10) {
+                if self.peek().is_ascii_digit() {
                     self.number();
                 } else {
                     self.add_token(Minus);
@@ -63,6 +115,13 @@ impl Lexer {
             '~' => {
                 self.add_token(Tilde);
             }
+            '?' => {
+                if self.match_('?') {
+                    self.add_token(QuestionQuestion);
+                } else {
+                    self.unexpected(c);
+                }
+            }
             '=' => {
                 self.add_token(Equal);
             }
@@ -84,6 +143,7 @@ impl Lexer {
                 while self.peek() != '\n' && !self.is_at_end() {
                     self.advance();
                 }
+                self.add_trivia(self.start + 1, self.current, self.line);
             }
             '%' => {
                 if self.match_('%') {
@@ -97,61 +157,91 @@ impl Lexer {
             '\'' => self.string('\''),
             '\n' => self.line += 1,
             _ => {
-                if c.is_digit(10) {
+                if c.is_ascii_digit() {
                     self.number();
-                } else if is_identifier_allowed(c) {
+                } else if is_identifier_start(c) {
                     self.identifier();
                 } else {
-                    self.errors
-                        .push(format!("{} | Unexpected character: {}", self.line, c));
+                    self.unexpected(c);
                 }
             }
         }
     }
     fn multi_line_comment(&mut self) {
-        while self.peek() != '%' && self.peek_next() != '%' && !self.is_at_end() {
+        let line = self.line;
+        while !self.is_at_end() && (self.peek() != '%' || self.peek_next() != '%') {
             if self.peek() == '\n' {
                 self.line += 1;
             }
             self.advance();
         }
+        if self.is_at_end() {
+            self.errors
+                .push(Diagnostic::new(line, "Unterminated block comment"));
+            return;
+        }
         self.advance(); // consume %
         self.advance(); // consume %
+        self.add_trivia(self.start + 2, self.current - 2, line);
+    }
+    fn add_trivia(&mut self, start: usize, end: usize, line: usize) {
+        if self.trivia {
+            let text = self.slice(start, end);
+            let lexeme = self.slice(self.start, self.current);
+            let mut comment = Token::new(Comment(text), lexeme, line);
+            comment.span = self.span();
+            self.pending_trivia.push(comment);
+        }
     }
     fn identifier(&mut self) {
         while is_identifier_allowed(self.peek()) {
             self.advance();
         }
-        let copied = self.clone();
-        let text = &copied.source[self.start..self.current];
+        // `?` may end an identifier (`get?`), but neither start nor split one, so `??` stays an
+        // operator and `a?b` is not a name
+        let next = self.peek_next();
+        if self.peek() == '?' && !is_identifier_allowed(next) && next != '?' {
+            self.advance();
+        }
+        let text = self.slice(self.start, self.current);
 
-        if copied.is_keyword(&text).is_none() {
-            self.add_token(Identifier(text.to_owned()));
-        } else {
-            self.add_token(copied.is_keyword(&text).unwrap()); // safe because checked above
+        match self.is_keyword(&text) {
+            Some(keyword) => self.add_token(keyword),
+            None => self.add_token(Identifier(text)),
         }
     }
-    fn is_keyword(&self, word: &str) -> Option<TokenType> {
-        if !self.keywords.contains_key(word) {
+    fn is_keyword(&mut self, word: &str) -> Option<TokenType> {
+        if let Some(keyword) = self.keywords.get(word) {
+            return Some(keyword.clone());
+        }
+        if !self.case_insensitive {
             return None;
-        } else {
-            return Some(self.keywords[word].clone());
         }
+        let keyword = self
+            .keywords
+            .iter()
+            .find(|(k, _)| k.to_lowercase() == word.to_lowercase())
+            .map(|(_, typ)| typ.clone());
+        if keyword.is_some() {
+            self.folded_keywords.push(self.line);
+        }
+        keyword
     }
     fn number(&mut self) {
-        while self.peek().is_digit(10) {
+        while self.peek().is_ascii_digit() {
             self.advance();
         }
 
-        if self.peek() == '.' && self.peek_next().is_digit(10) {
+        if self.peek() == '.' && self.peek_next().is_ascii_digit() {
             self.advance();
-            while self.peek().is_digit(10) {
+            while self.peek().is_ascii_digit() {
                 self.advance();
             }
         }
 
-        let num = self.source[self.start..self.current]
-            .parse::<f32>()
+        let num = self
+            .slice(self.start, self.current)
+            .parse::<f64>()
             .unwrap_or(-1.);
         self.add_token(Number(num));
     }
@@ -164,37 +254,70 @@ impl Lexer {
         }
         if self.is_at_end() {
             self.errors
-                .push(format!("{} | Unterminated string", self.line));
+                .push(Diagnostic::new(self.line, "Unterminated string"));
             return;
         }
         self.advance(); // Consume closing character
-        let value = (&self.source[self.start + 1..self.current - 1]).to_owned();
+        let value = self.slice(self.start + 1, self.current - 1);
         self.add_token(Str(value));
     }
+    fn slice(&self, start: usize, end: usize) -> String {
+        self.source[start..end].iter().collect()
+    }
     fn peek(&self) -> char {
         if self.is_at_end() {
             return '\0';
         }
-        self.source.chars().collect::<Vec<char>>()[self.current]
+        self.source[self.current]
     }
     fn peek_next(&self) -> char {
         if self.current + 1 >= self.source.len() {
             return '\0';
         }
-        self.source.chars().collect::<Vec<char>>()[self.current + 1]
+        self.source[self.current + 1]
+    }
+
+    fn unexpected(&mut self, c: char) {
+        let line_start = self.source[..self.start].iter().rposition(|c| *c == '\n');
+        let mut error = Diagnostic::new(self.line, format!("Unexpected character: {}", c));
+        error.column = Some(self.start - line_start.map_or(0, |i| i + 1) + 1);
+        error.span = Some(self.span());
+        self.errors.push(error);
     }
 
     pub fn get_errors(&self) -> Option<Vec<String>> {
         if self.errors.is_empty() {
             return None;
         }
-        Some(self.errors.clone())
+        let errors = self.errors.iter().take(self.max_errors);
+        Some(
+            errors
+                .map(|error| format!("{} | {}", error.line, error.message))
+                .collect(),
+        )
+    }
+    /// Every error, with its location.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.errors
+    }
+    /// The number of errors left out of [`Lexer::get_errors`] by [`Lexer::set_max_errors`].
+    pub fn suppressed_errors(&self) -> usize {
+        self.errors.len().saturating_sub(self.max_errors)
+    }
+    pub fn get_warnings(&self) -> Option<Vec<String>> {
+        self.folded_keywords.first().map(|line| {
+            vec![format!(
+                "Line {} | Matched {} keyword(s) case-insensitively",
+                line,
+                self.folded_keywords.len()
+            )]
+        })
     }
     fn match_(&mut self, expected: char) -> bool {
         if self.is_at_end() {
             return false;
         }
-        if self.source.chars().collect::<Vec<char>>()[self.current] != expected {
+        if self.source[self.current] != expected {
             return false;
         }
 
@@ -203,23 +326,42 @@ impl Lexer {
     }
     fn advance(&mut self) -> char {
         self.current += 1;
-        self.source.chars().collect::<Vec<char>>()[self.current - 1]
+        self.source[self.current - 1]
+    }
+    /// Where the lexeme being scanned is in the source.
+    fn span(&self) -> Span {
+        Span {
+            start: self.offsets[self.start],
+            end: self.offsets[self.current],
+        }
     }
     fn add_token(&mut self, typ: TokenType) {
-        let text = (&self.source[self.start..self.current]).to_owned();
-        self.tokens.push(Token::new(typ, text, self.line));
+        let text = self.slice(self.start, self.current);
+        let mut token = Token::new(typ, text, self.line);
+        token.span = self.span();
+        token.trivia = std::mem::take(&mut self.pending_trivia);
+        self.tokens.push(token);
     }
     pub fn scan_tokens(&mut self) -> Vec<Token> {
+        trace_phase!("lex", chars = self.source.len());
         while !self.is_at_end() {
             self.start = self.current;
             self.scan_token();
         }
-        self.tokens
-            .push(Token::new(TokenType::Eof, "".to_owned(), self.line));
+        self.start = self.current;
+        let mut eof = Token::new(TokenType::Eof, "".to_owned(), self.line);
+        eof.span = self.span();
+        eof.trivia = std::mem::take(&mut self.pending_trivia);
+        self.tokens.push(eof);
         self.tokens.clone()
     }
 }
 
+/// `:` is reserved for namespacing (`math:cos`) and can not start an identifier.
+fn is_identifier_start(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
 fn is_identifier_allowed(c: char) -> bool {
     c.is_alphanumeric() || c == '_' || c == ':'
 }
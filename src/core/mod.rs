@@ -1,3 +1,4 @@
+pub mod grammar;
 pub mod interpreter;
 pub mod lexer;
 pub mod parser;
@@ -1,6 +1,8 @@
+use crate::error::Diagnostic;
 use crate::utils::{
     node::{NodeType::*, *},
     token::{TokenType::*, *},
+    trace::trace_phase,
 };
 
 #[derive(Debug)]
@@ -8,29 +10,78 @@ pub struct Parser {
     tokens: Vec<Token>,
     ast: Node,
     current: usize,
-    errors: Vec<String>,
+    errors: Vec<Diagnostic>,
+    warnings: Vec<String>,
     line: usize,
+    /// The span of the last token consumed.
+    span: Span,
+    strict: bool,
+    infix: bool,
+    lenient: bool,
+    tolerated_commas: Vec<usize>,
+    max_errors: usize,
 }
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
+        let tokens = tokens
+            .into_iter()
+            .filter(|tok| !matches!(tok.typ, Comment(_)))
+            .collect();
         Self {
             tokens,
             ast: Node::new(Block),
             current: 0,
             errors: vec![],
+            warnings: vec![],
             line: 1,
+            span: Span::default(),
+            strict: false,
+            infix: false,
+            lenient: false,
+            tolerated_commas: vec![],
+            max_errors: usize::MAX,
         }
     }
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+    /// Accepts `let x = 1 + 2 * 3` style assignments and calls such as `f(a, b)`
+    /// alongside the usual prefix forms. Infix expressions lower to the same AST.
+    pub fn set_infix(&mut self, infix: bool) {
+        self.infix = infix;
+    }
+    /// Tolerates commas between elements, `(list 1, 2, 3)`, warning when one was skipped.
+    pub fn set_lenient(&mut self, lenient: bool) {
+        self.lenient = lenient;
+    }
+    /// Keeps only the first `max_errors` errors for [`Parser::get_errors`]; the others are
+    /// counted by [`Parser::suppressed_errors`].
+    pub fn set_max_errors(&mut self, max_errors: usize) {
+        self.max_errors = max_errors;
+    }
     fn advance(&mut self) -> Token {
         self.current += 1;
         let toret = self.tokens[self.current - 1].clone();
         self.line = toret.line;
+        self.span = toret.span;
         toret
     }
+    /// A node read from the token just consumed.
+    fn leaf(&self, typ: NodeType) -> Node {
+        let mut node = Node::new(typ);
+        node.set_span(Some(self.span));
+        node
+    }
+    /// `node`, spanning from `start` to the token just consumed.
+    fn spanned(&self, mut node: Node, start: Span) -> Node {
+        node.set_span(Some(start.to(self.span)));
+        node
+    }
     fn is_at_end(&self) -> bool {
         self.current >= self.tokens.len() || self.tokens[self.current].typ == Eof
     }
     fn parse_block(&mut self, ast: bool) -> Node {
+        let start = self.span;
         let mut toret = Node::new(Block);
 
         loop {
@@ -42,32 +93,45 @@ impl Parser {
             }
             let current = self.advance();
             let to_add = match current.typ {
+                Comma if self.lenient => {
+                    self.tolerated_commas.push(self.line);
+                    continue;
+                }
                 TokenType::Return => self.parse_return(),
                 If => self.parse_condition(),
                 While => self.parse_loop(),
                 LeftBrace => self.parse_scope(false),
                 LeftParen => self.parse_block(false),
                 Let | Const | Set => self.parse_assignement(&current.typ),
+                Pub => self.parse_export(),
+                TokenType::Enum => self.parse_enum(),
                 Plus | Minus | Star | Slash | Less | LessEqual | And | Or | Tilde | Equal
-                | Greater | Percent | GreaterEqual => self.parse_op(&current.typ),
+                | Greater | Percent | GreaterEqual | QuestionQuestion => self.parse_op(&current.typ),
                 TokenType::Func => self.parse_func(),
+                TokenType::Do => self.parse_do(),
+                TokenType::Cond => self.parse_cond(),
                 Identifier(s) => self.function_call(s),
                 _ => {
-                    self.errors.push(format!(
-                        "Line {} | Found an invalid token in block parsing: `{}`",
-                        self.line, current.lexeme,
-                    ));
+                    self.error(
+                        self.line,
+                        format!(
+                            "Found an invalid token in block parsing: `{}`",
+                            current.lexeme
+                        ),
+                    );
                     Node::new(None)
                 }
             };
             toret.add_children(&to_add);
         }
+        let toret = self.spanned(toret, start);
         if ast {
             self.ast.add_children(&toret);
         }
         toret
     }
     fn parse_scope(&mut self, ast: bool) -> Node {
+        let start = self.span;
         let mut toret = Node::new(Scope);
 
         loop {
@@ -79,26 +143,38 @@ impl Parser {
             }
             let current = self.advance();
             let to_add = match current.typ {
+                Comma if self.lenient => {
+                    self.tolerated_commas.push(self.line);
+                    continue;
+                }
                 TokenType::Return => self.parse_return(),
                 If => self.parse_condition(),
                 While => self.parse_loop(),
                 LeftParen => self.parse_block(false),
                 LeftBrace => self.parse_scope(false),
                 Let | Const | Set => self.parse_assignement(&current.typ),
+                Pub => self.parse_export(),
+                TokenType::Enum => self.parse_enum(),
                 Plus | Minus | Star | Slash | Less | LessEqual | And | Or | Tilde | Equal
-                | Greater | GreaterEqual => self.parse_op(&current.typ),
+                | Greater | GreaterEqual | QuestionQuestion => self.parse_op(&current.typ),
                 TokenType::Func => self.parse_func(),
+                TokenType::Do => self.parse_do(),
+                TokenType::Cond => self.parse_cond(),
                 Identifier(s) => self.function_call(s),
                 _ => {
-                    self.errors.push(format!(
-                        "Line {} | Found an invalid token in block parsing: `{}`",
-                        self.line, current.lexeme,
-                    ));
+                    self.error(
+                        self.line,
+                        format!(
+                            "Found an invalid token in block parsing: `{}`",
+                            current.lexeme
+                        ),
+                    );
                     Node::new(None)
                 }
             };
             toret.add_children(&to_add);
         }
+        let toret = self.spanned(toret, start);
         if ast {
             self.ast.add_children(&toret);
         }
@@ -107,7 +183,7 @@ impl Parser {
     }
 
     fn function_call(&mut self, s: String) -> Node {
-        let mut master = Node::new(FunctionCall(s));
+        let mut master = self.leaf(FunctionCall(s));
         let mut args: Vec<Node> = vec![];
 
         loop {
@@ -117,21 +193,31 @@ impl Parser {
             let current = self.advance();
 
             let to_add = match &current.typ {
-                Identifier(s) => Node::new(NodeIdentifier(s.to_owned())),
-                Str(s) => Node::new(NodeStr(s.to_owned())),
-                Number(f) => Node::new(NodeNumber(*f)),
+                Comma if self.lenient => {
+                    self.tolerated_commas.push(self.line);
+                    continue;
+                }
+                Identifier(s) => self.leaf(NodeIdentifier(s.to_owned())),
+                Str(s) => self.leaf(NodeStr(s.to_owned())),
+                Number(f) => self.leaf(NodeNumber(*f)),
                 LeftParen => self.parse_block(false),
-                True => Node::new(NodeBool(true)),
-                False => Node::new(NodeBool(false)),
+                LeftBracket => self.parse_list(),
+                LeftBrace => self.parse_map(),
+                True => self.leaf(NodeBool(true)),
+                False => self.leaf(NodeBool(false)),
                 Nil => Node::new(None),
                 _ => {
-                    self.errors.push(format!(
-                        "Line {} | Found an invalid token in function call: `{}`",
-                        self.line, current.lexeme,
-                    ));
+                    self.error(
+                        self.line,
+                        format!(
+                            "Found an invalid token in function call: `{}`",
+                            current.lexeme
+                        ),
+                    );
                     Node::new(None)
                 }
             };
+            let to_add = self.range_literal(to_add);
             args.push(to_add);
         }
         for arg in args {
@@ -139,25 +225,265 @@ impl Parser {
         }
         master
     }
+    /// `[a b c]`, read as `(list a b c)`.
+    fn parse_list(&mut self) -> Node {
+        let start = self.span;
+        let mut master = Node::new(FunctionCall("list".to_owned()));
+
+        loop {
+            match self.peek().map(|t| t.typ) {
+                Some(RightBracket) => {
+                    self.advance();
+                    break;
+                }
+                Option::None | Some(Eof) => {
+                    self.error(self.line, "Expected `]` to close the list");
+                    break;
+                }
+                _ => {}
+            }
+            let current = self.advance();
+
+            let element = match current.typ {
+                Comma if self.lenient => {
+                    self.tolerated_commas.push(self.line);
+                    continue;
+                }
+                Identifier(s) => self.leaf(NodeIdentifier(s)),
+                Str(s) => self.leaf(NodeStr(s)),
+                Number(f) => self.leaf(NodeNumber(f)),
+                LeftParen => self.parse_block(false),
+                LeftBracket => self.parse_list(),
+                LeftBrace => self.parse_map(),
+                True => self.leaf(NodeBool(true)),
+                False => self.leaf(NodeBool(false)),
+                Nil => Node::new(None),
+                _ => {
+                    self.error(
+                        self.line,
+                        format!("Found an invalid token in list: `{}`", current.lexeme),
+                    );
+                    Node::new(None)
+                }
+            };
+            let element = self.range_literal(element);
+            master.add_children(&element);
+        }
+        let mut block = Node::new(Block);
+        block.add_children(&master);
+        self.spanned(block, start)
+    }
+    /// `{name value "other key" value}`, read as `(map "name" value "other key" value)`. A key is
+    /// a name, taken as a string, or a string.
+    fn parse_map(&mut self) -> Node {
+        let start = self.span;
+        let mut master = Node::new(FunctionCall("map".to_owned()));
+        let mut key = true;
+
+        loop {
+            match self.peek().map(|t| t.typ) {
+                Some(RightBrace) => {
+                    self.advance();
+                    break;
+                }
+                Option::None | Some(Eof) => {
+                    self.error(self.line, "Expected `}` to close the map");
+                    break;
+                }
+                _ => {}
+            }
+            let current = self.advance();
+
+            let element = match current.typ {
+                Comma if self.lenient => {
+                    self.tolerated_commas.push(self.line);
+                    continue;
+                }
+                Identifier(s) | Str(s) if key => self.leaf(NodeStr(s)),
+                _ if key => {
+                    self.error_spanning(
+                        self.line,
+                        current.span,
+                        format!(
+                            "Found an invalid map key: `{}`; keys are names or strings",
+                            current.lexeme
+                        ),
+                    );
+                    Node::new(None)
+                }
+                Identifier(s) => self.leaf(NodeIdentifier(s)),
+                Str(s) => self.leaf(NodeStr(s)),
+                Number(f) => self.leaf(NodeNumber(f)),
+                LeftParen => self.parse_block(false),
+                LeftBracket => self.parse_list(),
+                LeftBrace => self.parse_map(),
+                True => self.leaf(NodeBool(true)),
+                False => self.leaf(NodeBool(false)),
+                Nil => Node::new(None),
+                _ => {
+                    self.error(
+                        self.line,
+                        format!("Found an invalid token in map: `{}`", current.lexeme),
+                    );
+                    Node::new(None)
+                }
+            };
+            let element = if key { element } else { self.range_literal(element) };
+            master.add_children(&element);
+            key = !key;
+        }
+        if !key {
+            self.error_spanning(self.line, start.to(self.span), "Found a map key without a value");
+        }
+        let mut block = Node::new(Block);
+        block.add_children(&master);
+        self.spanned(block, start)
+    }
+    /// Turns `start..end`, with numbers or identifiers as bounds, into `(range start end)`.
+    fn range_literal(&mut self, start: Node) -> Node {
+        let is_bound = |node: &Node| matches!(node.get_type(), NodeNumber(_) | NodeIdentifier(_));
+        if !is_bound(&start) || self.peek().map(|t| t.typ) != Some(DotDot) {
+            return start;
+        }
+        self.advance();
+        let end_tok = self.advance();
+        let end = match end_tok.typ {
+            Number(f) => self.leaf(NodeNumber(f)),
+            Identifier(s) => self.leaf(NodeIdentifier(s)),
+            _ => {
+                self.error(
+                    self.line,
+                    format!("Found invalid range end: `{}`", end_tok.lexeme),
+                );
+                Node::new(None)
+            }
+        };
+        let mut range = Node::new(FunctionCall("range".to_owned()));
+        range.add_children(&start);
+        range.add_children(&end);
+        wrap_expression(range)
+    }
+    fn parse_do(&mut self) -> Node {
+        let mut master = Node::new(NodeType::Do);
+
+        loop {
+            if self.is_at_end() || self.peek().unwrap().typ == RightParen {
+                break;
+            }
+            let current = self.advance();
+
+            let to_add = match current.typ {
+                Comma if self.lenient => {
+                    self.tolerated_commas.push(self.line);
+                    continue;
+                }
+                LeftParen => self.parse_block(false),
+                LeftBracket => self.parse_list(),
+                LeftBrace => self.parse_scope(false),
+                Identifier(s) => self.leaf(NodeIdentifier(s)),
+                Str(s) => self.leaf(NodeStr(s)),
+                Number(f) => self.leaf(NodeNumber(f)),
+                True => self.leaf(NodeBool(true)),
+                False => self.leaf(NodeBool(false)),
+                Nil => Node::new(None),
+                _ => {
+                    self.error(
+                        self.line,
+                        format!(
+                            "Found an invalid token in do expression: `{}`",
+                            current.lexeme
+                        ),
+                    );
+                    Node::new(None)
+                }
+            };
+            let to_add = self.range_literal(to_add);
+            master.add_children(&to_add);
+        }
+        master
+    }
+    fn parse_cond(&mut self) -> Node {
+        let mut master = Node::new(NodeType::Cond);
+        let line = self.line;
+        let mut has_else = false;
+
+        loop {
+            if self.is_at_end() || self.peek().unwrap().typ == RightParen {
+                break;
+            }
+            let clause_tok = self.advance();
+            if clause_tok.typ != LeftParen {
+                self.error(
+                    self.line,
+                    format!(
+                        "Found an invalid token in cond branch: `{}`",
+                        clause_tok.lexeme
+                    ),
+                );
+                continue;
+            }
+            if has_else {
+                self.error(self.line, "`else` must be the last branch of a cond");
+            }
+
+            let test_tok = self.advance();
+            let test = match test_tok.typ {
+                Else => {
+                    has_else = true;
+                    self.leaf(NodeBool(true))
+                }
+                LeftParen => self.parse_block(false),
+                Identifier(s) => self.leaf(NodeIdentifier(s)),
+                True => self.leaf(NodeBool(true)),
+                False => self.leaf(NodeBool(false)),
+                _ => {
+                    self.error(
+                        self.line,
+                        format!("Found an invalid token in cond test: `{}`", test_tok.lexeme),
+                    );
+                    Node::new(None)
+                }
+            };
+            let body = self.parse_do();
+            if self.peek().is_some() && self.peek().unwrap().typ == RightParen {
+                self.advance(); // Consume closing char
+            }
+
+            let mut clause = Node::new(Block);
+            clause.add_children(&test);
+            clause.add_children(&body);
+            master.add_children(&clause);
+        }
+        if self.strict && !has_else {
+            self.warnings.push(format!(
+                "Line {} | cond has no `else` branch and may not match",
+                line
+            ));
+        }
+        master
+    }
     fn parse_return(&mut self) -> Node {
         let to_ret = self.advance();
 
         let mut master = Node::new(NodeType::Return);
 
         let value = match to_ret.typ {
-            Identifier(s) => Node::new(NodeIdentifier(s)),
-            Str(s) => Node::new(NodeStr(s)),
-            Number(f) => Node::new(NodeNumber(f)),
+            Identifier(s) => self.leaf(NodeIdentifier(s)),
+            Str(s) => self.leaf(NodeStr(s)),
+            Number(f) => self.leaf(NodeNumber(f)),
             LeftParen => self.parse_block(false),
+            LeftBracket => self.parse_list(),
+            LeftBrace => self.parse_map(),
             TokenType::Func => self.parse_func(),
             _ => {
-                self.errors.push(format!(
-                    "Line {} | Found an invalid token in return: `{}`",
-                    self.line, to_ret.lexeme,
-                ));
+                self.error(
+                    self.line,
+                    format!("Found an invalid token in return: `{}`", to_ret.lexeme),
+                );
                 Node::new(None)
             }
         };
+        let value = self.range_literal(value);
         master.add_children(&value);
         master
     }
@@ -167,12 +493,20 @@ impl Parser {
 
         let check = match &first_tok.typ {
             LeftParen => self.parse_block(false),
-            Identifier(s) => Node::new(NodeIdentifier(s.to_string())),
+            Identifier(s) => self.leaf(NodeIdentifier(s.to_string())),
+            Str(s) => self.leaf(NodeStr(s.to_owned())),
+            Number(f) => self.leaf(NodeNumber(*f)),
+            True => self.leaf(NodeBool(true)),
+            False => self.leaf(NodeBool(false)),
+            Nil => Node::new(None),
             _ => {
-                self.errors.push(format!(
-                    "Line {} | Found an invalid token in loop condition `{}`",
-                    self.line, first_tok.lexeme,
-                ));
+                self.error(
+                    self.line,
+                    format!(
+                        "Found an invalid token in loop condition `{}`",
+                        first_tok.lexeme
+                    ),
+                );
                 Node::new(None)
             }
         };
@@ -181,10 +515,10 @@ impl Parser {
         let body = match &body_tok.typ {
             LeftBrace => self.parse_scope(false),
             _ => {
-                self.errors.push(format!(
-                    "Line {} | Found an invalid token in loop body: `{}`",
-                    self.line, body_tok.lexeme,
-                ));
+                self.error(
+                    self.line,
+                    format!("Found an invalid token in loop body: `{}`", body_tok.lexeme),
+                );
                 Node::new(None)
             }
         };
@@ -201,10 +535,13 @@ impl Parser {
             LeftParen => self.parse_args(),
             Eof => return Node::new(None),
             _ => {
-                self.errors.push(format!(
-                    "Line {} | Found an invalid token in function arguments: `{}`",
-                    self.line, first_tok.lexeme
-                ));
+                self.error(
+                    self.line,
+                    format!(
+                        "Found an invalid token in function arguments: `{}`",
+                        first_tok.lexeme
+                    ),
+                );
                 Node::new(None)
             }
         };
@@ -212,10 +549,13 @@ impl Parser {
         let body = match &sec_tok.typ {
             LeftBrace => self.parse_scope(false),
             _ => {
-                self.errors.push(format!(
-                    "Line {} | Found an invalid token in function body: `{}`",
-                    self.line, sec_tok.lexeme
-                ));
+                self.error(
+                    self.line,
+                    format!(
+                        "Found an invalid token in function body: `{}`",
+                        sec_tok.lexeme
+                    ),
+                );
                 Node::new(Block)
             }
         };
@@ -237,7 +577,9 @@ impl Parser {
             let current = self.advance();
 
             match &current.typ {
-                Identifier(s) => args.push(Node::new(NodeIdentifier(s.to_owned()))),
+                Identifier(s) => args.push(self.leaf(NodeIdentifier(s.to_owned()))),
+                typ if typ.is_keyword() => self.reserved_word(&current),
+                Comma if self.lenient => self.tolerated_commas.push(self.line),
                 _ => {}
             }
         }
@@ -253,12 +595,20 @@ impl Parser {
 
         let check = match &first_tok.typ {
             LeftParen => self.parse_block(false),
-            Identifier(s) => Node::new(NodeIdentifier(s.to_string())),
+            Identifier(s) => self.leaf(NodeIdentifier(s.to_string())),
+            Str(s) => self.leaf(NodeStr(s.to_owned())),
+            Number(f) => self.leaf(NodeNumber(*f)),
+            True => self.leaf(NodeBool(true)),
+            False => self.leaf(NodeBool(false)),
+            Nil => Node::new(None),
             _ => {
-                self.errors.push(format!(
-                    "Line {} | Found an invalid token in condition: `{}`",
-                    self.line, first_tok.lexeme
-                ));
+                self.error(
+                    self.line,
+                    format!(
+                        "Found an invalid token in condition: `{}`",
+                        first_tok.lexeme
+                    ),
+                );
                 Node::new(None)
             }
         };
@@ -267,10 +617,7 @@ impl Parser {
         let todo_if = match &todo_if_tok.typ {
             LeftParen => self.parse_block(false),
             _ => {
-                self.errors.push(format!(
-                    "{} | Invalid character {:?}",
-                    self.line, todo_if_tok
-                ));
+                self.error(self.line, format!("Unexpected {}", todo_if_tok));
                 Node::new(None)
             }
         };
@@ -293,41 +640,54 @@ impl Parser {
         Some(self.tokens[self.current].clone())
     }
     fn parse_op(&mut self, typ: &TokenType) -> Node {
-        let first_tok = self.advance();
+        let mut operands = vec![];
 
-        let first = match first_tok.typ {
-            LeftParen => self.parse_block(false),
-            Number(f) => Node::new(NodeNumber(f)),
-            True => Node::new(NodeBool(true)),
-            False => Node::new(NodeBool(false)),
-            Str(s) => Node::new(NodeStr(s)),
-            Identifier(s) => Node::new(NodeIdentifier(s)),
-            _ => {
-                self.errors.push(format!(
-                    "Line {} | Found invalid token in operation's left expression: `{}`",
-                    self.line, first_tok.lexeme,
-                ));
-                Node::new(None)
+        loop {
+            if self.is_at_end()
+                || self.peek().unwrap().typ == RightParen
+                || self.peek().unwrap().typ == RightBrace
+            {
+                break;
             }
-        };
-
-        let second_tok = self.advance();
+            let current = self.advance();
 
-        let second = match second_tok.typ {
-            LeftParen => self.parse_block(false),
-            Number(f) => Node::new(NodeNumber(f)),
-            Str(s) => Node::new(NodeStr(s)),
-            True => Node::new(NodeBool(true)),
-            False => Node::new(NodeBool(false)),
-            Identifier(s) => Node::new(NodeIdentifier(s)),
-            _ => {
-                self.errors.push(format!(
-                    "Line {} | Found invalid token in operation's right expression: `{}`",
-                    self.line, first_tok.lexeme,
-                ));
-                Node::new(None)
-            }
-        };
+            let operand = match current.typ {
+                Comma if self.lenient => {
+                    self.tolerated_commas.push(self.line);
+                    continue;
+                }
+                LeftParen => self.parse_block(false),
+                LeftBracket => self.parse_list(),
+                LeftBrace => self.parse_map(),
+                Number(f) => self.leaf(NodeNumber(f)),
+                True => self.leaf(NodeBool(true)),
+                False => self.leaf(NodeBool(false)),
+                Str(s) => self.leaf(NodeStr(s)),
+                Identifier(s) => self.leaf(NodeIdentifier(s)),
+                Nil => Node::new(None),
+                _ => {
+                    self.error(
+                        self.line,
+                        format!(
+                            "Found invalid token in operation's operands: `{}`",
+                            current.lexeme
+                        ),
+                    );
+                    Node::new(None)
+                }
+            };
+            let operand = self.range_literal(operand);
+            operands.push(operand);
+        }
+        if operands.len() < 2 {
+            self.error(
+                self.line,
+                format!(
+                    "Operations need at least 2 operands, found {}",
+                    operands.len()
+                ),
+            );
+        }
 
         let mut master = match typ {
             Plus => Node::new(Operator(OperatorType::Plus)),
@@ -342,22 +702,36 @@ impl Parser {
             LessEqual => Node::new(Operator(OperatorType::LessEqual)),
             Tilde => Node::new(Operator(OperatorType::NotEqual)),
             Percent => Node::new(Operator(OperatorType::Modulo)),
+            QuestionQuestion => Node::new(Operator(OperatorType::NilCoalesce)),
             _ => Node::new(Operator(OperatorType::Div)),
         };
-        master.add_children(&first);
-        master.add_children(&second);
+        for operand in operands {
+            master.add_children(&operand);
+        }
         master
     }
     fn parse_assignement(&mut self, typ: &TokenType) -> Node {
+        let deprecated = self.deprecation();
         let name_tok = self.advance();
 
         let name = match name_tok.typ {
-            Identifier(s) => Node::new(NodeIdentifier(s)),
+            Identifier(s) => self.leaf(NodeIdentifier(s)),
+            // The name a macro template binds: `(set (unquote a) 1)`.
+            LeftParen if self.peek().map(|t| t.typ) == Some(Identifier("unquote".to_owned())) => {
+                self.parse_block(false)
+            }
+            ref typ if typ.is_keyword() => {
+                self.reserved_word(&name_tok);
+                return Node::new(None);
+            }
             _ => {
-                self.errors.push(format!(
-                    "Line {} | Found invalid token in variable name: `{}`",
-                    self.line, name_tok.lexeme
-                ));
+                self.error(
+                    self.line,
+                    format!(
+                        "Found invalid token in variable name: `{}`",
+                        name_tok.lexeme
+                    ),
+                );
                 return Node::new(None);
             }
         };
@@ -365,22 +739,29 @@ impl Parser {
         let value_tok = self.advance();
 
         let value = match value_tok.typ {
-            Number(f) => Node::new(NodeNumber(f)),
-            Str(s) => Node::new(NodeStr(s)),
-            Identifier(s) => Node::new(NodeIdentifier(s)),
+            Number(f) => self.leaf(NodeNumber(f)),
+            Str(s) => self.leaf(NodeStr(s)),
+            Identifier(s) => self.leaf(NodeIdentifier(s)),
             Nil => Node::new(None),
-            True => Node::new(NodeBool(true)),
-            False => Node::new(NodeBool(false)),
+            True => self.leaf(NodeBool(true)),
+            False => self.leaf(NodeBool(false)),
             Plus | Minus | Star | Slash => self.parse_op(&value_tok.typ),
             LeftParen => self.parse_block(false),
+            LeftBracket => self.parse_list(),
+            LeftBrace => self.parse_map(),
+            Equal if self.infix => wrap_expression(self.parse_infix_expression(1)),
             _ => {
-                self.errors.push(format!(
-                    "Line {} | Found invalid token in variable value: `{}`",
-                    self.line, value_tok.lexeme
-                ));
+                self.error(
+                    self.line,
+                    format!(
+                        "Found invalid token in variable value: `{}`",
+                        value_tok.lexeme
+                    ),
+                );
                 return Node::new(None);
             }
         };
+        let value = self.range_literal(value);
 
         let mut master = match typ {
             Const => Node::new(Assignement(AssignType::Const)),
@@ -390,9 +771,103 @@ impl Parser {
 
         master.add_children(&name);
         master.add_children(&value);
+        if let (Some(hint), false) = (deprecated, typ == &Set) {
+            master.add_children(&Node::new(Deprecated(hint)));
+        }
 
         master
     }
+    fn parse_enum(&mut self) -> Node {
+        let name_tok = self.advance();
+        let name = match name_tok.typ {
+            Identifier(s) if !s.contains(':') => s,
+            _ => {
+                self.error(
+                    self.line,
+                    format!("Found invalid enum name: `{}`", name_tok.lexeme),
+                );
+                return Node::new(None);
+            }
+        };
+        if self.advance().typ != LeftParen {
+            self.error(
+                self.line,
+                format!("Expected the variants of `{}` between parentheses", name),
+            );
+            return Node::new(None);
+        }
+        let mut master = Node::new(NodeType::Enum(name.to_owned()));
+        let mut variants = vec![];
+        loop {
+            if self.is_at_end() {
+                break;
+            }
+            let current = self.advance();
+            match current.typ {
+                RightParen => break,
+                Identifier(s) if !s.contains(':') && !variants.contains(&s) => {
+                    master.add_children(&self.leaf(NodeIdentifier(s.to_owned())));
+                    variants.push(s);
+                }
+                _ => self.error(
+                    self.line,
+                    format!(
+                        "Found invalid or duplicate variant in enum `{}`: `{}`",
+                        name, current.lexeme
+                    ),
+                ),
+            }
+        }
+        master
+    }
+    fn parse_export(&mut self) -> Node {
+        let keyword = self.advance();
+        match keyword.typ {
+            Let | Const => {
+                let mut master = Node::new(Export);
+                master.add_children(&self.parse_assignement(&keyword.typ));
+                master
+            }
+            TokenType::Enum => {
+                let mut master = Node::new(Export);
+                master.add_children(&self.parse_enum());
+                master
+            }
+            _ => {
+                self.error(
+                    self.line,
+                    format!(
+                        "Expected `let`, `const` or `enum` after `pub`, found `{}`",
+                        keyword.lexeme
+                    ),
+                );
+                Node::new(None)
+            }
+        }
+    }
+    /// The hint of a `# @deprecated "hint"` comment right before the keyword just consumed,
+    /// its `pub` or its opening parenthesis. Comments are only there when lexed in trivia mode.
+    fn deprecation(&self) -> Option<String> {
+        let mut i = self.current - 1;
+        let mut annotated = vec![&self.tokens[i]];
+        while i > 0 && matches!(self.tokens[i - 1].typ, Pub | LeftParen) {
+            i -= 1;
+            annotated.push(&self.tokens[i]);
+            if self.tokens[i].typ == LeftParen {
+                break;
+            }
+        }
+        annotated
+            .iter()
+            .flat_map(|tok| &tok.trivia)
+            .find_map(|comment| match &comment.typ {
+                Comment(text) => text
+                    .trim()
+                    .strip_prefix("@deprecated")
+                    .map(|hint| hint.trim().trim_matches('"').to_owned()),
+                _ => Option::None,
+            })
+    }
     fn parse_token(&mut self) {
         let current = self.advance();
 
@@ -405,24 +880,300 @@ impl Parser {
                 let mut blck = Node::new(Scope);
                 blck.add_children(&self.parse_scope(true));
             }
+            Let | Const | Set if self.infix => {
+                let mut blck = Node::new(Block);
+                blck.add_children(&self.parse_assignement(&current.typ));
+                self.ast.add_children(&blck);
+            }
+            Identifier(_) if self.infix => {
+                self.current -= 1; // Let the expression parser see the callee
+                let mut blck = Node::new(Block);
+                blck.add_children(&self.parse_infix_primary());
+                self.ast.add_children(&blck);
+            }
+            _ => self.skip_stray_tokens(current),
+        }
+    }
+    /// Reports the tokens from `first` up to the next form, such as the extra `)` and atoms of
+    /// `(let x 5)) stray`, in one diagnostic, and resumes parsing at that form.
+    fn skip_stray_tokens(&mut self, first: Token) {
+        let line = first.line;
+        let mut skipped = vec![first.lexeme.to_owned()];
+        while let Some(next) = self.peek() {
+            let starts_form = match next.typ {
+                LeftParen | LeftBrace => true,
+                Let | Const | Set | Identifier(_) => self.infix,
+                _ => false,
+            };
+            if starts_form {
+                break;
+            }
+            skipped.push(self.advance().lexeme);
+        }
+        let resumed = match self.peek() {
+            Some(next) => format!("resumed at the `{}` of line {}", next.lexeme, next.line),
+            std::option::Option::None => "nothing follows".to_owned(),
+        };
+        if skipped.len() > 5 {
+            skipped.truncate(4);
+            skipped.push("...".to_owned());
+        }
+        let skipped = skipped.join(" ");
+        let message = match first.typ {
+            RightParen | RightBrace => format!(
+                "Unbalanced `{}` closes no open form; skipped `{}`, {}",
+                first.lexeme, skipped, resumed
+            ),
+            _ => format!(
+                "Stray tokens outside of a form; skipped `{}`, {}",
+                skipped, resumed
+            ),
+        };
+        self.error_spanning(line, first.span.to(self.span), message);
+    }
+    fn parse_infix_expression(&mut self, min_prec: u8) -> Node {
+        let lhs = self.parse_infix_primary();
+        self.climb_infix(lhs, min_prec)
+    }
+    fn climb_infix(&mut self, mut lhs: Node, min_prec: u8) -> Node {
+        while let Some(next) = self.peek() {
+            if let Some((prec, op)) = infix_operator(&next.typ) {
+                if prec < min_prec {
+                    break;
+                }
+                self.advance();
+                let rhs = self.parse_infix_expression(prec + 1);
+                lhs = binary_operation(op, lhs, rhs);
+            } else if let Number(f) = next.typ {
+                // `a -2` lexes as a negative literal, it is a subtraction here
+                if f >= 0. || !next.lexeme.starts_with('-') || min_prec > 5 {
+                    break;
+                }
+                self.advance();
+                let rhs = self.climb_infix(self.leaf(NodeNumber(-f)), 6);
+                lhs = binary_operation(OperatorType::Minus, lhs, rhs);
+            } else {
+                break;
+            }
+        }
+        lhs
+    }
+    fn parse_infix_primary(&mut self) -> Node {
+        let current = self.advance();
+
+        match current.typ {
+            Number(f) => self.leaf(NodeNumber(f)),
+            Str(s) => self.leaf(NodeStr(s)),
+            True => self.leaf(NodeBool(true)),
+            False => self.leaf(NodeBool(false)),
+            Nil => Node::new(None),
+            Identifier(s) => {
+                if self.peek().map(|t| t.typ) == Some(LeftParen) {
+                    self.advance();
+                    self.parse_infix_call(s)
+                } else {
+                    self.leaf(NodeIdentifier(s))
+                }
+            }
+            Minus => {
+                let operand = self.parse_infix_primary();
+                binary_operation(OperatorType::Minus, Node::new(NodeNumber(0.)), operand)
+            }
+            LeftBracket => self.parse_list(),
+            LeftBrace => self.parse_map(),
+            LeftParen => {
+                if self.starts_prefix_form() {
+                    return self.parse_block(false);
+                }
+                let inner = self.parse_infix_expression(1);
+                if self.peek().map(|t| t.typ) == Some(RightParen) {
+                    self.advance();
+                } else {
+                    self.error(self.line, "Expected `)` to close infix expression");
+                }
+                inner
+            }
             _ => {
-                self.errors.push(format!(
-                    "Line {} | Found an invalid token: `{}`",
-                    self.line, current.lexeme
-                ));
+                self.error(
+                    self.line,
+                    format!(
+                        "Found an invalid token in infix expression: `{}`",
+                        current.lexeme
+                    ),
+                );
+                Node::new(None)
+            }
+        }
+    }
+    fn parse_infix_call(&mut self, name: String) -> Node {
+        let mut master = Node::new(FunctionCall(name));
+
+        if self.peek().map(|t| t.typ) == Some(RightParen) {
+            self.advance();
+            return master;
+        }
+        loop {
+            let arg = self.parse_infix_expression(1);
+            master.add_children(&wrap_expression(arg));
+            match self.peek().map(|t| t.typ) {
+                Some(Comma) => {
+                    self.advance();
+                }
+                Some(RightParen) => {
+                    self.advance();
+                    break;
+                }
+                _ => {
+                    self.error(self.line, "Expected `,` or `)` in function call arguments");
+                    break;
+                }
             }
         }
+        master
+    }
+    /// Tells `(square 3)` and `(+ 1 2)` apart from a parenthesized `(a + b)`.
+    fn starts_prefix_form(&self) -> bool {
+        let first = match self.peek() {
+            Some(t) => t.typ,
+            _ => return false,
+        };
+        match first {
+            Identifier(_) => {
+                let second = self.tokens.get(self.current + 1).map(|t| &t.typ);
+                !matches!(second, Some(RightParen) | Some(LeftParen) | Some(Eof) | Option::None)
+                    && second.is_none_or(|t| infix_operator(t).is_none())
+            }
+            Number(_) | Str(_) | True | False | Nil | LeftParen => false,
+            Minus => false,
+            _ => true,
+        }
+    }
+    fn reserved_word(&mut self, tok: &Token) {
+        self.error_spanning(
+            tok.line,
+            tok.span,
+            format!(
+                "`{}` is a reserved keyword and can not be used as a variable name",
+                tok.lexeme
+            ),
+        );
     }
     pub fn parse(&mut self) -> Node {
+        trace_phase!("parse", tokens = self.tokens.len());
+        for tok in &self.tokens {
+            if let Identifier(name) = &tok.typ {
+                if let Err(e) = validate_identifier(name) {
+                    let mut error = Diagnostic::new(tok.line, e);
+                    error.span = Some(tok.span);
+                    self.errors.push(error);
+                }
+            }
+        }
         while !self.is_at_end() {
             self.parse_token();
         }
+        if let Some(line) = self.tolerated_commas.first() {
+            self.warnings.push(format!(
+                "Line {} | Ignored {} comma(s) in lenient mode",
+                line,
+                self.tolerated_commas.len()
+            ));
+        }
         self.ast.clone()
     }
     pub fn get_errors(&self) -> Option<Vec<String>> {
         if self.errors.is_empty() {
             return std::option::Option::None;
         }
-        Some(self.errors.clone())
+        let errors = self.errors.iter().take(self.max_errors);
+        Some(
+            errors
+                .map(|error| format!("Line {} | {}", error.line, error.message))
+                .collect(),
+        )
+    }
+    /// Every error, with its location.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.errors
+    }
+    fn error(&mut self, line: usize, message: impl Into<String>) {
+        self.error_spanning(line, self.span, message);
+    }
+    fn error_spanning(&mut self, line: usize, span: Span, message: impl Into<String>) {
+        let mut error = Diagnostic::new(line, message);
+        error.span = Some(span);
+        self.errors.push(error);
+    }
+    /// The number of errors left out of [`Parser::get_errors`] by [`Parser::set_max_errors`].
+    pub fn suppressed_errors(&self) -> usize {
+        self.errors.len().saturating_sub(self.max_errors)
+    }
+    pub fn get_warnings(&self) -> Option<Vec<String>> {
+        if self.warnings.is_empty() {
+            return std::option::Option::None;
+        }
+        Some(self.warnings.clone())
     }
 }
+
+/// Checks the shape of an identifier: `:` only separates a namespace from a name, as in
+/// `math:cos`, so it may appear once and never at either end.
+pub fn validate_identifier(name: &str) -> Result<(), String> {
+    let segments = name.split(':').collect::<Vec<_>>();
+    if segments.len() > 2 || segments.iter().any(|segment| segment.is_empty()) {
+        return Err(format!(
+            "Invalid identifier `{}`: `:` is reserved to separate a namespace from a name, as in `math:cos`",
+            name
+        ));
+    }
+    Ok(())
+}
+
+/// Binding power and AST operator of an infix operator token.
+fn infix_operator(typ: &TokenType) -> Option<(u8, OperatorType)> {
+    match typ {
+        QuestionQuestion => Some((1, OperatorType::NilCoalesce)),
+        Or => Some((2, OperatorType::Or)),
+        And => Some((3, OperatorType::And)),
+        Equal => Some((4, OperatorType::Equal)),
+        Tilde => Some((4, OperatorType::NotEqual)),
+        Less => Some((4, OperatorType::Less)),
+        LessEqual => Some((4, OperatorType::LessEqual)),
+        Greater => Some((4, OperatorType::Greater)),
+        GreaterEqual => Some((4, OperatorType::GreaterEqual)),
+        Plus => Some((5, OperatorType::Plus)),
+        Minus => Some((5, OperatorType::Minus)),
+        Star => Some((6, OperatorType::Times)),
+        Slash => Some((6, OperatorType::Div)),
+        Percent => Some((6, OperatorType::Modulo)),
+        _ => Option::None,
+    }
+}
+
+fn binary_operation(op: OperatorType, lhs: Node, rhs: Node) -> Node {
+    let mut master = Node::new(Operator(op));
+    master.add_children(&wrap_expression(lhs));
+    master.add_children(&wrap_expression(rhs));
+    master
+}
+
+/// Operands and values that are not atoms live inside a `Block`, as the prefix parser does it.
+fn wrap_expression(node: Node) -> Node {
+    match node.get_type() {
+        NodeNumber(_) | NodeStr(_) | NodeBool(_) | NodeIdentifier(_) | None | Block => node,
+        _ => {
+            let mut blck = Node::new(Block);
+            blck.add_children(&node);
+            blck
+        }
+    }
+}
+
+/// Whether the leading comments of `source` contain the `# nixt: infix` pragma.
+pub fn has_infix_pragma(source: &str) -> bool {
+    source
+        .lines()
+        .map(|l| l.trim())
+        .take_while(|l| l.is_empty() || l.starts_with('#'))
+        .any(|l| l == "# nixt: infix")
+}
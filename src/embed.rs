@@ -0,0 +1,84 @@
+//! The entry point for Rust programs embedding nixt as their scripting language.
+
+use crate::error::{take_raised, NixtError, RuntimeError};
+use crate::{Interpreter, Value};
+
+/// A nixt runtime: evaluates source strings, keeping the bindings they define for the next
+/// ones, and calls the functions they define.
+///
+/// ```
+/// use nixt::{Nixt, NixtError, Value};
+/// let mut nixt = Nixt::new();
+/// nixt.eval("(let greet (func (name) { (ret (+ \"hello \" name)) }))")?;
+/// assert_eq!(nixt.call("greet", &[Value::from("world")])?.to_string(), "hello world");
+/// nixt.set("limit", Value::from(10.))?;
+/// assert_eq!(nixt.eval("(* limit 2)")?.to_string(), "20");
+/// assert!(matches!(nixt.eval("(print missing)"), Err(NixtError::Runtime(_))));
+/// # Ok::<(), NixtError>(())
+/// ```
+pub struct Nixt {
+  interpreter: Interpreter,
+}
+
+impl Nixt {
+  /// A runtime with the default interpreter, `Interpreter::builder().build()`. Use
+  /// `Nixt::from` to embed an interpreter configured through its builder instead.
+  pub fn new() -> Self {
+    let interpreter = Interpreter::builder().build();
+    Self::from(interpreter.expect("The default interpreter has nothing that can fail to load"))
+  }
+  /// Lexes, parses and evaluates `source`, returning the value of its last expression.
+  pub fn eval(&mut self, source: &str) -> Result<Value, NixtError> {
+    self.interpreter.eval_source(source)
+  }
+  /// Calls the function or builtin bound to `name`.
+  pub fn call(&mut self, name: &str, args: &[Value]) -> Result<Value, NixtError> {
+    take_raised();
+    let callee = match self.interpreter.get(name) {
+      Some(callee) => callee,
+      None => self
+        .interpreter
+        .builtin_value(name, || "Attempted to call an undefined function".to_owned())
+        .map_err(runtime)?,
+    };
+    self.interpreter.call_value(&callee, args).map_err(runtime)
+  }
+  /// The value bound to `name`, if any.
+  pub fn get(&mut self, name: &str) -> Option<Value> {
+    self.interpreter.get(name)
+  }
+  /// Binds `name` to `value`, for the scripts evaluated after to read.
+  pub fn set(&mut self, name: &str, value: Value) -> Result<(), NixtError> {
+    take_raised();
+    self.interpreter.set(name, value).map_err(runtime)
+  }
+  /// Exposes the Rust function `function` to programs as the builtin `name`, see
+  /// [`Interpreter::register_native`].
+  pub fn register_native(
+    &mut self,
+    name: &str,
+    function: impl Fn(&[Value]) -> Result<Value, NixtError> + 'static,
+  ) -> Result<(), NixtError> {
+    self.interpreter.register_native(name, function).map_err(runtime)
+  }
+  /// The interpreter, for what the facade does not cover.
+  pub fn interpreter(&mut self) -> &mut Interpreter {
+    &mut self.interpreter
+  }
+}
+
+impl Default for Nixt {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl From<Interpreter> for Nixt {
+  fn from(interpreter: Interpreter) -> Self {
+    Self { interpreter }
+  }
+}
+
+fn runtime(message: String) -> NixtError {
+  NixtError::Runtime(RuntimeError::raised(message))
+}
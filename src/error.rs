@@ -0,0 +1,140 @@
+//! The errors of lexing, parsing and evaluating nixt code, for embedders to tell apart.
+
+use crate::utils::token::Span;
+use std::cell::Cell;
+
+/// Why some source could not be run.
+///
+/// ```
+/// use nixt::error::{NixtError, RuntimeErrorKind};
+/// let mut interpreter = nixt::Interpreter::builder().build()?;
+/// match interpreter.eval_source("(print missing)") {
+///   Err(NixtError::Runtime(error)) => assert_eq!(error.kind, RuntimeErrorKind::Undefined),
+///   other => panic!("{:?}", other),
+/// }
+/// match interpreter.eval_source("(print 'a') ?") {
+///   Err(NixtError::Lex(errors)) => assert_eq!((errors[0].line, errors[0].column), (1, Some(13))),
+///   other => panic!("{:?}", other),
+/// }
+/// # Ok::<(), String>(())
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum NixtError {
+  /// Characters the lexer does not accept, or unterminated strings and comments.
+  Lex(Vec<Diagnostic>),
+  /// Tokens that do not form a program.
+  Parse(Vec<Diagnostic>),
+  /// Evaluating the program failed.
+  Runtime(RuntimeError),
+}
+
+/// An error found in the source, at a line and, when it is known, a column, both from 1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+  pub line: usize,
+  pub column: Option<usize>,
+  /// The bytes of the source the error is about, when they are known.
+  pub span: Option<Span>,
+  pub message: String,
+}
+
+impl Diagnostic {
+  pub fn new(line: usize, message: impl Into<String>) -> Self {
+    Self {
+      line,
+      column: None,
+      span: None,
+      message: message.into(),
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError {
+  pub kind: RuntimeErrorKind,
+  pub message: String,
+  /// The bytes of the source of the failing code: the undefined variable, or the innermost
+  /// statement that failed. A function keeps the spans of the source it was defined in, which
+  /// may be that of an earlier evaluation.
+  pub span: Option<Span>,
+}
+
+/// What kind of runtime error happened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuntimeErrorKind {
+  /// A variable or function that is not defined, or private to a module.
+  Undefined,
+  /// A value of the wrong type: adding a bool, calling a number, a builtin argument.
+  Type,
+  /// A function or builtin called with the wrong number of arguments.
+  Arity,
+  /// A failed `assert` or `assert_eq`.
+  Assertion,
+  /// A builtin the sandbox policy does not allow.
+  Sandbox,
+  /// A module that could not be found or read, or an import cycle.
+  Import,
+  /// Evaluation stopped through the interrupt flag.
+  Interrupted,
+  Other,
+}
+
+impl RuntimeError {
+  /// An error of the host, such as a native builtin failing, of kind
+  /// [`RuntimeErrorKind::Other`] unless [`RuntimeError::with_kind`] gives it another.
+  pub fn new(message: impl Into<String>) -> Self {
+    Self::with_kind(RuntimeErrorKind::Other, message)
+  }
+  pub fn with_kind(kind: RuntimeErrorKind, message: impl Into<String>) -> Self {
+    Self {
+      kind,
+      message: message.into(),
+      span: None,
+    }
+  }
+  /// The error the interpreter reported as `message`, with the kind it was raised with.
+  pub(crate) fn raised(message: impl Into<String>) -> Self {
+    Self::with_kind(take_raised().unwrap_or(RuntimeErrorKind::Other), message)
+  }
+}
+
+thread_local! {
+  /// The kind of the error being raised on this thread, until it is reported.
+  static RAISED: Cell<Option<RuntimeErrorKind>> = const { Cell::new(None) };
+}
+
+/// `message`, as the error of `kind` being raised. Errors travel through the interpreter as
+/// strings: their kind is kept aside for [`RuntimeError::raised`], the first error raised since
+/// the last one was reported giving it, as the innermost is raised first.
+pub(crate) fn raise(kind: RuntimeErrorKind, message: impl Into<String>) -> String {
+  RAISED.with(|raised| {
+    if raised.get().is_none() {
+      raised.set(Some(kind));
+    }
+  });
+  message.into()
+}
+
+/// The kind of the error raised since the last call, forgetting it.
+pub(crate) fn take_raised() -> Option<RuntimeErrorKind> {
+  RAISED.with(Cell::take)
+}
+
+impl std::fmt::Display for NixtError {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    let errors = match self {
+      Self::Lex(errors) | Self::Parse(errors) => errors,
+      Self::Runtime(error) => return write!(f, "{}", error.message),
+    };
+    let lines = errors.iter().map(|error| format!("Line {} | {}", error.line, error.message));
+    write!(f, "{}", lines.collect::<Vec<_>>().join("\n"))
+  }
+}
+
+impl std::error::Error for NixtError {}
+
+impl From<NixtError> for String {
+  fn from(error: NixtError) -> Self {
+    error.to_string()
+  }
+}
@@ -0,0 +1,44 @@
+pub mod ast;
+pub mod core;
+mod embed;
+pub mod error;
+pub mod stdlib;
+pub mod testing;
+mod tests;
+pub mod tools;
+pub mod utils;
+
+pub use self::core::interpreter::builder::InterpreterBuilder;
+pub use self::core::interpreter::interpreter::{EvalOutput, Interpreter};
+pub use self::core::interpreter::pool::{InterpreterPool, PoolStats};
+pub use self::core::interpreter::sandbox::SandboxPolicy;
+pub use self::core::interpreter::shared::SharedEnv;
+pub use self::core::interpreter::transfer::Transfer;
+pub use self::core::interpreter::verbose::Verbosity;
+pub use self::embed::Nixt;
+pub use self::error::NixtError;
+pub use self::utils::element::Value;
+
+use std::cell::RefCell;
+
+thread_local! {
+  static DEFAULT_INTERPRETER: RefCell<Option<Interpreter>> = const { RefCell::new(None) };
+}
+
+/// Evaluates `source` with this thread's default interpreter, built on first use with
+/// `Interpreter::builder().build()`. Bindings persist across calls in the same thread.
+///
+/// ```
+/// nixt::quick_eval("(let answer 42)")?;
+/// assert_eq!(format!("{}", nixt::quick_eval("(+ answer 1)")?), "43");
+/// # Ok::<(), String>(())
+/// ```
+pub fn quick_eval(source: &str) -> Result<Value, String> {
+  DEFAULT_INTERPRETER.with(|default| {
+    let mut default = default.borrow_mut();
+    if default.is_none() {
+      *default = Some(Interpreter::builder().build()?);
+    }
+    default.as_mut().unwrap().eval_str(source)
+  })
+}
@@ -1,80 +1,825 @@
-mod core;
-mod stdlib;
-mod tests;
-mod utils;
-
-use self::core::interpreter::interpreter::*;
-use self::core::lexer::*;
-use self::core::parser::*;
+use nixt::core::interpreter::harness::Snapshots;
+use nixt::core::interpreter::replay::Trace;
+use nixt::core::interpreter::sandbox::SandboxPolicy;
+use nixt::core::lexer::*;
+use nixt::core::parser::*;
+use nixt::tools::config::{self, ProjectConfig};
+use nixt::tools::lint::{self, LintConfig, Severity};
+use nixt::tools::repl::Repl;
+use nixt::tools::{doc, grammar, graph, metrics, rename, serve, test};
+use nixt::utils::json::{self, Json};
+use nixt::utils::node::Node;
+use nixt::{Interpreter, InterpreterBuilder, Value, Verbosity};
 use std::fs;
 use std::io::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[derive(Default)]
+struct Options {
+    strict: bool,
+    infix: bool,
+    lenient: bool,
+    const_exprs: bool,
+    quiet: bool,
+    dump_ast: bool,
+    dump_ast_json: bool,
+    dump_tokens: bool,
+    debug: bool,
+    verbose: bool,
+    dump_state: bool,
+    eval: Option<String>,
+    file: Option<String>,
+    record: Option<String>,
+    replay: Option<String>,
+    watches: Vec<String>,
+    max_errors: Option<usize>,
+    no_config: bool,
+    search_paths: Vec<PathBuf>,
+    sandbox: SandboxPolicy,
+    defines: Vec<(String, Value)>,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--strict" => options.strict = true,
+            "--infix" => options.infix = true,
+            "--lenient" => options.lenient = true,
+            "--const-exprs" => options.const_exprs = true,
+            "--quiet" => options.quiet = true,
+            "--dump-ast" => options.dump_ast = true,
+            "--dump-ast=json" => options.dump_ast_json = true,
+            "--dump-tokens" => options.dump_tokens = true,
+            "--debug" => options.debug = true,
+            "--verbose" => options.verbose = true,
+            "--no-config" => options.no_config = true,
+            "--dump-state=json" => options.dump_state = true,
+            "-e" | "--eval" => match args.next() {
+                Some(code) => options.eval = Some(code.to_owned()),
+                None => return Err(format!("Missing code after `{}`", arg)),
+            },
+            "--define" => match args.next() {
+                Some(define) => options.defines.push(parse_define(define)?),
+                None => return Err("Missing `key=value` after `--define`".to_owned()),
+            },
+            "--define-json" => match args.next() {
+                Some(path) => options.defines.extend(read_defines(path)?),
+                None => return Err("Missing file after `--define-json`".to_owned()),
+            },
+            "--record" => match args.next() {
+                Some(path) => options.record = Some(path.to_owned()),
+                None => return Err("Missing file after `--record`".to_owned()),
+            },
+            "--max-errors" => match args.next().map(|n| n.parse::<usize>()) {
+                Some(Ok(n)) => options.max_errors = Some(n),
+                _ => return Err("Missing number after `--max-errors`".to_owned()),
+            },
+            "--watch" => match args.next() {
+                Some(name) => options.watches.push(name.to_owned()),
+                None => return Err("Missing name after `--watch`".to_owned()),
+            },
+            "--replay" => match args.next() {
+                Some(path) => options.replay = Some(path.to_owned()),
+                None => return Err("Missing file after `--replay`".to_owned()),
+            },
+            _ if arg.starts_with('-') => return Err(format!("Unknown option: `{}`", arg)),
+            _ => options.file = Some(arg.to_owned()),
+        }
+    }
+    Ok(options)
+}
+
+fn parse_define(define: &str) -> Result<(String, Value), String> {
+    let (name, raw) = match define.find('=') {
+        Some(i) => (&define[..i], &define[i + 1..]),
+        None => return Err(format!("Invalid define `{}`, expected `key=value`", define)),
+    };
+    let value = match raw {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        "nil" => Value::Nil,
+        _ => match raw.parse::<f64>() {
+            Ok(n) => Value::Number(n),
+            Err(_) => Value::String(raw.to_owned()),
+        },
+    };
+    Ok((name.to_owned(), value))
+}
+
+fn read_defines(path: &str) -> Result<Vec<(String, Value)>, String> {
+    let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    match json::parse(&content)? {
+        Json::Object(members) => members
+            .iter()
+            .map(|(name, value)| Ok((name.to_owned(), json::to_value(value)?)))
+            .collect(),
+        _ => Err(format!("`{}` must contain a JSON object", path)),
+    }
+}
+
+/// Prints the interpreter's spans and events to stderr, at the level `NIXT_LOG` names.
+#[cfg(feature = "tracing")]
+fn init_tracing() {
+    use tracing_subscriber::fmt::format::FmtSpan;
+    let level = std::env::var("NIXT_LOG").ok().and_then(|l| l.parse::<tracing::Level>().ok());
+    if let Some(level) = level {
+        tracing_subscriber::fmt()
+            .with_max_level(level)
+            .with_span_events(FmtSpan::CLOSE)
+            .with_writer(std::io::stderr)
+            .init();
+    }
+}
 
 fn main() -> Result<(), String> {
-    let args = std::env::args().collect::<Vec<String>>();
-    if args.len() < 2 {
-        repl()?;
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    match args.first().map(|a| a.as_str()) {
+        Some("doc") => return run_doc(&args[1..]),
+        Some("serve") => return run_serve(&args[1..]),
+        Some("grammar") => return run_grammar(&args[1..]),
+        Some("rename") => return run_rename(&args[1..]),
+        Some("lint") => return run_lint(&args[1..]),
+        Some("metrics") => return run_metrics(&args[1..]),
+        Some("graph-ast") => return run_graph_ast(&args[1..]),
+        Some("test") => return run_test(&args[1..]),
+        Some("doctest") => return run_doctest(&args[1..]),
+        _ => {}
+    }
+    let mut options = parse_args(&args)?;
+    if !options.no_config {
+        let start = match &options.file {
+            Some(file) => script_dir(Path::new(file)).to_owned(),
+            None => PathBuf::from("."),
+        };
+        apply_config(&mut options, project_config(&start)?);
+    }
+    #[cfg(feature = "tracing")]
+    init_tracing();
+
+    let content = if let Some(code) = &options.eval {
+        code.to_owned()
+    } else if let Some(file) = &options.file {
+        if !Path::new(file).exists() {
+            return Err("File not found".to_owned());
+        }
+        match fs::read_to_string(file) {
+            Ok(c) => c,
+            Err(e) => return Err(e.to_string()),
+        }
+    } else {
+        return repl(&options);
+    };
+
+    if options.dump_tokens {
+        for token in Lexer::new(&content).scan_tokens() {
+            println!("{}", token);
+        }
+        return Ok(());
+    }
+
+    let ast = parse_source(&content, &options)?;
+    if options.dump_ast_json {
+        println!("{}", graph::to_json(&ast));
+        return Ok(());
+    }
+    if options.dump_ast {
+        for form in ast.get_child() {
+            println!("{}", form);
+        }
+        return Ok(());
+    }
+    let mut builder = builder(&options).record(options.record.is_some());
+    if let Some(path) = &options.replay {
+        let mut file = fs::File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+        builder = builder.replay(Trace::read_from(&mut file)?);
+    }
+    let mut interpreter = builder.build()?;
+    watch(&mut interpreter, &options);
+    let signal = catch_signals(&interpreter)?;
+    let result = interpreter.eval(&ast);
+    print_warnings(&mut interpreter);
+    let exited = interpreter.run_exit_handlers();
+    // The trace is written even if the script failed, to replay the failure.
+    if let (Some(path), Some(trace)) = (&options.record, interpreter.take_trace()) {
+        let mut file = fs::File::create(path).map_err(|e| format!("{}: {}", path, e))?;
+        trace.write_to(&mut file).map_err(|e| format!("{}: {}", path, e))?;
+    }
+    if options.dump_state {
+        println!("{}", interpreter.dump_state());
+    }
+    if let Some(signal) = caught(&signal) {
+        eprintln!("Interrupted by signal {}", signal);
+        std::process::exit(128 + signal as i32);
+    }
+    let result = result?;
+    exited?;
+    if !options.quiet && !matches!(result, Value::Nil) {
+        println!("{}", result);
+    }
+
+    Ok(())
+}
+
+/// The number of the last signal caught, shared with the signal handlers.
+type CaughtSignal = std::sync::Arc<std::sync::atomic::AtomicUsize>;
+
+/// Turns Ctrl-C and SIGTERM into interrupts of `interpreter`, recording which one was caught.
+#[cfg(feature = "signals")]
+fn catch_signals(interpreter: &Interpreter) -> Result<CaughtSignal, String> {
+    use signal_hook::consts::{SIGINT, SIGTERM};
+    let signal = CaughtSignal::default();
+    for &number in &[SIGINT, SIGTERM] {
+        let failed = |e: std::io::Error| format!("Failed to catch signal {}: {}", number, e);
+        signal_hook::flag::register(number, interpreter.interrupt_flag()).map_err(failed)?;
+        signal_hook::flag::register_usize(number, signal.clone(), number as usize)
+            .map_err(failed)?;
+    }
+    Ok(signal)
+}
+
+#[cfg(not(feature = "signals"))]
+fn catch_signals(_: &Interpreter) -> Result<CaughtSignal, String> {
+    Ok(CaughtSignal::default())
+}
+
+fn caught(signal: &CaughtSignal) -> Option<usize> {
+    match signal.swap(0, std::sync::atomic::Ordering::Relaxed) {
+        0 => None,
+        number => Some(number),
+    }
+}
+
+/// `nixt doc [--json] <file>`: prints the documentation of a script's bindings.
+fn run_doc(args: &[String]) -> Result<(), String> {
+    let mut json = false;
+    let mut file = None;
+    for arg in args {
+        match arg.as_str() {
+            "--json" => json = true,
+            _ if arg.starts_with('-') => return Err(format!("Unknown option: `{}`", arg)),
+            _ => file = Some(arg),
+        }
+    }
+    let file = file.ok_or("Missing file after `doc`")?;
+    let content = fs::read_to_string(file).map_err(|e| e.to_string())?;
+    let entries = doc::extract(&content)?;
+    if json {
+        println!("{}", doc::to_json(&entries));
+    } else {
+        print!("{}", doc::to_markdown(file, &entries));
+    }
+    Ok(())
+}
+
+/// `nixt grammar [--format tree-sitter]`: prints the grammar of the parser for other tools.
+fn run_grammar(args: &[String]) -> Result<(), String> {
+    let format = match args {
+        [] => "tree-sitter",
+        [flag, format] if flag == "--format" => format.as_str(),
+        _ => return Err("Usage: nixt grammar [--format tree-sitter]".to_owned()),
+    };
+    match format {
+        "tree-sitter" => print!("{}", grammar::to_tree_sitter(&nixt::core::grammar::grammar())),
+        _ => return Err(format!("Unknown grammar format `{}`", format)),
+    }
+    Ok(())
+}
+
+/// `nixt metrics [--json] <file>`: prints the size and complexity of each function of a script.
+fn run_metrics(args: &[String]) -> Result<(), String> {
+    let mut json = false;
+    let mut file = None;
+    for arg in args {
+        match arg.as_str() {
+            "--json" => json = true,
+            _ if arg.starts_with('-') => return Err(format!("Unknown option: `{}`", arg)),
+            _ => file = Some(arg),
+        }
+    }
+    let file = file.ok_or("Missing file after `metrics`")?;
+    let content = fs::read_to_string(file).map_err(|e| e.to_string())?;
+    let ast = parse_source(&content, &Options::default())?;
+    let functions = metrics::metrics(&ast);
+    if json {
+        println!("{}", metrics::to_json(&functions));
+    } else {
+        print!("{}", metrics::to_table(&functions));
+    }
+    Ok(())
+}
+
+/// `nixt graph-ast [--dot] <file>`: prints the syntax tree of the file as a Graphviz digraph.
+fn run_graph_ast(args: &[String]) -> Result<(), String> {
+    let mut file = None;
+    for arg in args {
+        match arg.as_str() {
+            "--dot" => {}
+            _ if arg.starts_with('-') => return Err(format!("Unknown option: `{}`", arg)),
+            _ => file = Some(arg),
+        }
+    }
+    let file = file.ok_or("Usage: nixt graph-ast [--dot] <file>")?;
+    let content = fs::read_to_string(file).map_err(|e| e.to_string())?;
+    let ast = parse_source(&content, &Options::default())?;
+    print!("{}", graph::to_dot(&ast));
+    Ok(())
+}
+
+/// `nixt test [--update-snapshots] [--jobs <n>] [--filter <s>] [--skip-tag <tag>]... [--list]
+/// [--test-timeout <ms>] [--report <junit.xml>] [--report-json <file>] [<path>...]`: runs the
+/// tests of the given scripts, and of the `_test.nxt` scripts under the given directories (the
+/// current one by default). With more than one job, each test runs in its own interpreter.
+fn run_test(args: &[String]) -> Result<(), String> {
+    let mut update = false;
+    let mut list = false;
+    let mut junit = None;
+    let mut json_report = None;
+    let mut jobs = 1;
+    let mut timeout = None;
+    let mut filter = test::Filter::default();
+    let mut paths = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--update-snapshots" => update = true,
+            "--list" => list = true,
+            "--report" => match args.next() {
+                Some(path) => junit = Some(path.to_owned()),
+                None => return Err("Missing file after `--report`".to_owned()),
+            },
+            "--report-json" => match args.next() {
+                Some(path) => json_report = Some(path.to_owned()),
+                None => return Err("Missing file after `--report-json`".to_owned()),
+            },
+            "--filter" => match args.next() {
+                Some(name) => filter.name = Some(name.to_owned()),
+                None => return Err("Missing text after `--filter`".to_owned()),
+            },
+            "--skip-tag" => match args.next() {
+                Some(tag) => filter.skip_tags.push(tag.to_owned()),
+                None => return Err("Missing tag after `--skip-tag`".to_owned()),
+            },
+            "--jobs" => match args.next().map(|n| n.parse::<usize>()) {
+                Some(Ok(n)) if n > 0 => jobs = n,
+                _ => return Err("Missing number of jobs after `--jobs`".to_owned()),
+            },
+            "--test-timeout" => match args.next().map(|ms| ms.parse::<u64>()) {
+                Some(Ok(ms)) if ms > 0 => timeout = Some(Duration::from_millis(ms)),
+                _ => return Err("Missing milliseconds after `--test-timeout`".to_owned()),
+            },
+            _ if arg.starts_with('-') => return Err(format!("Unknown option: `{}`", arg)),
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+    if paths.is_empty() {
+        paths.push(PathBuf::from("."));
+    }
+    let mut failures = vec![];
+    let mut passed = 0;
+    let mut suites = vec![];
+    for path in paths {
+        for script in test::discover(&path)? {
+            let options = script_options(&script)?;
+            let shown = script.strip_prefix(".").unwrap_or(&script).display();
+            let source = fs::read_to_string(&script)
+                .map_err(|e| format!("{}: {}", script.display(), e))?;
+            let builder = || {
+                let builder = builder(&options).snapshots(Snapshots::for_script(&script, update));
+                match timeout {
+                    Some(limit) => builder.test_timeout(limit),
+                    None => builder,
+                }
+            };
+            if list {
+                let cases = test::list(&source, builder())?;
+                for case in cases.iter().filter(|case| filter.matches(case)) {
+                    let tags = match case.tags.as_slice() {
+                        [] => String::new(),
+                        tags => format!(" [{}]", tags.join(", ")),
+                    };
+                    println!("{}: {}{}", shown, case.name, tags);
+                }
+                continue;
+            }
+            let results = match jobs {
+                1 => test::run(&source, builder(), &filter),
+                _ => test::run_parallel(&source, jobs, builder, &filter),
+            };
+            let suite = test::Suite {
+                name: shown.to_string(),
+                results,
+            };
+            print_suite(&suite, &mut passed, &mut failures);
+            suites.push(suite);
+        }
+    }
+    if list {
+        return Ok(());
+    }
+    if let Some(path) = &junit {
+        fs::write(path, test::to_junit(&suites)).map_err(|e| format!("{}: {}", path, e))?;
+    }
+    if let Some(path) = &json_report {
+        let report = format!("{}\n", test::to_json(&suites));
+        fs::write(path, report).map_err(|e| format!("{}: {}", path, e))?;
+    }
+    summarize(passed, &failures)
+}
+
+/// `nixt doctest <file>...`: runs the examples of the `##` doc comments of the files, failing
+/// those that do not print what their `=> ` lines give.
+fn run_doctest(args: &[String]) -> Result<(), String> {
+    if args.is_empty() || args.iter().any(|arg| arg.starts_with('-')) {
+        return Err("Usage: nixt doctest <file>...".to_owned());
+    }
+    let mut failures = vec![];
+    let mut passed = 0;
+    for file in args {
+        let options = script_options(Path::new(file))?;
+        let source = fs::read_to_string(file).map_err(|e| format!("{}: {}", file, e))?;
+        let suite = test::Suite {
+            name: file.to_owned(),
+            results: doc::run_examples(&source, || builder(&options)),
+        };
+        print_suite(&suite, &mut passed, &mut failures);
+    }
+    summarize(passed, &failures)
+}
+
+/// Prints the failures and the number of tests that passed and failed, failing if any did.
+fn summarize(passed: usize, failures: &[(String, String)]) -> Result<(), String> {
+    if !failures.is_empty() {
+        println!("\nfailures:");
+        for (name, failure) in failures {
+            println!("\n---- {} ----\n{}", name, failure);
+        }
     }
-    if !Path::new(&args[1]).exists() {
-        return Err("File not found".to_owned());
+    let status = if failures.is_empty() { "ok" } else { "FAILED" };
+    println!(
+        "\ntest result: {}. {} passed; {} failed",
+        status,
+        passed,
+        failures.len()
+    );
+    match failures.len() {
+        0 => Ok(()),
+        failed => Err(format!("{} test(s) failed", failed)),
     }
+}
 
-    let content = match fs::read_to_string(&args[1]) {
-        Ok(c) => c,
-        Err(e) => return Err(e.to_string()),
+/// Prints the outcome of each test of `suite`, counting those that passed and collecting the
+/// failures.
+fn print_suite(suite: &test::Suite, passed: &mut usize, failures: &mut Vec<(String, String)>) {
+    let results = match &suite.results {
+        Ok(results) => results,
+        Err(e) => {
+            println!("test {} ... FAILED", suite.name);
+            failures.push((suite.name.to_owned(), e.to_owned()));
+            return;
+        }
     };
-    let mut lexer = Lexer::new(&content);
+    for result in results {
+        let name = format!("{}: {}", suite.name, result.name);
+        match &result.failure {
+            None => {
+                println!("test {} ... ok", name);
+                *passed += 1;
+            }
+            Some(failure) => {
+                println!("test {} ... FAILED", name);
+                failures.push((name, failure.to_owned() + &captured(&result.output)));
+            }
+        }
+    }
+}
+
+/// The output a failing test printed, to show after its failure.
+fn captured(output: &str) -> String {
+    match output {
+        "" => String::new(),
+        _ => format!("\n---- output ----\n{}", output.trim_end()),
+    }
+}
+
+/// `nixt rename [--write] <old> <new> <file>...`: lists the edits renaming a global binding
+/// across the files, or makes them with `--write`.
+fn run_rename(args: &[String]) -> Result<(), String> {
+    let write = args.first().map(|a| a.as_str()) == Some("--write");
+    let args = if write { &args[1..] } else { args };
+    if args.len() < 3 || args.iter().any(|arg| arg.starts_with('-')) {
+        return Err("Usage: nixt rename [--write] <old> <new> <file>...".to_owned());
+    }
+    let (old, new, files) = (&args[0], &args[1], &args[2..]);
+    let sources = files
+        .iter()
+        .map(|file| fs::read_to_string(file).map_err(|e| format!("{}: {}", file, e)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let sources = sources.iter().map(|s| s.as_str()).collect::<Vec<_>>();
+    let edits = rename::rename(&sources, old, new)?;
+    for ((file, source), edits) in files.iter().zip(sources).zip(edits) {
+        if write && !edits.is_empty() {
+            fs::write(file, rename::apply(source, &edits)).map_err(|e| e.to_string())?;
+        }
+        for edit in edits {
+            let replaced = &source[edit.start..edit.end];
+            println!("{}:{}: {} -> {}", file, edit.line, replaced, edit.text);
+        }
+    }
+    Ok(())
+}
+
+/// `nixt lint [--allow|--warn|--deny <rule>]... [--max-function-lines <n>] <file>...`: reports
+/// the lints of the files, failing when a denied rule is broken.
+fn run_lint(args: &[String]) -> Result<(), String> {
+    let mut config = project_config(Path::new("."))?.lint_config(LintConfig::default());
+    let mut files = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let severity = match arg.as_str() {
+            "--allow" => Severity::Allow,
+            "--warn" => Severity::Warn,
+            "--deny" => Severity::Deny,
+            "--max-function-lines" => {
+                let max = args.next().and_then(|n| n.parse().ok());
+                config.max_function_lines =
+                    max.ok_or("Missing line count after `--max-function-lines`")?;
+                continue;
+            }
+            _ if arg.starts_with('-') => return Err(format!("Unknown option: `{}`", arg)),
+            _ => {
+                files.push(arg);
+                continue;
+            }
+        };
+        let rule = args.next().ok_or_else(|| format!("Missing rule after `{}`", arg))?;
+        config.set(rule, severity)?;
+    }
+    if files.is_empty() {
+        return Err("Missing file after `lint`".to_owned());
+    }
+    let mut denied = 0;
+    for file in files {
+        let content = fs::read_to_string(file).map_err(|e| format!("{}: {}", file, e))?;
+        for lint in lint::lint(&content, &config).map_err(|e| format!("{}: {}", file, e))? {
+            if lint.severity == Severity::Deny {
+                denied += 1;
+            }
+            println!("{}:{}", file, lint);
+        }
+    }
+    match denied {
+        0 => Ok(()),
+        _ => Err(format!("{} denied lint(s)", denied)),
+    }
+}
+
+/// `nixt serve (--socket <path> | --tcp <address>)`: evaluates the newline-delimited JSON
+/// requests of each connection with one warm interpreter, connections being served in turn.
+fn run_serve(args: &[String]) -> Result<(), String> {
+    // Requests are evaluated like scripts of the working directory, with its project settings.
+    let mut options = Options::default();
+    apply_config(&mut options, project_config(Path::new("."))?);
+    let mut interpreter = builder(&options).build()?;
+    let mut args = args.iter();
+    match (args.next().map(|a| a.as_str()), args.next()) {
+        (Some("--tcp"), Some(address)) => {
+            let listener = std::net::TcpListener::bind(address).map_err(|e| e.to_string())?;
+            serve_connections(&mut interpreter, listener.incoming())
+        }
+        #[cfg(unix)]
+        (Some("--socket"), Some(path)) => {
+            use std::os::unix::net::UnixListener;
+            let listener = UnixListener::bind(path).map_err(|e| format!("{}: {}", path, e))?;
+            serve_connections(&mut interpreter, listener.incoming())
+        }
+        _ => Err("Usage: nixt serve (--socket <path> | --tcp <address>)".to_owned()),
+    }
+}
+
+fn serve_connections<S>(
+    interpreter: &mut Interpreter,
+    incoming: impl Iterator<Item = std::io::Result<S>>,
+) -> Result<(), String>
+where
+    for<'a> &'a S: std::io::Read + Write,
+{
+    for stream in incoming {
+        let stream = stream.map_err(|e| e.to_string())?;
+        let reader = std::io::BufReader::new(&stream);
+        if let Err(e) = serve::serve(interpreter, reader, &stream) {
+            eprintln!("Connection error: {}", e);
+        }
+    }
+    Ok(())
+}
+
+fn parse_source(content: &str, options: &Options) -> Result<Node, String> {
+    let mut lexer = Lexer::new(content);
+    lexer.set_case_insensitive(options.lenient);
+    lexer.set_trivia(true);
     let toks = lexer.scan_tokens();
+    if let Some(warnings) = lexer.get_warnings() {
+        for warning in warnings {
+            eprintln!("Warning: {}", warning);
+        }
+    }
+    lexer.set_max_errors(options.max_errors.unwrap_or(DEFAULT_MAX_ERRORS));
     let errs_lex = lexer.get_errors();
-    if *(&errs_lex.is_some()) {
-        let err_unwraped = &errs_lex.unwrap();
+    if let Some(err_unwraped) = &errs_lex {
         for error in err_unwraped {
             println!("{}", error);
         }
-        panic!("{} lexing errors occured !", err_unwraped.len());
+        return Err(error_summary("lexing", err_unwraped.len(), lexer.suppressed_errors()));
     }
     let mut parser = Parser::new(toks);
+    parser.set_strict(options.strict);
+    parser.set_infix(options.infix || has_infix_pragma(content));
+    parser.set_lenient(options.lenient);
     let ast = parser.parse();
+    if let Some(warnings) = parser.get_warnings() {
+        for warning in warnings {
+            eprintln!("Warning: {}", warning);
+        }
+    }
+    parser.set_max_errors(options.max_errors.unwrap_or(DEFAULT_MAX_ERRORS));
     let errs = parser.get_errors();
-    if errs.is_some() {
-        let err_unwraped = &errs.unwrap();
+    if let Some(err_unwraped) = &errs {
         for error in err_unwraped {
             println!("{}", error);
         }
-        panic!("{} parsing errors occured !", err_unwraped.len());
+        return Err(error_summary("parsing", err_unwraped.len(), parser.suppressed_errors()));
     }
-    Interpreter::new(Some(&ast))?;
+    Ok(ast)
+}
 
-    Ok(())
+/// The lexing or parsing errors printed before stopping, unless `--max-errors` says otherwise.
+const DEFAULT_MAX_ERRORS: usize = 20;
+
+fn error_summary(phase: &str, shown: usize, suppressed: usize) -> String {
+    if suppressed == 0 {
+        format!("{} {} errors occured !", shown, phase)
+    } else {
+        format!(
+            "{} {} errors occured, {} of them not shown (see `--max-errors`) !",
+            shown + suppressed,
+            phase,
+            suppressed
+        )
+    }
+}
+
+fn print_warnings(interpreter: &mut Interpreter) {
+    for warning in interpreter.take_warnings() {
+        eprintln!("Warning: {}", warning);
+    }
+}
+
+fn builder(options: &Options) -> InterpreterBuilder {
+    let mut builder = Interpreter::builder()
+        .strict(options.strict)
+        .infix(options.infix)
+        .lenient(options.lenient)
+        .const_exprs(options.const_exprs)
+        .history(options.debug)
+        .introspection(options.debug)
+        .sandbox(options.sandbox.clone());
+    for dir in &options.search_paths {
+        builder = builder.search_path(dir);
+    }
+    for (name, value) in &options.defines {
+        builder = builder.define(name, value.clone());
+    }
+    if options.verbose {
+        builder = builder.verbose(std::io::stderr(), Verbosity::all());
+    }
+    builder
+}
+
+/// The `.nixt.toml` closest to `start`, or the defaults without one.
+fn project_config(start: &Path) -> Result<ProjectConfig, String> {
+    Ok(config::discover(start)?.map(|(_, config)| config).unwrap_or_default())
+}
+
+/// The directory of `script`, from which its `.nixt.toml` is searched.
+fn script_dir(script: &Path) -> &Path {
+    match script.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    }
 }
 
-fn repl() -> Result<(), String> {
-    let mut interpreter = Interpreter::new(None)?;
+/// The options the `.nixt.toml` closest to `script` sets.
+fn script_options(script: &Path) -> Result<Options, String> {
+    let mut options = Options::default();
+    apply_config(&mut options, project_config(script_dir(script))?);
+    Ok(options)
+}
+
+/// Merges the project settings into the flags: a flag turns a setting on even if the project
+/// turns it off.
+fn apply_config(options: &mut Options, config: ProjectConfig) {
+    options.strict |= config.strict.unwrap_or(false);
+    options.infix |= config.infix.unwrap_or(false);
+    options.lenient |= config.lenient.unwrap_or(false);
+    options.const_exprs |= config.const_exprs.unwrap_or(false);
+    options.search_paths = config.search_paths;
+    options.sandbox = config.sandbox.unwrap_or_default();
+}
+
+/// Prints how the binding `name` evolved, for `:history name` in the repl.
+fn print_history(interpreter: &Interpreter, name: &str, options: &Options) {
+    if !options.debug {
+        eprintln!("Error: `:history` needs the `--debug` flag");
+    } else if name.is_empty() {
+        eprintln!("Error: Usage: :history <name>");
+    } else if interpreter.history(name).is_empty() {
+        println!("`{}` was never assigned", name);
+    } else {
+        for mutation in interpreter.history(name) {
+            println!("{}", mutation);
+        }
+    }
+}
+
+/// Watches the bindings of `--watch`, pausing at each change until Enter is pressed.
+fn watch(interpreter: &mut Interpreter, options: &Options) {
+    for name in &options.watches {
+        interpreter.watch(name);
+    }
+    interpreter.on_watch(|hit| {
+        eprint!("Watchpoint: {}\n(Enter to continue, q to stop) ", hit);
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer).map_err(|e| e.to_string())?;
+        match answer.trim() {
+            "q" => Err(format!("Stopped at the watchpoint on `{}`", hit.name)),
+            _ => Ok(()),
+        }
+    });
+}
+
+fn repl(options: &Options) -> Result<(), String> {
+    let mut interpreter = builder(options).build()?;
+    watch(&mut interpreter, options);
+    let signal = catch_signals(&interpreter)?;
+    let mut repl = Repl::new(interpreter);
     loop {
-        let mut input = String::new();
-        print!("> ");
+        let mut line = String::new();
+        print!("{}", repl.prompt());
         std::io::stdout().flush().unwrap();
-        std::io::stdin().read_line(&mut input).unwrap();
-        let mut lexer = Lexer::new(&input.trim());
-        let toks = lexer.scan_tokens();
-        let errs_lex = lexer.get_errors();
-        if *(&errs_lex.is_some()) {
-            let err_unwraped = &errs_lex.unwrap();
-            for error in err_unwraped {
-                println!("{}", error);
-            }
-            panic!("{} lexing errors occured !", err_unwraped.len());
-        }
-        let mut parser = Parser::new(toks);
-        let ast = parser.parse();
-        let errs = parser.get_errors();
-        if errs.is_some() {
-            let err_unwraped = &errs.unwrap();
-            for error in err_unwraped {
-                println!("{}", error);
+        if std::io::stdin().read_line(&mut line).map_err(|e| e.to_string())? == 0 {
+            println!();
+            return repl.interpreter().run_exit_handlers();
+        }
+        if !repl.continuing() && repl_command(repl.interpreter(), line.trim(), options) {
+            continue;
+        }
+        // Lines are read until the brackets of the input balance.
+        let input = match repl.feed(&line) {
+            Some(input) => input,
+            None => continue,
+        };
+        let interpreter = repl.interpreter();
+        // Ctrl-C interrupts the input being evaluated, not the session.
+        caught(&signal);
+        interpreter.interrupt_flag().store(false, std::sync::atomic::Ordering::Relaxed);
+        // A failing input leaves the session as it was before the failure: the interpreter
+        // unwinds its scopes, and the globals defined so far stay available.
+        let ast = match parse_source(input.trim(), options) {
+            Ok(ast) => ast,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                continue;
             }
-            panic!("{} parsing errors occured !", err_unwraped.len());
+        };
+        let result = interpreter.eval(&ast);
+        print_warnings(interpreter);
+        match result {
+            Ok(Value::Nil) => {}
+            Ok(result) if !options.quiet => println!("{}", result),
+            Ok(_) => {}
+            Err(e) => eprintln!("Error: {}", e),
+        }
+    }
+}
+
+/// Runs the REPL command `line`, `:history`, `:watch` or `:unwatch`, if it is one.
+fn repl_command(interpreter: &mut Interpreter, line: &str, options: &Options) -> bool {
+    if let Some(name) = line.strip_prefix(":history") {
+        print_history(interpreter, name.trim(), options);
+    } else if let Some(name) = line.strip_prefix(":watch") {
+        match name.trim() {
+            "" => interpreter.watched().for_each(|name| println!("{}", name)),
+            name => interpreter.watch(name),
+        }
+    } else if let Some(name) = line.strip_prefix(":unwatch") {
+        if !interpreter.unwatch(name.trim()) {
+            eprintln!("Error: `{}` is not watched", name.trim());
         }
-        interpreter.process_ast(&ast)?;
+    } else {
+        return false;
     }
+    true
 }
@@ -0,0 +1,102 @@
+use crate::error::{raise, RuntimeErrorKind};
+use crate::utils::element::{Func, List, Map, Value};
+use crate::utils::resource::Resource;
+
+/// Validates the arguments of a builtin, reporting mistakes uniformly:
+/// "builtin `sort_by` expected function at arg 0, got number".
+///
+/// ```
+/// use nixt::{stdlib::args::ArgParser, Value};
+/// let given = [Value::from("a,b"), Value::from(1.)];
+/// let args = ArgParser::new("split", &given).exact(2)?;
+/// assert_eq!(args.get_str(0)?, "a,b");
+/// assert_eq!(args.get_str(1).unwrap_err(), "builtin `split` expected string at arg 1, got number");
+/// # Ok::<(), String>(())
+/// ```
+#[derive(Clone, Copy)]
+pub struct ArgParser<'a> {
+  name: &'a str,
+  args: &'a [Value],
+}
+
+impl<'a> ArgParser<'a> {
+  pub fn new(name: &'a str, args: &'a [Value]) -> Self {
+    Self { name, args }
+  }
+  /// Errors unless exactly `count` arguments were given.
+  pub fn exact(self, count: usize) -> Result<Self, String> {
+    self.between(count, count)
+  }
+  /// Errors unless `min` to `max` arguments were given.
+  pub fn between(self, min: usize, max: usize) -> Result<Self, String> {
+    if (min..=max).contains(&self.args.len()) {
+      return Ok(self);
+    }
+    let expected = if min == max {
+      format!("{}", min)
+    } else {
+      format!("{} to {}", min, max)
+    };
+    let message = format!(
+      "builtin `{}` expected {} argument(s), got {}",
+      self.name,
+      expected,
+      self.args.len()
+    );
+    Err(raise(RuntimeErrorKind::Arity, message))
+  }
+  pub fn len(&self) -> usize {
+    self.args.len()
+  }
+  pub fn is_empty(&self) -> bool {
+    self.args.is_empty()
+  }
+  /// The argument at `index`, of any type.
+  pub fn get(&self, index: usize) -> Result<&'a Value, String> {
+    self.args.get(index).ok_or_else(|| self.expected(index, "a value"))
+  }
+  pub fn get_num(&self, index: usize) -> Result<f64, String> {
+    self.get(index)?.as_number().ok_or_else(|| self.expected(index, "number"))
+  }
+  /// A number without a fractional part, such as an index or a count.
+  pub fn get_int(&self, index: usize) -> Result<i64, String> {
+    match self.get(index)?.as_number() {
+      Some(n) if n.fract() == 0. => Ok(n as i64),
+      _ => Err(self.expected(index, "whole number")),
+    }
+  }
+  pub fn get_str(&self, index: usize) -> Result<&'a str, String> {
+    self.get(index)?.as_str().ok_or_else(|| self.expected(index, "string"))
+  }
+  pub fn get_bool(&self, index: usize) -> Result<bool, String> {
+    self.get(index)?.as_bool().ok_or_else(|| self.expected(index, "bool"))
+  }
+  pub fn get_list(&self, index: usize) -> Result<&'a List, String> {
+    self.get(index)?.as_list().ok_or_else(|| self.expected(index, "list"))
+  }
+  pub fn get_map(&self, index: usize) -> Result<&'a Map, String> {
+    self.get(index)?.as_map().ok_or_else(|| self.expected(index, "map"))
+  }
+  pub fn get_func(&self, index: usize) -> Result<&'a Func, String> {
+    self.get(index)?.as_func().ok_or_else(|| self.expected(index, "function"))
+  }
+  pub fn get_resource(&self, index: usize) -> Result<&'a Resource, String> {
+    self.get(index)?.as_resource().ok_or_else(|| self.expected(index, "resource"))
+  }
+  /// A function or a builtin, as [`Interpreter::call_value`](crate::Interpreter) calls.
+  pub fn get_callable(&self, index: usize) -> Result<&'a Value, String> {
+    match self.get(index)? {
+      callable @ (Value::Func(_) | Value::Native(_)) => Ok(callable),
+      _ => Err(self.expected(index, "function")),
+    }
+  }
+  /// The error for an argument that is not a `what`.
+  pub fn expected(&self, index: usize, what: &str) -> String {
+    let got = self.args.get(index).map_or("nothing", |arg| arg.type_name());
+    let message = format!(
+      "builtin `{}` expected {} at arg {}, got {}",
+      self.name, what, index, got
+    );
+    raise(RuntimeErrorKind::Type, message)
+  }
+}
@@ -1,18 +1,51 @@
+use crate::stdlib::args::ArgParser;
+use crate::stdlib::list::Caller;
 use crate::utils::element::Value;
-use std::io::Write;
+use std::io::{BufRead, Write};
 
-pub fn print(to_display: &Vec<Value>) -> Result<Value, String> {
+pub fn print(out: &mut dyn Write, to_display: &[Value]) -> Result<Value, String> {
   for val in to_display {
-    print!("{}", val);
+    write!(out, "{}", val).map_err(|e| e.to_string())?;
   }
-  println!();
+  writeln!(out).map_err(|e| e.to_string())?;
   Ok(Value::Nil)
 }
 
-pub fn puts(to_display: &Vec<Value>) -> Result<Value, String> {
+pub fn puts(out: &mut dyn Write, to_display: &[Value]) -> Result<Value, String> {
   for val in to_display {
-    print!("{}", val);
+    write!(out, "{}", val).map_err(|e| e.to_string())?;
   }
-  std::io::stdout().flush().unwrap();
+  out.flush().map_err(|e| e.to_string())?;
   Ok(Value::Nil)
 }
+
+/// `(for_lines "big.log" f)`: calls `f` with each line of the file, without its line break,
+/// reading one line at a time so that the file is never held whole in memory. `f` returning
+/// `false` stops the reading. Returns the number of lines `f` was called with.
+///
+/// Deny `for_lines` in the sandbox policy to keep scripts from reading files.
+///
+/// ```
+/// let path = std::env::temp_dir().join("nixt_for_lines_doc.log");
+/// std::fs::write(&path, "GET /\nGET /about\r\nstop\nGET /late\n").unwrap();
+/// let mut interpreter = nixt::Interpreter::builder().build()?;
+/// interpreter.set("path", nixt::Value::from(path.to_string_lossy().into_owned()))?;
+/// let code = "(for_lines path (func (line) { (ret (~ line \"stop\")) }))";
+/// assert_eq!(interpreter.eval_str(code)?.to_string(), "3");
+/// # Ok::<(), String>(())
+/// ```
+pub fn for_lines(args: &[Value], call: &mut Caller) -> Result<Value, String> {
+  let args = ArgParser::new("for_lines", args).exact(2)?;
+  let path = args.get_str(0)?;
+  let f = args.get_callable(1)?;
+  let file = std::fs::File::open(path).map_err(|e| format!("Could not read `{}`: {}", path, e))?;
+  let mut count = 0;
+  for line in std::io::BufReader::new(file).lines() {
+    let line = line.map_err(|e| format!("Could not read line {} of `{}`: {}", count + 1, path, e))?;
+    count += 1;
+    if let Value::Bool(false) = call(f, &[Value::String(line)])? {
+      break;
+    }
+  }
+  Ok(Value::Number(count as f64))
+}
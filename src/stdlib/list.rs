@@ -1,23 +1,29 @@
+use crate::stdlib::args::ArgParser;
+use crate::utils::collections;
 use crate::utils::element::Value;
+use indexmap::IndexMap;
+use std::cmp::Ordering;
 
-pub fn list(args: &Vec<Value>) -> Result<Value, String> {
-  Ok(Value::List(args.to_owned()))
+pub fn list(args: &[Value]) -> Result<Value, String> {
+  Ok(Value::from(args.to_owned()))
 }
 
-pub fn pop(args: &Vec<Value>) -> Result<Value, String> {
-  if args.len() < 1 {
+pub fn pop(args: &[Value]) -> Result<Value, String> {
+  if args.is_empty() {
     return Ok(Value::Nil);
   }
   if let Value::List(l) = &args[0] {
-    return Ok(Value::List((&l[..(l.len() - 1)]).to_owned()));
+    return Ok(Value::List(l.iter().take(l.len().saturating_sub(1)).cloned().collect()));
   } else if let Value::String(s) = &args[0] {
-    return Ok(Value::String((&s[..(s.len() - 1)]).to_owned()));
+    let mut chars = s.chars();
+    chars.next_back();
+    return Ok(Value::String(chars.as_str().to_owned()));
   }
 
   Ok(Value::Nil)
 }
 
-pub fn push(args: &Vec<Value>) -> Result<Value, String> {
+pub fn push(args: &[Value]) -> Result<Value, String> {
   if args.len() < 2 {
     return Ok(Value::Nil);
   }
@@ -56,49 +62,337 @@ pub fn push(args: &Vec<Value>) -> Result<Value, String> {
   Ok(Value::Nil)
 }
 
-pub fn index(args: &Vec<Value>) -> Result<Value, String> {
+/// `(range start end)` or `(range start end step)`, the end being excluded.
+pub fn range(args: &[Value]) -> Result<Value, String> {
+  let bounds = args
+    .iter()
+    .map(|arg| match arg {
+      Value::Number(n) => Ok(*n),
+      _ => Err("Range bounds must be numbers".to_owned()),
+    })
+    .collect::<Result<Vec<f64>, String>>()?;
+  match bounds[..] {
+    [start, end] => Ok(Value::Range(start, end, 1.)),
+    [_, _, 0.] => Err("A range step can not be 0".to_owned()),
+    [start, end, step] => Ok(Value::Range(start, end, step)),
+    _ => Err(format!(
+      "Invalid arguments number: expected 2 or 3 found {}",
+      args.len()
+    )),
+  }
+}
+
+/// How many numbers a range goes through.
+pub fn range_len(start: f64, end: f64, step: f64) -> usize {
+  ((end - start) / step).ceil().max(0.) as usize
+}
+
+/// The numbers a range goes through.
+pub fn range_items(start: f64, end: f64, step: f64) -> Vec<f64> {
+  (0..range_len(start, end, step))
+    .map(|i| start + i as f64 * step)
+    .collect()
+}
+
+/// `(in x collection)`: whether a range or a list holds `x`, or a string contains it.
+pub fn contains(args: &[Value]) -> Result<Value, String> {
+  if args.len() != 2 {
+    return Err(format!(
+      "Invalid arguments number: expected 2 found {}",
+      args.len()
+    ));
+  }
+  let found = match (&args[0], &args[1]) {
+    (Value::Number(n), Value::Range(start, end, step)) => {
+      let in_bounds = if *step > 0. {
+        n >= start && n < end
+      } else {
+        n <= start && n > end
+      };
+      in_bounds && ((n - start) / step).fract() == 0.
+    }
+    (x, Value::List(l)) => l.iter().any(|item| values_equal(x, item)),
+    (Value::String(x), Value::String(s)) => s.contains(x.as_str()),
+    _ => false,
+  };
+  Ok(Value::Bool(found))
+}
+
+/// Equality as `=` sees it, for values held in collections.
+pub fn values_equal(lhs: &Value, rhs: &Value) -> bool {
+  match (lhs, rhs) {
+    (Value::Number(l), Value::Number(r)) => l == r,
+    (Value::String(l), Value::String(r)) => l == r,
+    (Value::Bool(l), Value::Bool(r)) => l == r,
+    (Value::Nil, Value::Nil) => true,
+    (Value::Enum(ln, lv), Value::Enum(rn, rv)) => ln == rn && lv == rv,
+    (Value::Range(ls, le, lst), Value::Range(rs, re, rst)) => ls == rs && le == re && lst == rst,
+    (Value::Resource(l), Value::Resource(r)) => l.ptr_eq(r),
+    (Value::Map(l), Value::Map(r)) => {
+      let same = |(k, v): (&String, &Value)| r.get(k).is_some_and(|r| values_equal(v, r));
+      l.len() == r.len() && l.iter().all(same)
+    }
+    _ => false,
+  }
+}
+
+/// Picks the elements of a sequence of `len` elements, which `get` reads, at the indices a range
+/// goes through, skipping missing ones.
+fn slice<T>(
+  len: usize,
+  get: impl Fn(usize) -> Option<T>,
+  start: f64,
+  end: f64,
+  step: f64,
+) -> Vec<T> {
+  let end = if step > 0. { end.min(len as f64) } else { end };
+  range_items(start, end, step)
+    .into_iter()
+    .filter(|i| *i >= 0. && i.fract() == 0.)
+    .filter_map(|i| get(i as usize))
+    .collect()
+}
+
+pub fn index(args: &[Value]) -> Result<Value, String> {
   if args.len() < 2 {
     return Ok(Value::Nil);
   }
 
+  match (&args[0], &args[1]) {
+    (Value::String(s), Value::Range(start, end, step)) => {
+      let chars = s.chars().collect::<Vec<char>>();
+      let sliced = slice(chars.len(), |i| chars.get(i).copied(), *start, *end, *step);
+      return Ok(Value::String(sliced.into_iter().collect()));
+    }
+    (Value::List(l), Value::Range(start, end, step)) => {
+      return Ok(Value::from(slice(l.len(), |i| l.get(i).cloned(), *start, *end, *step)));
+    }
+    (Value::Map(m), Value::String(key)) => return Ok(m.get(key).cloned().unwrap_or(Value::Nil)),
+    (Value::Range(start, end, step), Value::Number(n)) => {
+      let n = n.floor();
+      if n < 0. || n as usize >= range_len(*start, *end, *step) {
+        return Ok(Value::Nil);
+      }
+      return Ok(Value::Number(start + n * step));
+    }
+    _ => {}
+  }
+
   if let Value::String(s) = &args[0] {
     if let Value::Number(n) = args[1] {
-      if n.floor() as usize >= s.len() {
-        return Ok(Value::Nil);
-      } else {
-        return Ok(Value::String(format!(
-          "{}",
-          s.chars().collect::<Vec<char>>()[n.floor() as usize]
-        )));
+      match s.chars().nth(n.floor() as usize) {
+        Some(c) => Ok(Value::String(c.to_string())),
+        None => Ok(Value::Nil),
       }
     } else {
-      return Ok(args[0].to_owned());
+      Ok(args[0].to_owned())
     }
   } else if let Value::List(l) = &args[0] {
     if let Value::Number(n) = args[1] {
       if n.floor() as usize >= l.len() {
-        return Ok(Value::Nil);
+        Ok(Value::Nil)
       } else {
-        return Ok(l[n.floor() as usize].to_owned());
+        Ok(l[n.floor() as usize].to_owned())
       }
     } else {
-      return Ok(args[0].to_owned());
+      Ok(args[0].to_owned())
     }
   } else {
-    return Ok(args[0].to_owned());
+    Ok(args[0].to_owned())
   }
 }
 
-pub fn len(args: &Vec<Value>) -> Result<Value, String> {
-  if args.len() < 1 {
+/// `(len x)`: the number of elements of a list or a range, or of characters of a string.
+///
+/// Strings are sequences of characters, Unicode scalar values, for `len`, `at`, `pop`, `reverse`
+/// and slicing alike: `(len "héllo")` is 5. A character is not always what a reader sees as one,
+/// as an accent may be a character of its own; `str:byte_len` and `str:bytes` give the UTF-8
+/// encoding.
+pub fn len(args: &[Value]) -> Result<Value, String> {
+  if args.is_empty() {
     return Ok(Value::Nil);
   }
 
   if let Value::String(s) = &args[0] {
-    return Ok(Value::Number(s.len() as f32));
+    Ok(Value::Number(s.chars().count() as f64))
   } else if let Value::List(l) = &args[0] {
-    return Ok(Value::Number(l.len() as f32));
+    Ok(Value::Number(l.len() as f64))
+  } else if let Value::Range(start, end, step) = &args[0] {
+    Ok(Value::Number(range_len(*start, *end, *step) as f64))
   } else {
-    return Ok(Value::Number(0.));
+    Ok(Value::Number(0.))
+  }
+}
+
+/// `(get? xs i)` or `(get? m "key")`: the element at `i` or the value of `key`, or nil if there
+/// is none or `xs` is not a collection.
+pub fn get_nil_safe(args: &[Value]) -> Result<Value, String> {
+  if args.len() < 2 {
+    return Ok(Value::Nil);
+  }
+
+  match (&args[0], &args[1]) {
+    (Value::List(_), Value::Number(n)) | (Value::String(_), Value::Number(n)) if *n >= 0. => {
+      index(args)
+    }
+    (Value::Map(m), Value::String(key)) => Ok(m.get(key).cloned().unwrap_or(Value::Nil)),
+    _ => Ok(Value::Nil),
+  }
+}
+
+/// `(get_in value (list 0 "a"))`: follows a path through nested collections, numbers indexing
+/// lists and strings keying maps. A missing element yields nil, or an error in strict mode.
+pub fn get_in(args: &[Value], strict: bool) -> Result<Value, String> {
+  if args.len() != 2 {
+    return Err(format!(
+      "Invalid arguments number: expected 2 found {}",
+      args.len()
+    ));
+  }
+  let path = path_of(&args[1])?;
+  let mut current = &args[0];
+  for (depth, segment) in path.iter().enumerate() {
+    match child(current, segment) {
+      Some(next) => current = next,
+      None if strict => return Err(missing(&path, depth)),
+      None => return Ok(Value::Nil),
+    }
+  }
+  Ok(current.clone())
+}
+
+/// `(set_in value (list 0 "a") new)`: a copy of `value` with the element at the path replaced.
+/// An index equal to a list's length appends, and a key a map lacks is inserted, both only as
+/// the last segment. An invalid path yields nil, or an error in strict mode.
+pub fn set_in(args: &[Value], strict: bool) -> Result<Value, String> {
+  if args.len() != 3 {
+    return Err(format!(
+      "Invalid arguments number: expected 3 found {}",
+      args.len()
+    ));
+  }
+  let path = path_of(&args[1])?;
+  let mut toret = args[0].clone();
+  match replace_in(&mut toret, &path, args[2].clone()) {
+    Ok(()) => Ok(toret),
+    Err(depth) if strict => Err(missing(&path, depth)),
+    Err(_) => Ok(Value::Nil),
+  }
+}
+
+fn path_of(path: &Value) -> Result<Vec<Value>, String> {
+  match path {
+    Value::List(segments) => Ok(collections::to_vec(segments)),
+    _ => Err("A path must be a list".to_owned()),
+  }
+}
+
+fn child<'a>(value: &'a Value, segment: &Value) -> Option<&'a Value> {
+  match (value, segment) {
+    (Value::List(l), Value::Number(n)) if *n >= 0. => l.get(*n as usize),
+    (Value::Map(m), Value::String(key)) => m.get(key),
+    _ => None,
+  }
+}
+
+/// Replaces the element at `path`, or returns the depth of the first invalid segment.
+fn replace_in(value: &mut Value, path: &[Value], new: Value) -> Result<(), usize> {
+  let (segment, rest) = match path.split_first() {
+    Some(split) => split,
+    None => {
+      *value = new;
+      return Ok(());
+    }
+  };
+  let (l, i) = match (value, segment) {
+    (Value::List(l), Value::Number(n)) if *n >= 0. && (*n as usize) <= l.len() => (l, *n as usize),
+    (Value::Map(m), Value::String(key)) => {
+      return match m.get_mut(key) {
+        Some(child) => replace_in(child, rest, new).map_err(|depth| depth + 1),
+        None if rest.is_empty() => {
+          m.insert(key.clone(), new);
+          Ok(())
+        }
+        None => Err(1),
+      }
+    }
+    _ => return Err(0),
+  };
+  if i == l.len() {
+    if !rest.is_empty() {
+      return Err(1);
+    }
+    l.insert(i, new);
+    return Ok(());
+  }
+  replace_in(&mut l[i], rest, new).map_err(|depth| depth + 1)
+}
+
+fn missing(path: &[Value], depth: usize) -> String {
+  let shown = path[..=depth]
+    .iter()
+    .map(|segment| format!("{}", segment))
+    .collect::<Vec<_>>();
+  format!("Nothing at path [{}]", shown.join(" "))
+}
+
+/// Calls a nixt function or a builtin from a builtin, through the interpreter.
+pub type Caller<'a> = dyn FnMut(&Value, &[Value]) -> Result<Value, String> + 'a;
+
+fn function_and_list(name: &str, args: &[Value]) -> Result<(Value, Vec<Value>), String> {
+  let args = ArgParser::new(name, args).exact(2)?;
+  Ok((args.get_callable(0)?.clone(), collections::to_vec(args.get_list(1)?)))
+}
+
+/// `(sort_by f xs)`: `xs` sorted by the keys `f` gives, keeping the order of equal keys.
+pub fn sort_by(
+  args: &[Value],
+  call: &mut Caller,
+) -> Result<Value, String> {
+  let (f, items) = function_and_list("sort_by", args)?;
+  let mut keyed = items
+    .into_iter()
+    .map(|item| Ok((call(&f, std::slice::from_ref(&item))?, item)))
+    .collect::<Result<Vec<_>, String>>()?;
+  keyed.sort_by(|(l, _), (r, _)| l.compare(r));
+  Ok(Value::List(keyed.into_iter().map(|(_, item)| item).collect()))
+}
+
+/// `(group_by f xs)`: a map from the keys `f` gives to the lists of the elements giving them,
+/// in the order keys are first seen. Keys that are not strings are written as they print, so
+/// that `4` and `"4"` share a group.
+pub fn group_by(
+  args: &[Value],
+  call: &mut Caller,
+) -> Result<Value, String> {
+  let (f, items) = function_and_list("group_by", args)?;
+  let mut groups: IndexMap<String, Vec<Value>> = IndexMap::new();
+  for item in items {
+    let key = match call(&f, std::slice::from_ref(&item))? {
+      Value::String(key) => key,
+      key => key.to_string(),
+    };
+    groups.entry(key).or_default().push(item);
+  }
+  Ok(Value::Map(groups.into_iter().map(|(key, group)| (key, Value::from(group))).collect()))
+}
+
+/// `(unique xs)`: `xs` without the elements deeply equal to an earlier one.
+pub fn unique(args: &[Value]) -> Result<Value, String> {
+  let mut toret: Vec<Value> = vec![];
+  for item in ArgParser::new("unique", args).exact(1)?.get_list(0)? {
+    if !toret.iter().any(|seen| seen.compare(item) == Ordering::Equal) {
+      toret.push(item.clone());
+    }
+  }
+  Ok(Value::from(toret))
+}
+
+/// `(reverse xs)`, also reversing strings.
+pub fn reverse(args: &[Value]) -> Result<Value, String> {
+  match args {
+    [Value::List(l)] => Ok(Value::List(l.iter().rev().cloned().collect())),
+    [Value::String(s)] => Ok(Value::String(s.chars().rev().collect())),
+    _ => Err(ArgParser::new("reverse", args).exact(1)?.expected(0, "list or string")),
   }
 }
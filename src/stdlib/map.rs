@@ -0,0 +1,89 @@
+use crate::error::{raise, RuntimeErrorKind};
+use crate::stdlib::args::ArgParser;
+use crate::utils::element::{sorted_entries, Map, Value};
+
+/// `(map "a" 1 "b" 2)`: the map of the keys and values given in turn, which `{a 1 b 2}` reads
+/// as. The keys stay in the order they are given; a key given twice keeps its first place and
+/// its last value.
+///
+/// ```
+/// let mut interpreter = nixt::Interpreter::builder().build()?;
+/// let code = "(let m {name \"nixt\" \"first release\" 2020}) (insert m \"stars\" 3)";
+/// let inserted = "{name: nixt, first release: 2020, stars: 3}";
+/// assert_eq!(interpreter.eval_str(code)?.to_string(), inserted);
+/// assert_eq!(interpreter.eval_str("(get m \"name\")")?.to_string(), "nixt");
+/// # Ok::<(), String>(())
+/// ```
+pub fn map(args: &[Value]) -> Result<Value, String> {
+  let parser = ArgParser::new("map", args);
+  if !args.len().is_multiple_of(2) {
+    let message = format!(
+      "builtin `map` expected keys and values in turn, got {} argument(s)",
+      args.len()
+    );
+    return Err(raise(RuntimeErrorKind::Arity, message));
+  }
+  let mut toret = Map::new();
+  for i in (0..args.len()).step_by(2) {
+    toret.insert(parser.get_str(i)?.to_owned(), args[i + 1].clone());
+  }
+  Ok(Value::Map(toret))
+}
+
+/// `(get m key)`: the value of `key` in `m`, or nil if `m` has none. `(get xs i)` is the
+/// element at index `i` of a list, or nil past its end.
+pub fn get(args: &[Value]) -> Result<Value, String> {
+  let args = ArgParser::new("get", args).exact(2)?;
+  let value = match args.get(0)? {
+    Value::Map(m) => m.get(args.get_str(1)?),
+    Value::List(items) => match args.get_int(1)? {
+      i if i >= 0 => items.get(i as usize),
+      _ => None,
+    },
+    _ => return Err(args.expected(0, "map or list")),
+  };
+  Ok(value.cloned().unwrap_or(Value::Nil))
+}
+
+/// `(insert m key value)`: a copy of `m` with `key` bound to `value`, added last if `m` has no
+/// value for it.
+pub fn insert(args: &[Value]) -> Result<Value, String> {
+  let parser = ArgParser::new("insert", args).exact(3)?;
+  let mut toret = parser.get_map(0)?.clone();
+  toret.insert(parser.get_str(1)?.to_owned(), args[2].clone());
+  Ok(Value::Map(toret))
+}
+
+/// `(remove m key)`: a copy of `m` without `key`, the other keys keeping their order.
+pub fn remove(args: &[Value]) -> Result<Value, String> {
+  let args = ArgParser::new("remove", args).exact(2)?;
+  let mut toret = args.get_map(0)?.clone();
+  toret.shift_remove(args.get_str(1)?);
+  Ok(Value::Map(toret))
+}
+
+/// `(keys m)`: the keys of `m`, in their order.
+pub fn keys(args: &[Value]) -> Result<Value, String> {
+  let m = ArgParser::new("keys", args).exact(1)?.get_map(0)?;
+  Ok(Value::list(m.keys().cloned()))
+}
+
+/// `(contains m key)`: whether `m` has a value for `key`.
+pub fn contains(args: &[Value]) -> Result<Value, String> {
+  let args = ArgParser::new("contains", args).exact(2)?;
+  Ok(Value::Bool(args.get_map(0)?.contains_key(args.get_str(1)?)))
+}
+
+/// `(sort_keys m)`: a copy of `m` with its keys sorted.
+///
+/// ```
+/// let mut interpreter = nixt::Interpreter::builder().build()?;
+/// let value = interpreter.eval_str("(sort_keys {b 1 c 2 a 3})")?;
+/// assert_eq!(value.to_string(), "{a: 3, b: 1, c: 2}");
+/// # Ok::<(), String>(())
+/// ```
+pub fn sort_keys(args: &[Value]) -> Result<Value, String> {
+  let m = ArgParser::new("sort_keys", args).exact(1)?.get_map(0)?;
+  let sorted = sorted_entries(m).into_iter().map(|(k, v)| (k.clone(), v.clone()));
+  Ok(Value::Map(sorted.collect()))
+}
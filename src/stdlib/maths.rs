@@ -1,7 +1,8 @@
+use crate::stdlib::args::ArgParser;
 use crate::utils::element::Value;
 
-pub fn cos(args: &Vec<Value>) -> Result<Value, String> {
-  if args.len() < 1 {
+pub fn cos(args: &[Value]) -> Result<Value, String> {
+  if args.is_empty() {
     return Ok(Value::Nil);
   }
 
@@ -12,8 +13,8 @@ pub fn cos(args: &Vec<Value>) -> Result<Value, String> {
   }
 }
 
-pub fn acos(args: &Vec<Value>) -> Result<Value, String> {
-  if args.len() < 1 {
+pub fn acos(args: &[Value]) -> Result<Value, String> {
+  if args.is_empty() {
     return Ok(Value::Nil);
   }
 
@@ -24,8 +25,8 @@ pub fn acos(args: &Vec<Value>) -> Result<Value, String> {
   }
 }
 
-pub fn sin(args: &Vec<Value>) -> Result<Value, String> {
-  if args.len() < 1 {
+pub fn sin(args: &[Value]) -> Result<Value, String> {
+  if args.is_empty() {
     return Ok(Value::Nil);
   }
 
@@ -36,8 +37,8 @@ pub fn sin(args: &Vec<Value>) -> Result<Value, String> {
   }
 }
 
-pub fn asin(args: &Vec<Value>) -> Result<Value, String> {
-  if args.len() < 1 {
+pub fn asin(args: &[Value]) -> Result<Value, String> {
+  if args.is_empty() {
     return Ok(Value::Nil);
   }
 
@@ -48,8 +49,8 @@ pub fn asin(args: &Vec<Value>) -> Result<Value, String> {
   }
 }
 
-pub fn tan(args: &Vec<Value>) -> Result<Value, String> {
-  if args.len() < 1 {
+pub fn tan(args: &[Value]) -> Result<Value, String> {
+  if args.is_empty() {
     return Ok(Value::Nil);
   }
 
@@ -60,8 +61,8 @@ pub fn tan(args: &Vec<Value>) -> Result<Value, String> {
   }
 }
 
-pub fn atan(args: &Vec<Value>) -> Result<Value, String> {
-  if args.len() < 1 {
+pub fn atan(args: &[Value]) -> Result<Value, String> {
+  if args.is_empty() {
     return Ok(Value::Nil);
   }
 
@@ -72,8 +73,8 @@ pub fn atan(args: &Vec<Value>) -> Result<Value, String> {
   }
 }
 
-pub fn floor(args: &Vec<Value>) -> Result<Value, String> {
-  if args.len() < 1 {
+pub fn floor(args: &[Value]) -> Result<Value, String> {
+  if args.is_empty() {
     return Ok(Value::Nil);
   }
   if let Value::Number(n) = args[0] {
@@ -83,8 +84,8 @@ pub fn floor(args: &Vec<Value>) -> Result<Value, String> {
   }
 }
 
-pub fn ceil(args: &Vec<Value>) -> Result<Value, String> {
-  if args.len() < 1 {
+pub fn ceil(args: &[Value]) -> Result<Value, String> {
+  if args.is_empty() {
     return Ok(Value::Nil);
   }
   if let Value::Number(n) = args[0] {
@@ -93,3 +94,21 @@ pub fn ceil(args: &Vec<Value>) -> Result<Value, String> {
     Ok(Value::Nil)
   }
 }
+
+/// `(math:round_to x digits)`: `x` rounded to `digits` decimals, negative ones rounding to
+/// tens, hundreds, ... More decimals than a number holds leave it unchanged.
+pub fn round_to(args: &[Value]) -> Result<Value, String> {
+  let args = ArgParser::new("math:round_to", args).exact(2)?;
+  let (x, digits) = (args.get_num(0)?, args.get_int(1)?);
+  let scale = 10f64.powi(digits.unsigned_abs().min(i32::MAX as u64) as i32);
+  let rounded = if digits >= 0 {
+    (x * scale).round() / scale
+  } else {
+    (x / scale).round() * scale
+  };
+  Ok(Value::Number(match rounded {
+    rounded if rounded.is_finite() => rounded,
+    _ if digits >= 0 => x,
+    _ => 0.,
+  }))
+}
@@ -1,39 +1,117 @@
-use crate::utils::element::Value;
+use crate::error::{raise, RuntimeErrorKind};
+use crate::stdlib::args::ArgParser;
+use crate::testing;
+use crate::utils::element::{Func, Value};
+use crate::utils::node::{Node, NodeType};
 
-pub fn assert(args: &Vec<Value>) -> Result<Value, String> {
+pub fn assert(args: &[Value]) -> Result<Value, String> {
   if args.len() != 1 {
-    return Err(format!(
-      "Invalid arguments number: expected 1 found {}",
-      args.len()
-    ));
+    let message = format!("Invalid arguments number: expected 1 found {}", args.len());
+    return Err(raise(RuntimeErrorKind::Arity, message));
   }
   if let Value::Bool(b) = &args[0] {
     if *b {
       return Ok(Value::Nil);
     } else {
-      return Err("Code panicked at assertion failed".to_owned());
+      return Err(raise(RuntimeErrorKind::Assertion, "Code panicked at assertion failed"));
     }
   }
   Ok(Value::Nil)
 }
-pub fn r#type_of(args: &Vec<Value>) -> Result<Value, String> {
-  if args.len() < 1 {
+/// `(assert_eq actual expected)`: fails with the elements that differ between the two values.
+pub fn assert_eq(args: &[Value]) -> Result<Value, String> {
+  ArgParser::new("assert_eq", args).exact(2)?;
+  let lines = testing::diff_values(&args[0], &args[1]);
+  if !lines.is_empty() {
+    let message = format!("Assertion failed, {}", testing::report(&lines));
+    return Err(raise(RuntimeErrorKind::Assertion, message));
+  }
+  Ok(Value::Nil)
+}
+pub fn r#type_of(args: &[Value]) -> Result<Value, String> {
+  if args.is_empty() {
     return Ok(Value::Nil);
   }
 
-  let toret = if let Value::String(_) = args[0] {
-    "string"
-  } else if let Value::Bool(_) = args[0] {
-    "bool"
-  } else if let Value::Func(_) = args[0] {
-    "function"
-  } else if let Value::List(_) = args[0] {
-    "list"
-  } else if let Value::Number(_) = args[0] {
-    "number"
-  } else {
-    "nil"
-  };
-
-  Ok(Value::String(toret.to_owned()))
+  Ok(Value::String(args[0].type_name().to_owned()))
+}
+
+/// `(version)`: the version of nixt running the script.
+pub fn version(args: &[Value]) -> Result<Value, String> {
+  ArgParser::new("version", args).exact(0)?;
+  Ok(Value::String(env!("CARGO_PKG_VERSION").to_owned()))
+}
+
+/// `(fn_arity f)`: the number of parameters of `f`.
+pub fn fn_arity(args: &[Value]) -> Result<Value, String> {
+  Ok(Value::Number(function("fn_arity", args)?.args.len() as f64))
+}
+
+/// `(fn_params f)`: the names of the parameters of `f`.
+pub fn fn_params(args: &[Value]) -> Result<Value, String> {
+  Ok(Value::list(function("fn_params", args)?.args.iter().cloned()))
+}
+
+/// `(fn_body f)`: the statements of the body of `f`, as an `ast` that `eval` evaluates.
+pub fn fn_body(args: &[Value]) -> Result<Value, String> {
+  let mut program = Node::new(NodeType::Block);
+  for statement in function("fn_body", args)?.body.get_child() {
+    program.add_children(statement);
+  }
+  Ok(Value::Ast(program))
+}
+
+/// `(close r)`: closes the resource `r` of the host, for every value holding it. Returns whether
+/// it was open, so that closing a resource twice does no harm.
+pub fn close(args: &[Value]) -> Result<Value, String> {
+  let resource = ArgParser::new("close", args).exact(1)?.get_resource(0)?;
+  Ok(Value::Bool(resource.close()?))
+}
+
+/// `(fingerprint x)`: [`Value::fingerprint`] as 16 hexadecimal digits, to key caches kept
+/// across runs.
+pub fn fingerprint(args: &[Value]) -> Result<Value, String> {
+  let value = ArgParser::new("fingerprint", args).exact(1)?.get(0)?;
+  Ok(Value::String(format!("{:016x}", value.fingerprint())))
+}
+
+/// `(value_encode x)`: [`Value::to_bytes`], the MessagePack encoding of `x` as a list of
+/// bytes, as `str:bytes` gives them.
+#[cfg(feature = "msgpack")]
+pub fn value_encode(args: &[Value]) -> Result<Value, String> {
+  let value = ArgParser::new("value_encode", args).exact(1)?.get(0)?;
+  Ok(Value::list(value.to_bytes()?.into_iter().map(|byte| byte as f64)))
+}
+
+/// `(value_decode bytes)`: [`Value::from_bytes`], the value a list of bytes encodes.
+#[cfg(feature = "msgpack")]
+pub fn value_decode(args: &[Value]) -> Result<Value, String> {
+  let args = ArgParser::new("value_decode", args).exact(1)?;
+  let mut bytes = vec![];
+  for (i, byte) in args.get_list(0)?.iter().enumerate() {
+    match byte.as_number() {
+      Some(n) if n.fract() == 0. && (0. ..=255.).contains(&n) => bytes.push(n as u8),
+      _ => {
+        let message = format!(
+          "builtin `value_decode` expected a list of bytes, found {} at {}",
+          byte, i
+        );
+        return Err(raise(RuntimeErrorKind::Type, message));
+      }
+    }
+  }
+  Value::from_bytes(&bytes)
+}
+
+/// The nixt function a reflection builtin inspects. Builtins have no parameters or body to
+/// show.
+fn function<'a>(name: &'static str, args: &'a [Value]) -> Result<&'a Func, String> {
+  let args = ArgParser::new(name, args).exact(1)?;
+  match args.get(0)? {
+    Value::Native(builtin) => {
+      let message = format!("`{}` can not inspect the builtin `{}`", name, builtin);
+      Err(raise(RuntimeErrorKind::Type, message))
+    }
+    _ => args.get_func(0),
+  }
 }
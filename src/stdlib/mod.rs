@@ -1,6 +1,9 @@
+pub mod args;
 pub mod io;
 pub mod list;
+pub mod map;
 pub mod maths;
 pub mod misc;
 pub mod str;
+pub mod table;
 pub mod time;
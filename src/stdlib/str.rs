@@ -1,11 +1,14 @@
-use crate::utils::element::Value;
+use crate::error::{raise, RuntimeErrorKind};
+use crate::stdlib::args::ArgParser;
+use crate::utils::element::{format_number, Value};
+use std::convert::TryFrom;
 
-pub fn cat(args: &Vec<Value>) -> Result<Value, String> {
+pub fn cat(args: &[Value]) -> Result<Value, String> {
   let mut toret = String::new();
 
   for arg in args {
     if let Value::String(s) = arg {
-      toret.push_str(&s);
+      toret.push_str(s);
     } else if let Value::Number(f) = arg {
       toret.push_str(&format!("{}", f));
     } else if let Value::Bool(b) = arg {
@@ -17,3 +20,91 @@ pub fn cat(args: &Vec<Value>) -> Result<Value, String> {
 
   Ok(Value::String(toret))
 }
+
+/// `(num_to_str x)`: `x` as it prints, which `parse_num` reads back as exactly `x`.
+pub fn num_to_str(args: &[Value]) -> Result<Value, String> {
+  let n = ArgParser::new("num_to_str", args).exact(1)?.get_num(0)?;
+  Ok(Value::String(format_number(n)))
+}
+
+/// `(parse_num "1.5e-8")`: the number a string spells in decimal or exponent notation, or as
+/// `inf`, `-inf` or `NaN`, ignoring the whitespace around it.
+///
+/// ```
+/// let mut interpreter = nixt::Interpreter::builder().build()?;
+/// assert_eq!(interpreter.eval_str("(parse_num \" 2.5e3\")")?.to_string(), "2500");
+/// assert!(interpreter.eval_str("(parse_num \"12px\")").is_err());
+/// # Ok::<(), String>(())
+/// ```
+pub fn parse_num(args: &[Value]) -> Result<Value, String> {
+  let s = ArgParser::new("parse_num", args).exact(1)?.get_str(0)?;
+  match s.trim().parse::<f64>() {
+    Ok(n) => Ok(Value::Number(n)),
+    Err(_) => {
+      let message = format!("builtin `parse_num` can not read {:?} as a number", s);
+      Err(raise(RuntimeErrorKind::Type, message))
+    }
+  }
+}
+
+/// `(str:upper s)`: `s` in upper case, by the Unicode case mapping rather than the rules of a
+/// locale: `(str:upper "straße")` is `STRASSE`.
+pub fn upper(args: &[Value]) -> Result<Value, String> {
+  let s = ArgParser::new("str:upper", args).exact(1)?.get_str(0)?;
+  Ok(Value::String(s.to_uppercase()))
+}
+
+/// `(str:lower s)`: `s` in lower case, by the Unicode case mapping.
+pub fn lower(args: &[Value]) -> Result<Value, String> {
+  let s = ArgParser::new("str:lower", args).exact(1)?.get_str(0)?;
+  Ok(Value::String(s.to_lowercase()))
+}
+
+/// `(str:byte_len s)`: the length of `s` encoded as UTF-8, where `len` counts characters.
+pub fn byte_len(args: &[Value]) -> Result<Value, String> {
+  let s = ArgParser::new("str:byte_len", args).exact(1)?.get_str(0)?;
+  Ok(Value::Number(s.len() as f64))
+}
+
+/// `(str:bytes s)`: the bytes of `s` encoded as UTF-8, as a list of numbers.
+pub fn bytes(args: &[Value]) -> Result<Value, String> {
+  let s = ArgParser::new("str:bytes", args).exact(1)?.get_str(0)?;
+  Ok(Value::list(s.bytes().map(|b| b as f64)))
+}
+
+/// `(str:chars s)`: the characters of `s`, as a list of strings of one character each, the
+/// strings `(at s i)` gives. nixt has no character type.
+pub fn chars(args: &[Value]) -> Result<Value, String> {
+  let s = ArgParser::new("str:chars", args).exact(1)?.get_str(0)?;
+  Ok(Value::list(s.chars().map(String::from)))
+}
+
+/// `(str:char_code "é")`: the Unicode code point of a string of one character, 233.
+pub fn char_code(args: &[Value]) -> Result<Value, String> {
+  let parser = ArgParser::new("str:char_code", args).exact(1)?;
+  let mut chars = parser.get_str(0)?.chars();
+  match (chars.next(), chars.next()) {
+    (Some(c), None) => Ok(Value::Number(c as u32 as f64)),
+    _ => Err(parser.expected(0, "string of one character")),
+  }
+}
+
+/// `(str:from_code 233)`: the string of the character of a Unicode code point, `é`.
+///
+/// ```
+/// let mut interpreter = nixt::Interpreter::builder().build()?;
+/// let code = "(str:from_code (+ (str:char_code (at \"héllo\" 1)) 1))";
+/// assert_eq!(interpreter.eval_str(code)?.to_string(), "ê");
+/// assert!(interpreter.eval_str("(str:from_code 55296)").is_err());
+/// # Ok::<(), String>(())
+/// ```
+pub fn from_code(args: &[Value]) -> Result<Value, String> {
+  let code = ArgParser::new("str:from_code", args).exact(1)?.get_int(0)?;
+  match u32::try_from(code).ok().and_then(std::char::from_u32) {
+    Some(c) => Ok(Value::String(c.to_string())),
+    None => {
+      let message = format!("builtin `str:from_code`: {} is not a Unicode code point", code);
+      Err(raise(RuntimeErrorKind::Type, message))
+    }
+  }
+}
@@ -0,0 +1,245 @@
+use crate::error::{raise, RuntimeErrorKind};
+use crate::stdlib::args::ArgParser;
+#[cfg(feature = "arrow")]
+use crate::utils::element::Map;
+use crate::utils::element::Value;
+
+/// `(csv_parse text)`: the records of the CSV `text` as maps from the names of its header, its
+/// first record, to the fields, kept as strings for `parse_num` to read. `(csv_parse text
+/// "rows")` gives every record, the header included, as a list of fields instead.
+///
+/// Fields are separated by commas and records by line breaks. A field in double quotes may hold
+/// either, and `""` for a quote. Empty lines are skipped.
+///
+/// ```
+/// let mut interpreter = nixt::Interpreter::builder().build()?;
+/// let code = "(csv_parse 'name,stars\nnixt,3\n\"a, b\",\"\"\"\"')";
+/// let parsed = interpreter.eval_str(code)?;
+/// assert_eq!(parsed.to_string(), "[{name: nixt, stars: 3}, {name: a, b, stars: \"}]");
+/// let rows = interpreter.eval_str("(csv_parse \"a,b\n1,2\" \"rows\")")?;
+/// assert_eq!(rows.to_string(), "[[a, b], [1, 2]]");
+/// # Ok::<(), String>(())
+/// ```
+pub fn csv_parse(args: &[Value]) -> Result<Value, String> {
+  let parser = ArgParser::new("csv_parse", args).between(1, 2)?;
+  let records = records(parser.get_str(0)?)?;
+  match args.get(1) {
+    None => {}
+    Some(_) if parser.get_str(1)? == "rows" => {
+      let rows = records.into_iter().map(|(_, fields)| Value::list(fields));
+      return Ok(Value::List(rows.collect()));
+    }
+    Some(_) => {
+      return Err(format!(
+        "`csv_parse` reads records as maps, or as \"rows\", not {:?}",
+        parser.get_str(1)?
+      ))
+    }
+  }
+  let mut records = records.into_iter();
+  let header = match records.next() {
+    Some((_, header)) => header,
+    None => return Ok(Value::List(Default::default())),
+  };
+  for (i, name) in header.iter().enumerate() {
+    if header[..i].contains(name) {
+      return Err(format!("builtin `csv_parse`: the header names `{}` twice", name));
+    }
+  }
+  let mut rows = vec![];
+  for (line, fields) in records {
+    if fields.len() != header.len() {
+      return Err(format!(
+        "builtin `csv_parse`: line {} has {} field(s), the header {}",
+        line,
+        fields.len(),
+        header.len()
+      ));
+    }
+    let row = header.iter().cloned().zip(fields.into_iter().map(Value::String));
+    rows.push(Value::Map(row.collect()));
+  }
+  Ok(Value::from(rows))
+}
+
+/// The records of `text`, with the line each starts at.
+fn records(text: &str) -> Result<Vec<(usize, Vec<String>)>, String> {
+  let mut records = vec![];
+  let (mut fields, mut field) = (vec![], String::new());
+  let (mut line, mut start) = (1, 1);
+  // Whether the current field is quoted, and whether its closing quote is not yet read.
+  let (mut quoted, mut in_quotes) = (false, false);
+  let mut chars = text.chars().peekable();
+  while let Some(c) = chars.next() {
+    match c {
+      '"' if in_quotes && chars.peek() == Some(&'"') => {
+        chars.next();
+        field.push('"');
+      }
+      '"' if in_quotes => in_quotes = false,
+      '"' if field.is_empty() && !quoted => {
+        quoted = true;
+        in_quotes = true;
+      }
+      '\n' if in_quotes => {
+        line += 1;
+        field.push(c);
+      }
+      ',' if !in_quotes => {
+        fields.push(std::mem::take(&mut field));
+        quoted = false;
+      }
+      '\r' if !in_quotes && chars.peek() == Some(&'\n') => {}
+      '\n' if !in_quotes => {
+        if quoted || !field.is_empty() || !fields.is_empty() {
+          fields.push(std::mem::take(&mut field));
+          records.push((start, std::mem::take(&mut fields)));
+        }
+        quoted = false;
+        line += 1;
+        start = line;
+      }
+      _ => field.push(c),
+    }
+  }
+  if in_quotes {
+    return Err(format!("builtin `csv_parse`: the quoted field at line {} is not closed", start));
+  }
+  if quoted || !field.is_empty() || !fields.is_empty() {
+    fields.push(field);
+    records.push((start, fields));
+  }
+  Ok(records)
+}
+
+/// `(csv_write rows)`: the CSV of `rows`, a list of maps or of lists. Maps are written under a
+/// header of their keys, in the order they are first seen, leaving the fields of missing keys
+/// empty. Strings are written as they are, nil as an empty field and other values as they
+/// print; fields holding a comma, a quote or a line break are quoted.
+///
+/// ```
+/// let mut interpreter = nixt::Interpreter::builder().build()?;
+/// let code = "(csv_write [{name \"nixt\" stars 3} {name \"a, b\" tags nil}])";
+/// let csv = interpreter.eval_str(code)?;
+/// assert_eq!(csv.to_string(), "name,stars,tags\nnixt,3,\n\"a, b\",,\n");
+/// let csv = interpreter.eval_str(r#"(csv_write [[1 'say "hi"']])"#)?;
+/// assert_eq!(csv.to_string(), "1,\"say \"\"hi\"\"\"\n");
+/// # Ok::<(), String>(())
+/// ```
+pub fn csv_write(args: &[Value]) -> Result<Value, String> {
+  let parser = ArgParser::new("csv_write", args).exact(1)?;
+  let rows = parser.get_list(0)?;
+  if rows.is_empty() {
+    return Ok(Value::from(""));
+  }
+  if rows.iter().all(|row| row.as_map().is_some()) {
+    let mut header: Vec<&String> = vec![];
+    for row in rows.iter().filter_map(Value::as_map) {
+      for key in row.keys() {
+        if !header.contains(&key) {
+          header.push(key);
+        }
+      }
+    }
+    let mut csv = record(header.iter().map(|key| key.as_str()));
+    for row in rows.iter().filter_map(Value::as_map) {
+      let fields = header.iter().map(|key| row.get(key.as_str()).unwrap_or(&Value::Nil));
+      csv.push_str(&record(fields.map(field)));
+    }
+    return Ok(Value::String(csv));
+  }
+  let mut records: Vec<Vec<&Value>> = vec![];
+  for row in rows.iter() {
+    match row.as_list() {
+      Some(fields) => records.push(fields.iter().collect()),
+      None => {
+        let message = format!(
+          "builtin `csv_write` expected a list of maps or of lists, found a {} in it",
+          row.type_name()
+        );
+        return Err(raise(RuntimeErrorKind::Type, message));
+      }
+    }
+  }
+  let csv = records.into_iter().map(|fields| record(fields.into_iter().map(field)));
+  Ok(Value::String(csv.collect()))
+}
+
+/// `value` as the text of a field.
+fn field(value: &Value) -> String {
+  match value {
+    Value::String(s) => s.clone(),
+    Value::Nil => String::new(),
+    other => other.to_string(),
+  }
+}
+
+/// The line of a record of `fields`, quoting those that need it.
+fn record<S: AsRef<str>>(fields: impl Iterator<Item = S>) -> String {
+  let fields = fields.map(|field| {
+    let field = field.as_ref();
+    if field.contains(&[',', '"', '\n', '\r'][..]) {
+      format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+      field.to_owned()
+    }
+  });
+  let mut line = fields.collect::<Vec<_>>().join(",");
+  line.push('\n');
+  line
+}
+
+/// `(arrow_read "data.arrow")`: the rows of the Arrow IPC file at the path, as maps from the
+/// names of its columns to the values. Numbers of any width are read as numbers, strings as
+/// strings, booleans as bools and nulls as nil; other column types are an error.
+#[cfg(feature = "arrow")]
+pub fn arrow_read(args: &[Value]) -> Result<Value, String> {
+  let path = ArgParser::new("arrow_read", args).exact(1)?.get_str(0)?;
+  let invalid = |e: &dyn std::fmt::Display| format!("Could not read `{}` as Arrow: {}", path, e);
+  let file = std::fs::File::open(path).map_err(|e| invalid(&e))?;
+  let reader = arrow_ipc::reader::FileReader::try_new(file, None).map_err(|e| invalid(&e))?;
+  let mut rows = vec![];
+  for batch in reader {
+    let batch = batch.map_err(|e| invalid(&e))?;
+    let schema = batch.schema();
+    for row in 0..batch.num_rows() {
+      let mut values = Map::new();
+      for (field, column) in schema.fields().iter().zip(batch.columns()) {
+        let value = arrow_value(column.as_ref(), row)
+          .ok_or_else(|| invalid(&format!("column `{}` is {}", field.name(), field.data_type())))?;
+        values.insert(field.name().clone(), value);
+      }
+      rows.push(Value::Map(values));
+    }
+  }
+  Ok(Value::from(rows))
+}
+
+/// The value at `row` of `column`, if nixt has a type for it.
+#[cfg(feature = "arrow")]
+fn arrow_value(column: &dyn arrow_array::Array, row: usize) -> Option<Value> {
+  use arrow_array::cast::AsArray;
+  use arrow_array::types::*;
+  use arrow_schema::DataType;
+  if column.is_null(row) {
+    return Some(Value::Nil);
+  }
+  let number = match column.data_type() {
+    DataType::Boolean => return Some(Value::Bool(column.as_boolean().value(row))),
+    DataType::Utf8 => return Some(Value::from(column.as_string::<i32>().value(row))),
+    DataType::LargeUtf8 => return Some(Value::from(column.as_string::<i64>().value(row))),
+    DataType::Int8 => column.as_primitive::<Int8Type>().value(row) as f64,
+    DataType::Int16 => column.as_primitive::<Int16Type>().value(row) as f64,
+    DataType::Int32 => column.as_primitive::<Int32Type>().value(row) as f64,
+    DataType::Int64 => column.as_primitive::<Int64Type>().value(row) as f64,
+    DataType::UInt8 => column.as_primitive::<UInt8Type>().value(row) as f64,
+    DataType::UInt16 => column.as_primitive::<UInt16Type>().value(row) as f64,
+    DataType::UInt32 => column.as_primitive::<UInt32Type>().value(row) as f64,
+    DataType::UInt64 => column.as_primitive::<UInt64Type>().value(row) as f64,
+    DataType::Float16 => column.as_primitive::<Float16Type>().value(row).to_f64(),
+    DataType::Float32 => column.as_primitive::<Float32Type>().value(row) as f64,
+    DataType::Float64 => column.as_primitive::<Float64Type>().value(row),
+    _ => return None,
+  };
+  Some(Value::Number(number))
+}
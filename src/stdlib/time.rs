@@ -8,7 +8,7 @@ pub fn now() -> Result<Value, String> {
     Err(_) => return Err("Time went backwards !".to_owned()),
   };
 
-  let toret = since_1970.as_secs_f32();
+  let toret = since_1970.as_secs_f64();
 
   Ok(Value::Number(toret))
 }
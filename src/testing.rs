@@ -0,0 +1,79 @@
+//! Helpers for testing code that embeds nixt.
+
+use crate::stdlib::list::values_equal;
+use crate::utils::element::Value;
+
+/// The differences between `actual` and `expected`, one line for each element that differs,
+/// located by its path of list indices as [`Value::get_path`] takes it. Empty if they are equal.
+///
+/// ```
+/// use nixt::{testing::diff_values, Value};
+/// let actual = Value::list(vec![Value::list([1, 2]), Value::from(3)]);
+/// let expected = Value::list(vec![Value::list([1, 5]), Value::from(3), Value::from("a")]);
+/// assert_eq!(
+///   diff_values(&actual, &expected),
+///   ["at 0.1: expected 5, found 2", "at 2: expected \"a\", missing"]
+/// );
+/// ```
+pub fn diff_values(actual: &Value, expected: &Value) -> Vec<String> {
+  let mut lines = vec![];
+  diff_at("", actual, expected, &mut lines);
+  lines
+}
+
+/// Panics with the structural diff of `actual` and `expected` if they are not equal, as `=`
+/// compares elements.
+///
+/// ```should_panic
+/// use nixt::{testing::assert_value_eq, Value};
+/// assert_value_eq(&Value::list([1, 2, 3]), &Value::list([1, 2, 4]));
+/// ```
+#[track_caller]
+pub fn assert_value_eq(actual: &Value, expected: &Value) {
+  let lines = diff_values(actual, expected);
+  if !lines.is_empty() {
+    panic!("{}", report(&lines));
+  }
+}
+
+/// The lines of a diff under a heading, as `assert_value_eq` and `(assert_eq a b)` show them.
+pub(crate) fn report(lines: &[String]) -> String {
+  format!("values differ:\n  {}", lines.join("\n  "))
+}
+
+fn diff_at(path: &str, actual: &Value, expected: &Value, lines: &mut Vec<String>) {
+  let at = if path.is_empty() { String::new() } else { format!("at {}: ", path) };
+  match (actual, expected) {
+    (Value::List(actual), Value::List(expected)) => {
+      for i in 0..actual.len().max(expected.len()) {
+        let path = if path.is_empty() { i.to_string() } else { format!("{}.{}", path, i) };
+        match (actual.get(i), expected.get(i)) {
+          (Some(a), Some(e)) => diff_at(&path, a, e, lines),
+          (None, Some(e)) => lines.push(format!("at {}: expected {}, missing", path, show(e))),
+          (Some(a), None) => lines.push(format!("at {}: unexpected {}", path, show(a))),
+          (None, None) => {}
+        }
+      }
+    }
+    _ if values_equal(actual, expected) => {}
+    _ => lines.push(format!("{}expected {}, found {}", at, show(expected), show(actual))),
+  }
+}
+
+/// `value` as written in a diff or a snapshot, with strings quoted to tell `"1"` from `1`.
+pub(crate) fn show(value: &Value) -> String {
+  match value {
+    Value::String(s) => format!("{:?}", s),
+    Value::List(items) => {
+      let items = items.iter().map(show).collect::<Vec<_>>();
+      format!("[{}]", items.join(", "))
+    }
+    Value::Map(m) => {
+      let entries = m.iter().map(|(k, v)| format!("{:?}: {}", k, show(v))).collect::<Vec<_>>();
+      format!("{{{}}}", entries.join(", "))
+    }
+    Value::Func(func) => format!("a function of ({})", func.args.join(" ")),
+    Value::Native(name) => format!("the builtin `{}`", name),
+    other => other.to_string(),
+  }
+}
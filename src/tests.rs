@@ -1,9 +1,16 @@
 #[cfg(test)]
 mod test {
   use crate::{
-    core::interpreter::interpreter::Interpreter, core::lexer::*, core::parser::*, utils::node::*,
+    ast, core::interpreter::harness::Snapshots, core::interpreter::interpreter::Interpreter,
+    core::interpreter::plugin::{Builtins, NixtPlugin}, core::interpreter::pool::InterpreterPool,
+    core::interpreter::replay::{Trace, TraceEvent}, core::interpreter::sandbox::SandboxPolicy,
+    core::grammar::{self, Rule}, core::lexer::*, core::parser::*, tools::config, tools::doc,
+    testing, tools::graph, tools::lint, tools::metrics, tools::rename, tools::serve, tools::test,
+    utils::element::Value, utils::json, utils::node::*, utils::token::{Token, TokenType},
+    Transfer, Verbosity,
   };
-  use std::time::Instant;
+  use std::path::Path;
+  use std::time::{Duration, Instant};
 
   fn get_ast(code: &str) -> Result<String, String> {
     let mut lexer = Lexer::new(code);
@@ -26,6 +33,23 @@ mod test {
     Ok(stringify(&ast, 0))
   }
 
+  fn run_infix(code: &str) -> Result<(), String> {
+    let mut lexer = Lexer::new(code);
+    let mut parser = Parser::new(lexer.scan_tokens());
+    parser.set_infix(true);
+    let ast = parser.parse();
+    if parser.get_errors().is_some() {
+      return Err(format!(
+        "Parsing errors occured: {:?}",
+        parser.get_errors().unwrap()
+      ));
+    }
+
+    Interpreter::builder().build()?.eval(&ast)?;
+
+    Ok(())
+  }
+
   fn run(code: &str) -> Result<(), String> {
     let mut lexer = Lexer::new(code);
     let tokens = lexer.scan_tokens();
@@ -44,8 +68,71 @@ mod test {
       ));
     }
 
-    Interpreter::new(Some(&ast))?;
+    Interpreter::builder().build()?.eval(&ast)?;
+
+    Ok(())
+  }
+
+  /// What a corpus program printed, and the value it ended with or the error it failed with.
+  #[derive(Debug, PartialEq)]
+  struct Outcome {
+    stdout: String,
+    result: Result<String, String>,
+  }
+
+  type Engine = fn(&str) -> Outcome;
+
+  fn tree_walker(code: &str) -> Outcome {
+    let out = SharedBuffer::default();
+    let result = Interpreter::builder()
+      .stdout(out.clone())
+      .build()
+      .and_then(|mut interpreter| interpreter.eval_str(code))
+      .map(|value| value.to_string());
+    let stdout = String::from_utf8_lossy(&out.0.borrow()).into_owned();
+    Outcome { stdout, result }
+  }
+
+  /// Every evaluation backend the golden corpus is checked against. Backends must agree with
+  /// each other on the output and the final value, and with the expected `.out` and `.err`
+  /// files, if any.
+  const ENGINES: &[(&str, Engine)] = &[("tree-walker", tree_walker)];
+
+  #[test]
+  fn golden_corpus() -> Result<(), String> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let mut programs = std::fs::read_dir(&dir)
+      .map_err(|e| e.to_string())?
+      .filter_map(|entry| entry.ok().map(|e| e.path()))
+      .filter(|path| path.extension().is_some_and(|ext| ext == "nxt"))
+      .collect::<Vec<_>>();
+    programs.sort();
+    assert!(!programs.is_empty(), "golden corpus is empty");
+
+    for program in programs {
+      let code = std::fs::read_to_string(&program).map_err(|e| e.to_string())?;
+      let expected = std::fs::read_to_string(program.with_extension("err")).ok();
+      let stdout = std::fs::read_to_string(program.with_extension("out")).unwrap_or_default();
 
+      let mut results = ENGINES.iter().map(|(name, engine)| (name, engine(&code)));
+      let (first_name, first) = results.next().unwrap();
+      for (name, result) in results {
+        assert_eq!(
+          first, result,
+          "{}: `{}` and `{}` disagree",
+          program.display(),
+          first_name,
+          name
+        );
+      }
+      assert_eq!(first.stdout, stdout, "{}: unexpected output", program.display());
+      match (expected, first.result) {
+        (None, Err(e)) => panic!("{}: unexpected error: {}", program.display(), e),
+        (Some(exp), Ok(_)) => panic!("{}: expected error `{}`", program.display(), exp.trim()),
+        (Some(exp), Err(e)) => assert_eq!(e, exp.trim(), "{}", program.display()),
+        (None, Ok(_)) => {}
+      }
+    }
     Ok(())
   }
 
@@ -234,6 +321,34 @@ mod test {
     Ok(())
   }
   #[test]
+  fn early_returns() -> Result<(), String> {
+    let code = r#"
+    (let sign (func (n) {
+      (if (> n 0) ((ret "positive")))
+      (if (< n 0) ((ret "negative")) ({(ret "zero")}))
+      (ret "unreachable")
+    }))
+    (assert (= (sign 2) "positive"))
+    (assert (= (sign (- 0 2)) "negative"))
+    (assert (= (sign 0) "zero"))
+    (let find (func (xs x) {
+      (let i 0)
+      (while (< i (len xs)) {
+        (if (= (at xs i) x) ((ret i)))
+        (set i (+ i 1))
+      })
+      (ret -1)
+    }))
+    (assert (= (find (list 4 5 6) 5) 1))
+    (assert (= (find (list 4 5 6) 7) -1))
+    "#;
+    run(code)?;
+    let error = run("(let f (func () {(ret (do (ret 1)))})) (f)").unwrap_err();
+    assert_eq!(error, "`ret` can not return from inside a `do` or `cond` expression");
+
+    Ok(())
+  }
+  #[test]
   fn loops() -> Result<(), String> {
     let code = r#"
     (let i 0)
@@ -247,6 +362,47 @@ mod test {
     Ok(())
   }
   #[test]
+  fn loop_scopes() -> Result<(), String> {
+    let code = r#"
+    (let i 10)
+    (let sum 0)
+    (while (> i 0) {
+      (let next (- i 1))
+      (set sum (+ sum next))
+      (set i next)
+    })
+    (assert (= sum 45))
+    (let next "defined once the loop is done")
+    (let fs [])
+    (while (< i 3) {
+      (let k i)
+      (set fs (push fs (func () {(ret k)})))
+      (set i (+ i 1))
+    })
+    (let first (at fs 0))
+    (let last (at fs 2))
+    (assert (= (first) 0))
+    (assert (= (last) 2))
+    (let collect (func (n) {
+      (let made [])
+      (while (> n 0) {
+        (let k n)
+        (set made (push made (func () {(ret (* k 10))})))
+        (set n (- n 1))
+      })
+      (ret made)
+    }))
+    (let made (collect 2))
+    (let twenty (at made 0))
+    (let ten (at made 1))
+    (assert (= (twenty) 20))
+    (assert (= (ten) 10))
+    "#;
+    run(code)?;
+
+    Ok(())
+  }
+  #[test]
   fn conditions() -> Result<(), String> {
     let code = r#"
     (let foo "bar")
@@ -261,6 +417,2691 @@ mod test {
 
     Ok(())
   }
+  #[test]
+  fn truthiness() -> Result<(), String> {
+    let code = r#"
+    (let hits 0)
+    (if 0 ((set hits 100)) ((set hits (+ hits 1))))
+    (if "" ((set hits 100)) ((set hits (+ hits 1))))
+    (if nil ((set hits 100)) ((set hits (+ hits 1))))
+    (if (+ 1 1) ((set hits (+ hits 1))))
+    (if "text" ((set hits (+ hits 1))))
+    (let n 2)
+    (while n {(set hits (+ hits 1)) (set n (- n 1))})
+    (assert (= hits 7))
+    (let zero 0)
+    (assert (= (cond (zero "zero") (hits "hits")) "hits"))
+    "#;
+    run(code)?;
+    assert_eq!(
+      run("(if (list 1) ((print 1)))").unwrap_err(),
+      "Attempted to use a list as a condition"
+    );
+
+    Ok(())
+  }
+
+  #[test]
+  fn nil_safety() -> Result<(), String> {
+    let code = r#"
+    (let l (list 1 2))
+    (assert (= (?? nil 5) 5))
+    (assert (= (?? (at l 0) 5) 1))
+    (assert (= (?? (get? l 7) "none") "none"))
+    (assert (= (get? nil 0) nil))
+    (assert (= (get? l -1) nil))
+    (let m {a 1 b nil})
+    (assert (= (get? m "a") 1))
+    (assert (= (?? (get? m "b") 2) 2))
+    (assert (= (?? (get? m "c") 3) 3))
+    (assert (= (get? m 0) nil))
+    (assert (= (?? 1 (undefined_function)) 1))
+    "#;
+    run(code)?;
+
+    // `?` only ends a name: it can not be in the middle of one.
+    let types = |code| Lexer::new(code).scan_tokens().into_iter().map(|t| t.typ);
+    assert_eq!(types("get?").next(), Some(TokenType::Identifier("get?".to_owned())));
+    let coalesced = types("a??").take(2).collect::<Vec<_>>();
+    assert_eq!(coalesced, [TokenType::Identifier("a".to_owned()), TokenType::QuestionQuestion]);
+    let mut lexer = Lexer::new("a?b");
+    assert_eq!(lexer.scan_tokens()[0].typ, TokenType::Identifier("a".to_owned()));
+    assert!(lexer.get_errors().is_some());
+    Ok(())
+  }
+
+  #[test]
+  fn do_expression() -> Result<(), String> {
+    let code = r#"
+    (let x (do
+      (let a 2)
+      (let b 3)
+      (set a (* a b))
+      (+ a 1)
+    ))
+    (assert (= x 7))
+    (assert (= (do) nil))
+    (assert (= (do 1 "two") "two"))
+    (do (let a 1))
+    (let a "outer")
+    "#;
+    run(code)?;
+
+    Ok(())
+  }
+
+  #[test]
+  fn cond_expression() -> Result<(), String> {
+    let code = r#"
+    (let sign (func (n) {
+      (ret (cond
+        ((< n 0) "negative")
+        ((= n 0) "zero")
+        (else "positive")
+      ))
+    }))
+    (assert (= (sign -4) "negative"))
+    (assert (= (sign 0) "zero"))
+    (assert (= (sign 9) "positive"))
+    (let hits 0)
+    (cond
+      (false (set hits 10))
+      (true (set hits (+ hits 1)) (set hits (+ hits 1)))
+      (true (set hits 100))
+    )
+    (assert (= hits 2))
+    (assert (= (cond (false 1)) nil))
+    "#;
+    run(code)?;
+
+    Ok(())
+  }
+
+  #[test]
+  fn comment_trivia() -> Result<(), String> {
+    let code = "# adds one\n%% 5 % 2\nspans lines %%\n(let x (+ 1 1)) # trailing";
+    let mut lexer = Lexer::new(code);
+    lexer.set_trivia(true);
+    let tokens = lexer.scan_tokens();
+    let comments = |tok: &Token| {
+      tok
+        .trivia
+        .iter()
+        .map(|c| (c.typ.clone(), c.line))
+        .collect::<Vec<_>>()
+    };
+    assert_eq!(
+      comments(&tokens[0]),
+      vec![
+        (TokenType::Comment(" adds one".to_owned()), 1),
+        (TokenType::Comment(" 5 % 2\nspans lines ".to_owned()), 2),
+      ]
+    );
+    assert_eq!(
+      comments(tokens.last().unwrap()),
+      vec![(TokenType::Comment(" trailing".to_owned()), 4)]
+    );
+    assert_eq!(tokens.len(), Lexer::new(code).scan_tokens().len());
+    assert_eq!(get_ast(code)?, stringify(&Parser::new(tokens).parse(), 0));
+
+    let mut lexer = Lexer::new("%% never closed");
+    lexer.scan_tokens();
+    assert!(lexer.get_errors().is_some());
+    Ok(())
+  }
+
+  #[test]
+  fn doc_extraction() -> Result<(), String> {
+    let code = r#"
+    # Not documentation
+    ## Adds `a` and `b`.
+    ## Works with any number.
+    (let add (func (a b) {
+      ## Nested, ignored
+      (let tmp 0)
+      (ret (+ a b))
+    }))
+    ## The answer.
+    (const answer 42)
+    ## Private.
+    (let _helper 1)
+    (let undocumented 2)
+    "#;
+    let entries = doc::extract(code)?;
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].name, "add");
+    assert_eq!(entries[0].params, Some(vec!["a".to_owned(), "b".to_owned()]));
+    assert_eq!(entries[0].doc, "Adds `a` and `b`.\nWorks with any number.");
+    assert!(entries[1].is_const);
+    assert_eq!(
+      doc::to_markdown("lib.nxt", &entries),
+      "# lib.nxt\n\n## `(add a b)`\n\nAdds `a` and `b`.\nWorks with any number.\n\n## `const answer`\n\nThe answer.\n"
+    );
+    assert_eq!(
+      format!("{}", doc::to_json(&entries[1..])),
+      r#"[{"name":"answer","line":11,"const":true,"params":null,"doc":"The answer."}]"#
+    );
+    Ok(())
+  }
+
+  #[test]
+  fn doc_examples() -> Result<(), String> {
+    let code = r#"
+    ## Adds one.
+    ## ```
+    ## (print (inc 41))
+    ## => 42
+    ## ```
+    ## ```text
+    ## not nixt
+    ## ```
+    ## ```nixt
+    ## (inc 1 2)
+    ## ```
+    (let inc (func (n) {(ret (+ n 1))}))
+    (print "loaded")
+    ## Off by one.
+    ## ```
+    ## (print (twice 2))
+    ## => 5
+    ## ```
+    (let twice (func (n) {(ret (* n 2))}))
+    "#;
+    let examples = doc::examples(&doc::extract(code)?);
+    let names = examples.iter().map(|example| example.name.as_str()).collect::<Vec<_>>();
+    assert_eq!(names, ["inc[0]", "inc[1]", "twice[0]"]);
+    assert_eq!(examples[1].expected, Vec::<String>::new());
+
+    let results = doc::run_examples(code, Interpreter::builder)?;
+    assert!(results[0].passed());
+    assert!(results[1].failure.is_some());
+    assert_eq!(results[2].failure.as_deref(), Some("Expected:\n  5\nPrinted:\n  4"));
+    let failure = doc::run_examples("## ```\n## 1\n## ```\n(let x (oops))", Interpreter::builder)?;
+    assert!(failure[0].failure.as_deref().unwrap_or_default().starts_with("The script failed"));
+    Ok(())
+  }
+
+  #[test]
+  fn module_visibility() -> Result<(), String> {
+    let module = std::env::temp_dir().join("nixt_module_visibility.nxt");
+    std::fs::write(
+      &module,
+      "(pub let double (func (x) {(ret (* x 2))}))\n(let helper 1)\n(pub const VERSION 2)\n\
+       (let offset (func (x) {(ret (+ x helper))}))\n\
+       (pub let shifted (func (x) {(ret (double (offset x)))}))",
+    )
+    .map_err(|e| e.to_string())?;
+    let module = module.to_str().unwrap().to_owned();
+
+    let mut interpreter = Interpreter::builder().build()?;
+    interpreter.eval_str(&format!("(import \"{}\")", module))?;
+    assert_eq!(format!("{}", interpreter.eval_str("(double VERSION)")?), "4");
+
+    let private = interpreter.eval_str("(print helper)").unwrap_err();
+    assert!(private.contains("`helper` is private to module"), "{}", private);
+    let undefined = interpreter.eval_str("(print missing)").unwrap_err();
+    assert!(undefined.contains("undefined variable"), "{}", undefined);
+    // The functions of the module still reach its private bindings, and only them
+    assert_eq!(interpreter.eval_str("(shifted 1)")?.to_string(), "4");
+    interpreter.eval_str("(let offset (func (x) {(ret x)}))")?;
+    assert_eq!(interpreter.eval_str("(shifted 1)")?.to_string(), "4");
+    assert!(interpreter.eval_str(&format!("(import \"{}\")", module)).is_err());
+    Ok(())
+  }
+
+  #[test]
+  fn project_config() -> Result<(), String> {
+    let project = std::env::temp_dir().join("nixt_project_config");
+    let scripts = project.join("scripts");
+    std::fs::create_dir_all(&scripts).map_err(|e| e.to_string())?;
+    std::fs::create_dir_all(project.join("lib")).map_err(|e| e.to_string())?;
+    std::fs::write(
+      project.join(config::FILE_NAME),
+      "strict = true # shared\nsearch_paths = ['lib']\n\n[lint]\nmagic-number = \"allow\"\n\
+       max_function_lines = 10\n[sandbox]\ndeny = [\"time:now\", \"version\"]\n",
+    )
+    .map_err(|e| e.to_string())?;
+    std::fs::write(project.join("lib/nixt_config_util.nxt"), "(pub let shared 7)")
+      .map_err(|e| e.to_string())?;
+
+    let (path, found) = config::discover(&scripts)?.unwrap();
+    assert_eq!(path.file_name().unwrap(), config::FILE_NAME);
+    assert_eq!(found.strict, Some(true));
+    assert_eq!(found.infix, None);
+    assert_eq!(found.sandbox, Some(SandboxPolicy::deny(["time:now", "version"])));
+    let lint = found.lint_config(lint::LintConfig::default());
+    assert_eq!(lint.severity("magic-number"), lint::Severity::Allow);
+    assert_eq!(lint.max_function_lines, 10);
+
+    let mut builder = Interpreter::builder();
+    for dir in &found.search_paths {
+      builder = builder.search_path(dir);
+    }
+    let mut interpreter = builder.build()?;
+    interpreter.eval_str("(import 'nixt_config_util.nxt')")?;
+    assert_eq!(format!("{}", interpreter.get("shared").unwrap()), "7");
+
+    let error = |source| config::ProjectConfig::parse(source, ".".as_ref()).unwrap_err();
+    assert_eq!(error("[format]"), "Line 1 | Unknown table `[format]`");
+    assert_eq!(error("strict = 'yes'"), "Line 1 | Invalid value for `strict`");
+    assert_eq!(
+      error("\n[lint]\nno-such-rule = 'deny'"),
+      "Line 3 | Unknown lint rule `no-such-rule`"
+    );
+    assert_eq!(
+      error("strict = true\nsearch_paths = [\n  'lib',\n]"),
+      "Line 2 | Unsupported syntax: arrays must fit on one line"
+    );
+    Ok(())
+  }
+
+  #[test]
+  fn cyclic_imports() -> Result<(), String> {
+    let dir = std::env::temp_dir();
+    let a = dir.join("nixt_cycle_a.nxt").to_str().unwrap().to_owned();
+    let b = dir.join("nixt_cycle_b.nxt").to_str().unwrap().to_owned();
+    std::fs::write(&a, format!("(import \"{}\")", b)).map_err(|e| e.to_string())?;
+    std::fs::write(&b, format!("(import \"{}\")", a)).map_err(|e| e.to_string())?;
+
+    let mut interpreter = Interpreter::builder().build()?;
+    let error = interpreter.eval_str(&format!("(import \"{}\")", a)).unwrap_err();
+    assert_eq!(error, format!("Cyclic import: {} -> {} -> {}", a, b, a));
+    assert!(interpreter.import_stack.is_empty());
+    Ok(())
+  }
+
+  #[test]
+  fn const_expressions() -> Result<(), String> {
+    let mut interpreter = Interpreter::builder().const_exprs(true).build()?;
+    let ast = Parser::new(
+      Lexer::new("(const tau (* 2 math:PI)) (const half (/ tau 4 math:PI)) (const big (> half 0.4))")
+        .scan_tokens(),
+    )
+    .parse();
+    let folded = interpreter.fold_consts(&ast)?;
+    let forms = folded
+      .get_child()
+      .iter()
+      .map(|form| format!("{}", form))
+      .collect::<Vec<_>>();
+    assert_eq!(forms, vec!["(const tau 6.283185307179586)", "(const half 0.5)", "(const big true)"]);
+    interpreter.eval(&ast)?;
+    assert_eq!(format!("{}", interpreter.get("half").unwrap()), "0.5");
+
+    let error = interpreter.eval_str("(let n 2) (const m (+ n 1))").unwrap_err();
+    assert_eq!(
+      error,
+      "`const m` must be initialized with a constant expression: `n` is not a constant"
+    );
+    assert!(interpreter.eval_str("(const now (time:now))").is_err());
+    assert!(Interpreter::builder().build()?.eval_str("(const now (time:now))").is_ok());
+    Ok(())
+  }
+
+  #[test]
+  fn enumerations() -> Result<(), String> {
+    let code = r#"
+    (enum Color (Red Green Blue))
+    (enum Light (Red Off))
+    (let c Color:Green)
+    (assert (= c Color:Green))
+    (assert (~ c Color:Red))
+    (assert (~ Color:Red Light:Red))
+    (assert (~ Color:Red "Color:Red"))
+    (assert (= (type c) "Color"))
+    (assert (= (len Color) 3))
+    (assert (= (at Color 2) Color:Blue))
+    (assert (= (cond ((= c Color:Red) 1) ((= c Color:Green) 2) (else 3)) 2))
+    "#;
+    run(code)?;
+    let mut interpreter = Interpreter::builder().build()?;
+    assert_eq!(format!("{}", interpreter.eval_str("(enum Dir (Up Down)) (at Dir 1)")?), "Dir:Down");
+    assert!(run("(enum Dir (Up)) (set Dir:Up 1)").is_err());
+    assert!(get_ast("(enum Dir (Up Up))").is_err());
+    assert!(get_ast("(enum Dir (a:b))").is_err());
+    Ok(())
+  }
+
+  #[test]
+  fn ranges() -> Result<(), String> {
+    let code = r#"
+    (let r (range 0 10 2))
+    (assert (= (len r) 5))
+    (assert (= (at r 4) 8))
+    (assert (= (at r 5) nil))
+    (assert (in 6 r))
+    (assert (~ (in 7 r) true))
+    (assert (~ (in 10 r) true))
+    (assert (in -1 (range 3 -3 -2)))
+    (assert (= (len 3..1) 0))
+    (assert (= (type 0..3) "range"))
+    (assert (= 0..3 (range 0 3 1)))
+    (let xs (list 10 20 30 40))
+    (let n 3)
+    (assert (= (len (at xs 1..n)) 2))
+    (assert (= (at (at xs 1..n) 1) 30))
+    (assert (= (at "nixt" 1..3) "ix"))
+    (assert (= (at "nixt" (range 3 -1 -1)) "txin"))
+    (assert (= (len (at xs 2..100)) 2))
+    (assert (in 20 xs))
+    (assert (in "ix" "nixt"))
+    "#;
+    run(code)?;
+    assert!(run("(let r (range 0 1 0))").is_err());
+    assert!(get_ast("(print 0..)").is_err());
+    Ok(())
+  }
+
+  #[test]
+  fn nested_paths() -> Result<(), String> {
+    let code = r#"
+    (let grid (list (list 1 2) (list 3 (list 4 5))))
+    (assert (= (get_in grid (list 1 1 0)) 4))
+    (assert (= (len (get_in grid (list))) 2))
+    (assert (= (get_in grid (list 0 9)) nil))
+    (assert (= (get_in grid (list "a")) nil))
+    (let edited (set_in grid (list 1 1 0) 40))
+    (assert (= (get_in edited (list 1 1 0)) 40))
+    (assert (= (get_in grid (list 1 1 0)) 4))
+    (assert (= (len (get_in (set_in grid (list 0 2) 9) (list 0))) 3))
+    (assert (= (set_in grid (list 5 0) 1) nil))
+    (let doc {a [1 {b 2}] c 3})
+    (assert (= (get_in doc ["a" 1 "b"]) 2))
+    (assert (= (get_in doc ["a" "b"]) nil))
+    (assert (= (get_in doc ["z" 0]) nil))
+    (assert (= (get_in doc [0]) nil))
+    (assert (= (get_in (set_in doc ["a" 1 "b"] 20) ["a" 1 "b"]) 20))
+    (assert (= (get_in doc ["a" 1 "b"]) 2))
+    (assert (= (set_in doc ["c" "d"] 1) nil))
+    (assert (= (set_in doc ["z" "d"] 1) nil))
+    (assert (= (get_in (set_in doc ["a" 1 "d"] 4) ["a" 1]) {b 2 d 4}))
+    "#;
+    run(code)?;
+
+    let mut strict = Interpreter::builder().strict(true).build()?;
+    strict.eval_str("(let grid (list (list 1 2)))")?;
+    assert_eq!(format!("{}", strict.eval_str("(get_in grid (list 0 1))")?), "2");
+    assert_eq!(
+      strict.eval_str("(get_in grid (list 0 7 1))").unwrap_err(),
+      "Nothing at path [0 7]"
+    );
+    assert!(strict.eval_str("(set_in grid (list 1 0) 3)").is_err());
+    strict.eval_str("(let doc {a {b 1}})")?;
+    let missing = strict.eval_str("(get_in doc [\"a\" \"c\"])").unwrap_err();
+    assert_eq!(missing, "Nothing at path [a c]");
+    let missing = strict.eval_str("(set_in doc [\"x\" \"c\"] 2)").unwrap_err();
+    assert_eq!(missing, "Nothing at path [x c]");
+    Ok(())
+  }
+
+  #[test]
+  fn sorting_and_grouping() -> Result<(), String> {
+    let mut interpreter = Interpreter::builder().build()?;
+    let mut eval = |code: &str| interpreter.eval_str(code).map(|v| format!("{}", v));
+    eval("(let words (list \"pear\" \"fig\" \"apple\" \"kiwi\" \"fig\"))")?;
+    eval("(let size (func (w) {(ret (len w))}))")?;
+    assert_eq!(eval("(sort_by size words)")?, "[fig, fig, pear, kiwi, apple]");
+    assert_eq!(
+      eval("(sort_by (func (x) {(ret x)}) (list 3 \"a\" nil (list 2) (list 1 5) true 1))")?,
+      "[nil, true, 1, 3, a, [1, 5], [2]]"
+    );
+    assert_eq!(
+      eval("(group_by size words)")?,
+      "{4: [pear, kiwi], 3: [fig, fig], 5: [apple]}"
+    );
+    assert_eq!(eval("(get (group_by size words) \"3\")")?, "[fig, fig]");
+    let initials = eval("(group_by (func (w) {(ret (at w 0))}) words)")?;
+    assert_eq!(initials, "{p: [pear], f: [fig, fig], a: [apple], k: [kiwi]}");
+    assert_eq!(eval("(group_by size [])")?, "{}");
+    assert_eq!(eval("(unique (list 1 (list 2) 1 (list 2) 3))")?, "[1, [2], 3]");
+    assert_eq!(eval("(reverse (unique words))")?, "[kiwi, apple, fig, pear]");
+    assert_eq!(eval("(reverse \"nixt\")")?, "txin");
+    assert!(eval("(sort_by words size)").is_err());
+    assert!(eval("(sort_by (func (a b) {(ret a)}) words)").is_err());
+    Ok(())
+  }
+
+  #[test]
+  fn addition_and_concatenation() -> Result<(), String> {
+    let mut interpreter = Interpreter::builder().build()?;
+    let mut eval = |code: &str| interpreter.eval_str(code).map(|v| v.to_string());
+    assert_eq!(eval("(+ 1 2.5)")?, "3.5");
+    assert_eq!(eval("(type (+ 'ni' 'xt'))")?, "string");
+    assert_eq!(eval("(+ 'ni' 'xt')")?, "nixt");
+    assert_eq!(eval("(+ 'n = ' 1.5)")?, "n = 1.5");
+    assert_eq!(eval("(+ (* 1000000000000 1000000000000) ' km')")?, "1e24 km");
+    assert_eq!(eval("(+ '' '')")?, "");
+    assert_eq!(eval("(+ 1 2 ' apples' 3)")?, "3 apples3");
+    assert_eq!(
+      eval("(+ 'a' nil)").unwrap_err(),
+      "Can not add a string and a nil: `+` adds numbers and concatenates strings"
+    );
+    assert!(eval("(+ 1 true)").unwrap_err().contains("a number and a bool"));
+    assert!(eval("(+ [1] [2])").unwrap_err().contains("a list and a list"));
+    Ok(())
+  }
+
+  #[test]
+  fn number_formatting() -> Result<(), String> {
+    let mut interpreter = Interpreter::builder().build()?;
+    let mut eval = |code: &str| interpreter.eval_str(code).map(|v| format!("{}", v));
+    assert_eq!(eval("(* 3 4)")?, "12");
+    assert_eq!(eval("(/ 1 4)")?, "0.25");
+    assert_eq!(eval("(- 0.1 0.1)")?, "0");
+    assert_eq!(eval("(* 0 -1)")?, "0");
+    assert_eq!(eval("(* 1000000000000 1000000000000)")?, "1e24");
+    assert_eq!(eval("(/ 1.5 100000000)")?, "1.5e-8");
+    assert_eq!(eval("(- 3.3 1.1)")?, "2.1999999999999997");
+    assert_eq!(eval("(math:round_to (- 3.3 1.1) 2)")?, "2.2");
+    assert_eq!(eval("(math:round_to 1234.5 -2)")?, "1200");
+    assert_eq!(eval("(math:round_to 1.5 400)")?, "1.5");
+    let big = eval("(* 1000000000 1000000000)")?;
+    assert_eq!(eval("(math:round_to (* 1000000000 1000000000) 300)")?, big);
+    assert_eq!(eval("(math:round_to 1234.5 -400)")?, "0");
+    assert!(eval("(math:round_to 1 0.5)").is_err());
+    Ok(())
+  }
+
+  #[test]
+  fn deprecated_bindings() -> Result<(), String> {
+    let mut interpreter = Interpreter::builder().build()?;
+    interpreter.eval_str(
+      r#"
+      # @deprecated "use `sum`"
+      (let add (func (a b) {(ret (+ a b))}))
+      (let sum (func (a b) {(ret (+ a b))}))
+      (let old 1) # @deprecated "not this one"
+      "#,
+    )?;
+    assert!(interpreter.take_warnings().is_empty());
+
+    interpreter.eval_str("(let x (add 1 2)) (let y (add x old)) (let z (sum x y))")?;
+    assert_eq!(
+      interpreter.take_warnings(),
+      vec!["`add` is deprecated: use `sum`".to_owned()]
+    );
+
+    interpreter.eval_str("(let f (func (add) {(ret add)})) (let w (f 1))")?;
+    assert!(interpreter.take_warnings().is_empty());
+    Ok(())
+  }
+
+  #[test]
+  fn lenient_input() -> Result<(), String> {
+    let code = "(LET xs (list 1, 2, 3,))\n(Let total (+ (at xs 0), (at xs 1), (at xs 2)))";
+    let mut lexer = Lexer::new(code);
+    lexer.set_case_insensitive(true);
+    let tokens = lexer.scan_tokens();
+    assert_eq!(
+      lexer.get_warnings(),
+      Some(vec!["Line 1 | Matched 2 keyword(s) case-insensitively".to_owned()])
+    );
+    let mut parser = Parser::new(tokens);
+    parser.set_lenient(true);
+    parser.parse();
+    assert!(parser.get_errors().is_none());
+    assert_eq!(
+      parser.get_warnings(),
+      Some(vec!["Line 1 | Ignored 5 comma(s) in lenient mode".to_owned()])
+    );
+
+    let mut interpreter = Interpreter::builder().lenient(true).build()?;
+    interpreter.eval_str(code)?;
+    assert_eq!(format!("{}", interpreter.get("total").unwrap()), "6");
+    assert!(Interpreter::builder().build()?.eval_str(code).is_err());
+    Ok(())
+  }
+
+  #[test]
+  fn reserved_words() {
+    let errors = |code: &str| {
+      let mut parser = Parser::new(Lexer::new(code).scan_tokens());
+      parser.parse();
+      parser.get_errors().unwrap_or_default()
+    };
+    assert!(errors("(let let 5)")[0].contains("`let` is a reserved keyword"));
+    assert!(errors("(let f (func (a while) {(ret a)}))")[0].contains("`while` is a reserved keyword"));
+    assert!(errors("(let a::b 1)")[0].contains("Invalid identifier `a::b`"));
+    assert!(errors("(print math:)")[0].contains("Invalid identifier `math:`"));
+    assert!(errors("(let math:tau (* 2 math:PI))").is_empty());
+
+    let mut lexer = Lexer::new("(let :x 1)");
+    lexer.scan_tokens();
+    assert!(lexer.get_errors().is_some());
+  }
+
+  #[test]
+  fn cond_exhaustiveness_warning() {
+    let mut lexer = Lexer::new("(cond ((= 1 2) 3))");
+    let mut parser = Parser::new(lexer.scan_tokens());
+    parser.parse();
+    assert!(parser.get_warnings().is_none());
+
+    let mut lexer = Lexer::new("(cond ((= 1 2) 3))");
+    let mut parser = Parser::new(lexer.scan_tokens());
+    parser.set_strict(true);
+    parser.parse();
+    assert_eq!(parser.get_warnings().map(|w| w.len()), Some(1));
+  }
+
+  #[test]
+  fn variadic_operators() -> Result<(), String> {
+    let code = r#"
+    (let a 2)
+    (assert (= (+ 1 2 3 4) 10))
+    (assert (= (* a a a) 8))
+    (assert (= (- 10 1 2 3) 4))
+    (assert (= (/ 64 2 4) 8))
+    (assert (< 1 2 3))
+    (assert (~ (< 1 3 2) true))
+    (assert (and true true true))
+    (assert (= (?? nil nil 3 4) 3))
+    "#;
+    run(code)?;
+
+    assert!(get_ast("(+ 1)").is_err());
+    Ok(())
+  }
+
+  #[test]
+  fn infix_expressions() -> Result<(), String> {
+    let code = r#"
+    let x = 1 + 2 * 3
+    assert(x = 7)
+    let y = (1 + 2) * 3 -1
+    assert(y = 8)
+    let square = (func (n) { (ret (* n n)) })
+    let z = square(x - 4) + -1
+    assert(z = 8)
+    assert(x > 1 and x < 10 or false)
+    assert((?? nil x) = 7)
+    const s = (str:cat "a" "b")
+    (assert (= s "ab"))
+    "#;
+    run_infix(code)?;
+
+    assert!(has_infix_pragma("# nixt: infix\nlet x = 1"));
+    assert!(!has_infix_pragma("(let x 1)\n# nixt: infix"));
+    Ok(())
+  }
+
+  #[test]
+  fn program_result() -> Result<(), String> {
+    let eval = |code: &str| -> Result<String, String> {
+      let mut lexer = Lexer::new(code);
+      let ast = Parser::new(lexer.scan_tokens()).parse();
+      Ok(format!("{}", Interpreter::builder().build()?.eval(&ast)?))
+    };
+    assert_eq!(eval("(+ 1 2)")?, "3");
+    assert_eq!(eval("(let x 4) (* x x)")?, "16");
+    assert_eq!(eval("(* 2 2) (let x 4)")?, "nil");
+    assert_eq!(eval("(str:cat 'a' 'b')")?, "ab");
+    assert_eq!(eval("(ret 1) (+ 1 1)")?, "1");
+    Ok(())
+  }
+
+  #[test]
+  fn defines() -> Result<(), String> {
+    let mut interpreter = Interpreter::builder()
+      .define("width", Value::Number(3.))
+      .build()?;
+    if let json::Json::Object(members) =
+      json::parse(r#"{"names": ["a", "b"], "debug": false, "height": null, "size": {"w": 1}}"#)?
+    {
+      for (name, value) in members {
+        interpreter.define(&name, json::to_value(&value)?)?;
+      }
+    }
+    assert!(interpreter.define("width", Value::Nil).is_err());
+    let error = interpreter.define("print", Value::Nil).unwrap_err();
+    assert_eq!(error, "Cannot modify builtin `print`");
+    let error = interpreter.define("a b", Value::Nil).unwrap_err();
+    assert_eq!(error, "`a b` is not a valid name");
+    assert!(interpreter.define("math:PI", Value::Nil).is_err());
+    assert!(interpreter.define("let", Value::Nil).is_err());
+    assert!(Interpreter::builder().define("len", Value::Nil).build().is_err());
+
+    let mut lexer = Lexer::new(
+      r#"
+      (assert (= width 3))
+      (assert (= (at names 1) "b"))
+      (assert (= debug false))
+      (assert (= height nil))
+      (assert (= size {w 1}))
+      "#,
+    );
+    let ast = Parser::new(lexer.scan_tokens()).parse();
+    interpreter.eval(&ast)?;
+
+    let mut lexer = Lexer::new("(set width 4)");
+    let ast = Parser::new(lexer.scan_tokens()).parse();
+    assert!(interpreter.eval(&ast).is_err());
+    Ok(())
+  }
+
+  #[test]
+  fn json_round_trip() -> Result<(), String> {
+    let source = r#"{"a":[1,2.5,"x\n\"y\""],"b":{"c":null,"d":true}}"#;
+    let parsed = json::parse(source)?;
+    assert_eq!(format!("{}", parsed), source);
+    assert!(json::parse("[1, 2").is_err());
+    assert!(json::parse("{} x").is_err());
+    Ok(())
+  }
+
+  #[test]
+  fn json_key_order() -> Result<(), String> {
+    let parsed = json::parse(r#"{"b":1,"a":{"z":[{"y":0,"x":0}],"c":2},"b":3}"#)?;
+    assert_eq!(
+      format!("{}", parsed.clone().ordered(json::KeyOrder::Insertion)),
+      r#"{"b":1,"a":{"z":[{"y":0,"x":0}],"c":2},"b":3}"#
+    );
+    assert_eq!(
+      format!("{}", parsed.clone().ordered(json::KeyOrder::Sorted)),
+      r#"{"a":{"c":2,"z":[{"x":0,"y":0}]},"b":1,"b":3}"#
+    );
+    // Maps keep the order of the members, and the first place of a key given twice.
+    let value = json::to_value(&parsed)?;
+    assert_eq!(value.to_string(), "{b: 3, a: {z: [{y: 0, x: 0}], c: 2}}");
+    let json = json::from_value(&value);
+    assert_eq!(format!("{}", json), r#"{"b":3,"a":{"z":[{"y":0,"x":0}],"c":2}}"#);
+    let sorted = json.ordered(json::KeyOrder::Sorted);
+    assert_eq!(format!("{}", sorted), r#"{"a":{"c":2,"z":[{"x":0,"y":0}]},"b":3}"#);
+    Ok(())
+  }
+
+  #[derive(Clone, Default)]
+  struct SharedBuffer(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+
+  impl std::io::Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+      self.0.borrow_mut().write(buf)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+      Ok(())
+    }
+  }
+
+  #[test]
+  fn error_recovery() -> Result<(), String> {
+    let mut interpreter = Interpreter::builder().build()?;
+    interpreter.eval_str("(let total 1)")?;
+    let fail = "(let f (func (n) { (let local n) (ret (undefined local)) })) \
+      (set total 2) { (let inner 3) (f inner) }";
+    assert!(interpreter.eval_str(fail).is_err());
+    assert_eq!(interpreter.env.depth(), 1);
+    assert!(interpreter.get("inner").is_none());
+    assert!(interpreter.get("local").is_none());
+    assert_eq!(
+      interpreter.eval_str("(+ total (f 0))").unwrap_err(),
+      "Attempted to call an undefined function"
+    );
+    assert_eq!(format!("{}", interpreter.get("total").unwrap()), "2");
+    assert_eq!(format!("{}", interpreter.eval_str("(let inner 4) (* total inner)")?), "8");
+
+    // A failed `do` leaves its scope even when the caller is not `eval_str`.
+    let mut parser = Parser::new(Lexer::new("(do (let scoped 1) (undefined))").scan_tokens());
+    assert!(interpreter.process_ast(&parser.parse()).is_err());
+    assert_eq!(interpreter.env.depth(), 1);
+    assert!(interpreter.get("scoped").is_none());
+    Ok(())
+  }
+
+  #[test]
+  fn transactional_eval() -> Result<(), String> {
+    let mut interpreter = Interpreter::builder().build()?;
+    interpreter.eval_str("(let count 1) (let names (list 'a'))")?;
+    let script = "(set count 2) (set names (push names 'b')) (let added 1) (assert (= count 3))";
+    assert!(interpreter.eval_transactional(script).is_err());
+    assert_eq!(format!("{}", interpreter.get("count").unwrap()), "1");
+    assert_eq!(interpreter.get("names").unwrap().as_list().unwrap().len(), 1);
+    assert!(interpreter.get("added").is_none());
+
+    interpreter.eval_transactional("(set count 3) (let added 1)")?;
+    assert_eq!(format!("{}", interpreter.get("count").unwrap()), "3");
+    assert!(interpreter.get("added").is_some());
+
+    let out = SharedBuffer::default();
+    let mut interpreter = Interpreter::builder().stdout(out.clone()).build()?;
+    let script = r#"
+    (macro twice (x) (quote (* 2 (unquote x))))
+    (use "math")
+    (on_exit (func () {(puts "exit")}))
+    (fixture "f" (func () {(ret 1)}))
+    (test "kept" (func () {}))
+    (tag "slow")
+    (timeout 10)
+    (undefined)
+    "#;
+    assert!(interpreter.eval_transactional(script).is_err());
+    assert!(interpreter.eval_str("(twice 2)").is_err());
+    assert!(interpreter.eval_str("(cos 0)").is_err());
+    interpreter.eval_str("(fixture \"f\" (func () {(ret 2)})) (test \"t\" (func () {}))")?;
+    let tests = interpreter.take_tests();
+    assert_eq!(tests.len(), 1);
+    assert!(tests[0].tags.is_empty() && tests[0].timeout.is_none());
+    interpreter.run_exit_handlers()?;
+    assert!(out.0.borrow().is_empty());
+    Ok(())
+  }
+
+  #[test]
+  fn frozen_prelude() -> Result<(), String> {
+    let mut interpreter = Interpreter::builder().build()?;
+    for code in &[
+      "(set print 5)",
+      "(let print 5)",
+      "(set math:abs 5)",
+      "{ (let math:PI 3) }",
+      "(let f (func () { (const len 2) })) (f)",
+    ] {
+      let error = interpreter.eval_str(code).unwrap_err();
+      assert!(error.starts_with("Cannot modify builtin `"), "{}", error);
+    }
+    assert_eq!(format!("{}", interpreter.eval_str("(math:abs -2)")?), "2");
+
+    let mut bare = Interpreter::builder().builtins(false).prelude(false).build()?;
+    bare.eval_str("(let print 5) (set print 6)")?;
+    Ok(())
+  }
+
+  #[test]
+  fn sandbox_policy() -> Result<(), String> {
+    let mut denied = Interpreter::builder()
+      .sandbox(SandboxPolicy::deny(["import", "print", "math:abs"]))
+      .build()?;
+    assert_eq!(
+      denied.eval_str("(print 1)").unwrap_err(),
+      "`print` is not allowed by the sandbox policy"
+    );
+    assert!(denied.eval_str("(import 'lib.nxt')").is_err());
+    assert!(denied.get("math:abs").is_none());
+    assert!(denied.eval_str("(math:abs -1)").is_err());
+    assert_eq!(format!("{}", denied.eval_str("(math:max 1 2)")?), "2");
+
+    let mut allowed = Interpreter::builder()
+      .sandbox(SandboxPolicy::allow(vec!["len", "list"]))
+      .build()?;
+    assert_eq!(format!("{}", allowed.eval_str("(len (list 1 2))")?), "2");
+    assert!(allowed.eval_str("(puts 1)").is_err());
+    assert!(allowed.get("math:max").is_none());
+    allowed.eval_str("(let f (func () { (ret 1) })) (f)")?;
+    Ok(())
+  }
+
+  #[test]
+  fn builtin_arguments() -> Result<(), String> {
+    let mut interpreter = Interpreter::builder().build()?;
+    let mut error = |code: &str| interpreter.eval_str(code).unwrap_err();
+    assert_eq!(
+      error("(sort_by 1 (list))"),
+      "builtin `sort_by` expected function at arg 0, got number"
+    );
+    assert_eq!(error("(unique (list) 2)"), "builtin `unique` expected 1 argument(s), got 2");
+    assert_eq!(
+      error("(math:round_to 1 0.5)"),
+      "builtin `math:round_to` expected whole number at arg 1, got number"
+    );
+    assert_eq!(
+      error("(reverse nil)"),
+      "builtin `reverse` expected list or string at arg 0, got nil"
+    );
+    Ok(())
+  }
+
+  #[test]
+  fn namespaces() -> Result<(), String> {
+    let mut interpreter = Interpreter::builder().build()?;
+    assert!(interpreter.env.scope(0).bindings().is_empty());
+    interpreter.eval_str("(math:abs -1)")?;
+    assert!(interpreter.env.scope(0).bindings().contains_key("math:PI"));
+    assert!(!interpreter.env.scope(0).bindings().contains_key("str:split"));
+
+    let mut eval = |code: &str| interpreter.eval_str(code).map(|v| format!("{}", v));
+    assert_eq!(eval("(use \"math\") (+ (cos 0) (abs -2) PI)")?, "6.141592653589793");
+    assert_eq!(eval("(let cos 5) (+ cos 1)")?, "6");
+    assert_eq!(eval("(let f (func () { (use \"str\") (ret (substr 'abc' 0 1)) })) (f)")?, "a");
+    assert!(eval("(substr 'abc' 0 1)").is_err());
+    assert_eq!(eval("(use \"nope\")").unwrap_err(), "Unknown namespace `nope`");
+    Ok(())
+  }
+
+  struct Counter(&'static str);
+
+  impl NixtPlugin for Counter {
+    fn name(&self) -> &str {
+      "counter"
+    }
+    fn register(&self, registry: &mut Builtins) {
+      registry
+        .register(self.0, |args| Ok(Value::Number(args.len() as f64)))
+        .register("count:none", |_| Ok(Value::Number(0.)));
+    }
+  }
+
+  #[test]
+  fn plugins() -> Result<(), String> {
+    let mut interpreter = Interpreter::builder().plugin(Counter("count:args")).build()?;
+    assert_eq!(format!("{}", interpreter.eval_str("(count:args 1 2 3)")?), "3");
+    assert_eq!(format!("{}", interpreter.eval_str("(use \"count\") (none)")?), "0");
+    assert!(interpreter.eval_str("(let count:args 1)").is_err());
+
+    let error = Interpreter::builder().plugin(Counter("print")).build().err().unwrap();
+    assert_eq!(error, "Plugin `counter` can not redefine builtin `print`");
+    assert!(Interpreter::builder().plugin(Counter("a::b")).build().is_err());
+
+    let mut denied = Interpreter::builder()
+      .plugin(Counter("count:args"))
+      .sandbox(SandboxPolicy::deny(["count:args"]))
+      .build()?;
+    assert!(denied.eval_str("(count:args)").is_err());
+    Ok(())
+  }
+
+  #[test]
+  fn introspection() -> Result<(), String> {
+    let mut interpreter = Interpreter::builder()
+      .strict(true)
+      .sandbox(SandboxPolicy::deny(["import"]))
+      .build()?;
+    let version = interpreter.eval_str("(version)")?;
+    assert_eq!(version.as_str(), Some(env!("CARGO_PKG_VERSION")));
+    assert!(interpreter.eval_str("(version 1)").is_err());
+    assert_eq!(interpreter.eval_str("(in 'strict' (features))")?.as_bool(), Some(true));
+    assert_eq!(interpreter.eval_str("(in 'fs' (features))")?.as_bool(), Some(false));
+    assert_eq!(Interpreter::builder().build()?.features()[0], "fs");
+    Ok(())
+  }
+
+  #[test]
+  fn interrupts_and_exit_handlers() -> Result<(), String> {
+    let out = SharedBuffer::default();
+    let mut interpreter = Interpreter::builder().stdout(out.clone()).build()?;
+    interpreter.eval_str(
+      "(on_exit (func () { (puts 'first') })) (on_exit (func () { (puts 'second ') }))",
+    )?;
+    let flag = interpreter.interrupt_flag();
+    let interrupter = std::thread::spawn(move || {
+      std::thread::sleep(std::time::Duration::from_millis(50));
+      flag.store(true, std::sync::atomic::Ordering::Relaxed);
+    });
+    let result = interpreter.eval_str("(let i 0) (while (= 0 0) { (set i (+ i 1)) })");
+    interrupter.join().unwrap();
+    assert_eq!(result.unwrap_err(), "Interrupted");
+
+    interpreter.run_exit_handlers()?;
+    assert_eq!(String::from_utf8(out.0.borrow().clone()).unwrap(), "second first");
+    assert_eq!(format!("{}", interpreter.eval_str("(+ 1 1)")?), "2");
+    Ok(())
+  }
+
+  #[test]
+  fn ast_diff() {
+    let parse = |code: &str| Parser::new(Lexer::new(code).scan_tokens()).parse();
+    let pairs = [
+      ("(let a 1) (print a)", "(let a 1) (let b 2) (print a)"),
+      ("(let a 1) (let b 2) (print a)", "(let a 1) (print a)"),
+      ("(print (+ 1 2))", "(puts (+ 1 2))"),
+      ("{(let f (func (x) {(ret (* x 2))}))}", "{(let f (func (x y) {(ret (* x y))}))}"),
+      ("(print 1) (print 2)", "(print 2) (print 3) (print 1)"),
+    ];
+    for (old, new) in &pairs {
+      let (old, new) = (parse(old), parse(new));
+      assert_eq!(apply(&old, &diff(&old, &new)), new);
+      assert!(diff(&new, &new).is_empty());
+    }
+
+    let (old, new) = (parse(pairs[0].0), parse(pairs[0].1));
+    assert!(matches!(&diff(&old, &new)[..], [AstEdit::Insert { path, .. }] if path == &[1]));
+    let (old, new) = (parse(pairs[2].0), parse(pairs[2].1));
+    assert!(matches!(&diff(&old, &new)[..], [AstEdit::Replace { path, .. }] if path == &[0, 0]));
+    let (old, new) = (parse(pairs[3].0), parse(pairs[3].1));
+    let paths = diff(&old, &new)
+      .into_iter()
+      .map(|edit| match edit {
+        AstEdit::Insert { path, .. } | AstEdit::Delete { path, .. } => path,
+        AstEdit::Replace { path, .. } => path,
+      })
+      .collect::<Vec<_>>();
+    assert_eq!(
+      paths,
+      vec![vec![0, 0, 0, 1, 0, 0, 1], vec![0, 0, 0, 1, 0, 1, 0, 0, 0, 0, 1]]
+    );
+  }
+
+  #[test]
+  fn stray_top_level_tokens() {
+    let errors = |code: &str| {
+      let mut parser = Parser::new(Lexer::new(code).scan_tokens());
+      let ast = parser.parse();
+      (ast.get_child().len(), parser.get_errors().unwrap_or_default())
+    };
+    assert_eq!(
+      errors("(let x 5)) stray tokens\n(print x)"),
+      (
+        2,
+        vec!["Line 1 | Unbalanced `)` closes no open form; skipped `) stray tokens`, resumed at \
+              the `(` of line 2"
+          .to_owned()]
+      )
+    );
+    assert_eq!(
+      errors("(print 1) 1 2 3 4 5 6"),
+      (
+        1,
+        vec!["Line 1 | Stray tokens outside of a form; skipped `1 2 3 4 ...`, nothing follows"
+          .to_owned()]
+      )
+    );
+    assert_eq!(errors("(print 1) }").1.len(), 1);
+  }
+
+  #[test]
+  fn max_errors() {
+    let code = "(let 1 2)\n".repeat(10);
+    let mut parser = Parser::new(Lexer::new(&code).scan_tokens());
+    parser.set_max_errors(3);
+    parser.parse();
+    assert_eq!(parser.get_errors().unwrap().len(), 3);
+    assert_eq!(parser.suppressed_errors(), 17);
+
+    let mut lexer = Lexer::new("(print 1 $ ^ `)");
+    lexer.set_max_errors(1);
+    lexer.scan_tokens();
+    assert_eq!(lexer.get_errors().unwrap().len(), 1);
+    assert_eq!(lexer.suppressed_errors(), 2);
+  }
+
+  #[test]
+  fn ast_graph() {
+    let ast = Parser::new(Lexer::new("(print (+ 'a\\b' 1.5))").scan_tokens()).parse();
+    let dot = graph::to_dot(&ast);
+    let labels = dot.lines().filter(|line| line.contains("[label=")).count();
+    let edges = dot.lines().filter(|line| line.contains(" -> ")).count();
+    assert_eq!(edges, labels - 1);
+    assert!(dot.contains(r#"[label="FunctionCall\nprint"];"#), "{}", dot);
+    assert!(dot.contains(r#"[label="Operator\nPlus"];"#), "{}", dot);
+    assert!(dot.contains(r#"[label="NodeStr\n\"a\\b\""];"#), "{}", dot);
+    assert!(dot.contains(r#"[label="NodeNumber\n1.5"];"#), "{}", dot);
+    assert!(dot.ends_with("}\n"));
+  }
+
+  #[test]
+  fn ast_json() {
+    let code = "(let f (func (x) {(if (> x 1) ((ret x)))})) (enum Color (Red)) (f 'a')";
+    let ast = Parser::new(Lexer::new(code).scan_tokens()).parse();
+    let json = graph::to_json(&ast);
+    assert_eq!(json.get("version").unwrap().to_string(), graph::AST_SCHEMA_VERSION.to_string());
+    let json = json.to_string();
+    for kind in ["let", "func", "scope", "if", "return", "enum", "call", "string"] {
+      assert!(json.contains(&format!(r#""kind":"{}""#, kind)), "{} in {}", kind, json);
+    }
+    let operator = r#"{"kind":"operator","span":null,"children":[{"kind":"identifier","#;
+    assert!(json.contains(operator), "{}", json);
+    assert!(json.contains(r#""children":[],"name":"x"}"#));
+    assert!(json.contains(r#""operator":">"}"#));
+    assert!(json.contains(r#"{"kind":"call","span":null,"children":[{"kind":"string""#));
+  }
+
+  #[test]
+  fn ast_builder() -> Result<(), String> {
+    let add = ast::func(
+      ["a", "b"],
+      ast::block().ret(ast::op(OperatorType::Plus, [ast::var("a"), ast::var("b")])),
+    );
+    let program = ast::block()
+      .const_("add", add)
+      .let_("i", ast::num(0.0))
+      .let_("total", ast::num(0.0))
+      .while_(
+        ast::op(OperatorType::Less, [ast::var("i"), ast::num(3.0)]),
+        ast::block()
+          .set("total", ast::call("add", [ast::var("total"), ast::var("i")]))
+          .set("i", ast::op(OperatorType::Plus, [ast::var("i"), ast::num(1.0)])),
+      )
+      .if_else(
+        ast::op(OperatorType::Equal, [ast::var("total"), ast::num(3.0)]),
+        ast::block().call("print", [ast::string("three"), ast::bool(true)]),
+        ast::block().call("print", [ast::nil()]),
+      )
+      .if_(ast::nil(), ast::block().call("print", [ast::num(-1.5)]))
+      .call("add", [ast::var("total"), ast::num(1.0)]);
+    let source = program.to_string();
+    let parsed = Parser::new(Lexer::new(&source).scan_tokens()).parse();
+    assert_eq!(parsed, program.build()?, "{}", source);
+
+    let out = SharedBuffer::default();
+    let mut interpreter = Interpreter::builder().stdout(out.clone()).build()?;
+    assert_eq!(program.eval(&mut interpreter)?.to_string(), "4");
+    assert_eq!(String::from_utf8(out.0.borrow().clone()).unwrap(), "threetrue\n");
+
+    let invalid = ast::block().let_("two words", ast::num(1.0)).build().unwrap_err();
+    assert_eq!(invalid, "`two words` is not a valid name");
+    assert!(ast::block().call("let", []).build().is_err());
+    assert!(ast::block().let_("x", ast::num(f64::NAN)).build().is_err());
+    Ok(())
+  }
+
+  #[test]
+  fn function_metrics() {
+    let code = "(let classify (func (n) {\
+        (let helper (func (x) {(ret (or (= x 1) (= x 2) (= x 3)))}))\
+        (while (> n 0) {(if (helper n) ((print n))) (set n (- n 1))})\
+        (ret (cond ((< n 0) 'neg') ((= n 0) 'zero') (else 'pos')))\
+      }))\
+      (print ((func () {(ret 1)})))";
+    let ast = Parser::new(Lexer::new(code).scan_tokens()).parse();
+    let found = metrics::metrics(&ast)
+      .into_iter()
+      .map(|f| (f.name, f.depth, f.branches))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      found,
+      vec![
+        ("classify".to_owned(), 2, 5),
+        ("classify/helper".to_owned(), 0, 3),
+        ("<anonymous>".to_owned(), 0, 1),
+      ]
+    );
+    let table = metrics::to_table(&metrics::metrics(&ast));
+    assert!(table.starts_with("function         nodes  depth  branches\nclassify   "));
+  }
+
+  #[test]
+  fn variable_history() -> Result<(), String> {
+    let mut interpreter = Interpreter::builder().prelude(false).history(true).build()?;
+    interpreter.eval_str(
+      "(let x 1) (const limit 3) (let bump (func (x) {(set x (* x 10)) (ret x)}))\
+       (set x (bump (+ x 1)))",
+    )?;
+    let history = interpreter
+      .history("x")
+      .iter()
+      .map(|mutation| mutation.to_string())
+      .collect::<Vec<_>>();
+    assert_eq!(
+      history,
+      vec![
+        "#0 (let x 1) => 1",
+        "#3 (set x (* x 10)) => 20 (local, depth 2)",
+        "#4 (set x (bump (+ x 1))) => 20",
+      ]
+    );
+    assert_eq!(interpreter.history("limit")[0].location, "(const limit 3)");
+    assert!(interpreter.history("y").is_empty());
+    let mut untracked = Interpreter::builder().prelude(false).build()?;
+    untracked.eval_str("(let x 1)")?;
+    assert!(untracked.history("x").is_empty());
+    Ok(())
+  }
+
+  #[test]
+  fn watchpoints() -> Result<(), String> {
+    use std::{cell::RefCell, rc::Rc};
+    let mut interpreter = Interpreter::builder().prelude(false).build()?;
+    let hits = Rc::new(RefCell::new(vec![]));
+    let seen = hits.clone();
+    interpreter.watch("n");
+    interpreter.on_watch(move |hit| {
+      seen.borrow_mut().push(hit.to_string());
+      match hit.new.as_number() {
+        Some(n) if n > 2. => Err("Stopped".to_owned()),
+        _ => Ok(()),
+      }
+    });
+    let result = interpreter.eval_str("(let n 0) (let m 0) (while (< n 5) {(set n (+ n 1))})");
+    assert_eq!(result.unwrap_err(), "Stopped");
+    assert_eq!(
+      *hits.borrow(),
+      vec![
+        "`n` defined as 0 at (let n 0)",
+        "`n` changed from 0 to 1 at (set n (+ n 1))",
+        "`n` changed from 1 to 2 at (set n (+ n 1))",
+        "`n` changed from 2 to 3 at (set n (+ n 1))",
+      ]
+    );
+    assert_eq!(interpreter.get("n").and_then(|n| n.as_number()), Some(2.));
+    assert!(interpreter.unwatch("n"));
+    assert_eq!(interpreter.watched().count(), 0);
+    Ok(())
+  }
+
+  #[test]
+  fn dump_state() -> Result<(), String> {
+    let output = Interpreter::builder().prelude(false).build()?.eval_capture(
+      "(enum Color (Red)) (let f (func (n) {(let r (range 0 n)) (debug_dump)})) (f 3)",
+    );
+    output.value?;
+    let state = json::parse(output.stdout.trim())?;
+    let scopes = match state.get("scopes") {
+      Some(json::Json::Array(scopes)) => scopes,
+      other => panic!("no scopes: {:?}", other),
+    };
+    assert_eq!(scopes.len(), 3);
+    assert_eq!(scopes[2].get("kind").and_then(|k| k.as_str()), Some("local"));
+    let text = state.to_string();
+    assert!(text.contains(r#"{"name":"n","const":false,"value":3}"#), "{}", text);
+    assert!(text.contains(r#""value":{"type":"range","start":0,"end":3,"step":1}"#), "{}", text);
+    assert!(text.contains(r#""value":{"type":"func","params":["n"]}"#), "{}", text);
+    assert!(text.contains(r#"{"type":"enum","enum":"Color","variant":"Red"}"#), "{}", text);
+    assert!(Interpreter::builder().build()?.eval_str("(debug_dump 1)").is_err());
+    Ok(())
+  }
+
+  #[test]
+  fn record_and_replay() -> Result<(), String> {
+    let code = "(let started (time:now)) (let twice (+ started started))";
+    let mut recording = Interpreter::builder().prelude(false).record(true).build()?;
+    recording.eval_str(code)?;
+    let trace = recording.take_trace().unwrap();
+    assert!(trace.events.contains(&TraceEvent::Step("Assignement(Let)".to_owned())));
+    let mut bytes = vec![];
+    trace.write_to(&mut bytes).map_err(|e| e.to_string())?;
+    let trace = Trace::read_from(&mut bytes.as_slice())?;
+
+    std::thread::sleep(std::time::Duration::from_millis(5));
+    let mut replaying = Interpreter::builder().prelude(false).replay(trace.clone()).build()?;
+    replaying.eval_str(code)?;
+    let twice = |interpreter: &mut Interpreter| interpreter.get("twice").map(|v| v.to_string());
+    assert_eq!(twice(&mut replaying), twice(&mut recording));
+
+    let mut diverging = Interpreter::builder().prelude(false).replay(trace).build()?;
+    let error = diverging.eval_str("(print 1)").unwrap_err();
+    assert!(error.starts_with("Replay diverged at event 0"), "{}", error);
+    assert!(Trace::read_from(&mut &b"not a trace"[..]).is_err());
+    Ok(())
+  }
+
+  #[test]
+  fn lint_rules() -> Result<(), String> {
+    let source = "(let maxSize 10)\n\
+      (let scale (func (x factor _unused) {\n\
+        (let maxSize (* x 1.5))\n\
+        (ret maxSize)\n\
+      }))\n\
+      (enum Shape (Circle Square))";
+    let mut config = lint::LintConfig::default();
+    let found = |config: &lint::LintConfig| -> Result<Vec<(&str, usize)>, String> {
+      Ok(lint::lint(source, config)?.iter().map(|l| (l.rule, l.line)).collect())
+    };
+    assert_eq!(
+      found(&config)?,
+      vec![
+        ("non-snake-case", 1),
+        ("unused-parameter", 2),
+        ("non-snake-case", 3),
+        ("shadowed-binding", 3),
+        ("magic-number", 3),
+      ]
+    );
+
+    config.set("non-snake-case", lint::Severity::Allow)?;
+    config.set("magic-number", lint::Severity::Deny)?;
+    config.max_function_lines = 3;
+    let lints = lint::lint(source, &config)?;
+    assert_eq!(lints.len(), 4);
+    assert_eq!(
+      format!("{}", lints[1]),
+      "2: warning[long-function]: the function spans 4 lines, more than 3"
+    );
+    assert_eq!(
+      format!("{}", lints[3]),
+      "3: error[magic-number]: `1.5` could be a named constant"
+    );
+    assert!(config.set("no-such-rule", lint::Severity::Deny).is_err());
+
+    let allowed = format!("# nixt-lint: allow(magic-number, non-snake-case)\n{}", source);
+    let rules = lint::lint(&allowed, &lint::LintConfig::default())?;
+    let rules = rules.iter().map(|l| l.rule).collect::<Vec<_>>();
+    assert_eq!(rules, vec!["unused-parameter", "shadowed-binding"]);
+    assert!(lint::lint("# nixt-lint: allow(typo)\n(print 1)", &config).is_err());
+    Ok(())
+  }
+
+  #[test]
+  fn rename_symbol() -> Result<(), String> {
+    let lib = "%% counts %% (pub let n 1) (enum Color (Red Green))";
+    let main = "(import \"lib.nxt\") (let f (func (x) {(ret (+ n x Color:Red))}))\n(set n 2)";
+    let edits = rename::rename(&[lib, main], "n", "total")?;
+    assert_eq!(
+      rename::apply(lib, &edits[0]),
+      "%% counts %% (pub let total 1) (enum Color (Red Green))"
+    );
+    assert_eq!(
+      rename::apply(main, &edits[1]),
+      "(import \"lib.nxt\") (let f (func (x) {(ret (+ total x Color:Red))}))\n(set total 2)"
+    );
+    assert_eq!(edits[1][1].line, 2);
+    let edits = rename::rename(&[lib, main], "Color", "Hue")?;
+    assert!(rename::apply(main, &edits[1]).contains("Hue:Red"));
+
+    assert_eq!(
+      rename::rename(&[lib, main], "n", "x").unwrap_err(),
+      "The local `x` would capture the reference at line 1"
+    );
+    assert_eq!(rename::rename(&[main], "n", "y").unwrap_err(), "`n` is not a global binding");
+    assert!(rename::rename(&[lib, main], "n", "f").is_err());
+    assert!(rename::rename(&[lib], "n", "while").is_err());
+    assert!(rename::rename(&[lib], "n", "len").is_err());
+    Ok(())
+  }
+
+  #[test]
+  fn grammar_spec() -> Result<(), String> {
+    fn literals(rule: &Rule, found: &mut Vec<String>) {
+      match rule {
+        Rule::Literal(text) => found.push(text.to_owned()),
+        Rule::Seq(rules) | Rule::Choice(rules) => rules.iter().for_each(|r| literals(r, found)),
+        Rule::Repeat(inner) | Rule::Optional(inner) => literals(inner, found),
+        Rule::Field(_, inner) | Rule::Greedy(inner) => literals(inner, found),
+        Rule::Pattern(_) | Rule::Ref(_) => {}
+      }
+    }
+    let spec = grammar::grammar();
+    let mut found = vec![];
+    spec.rules.iter().for_each(|(_, rule)| literals(rule, &mut found));
+    for keyword in default_keywords().keys() {
+      assert!(found.contains(keyword), "`{}` is missing from the grammar", keyword);
+    }
+    for op in grammar::OPERATORS {
+      assert_eq!(Lexer::new(op).scan_tokens().len(), 2, "`{}` is not one token", op);
+      get_ast(&format!("({} 1 2)", op))?;
+    }
+
+    let js = crate::tools::grammar::to_tree_sitter(&spec);
+    assert!(js.contains("    nil: $ => 'nil',\n"));
+    assert!(js.contains("identifier: $ => /[\\p{L}_][\\p{L}\\p{N}_:?]*/,"));
+    Ok(())
+  }
+
+  #[test]
+  fn serve_protocol() -> Result<(), String> {
+    let mut interpreter = Interpreter::builder().build()?;
+    let requests = "{\"source\": \"(let n 2) (puts n)\"}\n\n\
+      {\"source\": \"(list n x)\", \"inputs\": {\"x\": [true, null]}}\n\
+      {\"source\": \"(insert o \\\"a\\\" [1 2])\", \"inputs\": {\"o\": {\"z\": {}, \"b\": 1}}}\n\
+      {\"inputs\": {}}\n[]\n";
+    let mut output = vec![];
+    serve::serve(&mut interpreter, requests.as_bytes(), &mut output).unwrap();
+    let lines = String::from_utf8(output).unwrap();
+    let lines = lines.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 5);
+    assert!(lines[0].starts_with(r#"{"ok":true,"value":null,"stdout":"2""#));
+    assert!(lines[1].starts_with(r#"{"ok":true,"value":[2,[true,null]]"#));
+    assert!(lines[2].starts_with(r#"{"ok":true,"value":{"z":{},"b":1,"a":[1,2]}"#));
+    assert!(lines[3].starts_with(r#"{"ok":false,"error":"A request needs a `source`""#));
+    assert!(lines[4].starts_with(r#"{"ok":false,"error":"A request must be a JSON object""#));
+
+    // Inputs are only bound for their request, and can not rebind builtins
+    let requests = "{\"source\": \"(do x)\", \"inputs\": {\"x\": 1, \"math:abs\": 3}}\n\
+      {\"source\": \"(math:abs -1)\"}\n{\"source\": \"(do x)\"}\n\
+      {\"source\": \"(+ x n)\", \"inputs\": {\"x\": 2}}\n";
+    let mut output = vec![];
+    serve::serve(&mut interpreter, requests.as_bytes(), &mut output).unwrap();
+    let lines = String::from_utf8(output).unwrap();
+    let lines = lines.lines().collect::<Vec<_>>();
+    assert!(lines[0].starts_with(r#"{"ok":false,"error":"Cannot modify builtin `math:abs`""#));
+    assert!(lines[1].starts_with(r#"{"ok":true,"value":1,"#));
+    assert!(lines[2].starts_with(r#"{"ok":false,"#));
+    assert!(lines[3].starts_with(r#"{"ok":true,"value":4,"#));
+    assert!(interpreter.set("print", Value::Nil).is_err());
+    Ok(())
+  }
+
+  #[cfg(feature = "jupyter")]
+  #[test]
+  fn jupyter_messages() -> Result<(), String> {
+    use crate::tools::jupyter::{Kernel, Message};
+    let request = |msg_type: &str, content: &str| -> Result<Message, String> {
+      let header = format!(r#"{{"msg_id":"1","session":"s","msg_type":"{}"}}"#, msg_type);
+      Ok(Message {
+        identities: vec![b"peer".to_vec()],
+        header: json::parse(&header)?,
+        parent_header: json::parse("{}")?,
+        metadata: json::parse("{}")?,
+        content: json::parse(content)?,
+      })
+    };
+    let key = b"secret";
+    let execute = request("execute_request", r#"{"code": "(puts 'hi') (+ 40 2)"}"#)?;
+    let frames = execute.encode(key);
+    assert_eq!(Message::decode(&frames, key)?, execute);
+    assert_eq!(Message::decode(&frames, b"other").unwrap_err(), "Invalid message signature");
+
+    let mut kernel = Kernel::new(Interpreter::builder().build()?);
+    let outgoing = kernel.handle(&execute);
+    let types = outgoing.iopub.iter().map(Message::msg_type).collect::<Vec<_>>();
+    assert_eq!(
+      types,
+      vec!["status", "execute_input", "stream", "execute_result", "status"]
+    );
+    let reply = outgoing.reply.unwrap();
+    assert_eq!(reply.identities, vec![b"peer".to_vec()]);
+    assert_eq!(reply.msg_type(), "execute_reply");
+    assert_eq!(format!("{}", reply.content.get("execution_count").unwrap()), "1");
+
+    let failing = kernel.handle(&request("execute_request", r#"{"code": "(undefined)"}"#)?);
+    assert_eq!(failing.reply.unwrap().content.get("status").unwrap().as_str(), Some("error"));
+    let open = kernel.handle(&request("is_complete_request", r#"{"code": "(let x"}"#)?);
+    assert_eq!(open.reply.unwrap().content.get("status").unwrap().as_str(), Some("incomplete"));
+    assert!(kernel.handle(&request("shutdown_request", "{}")?).shutdown);
+    Ok(())
+  }
+
+  #[test]
+  fn captured_output() -> Result<(), String> {
+    let out = SharedBuffer::default();
+    let mut interpreter = Interpreter::builder().lenient(true).stdout(out.clone()).build()?;
+    let output = interpreter.eval_capture("(LET x 2) (puts x) (eprint 'no' x)");
+    assert!(output.value.is_ok());
+    assert_eq!(output.stdout, "2");
+    assert_eq!(output.stderr, "no2\n");
+    assert_eq!(output.diagnostics, vec!["Line 1 | Matched 1 keyword(s) case-insensitively"]);
+
+    let failed = interpreter.eval_capture("(print x) (undefined)");
+    assert!(failed.value.is_err());
+    assert_eq!(failed.stdout, "2\n");
+    interpreter.eval_str("(print x)")?;
+    assert_eq!(String::from_utf8(out.0.borrow().clone()).unwrap(), "2\n");
+    assert!(interpreter.take_warnings().is_empty());
+    Ok(())
+  }
+
+  #[test]
+  fn interpreter_builder() -> Result<(), String> {
+    let out = SharedBuffer::default();
+    let mut interpreter = Interpreter::builder().stdout(out.clone()).build()?;
+    interpreter.eval_str("(let x (math:abs -2))")?;
+    interpreter.eval_str("(print x) (puts 'a' 1)")?;
+    assert_eq!(String::from_utf8(out.0.borrow().clone()).unwrap(), "2\na1");
+    assert_eq!(format!("{}", interpreter.eval_str("(* x 21)")?), "42");
+    assert!(interpreter.eval_str("(let x 1)").is_err());
+
+    let mut bare = Interpreter::builder().prelude(false).builtins(false).build()?;
+    assert!(bare.eval_str("(math:abs -2)").is_err());
+    assert!(bare.eval_str("(list 1 2)").is_err());
+
+    let mut infix = Interpreter::builder().infix(true).build()?;
+    assert_eq!(format!("{}", infix.eval_str("let y = 1 + 2 * 3 (+ y 0)")?), "7");
+    Ok(())
+  }
+
+  #[test]
+  fn custom_keywords() -> Result<(), String> {
+    let mut aliased = Interpreter::builder()
+      .keyword("var", TokenType::Let)
+      .keyword("fn", TokenType::Func)
+      .build()?;
+    aliased.eval_str("(var double (fn (n) {(ret (* n 2))})) (let x 4)")?;
+    assert_eq!(format!("{}", aliased.eval_str("(double x)")?), "8");
+
+    let mut keywords = crate::core::lexer::default_keywords();
+    keywords.remove("let");
+    keywords.insert("soit".to_owned(), TokenType::Let);
+    let mut localized = Interpreter::builder().keywords(keywords).build()?;
+    assert_eq!(format!("{}", localized.eval_str("(soit x (math:abs -3)) (+ x 0)")?), "3");
+    assert!(localized.eval_str("(let y 1)").is_err());
+
+    assert!(Interpreter::builder()
+      .keyword("plus", TokenType::Plus)
+      .build()
+      .is_err());
+    Ok(())
+  }
+
+  #[test]
+  fn scope_inspection() -> Result<(), String> {
+    let mut interpreter = Interpreter::builder().build()?;
+    interpreter.eval_str("(let count 1) (const name 'nixt')")?;
+    assert_eq!(format!("{}", interpreter.get("count").unwrap()), "1");
+    assert!(interpreter.get("math:PI").is_some());
+    assert!(interpreter.get("missing").is_none());
+
+    interpreter.set("count", Value::Number(41.))?;
+    interpreter.set("fresh", Value::Bool(true))?;
+    assert!(interpreter.set("name", Value::Nil).is_err());
+    assert_eq!(format!("{}", interpreter.eval_str("(set count (+ count 1)) (+ count 0)")?), "42");
+
+    let names = interpreter
+      .iter_bindings()
+      .map(|(name, _, is_const)| (name.to_owned(), is_const))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      names,
+      vec![
+        ("count".to_owned(), false),
+        ("fresh".to_owned(), false),
+        ("name".to_owned(), true)
+      ]
+    );
+    Ok(())
+  }
+
+  #[test]
+  fn value_accessors() {
+    let value = Value::list(vec![
+      Value::from(1),
+      Value::from("two"),
+      Value::list(vec![true, false]),
+      Value::from(None::<f32>),
+    ]);
+    assert_eq!(value.get_path("0").and_then(Value::as_number), Some(1.));
+    assert_eq!(value.get_path("1").and_then(Value::as_str), Some("two"));
+    assert_eq!(value.get_path("2.1").and_then(Value::as_bool), Some(false));
+    assert!(value.get_path("3").unwrap().is_nil());
+    assert!(value.get_path("4").is_none());
+    assert!(value.get_path("1.0").is_none());
+    assert!(value.get_path("x").is_none());
+    let nested = Value::map([("a", Value::map([("b", value.clone())])), ("0", Value::from(7))]);
+    assert_eq!(nested.get_path("a.b.2.1").and_then(Value::as_bool), Some(false));
+    assert_eq!(nested.get_path("0").and_then(Value::as_number), Some(7.));
+    assert!(nested.get_path("a.c").is_none());
+    assert!(nested.get_path("a.b.x").is_none());
+    assert_eq!(Value::map([("k", 1), ("j", 2), ("k", 3)]).to_string(), "{k: 3, j: 2}");
+    assert_eq!(value.as_list().map(|l| l.len()), Some(4));
+    assert!(Value::from(2.5).as_str().is_none());
+    assert_eq!(format!("{}", value), "[1, two, [true, false], nil]");
+  }
+
+  #[test]
+  fn display_forms() -> Result<(), String> {
+    let code = "(let add (func (a b) {(ret (+ a b 1))}))\n(if (< x 2) ((print \"small\")))\n(cond ((= x 1) 1) (else nil))";
+    let tokens = Lexer::new(code).scan_tokens();
+    assert_eq!(format!("{}", tokens[1]), "keyword `let` at line 1");
+    assert_eq!(format!("{}", tokens[2]), "identifier `add` at line 1");
+    assert_eq!(
+      format!("{}", tokens.last().unwrap()),
+      "end of file at line 3"
+    );
+
+    let ast = Parser::new(tokens).parse();
+    let forms = ast
+      .get_child()
+      .iter()
+      .map(|form| format!("{}", form))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      forms,
+      vec![
+        "(let add (func (a b) { (ret (+ a b 1)) }))",
+        "(if (< x 2) ((print \"small\")))",
+        "(cond ((= x 1) 1) (true nil))",
+      ]
+    );
+
+    Ok(())
+  }
+
+  #[test]
+  fn prelude_source() -> Result<(), String> {
+    let vocabulary = "(const TILE 16) (let to_px (func (n) {(ret (* n TILE))}))";
+    let mut interpreter = Interpreter::builder().prelude_source(vocabulary).build()?;
+    assert_eq!(interpreter.eval_str("(to_px 3)")?.to_string(), "48");
+    assert!(interpreter.eval_str("(set to_px 0)").is_err());
+    assert!(interpreter.eval_str("(let TILE 8)").is_err());
+    // The bundled prelude is still there, and usable from the host prelude.
+    let mut interpreter = Interpreter::builder()
+      .prelude_source("(let half_turn (func () {(ret math:PI)}))")
+      .build()?;
+    assert_eq!(interpreter.eval_str("(= (half_turn) math:PI)")?.to_string(), "true");
+
+    let mut interpreter = Interpreter::builder()
+      .prelude(false)
+      .sandbox(SandboxPolicy::deny(["shout"]))
+      .prelude_source(vocabulary)
+      .prelude_source("(let shout (func (s) {(ret s)}))")
+      .build()?;
+    assert_eq!(interpreter.eval_str("(to_px 1)")?.to_string(), "16");
+    assert!(interpreter.eval_str("(shout 1)").is_err());
+    assert!(interpreter.eval_str("(math:abs 1)").is_err());
+
+    let error = Interpreter::builder().prelude_source("(undefined_fn)").build().err();
+    assert!(error.unwrap().starts_with("Failed to load the host prelude: "));
+
+    Ok(())
+  }
+
+  #[test]
+  fn shared_env() -> Result<(), String> {
+    let env = Interpreter::builder()
+      .define("LIMIT", Value::Number(10.0))
+      .prelude_source("(let clamp (func (n) {(ret (math:min n LIMIT))}))")
+      .freeze()?;
+    assert!(env.get("math:PI").is_some());
+    assert_eq!(env.get("LIMIT").unwrap().to_string(), "10");
+
+    let handles = (0..4).map(|i| {
+      let env = env.clone();
+      std::thread::spawn(move || -> Result<String, String> {
+        let mut interpreter = Interpreter::builder().shared_env(&env).build()?;
+        assert!(interpreter.pending.is_empty());
+        interpreter.eval_str(&format!("(let n (* {} 4))", i))?;
+        Ok(interpreter.eval_str("(clamp n)")?.to_string())
+      })
+    });
+    let results = handles.map(|handle| handle.join().unwrap()).collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(results, ["0", "4", "8", "10"]);
+
+    let mut interpreter = Interpreter::builder()
+      .shared_env(&env)
+      .sandbox(SandboxPolicy::deny(["clamp"]))
+      .define("n", Value::Number(2.0))
+      .build()?;
+    assert!(interpreter.eval_str("(set LIMIT 3)").is_err());
+    assert!(interpreter.eval_str("(let math:PI 3)").is_err());
+    assert!(interpreter.eval_str("(clamp 1)").is_err());
+    assert_eq!(interpreter.eval_str("(use \"math\") (min n PI)")?.to_string(), "2");
+    assert!(interpreter.get("n").is_some());
+    // Layering again keeps the first environment's bindings.
+    let layered = Interpreter::builder().shared_env(&env).define("STEP", Value::Number(1.0));
+    let layered = layered.freeze()?;
+    assert!(layered.get("LIMIT").is_some() && layered.get("STEP").is_some());
+
+    Ok(())
+  }
+
+  #[test]
+  fn interpreter_pool() -> Result<(), String> {
+    let env = Interpreter::builder().define("LIMIT", Value::Number(3.0)).freeze()?;
+    let mut pool = InterpreterPool::with_capacity(1, move || {
+      Interpreter::builder()
+        .shared_env(&env)
+        .define("request", Value::Number(1.0))
+    })?;
+    let mut first = pool.checkout()?;
+    let mut second = pool.checkout()?;
+    first.eval_str("(let x (+ request LIMIT)) (use \"math\")")?;
+    first.eval_str("(on_exit (func () {(ret 0)}))")?;
+    first.set("request", Value::Number(2.0)).unwrap_err();
+    assert_eq!(first.get("x").unwrap().to_string(), "4");
+    second.eval_str("(let y 1)")?;
+    pool.checkin(first);
+    pool.checkin(second);
+    assert_eq!(pool.idle(), 1);
+
+    let mut reused = pool.checkout()?;
+    assert!(reused.get("x").is_none());
+    assert_eq!(reused.get("request").unwrap().to_string(), "1");
+    assert!(reused.eval_str("(abs -1)").is_err());
+    assert!(reused.exit_handlers.is_empty());
+    assert_eq!(reused.eval_str("(let x (math:abs (- 0 LIMIT))) (+ x 0)")?.to_string(), "3");
+    let stats = pool.stats();
+    assert_eq!((stats.created, stats.checkouts, stats.reused, stats.discarded), (2, 3, 2, 1));
+    assert_eq!(stats.reuse_rate().to_string(), "0.6666666666666666");
+
+    Ok(())
+  }
+
+  #[test]
+  fn value_diff() -> Result<(), String> {
+    let nested = |last: i32| Value::list(vec![Value::list([1, 2]), Value::list([3, last])]);
+    assert!(testing::diff_values(&nested(4), &nested(4)).is_empty());
+    assert_eq!(testing::diff_values(&nested(4), &nested(5)), ["at 1.1: expected 5, found 4"]);
+    assert_eq!(
+      testing::diff_values(&Value::from("1"), &Value::from(1)),
+      ["expected 1, found \"1\""]
+    );
+    let longer = Value::list(vec![Value::from(1), Value::list([2])]);
+    assert_eq!(testing::diff_values(&longer, &Value::list([1])), ["at 1: unexpected [2]"]);
+    let panic = std::panic::catch_unwind(|| testing::assert_value_eq(&nested(4), &nested(5)));
+    let message = panic.unwrap_err().downcast::<String>().unwrap();
+    assert_eq!(*message, "values differ:\n  at 1.1: expected 5, found 4");
+
+    let mut interpreter = Interpreter::builder().prelude(false).build()?;
+    interpreter.eval_str("(assert_eq (list 1 (list 2 3)) (list 1 (list 2 3)))")?;
+    let error = interpreter
+      .eval_str("(assert_eq (list 1 (list 2 3)) (list 1 (list 2 'x') 4))")
+      .unwrap_err();
+    assert_eq!(
+      error,
+      "Assertion failed, values differ:\n  at 1.1: expected \"x\", found 3\n  \
+       at 2: expected 4, missing"
+    );
+
+    Ok(())
+  }
+
+  #[test]
+  fn snapshot_tests() -> Result<(), String> {
+    let dir = std::env::temp_dir().join("nixt_snapshot_tests");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("nested")).map_err(|e| e.to_string())?;
+    let script = dir.join("nested/sort_test.nxt");
+    let source = |last: &str| {
+      format!(
+        "(test \"sorted\" (func () {{(assert_snapshot \"sorted list\" (list 1 '2' {}))}}))
+         (test \"passes\" (func () {{(assert true)}}))",
+        last
+      )
+    };
+    std::fs::write(&script, source("3")).map_err(|e| e.to_string())?;
+    std::fs::write(dir.join("helper.nxt"), "").map_err(|e| e.to_string())?;
+    assert_eq!(test::discover(&dir)?, vec![script.clone()]);
+
+    let run = |last: &str, update: bool| {
+      let builder = Interpreter::builder().snapshots(Snapshots::for_script(&script, update));
+      test::run(&source(last), builder, &test::Filter::default())
+    };
+    // The first run writes the snapshot, the next ones compare with it.
+    assert!(run("3", false)?.iter().all(|result| result.passed()));
+    let stored = dir.join("nested/__snapshots__/sort_test__sorted_list.snap");
+    assert_eq!(std::fs::read_to_string(&stored).unwrap(), "[1, \"2\", 3]\n");
+    assert!(run("3", false)?[0].passed());
+    let results = run("4", false)?;
+    assert!(results[0].failure.as_ref().unwrap().contains("found    [1, \"2\", 4]"));
+    assert!(results[1].passed());
+    assert!(run("4", true)?[0].passed());
+    assert_eq!(std::fs::read_to_string(&stored).unwrap(), "[1, \"2\", 4]\n");
+
+    let mut interpreter = Interpreter::builder().build()?;
+    assert!(interpreter.eval_str("(assert_snapshot \"x\" 1)").is_err());
+    interpreter.eval_str("(test \"a\" (func () {}))")?;
+    assert!(interpreter.eval_str("(test \"a\" (func () {}))").is_err());
+    assert!(test::run("(test)", Interpreter::builder(), &Default::default()).is_err());
+
+    Ok(())
+  }
+
+  #[test]
+  fn parameterized_tests_and_fixtures() -> Result<(), String> {
+    let out = SharedBuffer::default();
+    let source = r#"
+      (fixture "numbers" (func () {(puts "setup ") (ret (list 3 1 2))}))
+      (fixture "broken" (func () {(ret (undefined))}))
+      (test_each "adds" (list (list 1 2 3) (list 2 2 5)) (func (a b sum) {
+        (assert_eq (+ a b) sum)
+      }))
+      (test_each "positive" (list 1 -1) (func (n) {(assert (> n 0))}))
+      (test "length" (func (numbers) {(assert_eq (len numbers) 3)}))
+      (test "first" (func (numbers) {(assert_eq (at numbers 0) 3)}))
+      (test "uses broken" (func (broken) {}))
+      (test "unknown" (func (nothing) {}))
+    "#;
+    let builder = Interpreter::builder().stdout(out.clone());
+    let results = test::run(source, builder, &test::Filter::default())?;
+    let names = results.iter().map(|result| result.name.as_str()).collect::<Vec<_>>();
+    assert_eq!(
+      names,
+      [
+        "adds[0]", "adds[1]", "positive[0]", "positive[1]", "length", "first", "uses broken",
+        "unknown"
+      ]
+    );
+    let passed = results.iter().map(|result| result.passed()).collect::<Vec<_>>();
+    assert_eq!(passed, [true, false, true, false, true, true, false, false]);
+    assert_eq!(String::from_utf8(out.0.borrow().clone()).unwrap(), "setup ");
+    assert!(results[6].failure.as_ref().unwrap().starts_with("Fixture `broken` failed: "));
+    assert_eq!(results[7].failure.as_deref(), Some("`nothing` is not a fixture"));
+
+    let mismatched = "(test_each \"t\" (list (list 1 2)) (func (a) {}))";
+    assert!(test::run(mismatched, Interpreter::builder(), &Default::default()).is_err());
+    let twice = "(fixture \"f\" (func () {})) (fixture \"f\" (func () {}))";
+    assert!(test::run(twice, Interpreter::builder(), &Default::default()).is_err());
+
+    Ok(())
+  }
+
+  #[test]
+  fn parallel_tests() -> Result<(), String> {
+    let cases = (0..20).map(|i| i.to_string()).collect::<Vec<_>>().join(" ");
+    let source = format!(
+      r#"
+      (fixture "base" (func () {{(puts "setup ") (ret 10)}}))
+      (test_each "case" (list {}) (func (i) {{(puts i) (assert (~ i 13))}}))
+      (test "fixture" (func (base) {{(assert_eq base 10)}}))
+      "#,
+      cases
+    );
+    let source = source.as_str();
+    let results = test::run_parallel(source, 4, Interpreter::builder, &Default::default())?;
+    assert_eq!(results.len(), 21);
+    for (i, result) in results.iter().take(20).enumerate() {
+      assert_eq!(result.name, format!("case[{}]", i));
+      assert_eq!(result.output, i.to_string());
+      assert_eq!(result.passed(), i != 13);
+    }
+    assert!(results[20].passed());
+    assert_eq!(results[20].output, "setup ");
+
+    let builder = Interpreter::builder().stdout(SharedBuffer::default());
+    let sequential = test::run(source, builder, &Default::default())?;
+    let outcomes = |results: &[test::TestResult]| {
+      results.iter().map(|r| (r.name.clone(), r.failure.clone())).collect::<Vec<_>>()
+    };
+    assert_eq!(outcomes(&sequential), outcomes(&results));
+    let failing = "(test 'a' (func () {})) (undefined)";
+    assert!(test::run_parallel(failing, 2, Interpreter::builder, &Default::default()).is_err());
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_filters_and_tags() -> Result<(), String> {
+    let source = r#"
+      (test "slow: db: big sort" (func () {}))
+      (tag "net" "slow")
+      (test_each "fetch" (list 1 2) (func (n) {}))
+      (test "not a tag: spaces" (func () {}))
+      (test "fast" (func () {(assert false)}))
+    "#;
+    let tests = test::list(source, Interpreter::builder())?;
+    let tags = tests.iter().map(|test| test.tags.join(",")).collect::<Vec<_>>();
+    assert_eq!(tags, ["slow,db", "net,slow", "net,slow", "", ""]);
+
+    let names = |filter: test::Filter| -> Result<Vec<String>, String> {
+      let results = test::run(source, Interpreter::builder(), &filter)?;
+      let parallel = test::run_parallel(source, 3, Interpreter::builder, &filter)?;
+      let names = results.into_iter().map(|result| result.name).collect::<Vec<_>>();
+      assert_eq!(names, parallel.into_iter().map(|result| result.name).collect::<Vec<_>>());
+      Ok(names)
+    };
+    let skip_slow = test::Filter { skip_tags: vec!["slow".to_owned()], ..Default::default() };
+    assert_eq!(names(skip_slow)?, ["not a tag: spaces", "fast"]);
+    let fetch = test::Filter { name: Some("fetch[".to_owned()), skip_tags: vec![] };
+    assert_eq!(names(fetch)?, ["fetch[0]", "fetch[1]"]);
+    assert_eq!(names(test::Filter::default())?.len(), 5);
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_reports() -> Result<(), String> {
+    let source =
+      "(test 'passes' (func () {(print '<ok>')})) (test 'fails' (func () {(assert false)}))";
+    let results = test::run_parallel(source, 2, Interpreter::builder, &Default::default())?;
+    let suites = [
+      test::Suite { name: "a_test.nxt".to_owned(), results: Ok(results) },
+      test::Suite { name: "b_test.nxt".to_owned(), results: Err("Undefined `x`".to_owned()) },
+    ];
+    let junit = test::to_junit(&suites);
+    assert!(junit.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuites tests=\"2\" \
+      failures=\"1\" errors=\"1\" time=\""));
+    let suite = "<testsuite name=\"a_test.nxt\" tests=\"2\" failures=\"1\" errors=\"0\"";
+    assert!(junit.contains(suite));
+    assert!(junit.contains("<system-out>&lt;ok&gt;\n</system-out>"));
+    assert!(junit.contains("<failure message=\"Code panicked at assertion failed\">"));
+    assert!(junit.contains("<error message=\"Undefined `x`\">Undefined `x`</error>"));
+    assert!(junit.trim_end().ends_with("</testsuites>"));
+
+    let report = test::to_json(&suites);
+    assert_eq!(report.get("passed").unwrap().to_string(), "1");
+    assert_eq!(report.get("failed").unwrap().to_string(), "2");
+    let report = report.to_string();
+    assert!(report.contains(r#""name":"fails","passed":false,"duration_ms":"#));
+    assert!(report.contains(r#"{"name":"b_test.nxt","error":"Undefined `x`"}"#));
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_timeouts() -> Result<(), String> {
+    let source = "(let bump (func (n) {(ret (+ n 1))}))
+      (test 'spins' (func () {(while (= 1 1) {(bump 1)})}))
+      (timeout 5000) (test 'quick' (func () {(assert true)}))
+      (test 'after' (func () {}))";
+    let builder = || Interpreter::builder().test_timeout(Duration::from_millis(50));
+    let results = test::run(source, builder(), &Default::default())?;
+    let failure = results[0].failure.as_deref().unwrap_or_default();
+    assert!(failure.starts_with("Timed out after 50 ms, last steps:\n  "), "{}", failure);
+    assert!(failure.contains("FunctionCall(\"bump\")"), "{}", failure);
+    assert!(results[1].passed() && results[2].passed());
+    let results = test::run_parallel(source, 2, builder, &Default::default())?;
+    assert!(results[0].failure.as_deref().unwrap_or_default().starts_with("Timed out"));
+    assert!(results[1].passed() && results[2].passed());
+
+    let mut interpreter = Interpreter::builder().build()?;
+    let source = "(timeout 20) (test_each 'waits' (list 1 2) (func (x) {(while (= 1 1) {})}))";
+    interpreter.eval_str(source)?;
+    let tests = interpreter.take_tests();
+    assert!(tests.iter().all(|test| test.timeout == Some(Duration::from_millis(20))));
+    assert!(interpreter.run_test(&tests[1]).unwrap_err().starts_with("Timed out after 20 ms"));
+    assert!(interpreter.eval_str("(let stopped false)").is_ok());
+    assert!(interpreter.eval_str("(timeout 0)").unwrap_err().contains("positive number"));
+
+    // Nondeterministic builtins are not traced again while the last steps are kept
+    let source = "(timeout 1000) (test 't' (func () {(let t (time:now)) (assert (> t 0))}))";
+    let results = test::run(source, builder(), &Default::default())?;
+    assert!(results[0].passed(), "{:?}", results[0].failure);
+
+    Ok(())
+  }
+
+  #[test]
+  fn builtins_as_values() -> Result<(), String> {
+    let out = SharedBuffer::default();
+    let mut interpreter = Interpreter::builder().stdout(out.clone()).build()?;
+    interpreter.eval_str("(let say println) (say 'a' 1) (print (list 2 true))")?;
+    assert_eq!(String::from_utf8(out.0.borrow().clone()).unwrap(), "a1\n[2, true]\n");
+    assert_eq!(interpreter.eval_str("(type println)")?.to_string(), "function");
+    let sorted = interpreter.eval_str("(sort_by len (list 'abc' 'a' 'ab'))")?;
+    assert_eq!(sorted.to_string(), "[a, ab, abc]");
+    interpreter.eval_str("(let size len)")?;
+    let size = interpreter.get("size").unwrap();
+    assert_eq!(interpreter.call_value(&size, &[Value::from("abc")])?.to_string(), "3");
+    assert!(interpreter.eval_str("(let println 1)").is_err());
+
+    let mut sandboxed =
+      Interpreter::builder().sandbox(SandboxPolicy::deny(["print"])).build()?;
+    assert!(sandboxed.eval_str("(let p print) (p 1)").unwrap_err().contains("sandbox"));
+
+    Ok(())
+  }
+
+  #[test]
+  fn macros() -> Result<(), String> {
+    let out = SharedBuffer::default();
+    let mut interpreter = Interpreter::builder().stdout(out.clone()).build()?;
+    interpreter.eval_str(
+      "(macro unless (test body) (quote (if (unquote test) () ((unquote body)))))\
+      (macro swap (a b) (quote (\
+        (let tmp (unquote a)) (set (unquote a) (unquote b)) (set (unquote b) tmp))))\
+      (let x 1) (let y 2)\
+      (unless (= x 2) (print 'not two'))\
+      (swap x y)\
+      (print x y)",
+    )?;
+    let read = |out: &SharedBuffer| String::from_utf8(out.0.borrow().clone()).unwrap();
+    assert_eq!(read(&out), "not two\n21\n");
+    // Macros stay defined, and expand in function bodies.
+    let code = "(let f (func (n) {(unless (> n 0) (ret 'small')) (ret 'big')})) (f 0)";
+    assert_eq!(interpreter.eval_str(code)?.to_string(), "small");
+    assert_eq!(interpreter.eval_str("(quote (+ x (* 2 y)))")?.to_string(), "(+ x (* 2 y))");
+
+    let error = |code: &str| run(code).unwrap_err();
+    assert_eq!(
+      error("(macro m (a) (quote (print (unquote a)))) (m 1 2)"),
+      "Macro `m` takes 1 argument(s), got 2"
+    );
+    assert!(error("(macro m () (quote (m))) (m)").contains("expands too deeply"));
+    assert!(error("(macro m (a) (print a))").starts_with("A macro is defined as"));
+    assert!(error("(macro print () (quote 1))").contains("is a builtin"));
+    assert!(error("(print (unquote x))").contains("template of a macro"));
+    Ok(())
+  }
+
+  #[test]
+  fn list_literals() -> Result<(), String> {
+    let parse = |code: &str| Parser::new(Lexer::new(code).scan_tokens()).parse();
+    assert_eq!(parse("(let xs [1 [a] (f)])"), parse("(let xs (list 1 (list a) (f)))"));
+    assert_eq!(parse("(print [] [nil])"), parse("(print (list) (list nil))"));
+
+    let mut interpreter = Interpreter::builder().build()?;
+    let value = interpreter.eval_str("(let x 2) (do [x 'b' (* x 2) 1..3])")?;
+    assert_eq!(value.to_string(), "[2, b, 4, 1..3]");
+    let mut eval = |code: &str| interpreter.eval_str(code).map(|v| v.to_string());
+    assert_eq!(eval("(get [1 2] 0)")?, "1");
+    assert_eq!(eval("(get [1 2] 2)")?, "nil");
+    assert_eq!(eval("(get [1 2] -1)")?, "nil");
+    assert_eq!(eval("(pop [1 2])")?, "[1]");
+    assert_eq!(eval("(pop [])")?, "[]");
+    let mut parser = Parser::new(Lexer::new("(print [1 2)").scan_tokens());
+    parser.parse();
+    assert!(parser.get_errors().is_some());
+    Ok(())
+  }
+
+  #[test]
+  fn eval_and_parse() -> Result<(), String> {
+    let mut interpreter = Interpreter::builder().build()?;
+    let mut eval = |code: &str| interpreter.eval_str(code).map(|v| v.to_string());
+    eval("(let handlers (list (parse \"(+ n 1)\") \"(* n 2)\"))")?;
+    assert_eq!(eval("(let n 5) (eval (at handlers 0))")?, "6");
+    assert_eq!(eval("(eval (at handlers 1))")?, "10");
+    assert_eq!(eval("(type (at handlers 0))")?, "ast");
+    assert_eq!(eval("(parse \"(let a 1) (print [a 2])\")")?, "(let a 1) (print (list a 2))");
+    // Definitions land in the current scope, unless the scope is fresh.
+    assert_eq!(eval("(eval \"(let m 3)\") (+ m 1)")?, "4");
+    let f = "(let f (func () {(eval \"(let local 1)\") (ret local)})) (f)";
+    assert_eq!(eval(f)?, "1");
+    assert!(eval("(eval \"(+ n 1)\" \"fresh\")").unwrap_err().contains("undefined"));
+    assert_eq!(eval("(eval \"(let n 1) (math:floor 1.5)\" \"fresh\") (+ n 0)")?, "5");
+    assert_eq!(eval("(eval (quote (+ n n)))")?, "10");
+
+    assert!(eval("(parse \"(let\")").is_err());
+    assert!(eval("(eval 1)").unwrap_err().contains("expected ast or string"));
+    assert!(eval("(eval \"1\" \"elsewhere\")").is_err());
+    Ok(())
+  }
+
+  #[test]
+  fn function_reflection() -> Result<(), String> {
+    let mut interpreter = Interpreter::builder().build()?;
+    let mut eval = |code: &str| interpreter.eval_str(code).map(|v| v.to_string());
+    eval("(let route (func (path user) {(let greeting 'hi ') (ret (str:cat greeting user))}))")?;
+    assert_eq!(eval("(fn_arity route)")?, "2");
+    assert_eq!(eval("(fn_params route)")?, "[path, user]");
+    assert_eq!(eval("(fn_body route)")?, "(let greeting \"hi \") (ret (str:cat greeting user))");
+    assert_eq!(eval("(let user 'ann') (eval (fn_body route))")?, "hi ann");
+    assert_eq!(eval("(fn_arity (func () {}))")?, "0");
+    assert!(eval("(fn_arity 1)").unwrap_err().contains("expected function"));
+    assert_eq!(
+      eval("(fn_params print)").unwrap_err(),
+      "`fn_params` can not inspect the builtin `print`"
+    );
+    Ok(())
+  }
+
+  #[test]
+  fn error_kinds() -> Result<(), String> {
+    use crate::error::{Diagnostic, NixtError, RuntimeError, RuntimeErrorKind};
+    let sandbox = SandboxPolicy::deny(["time:now"]);
+    let mut interpreter = Interpreter::builder().sandbox(sandbox).build()?;
+    let mut kind = |code: &str| match interpreter.eval_source(code) {
+      Err(NixtError::Runtime(error)) => Ok(error.kind),
+      other => Err(format!("{:?}", other)),
+    };
+    assert_eq!(kind("(print missing)")?, RuntimeErrorKind::Undefined);
+    assert_eq!(kind("(+ 1 true)")?, RuntimeErrorKind::Type);
+    assert_eq!(kind("(let f (func (a) {})) (f 1 2)")?, RuntimeErrorKind::Arity);
+    assert_eq!(kind("(assert_eq 1 2)")?, RuntimeErrorKind::Assertion);
+    assert_eq!(kind("(time:now)")?, RuntimeErrorKind::Sandbox);
+    assert_eq!(kind("(str:upper)")?, RuntimeErrorKind::Arity);
+    assert_eq!(kind("(str:upper 1)")?, RuntimeErrorKind::Type);
+    assert_eq!(kind("(let x 1) (x)")?, RuntimeErrorKind::Type);
+    // The kind comes from where the error was raised, not from its wording.
+    assert_eq!(kind("(assert_eq 'undefined' 1)")?, RuntimeErrorKind::Assertion);
+    let failing = |_: &[Value]| Err(NixtError::Runtime(RuntimeError::new("undefined arity")));
+    interpreter.register_native("failing", failing)?;
+    match interpreter.eval_source("(failing)") {
+      Err(NixtError::Runtime(error)) => assert_eq!(error.kind, RuntimeErrorKind::Other),
+      other => return Err(format!("{:?}", other)),
+    }
+
+    let message = "Unexpected character: $".to_owned();
+    let span = Some(crate::utils::token::Span { start: 12, end: 13 });
+    let expected = Diagnostic { line: 2, column: Some(3), span, message };
+    match interpreter.eval_source("(print 1)\n  $") {
+      Err(NixtError::Lex(errors)) => assert_eq!(errors, vec![expected]),
+      other => return Err(format!("{:?}", other)),
+    }
+    assert!(interpreter.eval_str("(print 1)\n  $").unwrap_err().starts_with("Line 2 | "));
+    match interpreter.eval_source("(print 1)\n(let)") {
+      Err(NixtError::Parse(errors)) => assert_eq!(errors[0].line, 2),
+      other => return Err(format!("{:?}", other)),
+    }
+    // `eval_str` reports the same errors as text.
+    assert_eq!(interpreter.eval_str("(print 1)\n(let)").unwrap_err().lines().count(), 1);
+    assert!(interpreter.eval_str("(print 1)\n(let)").unwrap_err().starts_with("Line 2 | "));
+    Ok(())
+  }
+
+  #[test]
+  fn introspect_state() -> Result<(), String> {
+    let mut interpreter = Interpreter::builder().introspection(true).build()?;
+    let mut eval = |code: &str| interpreter.eval_str(code).map(|v| v.to_string());
+    eval("(let where (func () {(ret (at (introspect) 'calls'))}))")?;
+    eval("(let outer (func (n) {(let local (* n 2)) (ret (where))}))")?;
+    assert_eq!(eval("(outer 1)")?, "[outer, where]");
+    assert_eq!(eval("(at (introspect) 'calls')")?, "[]");
+    assert_eq!(eval("(type (introspect))")?, "map");
+    eval("(let probe (func (n) {(let local (* n 2)) (ret (introspect))}))")?;
+    eval("(let state (probe 4)) (let scopes (at state 'scopes'))")?;
+    assert_eq!(eval("(at (at scopes 2) 'bindings')")?, "{local: 8, n: 4}");
+    assert_eq!(eval("(at (at scopes 1) 'kind')")?, "globals");
+    eval("(math:abs 1)")?;
+    assert_eq!(eval("(at (introspect) 'modules')")?, "[std/math]");
+    let bindings = "(at (at (at (probe 4) 'scopes') 2) 'bindings')";
+    assert_eq!(eval(&format!("(= (at (at scopes 2) 'bindings') {})", bindings))?, "true");
+    assert_eq!(eval("(in 'debug' (features))")?, "true");
+
+    let error = Interpreter::builder().build()?.eval_str("(introspect)").unwrap_err();
+    assert!(error.contains("needs the debug capability"));
+    Ok(())
+  }
+
+  #[test]
+  fn fingerprints() -> Result<(), String> {
+    let mut interpreter = Interpreter::builder().build()?;
+    let mut eval = |code: &str| interpreter.eval_str(code).map(|v| v.to_string());
+    assert_eq!(eval("(fingerprint (list 1 'a'))")?, eval("(fingerprint [1.0 \"a\"])")?);
+    assert_ne!(eval("(fingerprint 1)")?, eval("(fingerprint '1')")?);
+    assert_eq!(eval("(fingerprint 0)")?, eval("(fingerprint (* 0 (- 0 1)))")?);
+    assert_eq!(eval("(len (fingerprint nil))")?, "16");
+    let f = "(fingerprint (func (a) {(ret a)}))";
+    assert_eq!(eval(f)?, eval(f)?);
+    assert_ne!(eval(f)?, eval("(fingerprint (func (b) {(ret b)}))")?);
+    assert_ne!(eval("(fingerprint (parse '(f 1)'))")?, eval("(fingerprint (parse '(f 2)'))")?);
+
+    let parse = |code| Parser::new(Lexer::new(code).scan_tokens()).parse();
+    let (a, b) = (parse("(let x (+ 1 2))"), parse("(let x (+ 1 2))\n"));
+    assert_eq!(a.fingerprint(), b.fingerprint());
+    assert_ne!(a.fingerprint(), parse("(let x (+ 2 1))").fingerprint());
+    assert_ne!(a.fingerprint(), parse("(let x (- 1 2))").fingerprint());
+    assert_ne!(a.fingerprint(), parse("(const x (+ 1 2))").fingerprint());
+    // A value and the same tree as a program are told apart.
+    assert_ne!(Value::Ast(a.clone()).fingerprint(), a.fingerprint());
+    Ok(())
+  }
+
+  #[test]
+  fn source_spans() -> Result<(), String> {
+    use crate::error::NixtError;
+    let tokens = Lexer::new("(print \"é\" x)").scan_tokens();
+    let spans = tokens.iter().map(|t| (t.span.start, t.span.end)).collect::<Vec<_>>();
+    assert_eq!(spans, [(0, 1), (1, 6), (7, 11), (12, 13), (13, 14), (14, 14)]);
+
+    let mut interpreter = Interpreter::builder().build()?;
+    let mut span_of = |code: &str| match interpreter.eval_source(code) {
+      Err(NixtError::Runtime(error)) => {
+        let span = error.span.ok_or("The error has no span")?;
+        Ok(code[span.start..span.end].to_owned())
+      }
+      other => Err(format!("{:?}", other)),
+    };
+    assert_eq!(span_of("(let a 1)\n(print a missing)")?, "missing");
+    assert_eq!(span_of("(let b (+ 1 nothing))")?, "nothing");
+    assert_eq!(span_of("(print 1) (nope 2)")?, "nope");
+    assert_eq!(span_of("(let f (func (n) {(ret (+ n true))})) (f 1)")?, "(ret (+ n true))");
+    let code = "(print 1) (eval \"(print undefined)\")";
+    assert_eq!(span_of(code)?, &code[10..]);
+    assert_eq!(span_of("(print (at (list 1) 0)) (assert false)")?, "(assert false)");
+
+    let mut parser = Parser::new(Lexer::new("(let x 1) (let 5 2)").scan_tokens());
+    parser.parse();
+    let span = parser.diagnostics()[0].span.unwrap();
+    assert_eq!((span.start, span.end), (15, 16));
+    // Spans do not take part in equality.
+    let parse = |code| Parser::new(Lexer::new(code).scan_tokens()).parse();
+    assert_eq!(parse("(print 1)"), parse("  (print  1)"));
+    Ok(())
+  }
+
+  #[test]
+  fn repl_session() -> Result<(), String> {
+    use crate::tools::repl::{open_brackets, Repl};
+    let mut repl = Repl::new(Interpreter::builder().build()?);
+    let input = "(let xs [1\n  2 3])\n\n(len xs)\n(+ \"a\n(\" \"b\")\n(missing)\n(at xs 2)\n";
+    let (mut output, mut errors) = (vec![], vec![]);
+    repl.run(input.as_bytes(), &mut output, &mut errors).map_err(|e| e.to_string())?;
+    let output = String::from_utf8(output).map_err(|e| e.to_string())?;
+    assert_eq!(output, "> . > > 3\n> . a\n(b\n> > 3\n> \n");
+    let errors = String::from_utf8(errors).map_err(|e| e.to_string())?;
+    assert!(errors.starts_with("Error: Attempted to call an undefined function"), "{}", errors);
+    assert_eq!(open_brackets("(if x { (print \"}\") # )"), Some(2));
+    assert_eq!(open_brackets("%% ( %%"), Some(0));
+    assert_eq!(open_brackets("(]"), Some(0));
+    Ok(())
+  }
+
+  #[test]
+  fn number_round_trip() -> Result<(), String> {
+    use crate::stdlib::str::{num_to_str, parse_num};
+    let round_trip = |n: f64| -> Result<f64, String> {
+      match parse_num(&[num_to_str(&[Value::Number(n)])?])? {
+        Value::Number(read) => Ok(read),
+        other => Err(format!("{} read back as {}", n, other)),
+      }
+    };
+    let edges = [
+      0.1, 1. / 3., 1e21, 1e21 - 65536., 1e-7, 0.99999999e-7, 5e-324, f64::MIN_POSITIVE,
+      f64::MAX, f64::MIN, f64::EPSILON, 9007199254740992., 0.1 + 0.2, -2.5e-300,
+    ];
+    // Random bit patterns, from a fixed xorshift seed, cover every exponent.
+    let mut state = 0x2545_f491_4f6c_dd1du64;
+    let random = std::iter::repeat_with(move || {
+      state ^= state << 13;
+      state ^= state >> 7;
+      state ^= state << 17;
+      f64::from_bits(state)
+    });
+    for n in edges.iter().copied().chain(random.filter(|n| n.is_finite()).take(100_000)) {
+      assert_eq!(round_trip(n)?.to_bits(), n.to_bits(), "{:e} printed as {}", n, Value::Number(n));
+    }
+    assert_eq!(round_trip(-0.)?, 0.);
+    assert!(round_trip(f64::NAN)?.is_nan());
+    assert_eq!(round_trip(f64::NEG_INFINITY)?, f64::NEG_INFINITY);
+    assert_eq!(Value::from(0.1f32).to_string(), "0.1");
+
+    let mut interpreter = Interpreter::builder().build()?;
+    let mut eval = |code: &str| interpreter.eval_str(code).map(|v| v.to_string());
+    assert_eq!(eval("(= (parse_num (num_to_str (/ 1 3))) (/ 1 3))")?, "true");
+    assert_eq!(eval("(num_to_str 1.5)")?, "1.5");
+    assert_eq!(eval("(parse_num \"-1e-8\")")?, "-1e-8");
+    let error = eval("(parse_num \"1,5\")").unwrap_err();
+    assert_eq!(error, "builtin `parse_num` can not read \"1,5\" as a number");
+    Ok(())
+  }
+
+  #[test]
+  fn unicode_strings() -> Result<(), String> {
+    let mut interpreter = Interpreter::builder().build()?;
+    let mut eval = |code: &str| interpreter.eval_str(code).map(|v| v.to_string());
+    eval("(let s \"héllo wörld\")")?;
+    assert_eq!(eval("(len s)")?, "11");
+    assert_eq!(eval("(at s 1)")?, "é");
+    assert_eq!(eval("(at s 10)")?, "d");
+    assert_eq!(eval("(at s 11)")?, "nil");
+    assert_eq!(eval("(str:substr s 7 11)")?, "örld");
+    assert_eq!(eval("(reverse \"añb\")")?, "bña");
+    assert_eq!(eval("(pop \"añ\")")?, "a");
+    assert_eq!(eval("(pop \"\")")?, "");
+    assert_eq!(eval("(str:upper s)")?, "HÉLLO WÖRLD");
+    assert_eq!(eval("(str:upper \"straße\")")?, "STRASSE");
+    assert_eq!(eval("(str:lower \"ÀÉ İ\")")?, "àé i\u{307}");
+    assert_eq!(eval("(str:byte_len s)")?, "13");
+    assert_eq!(eval("(str:bytes \"é!\")")?, "[195, 169, 33]");
+    assert_eq!(eval("(len \"🎉\")")?, "1");
+    Ok(())
+  }
+
+  #[test]
+  fn embedding_facade() -> Result<(), String> {
+    use crate::{error::RuntimeErrorKind, Nixt, NixtError};
+    let mut nixt = Nixt::new();
+    assert_eq!(nixt.eval("(let xs [3 1 2]) (len xs)")?.to_string(), "3");
+    let xs = nixt.get("xs").unwrap();
+    assert_eq!(nixt.call("reverse", &[xs])?.to_string(), "[2, 1, 3]");
+    match nixt.call("nope", &[]) {
+      Err(NixtError::Runtime(error)) => assert_eq!(error.kind, RuntimeErrorKind::Undefined),
+      other => panic!("{:?}", other),
+    }
+    assert!(matches!(nixt.eval("(print 'a"), Err(NixtError::Lex(_))));
+    assert!(matches!(nixt.eval("(let)"), Err(NixtError::Parse(_))));
+    assert_eq!(nixt.eval("(len xs)")?.to_string(), "3");
+
+    let mut nixt = Nixt::from(Interpreter::builder().strict(true).build()?);
+    assert!(nixt.interpreter().features().contains(&"strict"));
+    Ok(())
+  }
+
+  #[test]
+  fn characters() -> Result<(), String> {
+    let mut interpreter = Interpreter::builder().build()?;
+    let mut eval = |code: &str| interpreter.eval_str(code).map(|v| v.to_string());
+    assert_eq!(eval("(at \"héllo\" 1)")?, "é");
+    assert_eq!(eval("(type (at \"héllo\" 1))")?, "string");
+    assert_eq!(eval("(str:chars \"héllo\")")?, "[h, é, l, l, o]");
+    assert_eq!(eval("(len (str:chars \"🎉!\"))")?, "2");
+    assert_eq!(eval("(str:char_code \"é\")")?, "233");
+    assert_eq!(eval("(str:from_code 127881)")?, "🎉");
+    assert_eq!(
+      eval("(str:char_code \"ab\")").unwrap_err(),
+      "builtin `str:char_code` expected string of one character at arg 0, got string"
+    );
+    assert!(eval("(str:from_code -1)").is_err());
+    assert!(eval("(str:from_code 1114112)").is_err());
+    Ok(())
+  }
+
+  #[test]
+  fn register_native() -> Result<(), String> {
+    use crate::error::{NixtError, RuntimeError};
+    use std::{cell::Cell, rc::Rc};
+    let calls = Rc::new(Cell::new(0));
+    let counted = calls.clone();
+    let mut interpreter = Interpreter::builder().build()?;
+    interpreter.register_native("negate", move |args| {
+      counted.set(counted.get() + 1);
+      match args {
+        [Value::Number(n)] => Ok(Value::Number(-n)),
+        _ => Err(NixtError::Runtime(RuntimeError::new("`negate` takes a number"))),
+      }
+    })?;
+    let mut eval = |code: &str| interpreter.eval_str(code).map(|v| v.to_string());
+    assert_eq!(eval("(negate 2)")?, "-2");
+    assert_eq!(eval("(sort_by negate (list 1 3 2))")?, "[3, 2, 1]");
+    assert_eq!(eval("(let f negate) (f 5)")?, "-5");
+    assert_eq!(eval("(negate \"a\")").unwrap_err(), "`negate` takes a number");
+    assert!(calls.get() >= 5);
+    assert!(eval("(set negate 1)").is_err());
+
+    let error = interpreter.register_native("len", |_| Ok(Value::Nil)).unwrap_err();
+    assert_eq!(error, "`len` is a builtin and can not be redefined");
+    assert!(interpreter.register_native("while", |_| Ok(Value::Nil)).is_err());
+    Ok(())
+  }
+
+  #[test]
+  fn map_literals() -> Result<(), String> {
+    let literal = get_ast("(let m {a 1 \"b c\" (+ 1 1)})")?;
+    assert_eq!(literal, get_ast("(let m (map \"a\" 1 \"b c\" (+ 1 1)))")?);
+    let mut interpreter = Interpreter::builder().build()?;
+    let mut eval = |code: &str| interpreter.eval_str(code).map(|v| v.to_string());
+    eval("(let x 3) (let m {name \"nixt\" xs [1 x] inner {deep true} r 0..2})")?;
+    assert_eq!(eval("(do m)")?, "{name: nixt, xs: [1, 3], inner: {deep: true}, r: 0..2}");
+    assert_eq!(eval("(get (get m \"inner\") \"deep\")")?, "true");
+    assert_eq!(eval("(get m \"missing\")")?, "nil");
+    assert_eq!(eval("(keys (insert m \"a\" 1))")?, "[name, xs, inner, r, a]");
+    assert_eq!(eval("(keys (remove m \"xs\"))")?, "[name, inner, r]");
+    assert_eq!(eval("(contains m \"name\")")?, "true");
+    assert_eq!(eval("(contains (remove m \"name\") \"name\")")?, "false");
+    assert_eq!(eval("(len (keys m))")?, "4");
+    assert_eq!(eval("(= {a 1 b 2} (insert {b 2} \"a\" 1))")?, "true");
+    assert_eq!(eval("(type {})")?, "map");
+    assert_eq!(eval("(at {a 1} \"a\")")?, "1");
+    assert_eq!(eval("(let f (func () { (ret {k \"v\"}) })) (f)")?, "{k: v}");
+    assert_eq!(eval("(map \"a\" 1 \"a\" 2)")?, "{a: 2}");
+    assert_eq!(
+      eval("(map \"a\")").unwrap_err(),
+      "builtin `map` expected keys and values in turn, got 1 argument(s)"
+    );
+    assert_eq!(
+      eval("(get [1] \"a\")").unwrap_err(),
+      "builtin `get` expected whole number at arg 1, got string"
+    );
+    assert_eq!(
+      eval("(get 1 \"a\")").unwrap_err(),
+      "builtin `get` expected map or list at arg 0, got number"
+    );
+    assert!(eval("(let map 1)").is_err());
+
+    for (code, error) in &[
+      ("(let m {1 2})", "Found an invalid map key: `1`; keys are names or strings"),
+      ("(let m {a})", "Found a map key without a value"),
+      ("(let m {a 1)", "Expected `}` to close the map"),
+    ] {
+      let mut lexer = Lexer::new(code);
+      let mut parser = Parser::new(lexer.scan_tokens());
+      parser.parse();
+      let messages = parser.diagnostics().iter().map(|d| d.message.as_str()).collect::<Vec<_>>();
+      assert!(messages.contains(error), "{}: {:?}", code, messages);
+    }
+    Ok(())
+  }
+
+  #[test]
+  fn ordered_maps() -> Result<(), String> {
+    let mut interpreter = Interpreter::builder().build()?;
+    let mut eval = |code: &str| interpreter.eval_str(code).map(|v| v.to_string());
+    eval("(let m {z 1 a 2 m 3})")?;
+    assert_eq!(eval("(do m)")?, "{z: 1, a: 2, m: 3}");
+    assert_eq!(eval("(keys (insert m \"b\" 4))")?, "[z, a, m, b]");
+    assert_eq!(eval("(insert m \"a\" 5)")?, "{z: 1, a: 5, m: 3}");
+    assert_eq!(eval("(remove m \"z\")")?, "{a: 2, m: 3}");
+    assert_eq!(eval("(map \"b\" 1 \"a\" 2 \"b\" 3)")?, "{b: 3, a: 2}");
+    assert_eq!(eval("(sort_keys m)")?, "{a: 2, m: 3, z: 1}");
+    assert_eq!(eval("(do m)")?, "{z: 1, a: 2, m: 3}");
+    assert_eq!(eval("(= m (sort_keys m))")?, "true");
+    assert_eq!(eval("(= m {z 1 a 2})")?, "false");
+    assert_eq!(eval("(= m {z 1 a 2 m 4})")?, "false");
+    assert_eq!(eval("(= (fingerprint m) (fingerprint (sort_keys m)))")?, "true");
+    assert_eq!(
+      eval("(sort_keys [1])").unwrap_err(),
+      "builtin `sort_keys` expected map at arg 0, got list"
+    );
+    Ok(())
+  }
+
+  #[test]
+  fn short_circuit() -> Result<(), String> {
+    let mut interpreter = Interpreter::builder().build()?;
+    let mut eval = |code: &str| interpreter.eval_str(code).map(|v| v.to_string());
+    // `boom` is undefined, so evaluating an operand that calls it fails
+    assert_eq!(eval("(and false (boom))")?, "false");
+    assert_eq!(eval("(or true (boom))")?, "true");
+    assert_eq!(eval("(and true false (boom))")?, "false");
+    assert_eq!(eval("(or false true (boom))")?, "true");
+    assert_eq!(eval("(and 0 (boom))")?, "0");
+    assert_eq!(eval("(or \"yes\" missing)")?, "yes");
+    assert_eq!(eval("(and 1 2)")?, "2");
+    assert_eq!(eval("(or nil 3)")?, "3");
+    assert_eq!(eval("(or nil \"\")")?, "");
+    assert_eq!(eval("(if (and 1 2) ((ret 1)) ((ret 0)))")?, "1");
+    assert_eq!(eval("(and true (< 1 2) (> 1 2))")?, "false");
+    assert_eq!(eval("(or false (> 1 2) (< 1 2))")?, "true");
+    assert_eq!(eval("(and true true)")?, "true");
+    assert_eq!(eval("(or false false)")?, "false");
+    assert!(eval("(and true (boom))").is_err());
+    assert!(eval("(or false missing)").is_err());
+    Ok(())
+  }
+
+  #[test]
+  fn persistent_collections() -> Result<(), String> {
+    let mut interpreter = Interpreter::builder().build()?;
+    let mut eval = |code: &str| interpreter.eval_str(code).map(|v| v.to_string());
+    eval("(let xs (list)) (let i 0) (while (< i 2000) { (set xs (push xs i)) (set i (+ i 1)) })")?;
+    eval("(let changed (set_in xs [5] \"five\")) (let m {a xs})")?;
+    assert_eq!(eval("(at xs 5)")?, "5");
+    assert_eq!(eval("(at changed 5)")?, "five");
+    assert_eq!(eval("(at (get (insert m \"a\" changed) \"a\") 5)")?, "five");
+    assert_eq!(eval("(at (get m \"a\") 5)")?, "5");
+    assert_eq!(eval("(len (pop xs))")?, "1999");
+    assert_eq!(eval("(at xs 100..103)")?, "[100, 101, 102]");
+
+    let xs = interpreter.get("xs").unwrap();
+    let copy = xs.clone();
+    #[cfg(feature = "persistent")]
+    assert!(xs.as_list().unwrap().ptr_eq(copy.as_list().unwrap()));
+    assert_eq!(copy.as_list().map(|l| l.len()), Some(2000));
+    Ok(())
+  }
+
+  #[test]
+  fn csv_builtins() -> Result<(), String> {
+    let mut interpreter = Interpreter::builder().build()?;
+    let csv = "id,name,note\r\n1,nixt,\"multi\nline\"\r\n\n2,\"\"\"q\"\"\",\r\n";
+    interpreter.set("csv", Value::from(csv))?;
+    let mut eval = |code: &str| interpreter.eval_str(code).map(|v| v.to_string());
+    eval("(let rows (csv_parse csv))")?;
+    assert_eq!(eval("(len rows)")?, "2");
+    assert_eq!(eval("(at rows 0)")?, "{id: 1, name: nixt, note: multi\nline}");
+    assert_eq!(eval("(at rows 1)")?, "{id: 2, name: \"q\", note: }");
+    assert_eq!(eval("(type (get (at rows 0) \"id\"))")?, "string");
+    assert_eq!(eval("(= (csv_write (csv_parse (csv_write rows))) (csv_write rows))")?, "true");
+    let written = "id,name,note\n1,nixt,\"multi\nline\"\n2,\"\"\"q\"\"\",\n";
+    assert_eq!(eval("(csv_write rows)")?, written);
+    assert_eq!(eval("(csv_parse \"a,b\" \"rows\")")?, "[[a, b]]");
+    assert_eq!(eval("(csv_parse \"a,b\")")?, "[]");
+    assert_eq!(eval("(csv_parse \"\")")?, "[]");
+    assert_eq!(eval("(csv_write [])")?, "");
+    assert_eq!(eval("(csv_write [[1 2] [true nil]])")?, "1,2\ntrue,\n");
+
+    for (code, error) in &[
+      ("(csv_parse \"a,b\n1\")", "builtin `csv_parse`: line 2 has 1 field(s), the header 2"),
+      ("(csv_parse \"a,a\n1,2\")", "builtin `csv_parse`: the header names `a` twice"),
+      ("(csv_parse 'a\n\"1')", "builtin `csv_parse`: the quoted field at line 2 is not closed"),
+      ("(csv_parse 'a' 'cols')", "`csv_parse` reads records as maps, or as \"rows\", not \"cols\""),
+      (
+        "(csv_write [[1] {a 1}])",
+        "builtin `csv_write` expected a list of maps or of lists, found a map in it",
+      ),
+      ("(csv_write 1)", "builtin `csv_write` expected list at arg 0, got number"),
+    ] {
+      assert_eq!(eval(code).unwrap_err(), *error, "{}", code);
+    }
+    Ok(())
+  }
+
+  #[cfg(feature = "msgpack")]
+  #[test]
+  fn msgpack_values() -> Result<(), String> {
+    let mut interpreter = Interpreter::builder().build()?;
+    let mut eval = |code: &str| interpreter.eval_str(code).map(|v| v.to_string());
+    eval("(enum Color (Red Green))")?;
+    let value = "{name \"nixt\" n -3 big (* 4294967296 4294967296) half 0.5 ok true \
+      none nil xs [1 [2]] c Color:Green r 0..10 f print}";
+    eval(&format!("(let value {})", value))?;
+    eval("(let bytes (value_encode value))")?;
+    assert_eq!(eval("(value_decode bytes)")?, eval("(do value)")?);
+    assert_eq!(eval("(value_encode [1 -1 nil])")?, "[147, 1, 255, 192]");
+    assert_eq!(eval("(value_decode [202 63 192 0 0])")?, "1.5");
+    assert_eq!(eval("(value_decode [196 2 104 105])")?, "[104, 105]");
+
+    for (code, error) in &[
+      ("(value_encode (func () {}))", "Can not encode a function, only data"),
+      ("(value_decode [1 2])", "Unexpected trailing MessagePack at byte 1"),
+      ("(value_decode [146 1])", "Unexpected end of MessagePack at byte 2"),
+      ("(value_decode [129 1 1])", "Map keys must be strings, found a number at byte 1"),
+      ("(value_decode [212 9 0])", "Invalid MessagePack extension of type 9 at byte 1"),
+      ("(value_decode [256])", "builtin `value_decode` expected a list of bytes, found 256 at 0"),
+    ] {
+      assert_eq!(eval(code).unwrap_err(), *error, "{}", code);
+    }
+
+    let value = interpreter.eval_str("(do value)")?;
+    let decoded = Value::from_bytes(&value.to_bytes()?)?;
+    assert_eq!(decoded.to_string(), value.to_string());
+    assert!(Value::from_bytes(&[0xc1]).is_err());
+    Ok(())
+  }
+
+  #[cfg(feature = "arrow")]
+  #[test]
+  fn arrow_read() -> Result<(), String> {
+    use arrow_array::{ArrayRef, BooleanArray, Float64Array, Int32Array, RecordBatch, StringArray};
+    use std::sync::Arc;
+    let columns: Vec<(&str, ArrayRef)> = vec![
+      ("id", Arc::new(Int32Array::from(vec![1, 2]))),
+      ("name", Arc::new(StringArray::from(vec![Some("nixt"), None]))),
+      ("score", Arc::new(Float64Array::from(vec![0.5, 2.0]))),
+      ("ok", Arc::new(BooleanArray::from(vec![true, false]))),
+    ];
+    let batch = RecordBatch::try_from_iter(columns).map_err(|e| e.to_string())?;
+    let path = std::env::temp_dir().join("nixt_arrow_read.arrow");
+    let file = std::fs::File::create(&path).map_err(|e| e.to_string())?;
+    let mut writer = arrow_ipc::writer::FileWriter::try_new(file, &batch.schema())
+      .map_err(|e| e.to_string())?;
+    writer.write(&batch).map_err(|e| e.to_string())?;
+    writer.finish().map_err(|e| e.to_string())?;
+
+    let mut interpreter = Interpreter::builder().build()?;
+    interpreter.set("path", Value::from(path.to_string_lossy().into_owned()))?;
+    let rows = interpreter.eval_str("(arrow_read path)")?.to_string();
+    assert_eq!(
+      rows,
+      "[{id: 1, name: nixt, score: 0.5, ok: true}, {id: 2, name: nil, score: 2, ok: false}]"
+    );
+    let missing = interpreter.eval_str("(arrow_read \"missing.arrow\")").unwrap_err();
+    assert!(missing.starts_with("Could not read `missing.arrow` as Arrow: "), "{}", missing);
+    assert_eq!(interpreter.eval_str("(in \"arrow\" (features))")?.to_string(), "true");
+    Ok(())
+  }
+
+  #[test]
+  fn verbose_mode() -> Result<(), String> {
+    let log = SharedBuffer::default();
+    let verbosity = Verbosity { expressions: true, scopes: true, timing: false };
+    let mut interpreter = Interpreter::builder().verbose(log.clone(), verbosity).build()?;
+    interpreter.eval_str("(let x 1) (if (= x 1) ((set x 2))) (+ x math:PI)")?;
+    let lines = [
+      "eval: (let x 1)",
+      "scope: x = 1",
+      "eval: (if (= x 1) ((set x 2)))",
+      "eval: (set x 2)",
+      "scope: x = 2",
+      "eval: (+ x math:PI)",
+      "scope: x = 2",
+    ];
+    let text = String::from_utf8(log.0.borrow().clone()).unwrap();
+    assert_eq!(text, lines.join("\n") + "\n");
+
+    let log = SharedBuffer::default();
+    let timing = Verbosity { timing: true, ..Verbosity::default() };
+    let mut interpreter = Interpreter::builder().verbose(log.clone(), timing).build()?;
+    interpreter.eval_str("(let s \"a\") (print s)")?;
+    let text = String::from_utf8(log.0.borrow().clone()).unwrap();
+    let lines = text.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 2, "{}", text);
+    assert!(lines[0].starts_with("took ") && lines[0].ends_with(": (let s \"a\")"), "{}", text);
+    assert!(lines[1].ends_with(": (print s)"), "{}", text);
+
+    let quiet = SharedBuffer::default();
+    let builder = Interpreter::builder().verbose(quiet.clone(), Verbosity::default());
+    builder.build()?.eval_str("(let x 1)")?;
+    assert!(quiet.0.borrow().is_empty());
+    Ok(())
+  }
+
+  #[test]
+  fn for_lines() -> Result<(), String> {
+    let path = std::env::temp_dir().join("nixt_for_lines_test.log");
+    std::fs::write(&path, "a\nbb\r\n\nstop\nlast").unwrap();
+    let mut interpreter = Interpreter::builder().stdout(SharedBuffer::default()).build()?;
+    interpreter.set("path", Value::from(path.to_string_lossy().into_owned()))?;
+    let mut eval = |code: &str| interpreter.eval_str(code).map(|v| v.to_string());
+    assert_eq!(eval("(for_lines path (func (line) { (ret (~ line \"stop\")) }))")?, "4");
+    assert_eq!(eval("(for_lines path (func (line) { (ret (len line)) }))")?, "5");
+    let error = eval("(for_lines path (func (line) { (ret (nope line)) }))").unwrap_err();
+    assert_eq!(error, eval("(nope \"a\")").unwrap_err());
+    let missing = std::env::temp_dir().join("nixt_for_lines_missing.log");
+    interpreter.set("missing", Value::from(missing.to_string_lossy().into_owned()))?;
+    let error = interpreter.eval_str("(for_lines missing print)").unwrap_err();
+    assert!(error.starts_with("Could not read `"), "{}", error);
+    std::fs::remove_file(&path).ok();
+    Ok(())
+  }
+
+  #[test]
+  fn closures() -> Result<(), String> {
+    let mut interpreter = Interpreter::builder().build()?;
+    let mut eval = |code: &str| interpreter.eval_str(code).map(|v| v.to_string());
+    eval("(let make_adder (func (n) { (ret (func (x) { (ret (+ x n)) })) }))")?;
+    eval("(let add2 (make_adder 2)) (let add5 (make_adder 5))")?;
+    assert_eq!(eval("(list (add2 3) (add5 3) (add2 0))")?, "[5, 8, 2]");
+    let scale = "(let scale (func (xs k) { (ret (sort_by (func (x) { (ret (* x k)) }) xs)) }))";
+    eval(scale)?;
+    assert_eq!(eval("(scale [3 1 2] -1)")?, "[3, 2, 1]");
+    // Inner scopes are kept too, and the captured variables are read when called.
+    let nested = "(let outer (func (a) { { (let b (* a 10)) \
+      (let inner (func (c) { (ret (+ a b c)) })) (ret inner) } }))";
+    eval(nested)?;
+    assert_eq!(eval("(let inner (outer 1)) (inner 2)")?, "13");
+
+    // The caller's variables are not visible to the functions it calls.
+    eval("(let show (func () { (ret secret) }))")?;
+    eval("(let caller (func () { (let secret 1) (ret (show)) }))")?;
+    assert_eq!(eval("(caller)").unwrap_err(), "Attempted to access an undefined variable");
+    // Functions defined in the globals see the ones defined after them.
+    eval("(let later (func () { (ret (+ defined_after 1)) })) (let defined_after 41)")?;
+    assert_eq!(eval("(later)")?, "42");
+
+    let add2 = interpreter.get("add2").unwrap();
+    let env = add2.as_func().and_then(|f| f.env.as_ref()).map(|env| env.depth());
+    assert_eq!(env, Some(2));
+    assert!(interpreter.get("make_adder").unwrap().as_func().unwrap().env.is_none());
+    assert_eq!(interpreter.call_value(&add2, &[Value::from(1.)])?.to_string(), "3");
+
+    // Calls made as the prelude loads are local scopes too.
+    let mut interpreter = Interpreter::builder()
+      .prelude_source("(let make (func (n) { (ret (func () { (ret n) })) })) (let one (make 1))")
+      .build()?;
+    assert_eq!(interpreter.eval_str("(one)")?.to_string(), "1");
+    assert!(interpreter.get("one").unwrap().as_func().unwrap().env.is_some());
+    Ok(())
+  }
+
+  #[test]
+  fn transfer_values() -> Result<(), String> {
+    let mut sender = Interpreter::builder().build()?;
+    sender.eval_str("(let data {xs [1 2 [3]] name \"a\" color (enum C (R G)) r 0..3})")?;
+    sender.eval_str("(let make (func (n) { (ret (func (x) { (ret (+ x n)) })) }))")?;
+    sender.eval_str("(let add1 (make 1)) (let nested [1 {f add1}])")?;
+    let data = sender.send("data")?;
+    assert!(!data.is_frozen());
+    assert_eq!(data.value().to_string(), sender.get("data").unwrap().to_string());
+
+    let closure = "Can not transfer a function defined in a local scope, as it keeps that scope";
+    assert_eq!(sender.send("add1").unwrap_err(), format!("{}: `add1`", closure));
+    assert!(sender.send("nested").is_err());
+    assert!(Transfer::frozen(sender.get("nested").unwrap()).is_err());
+    assert_eq!(sender.send("missing").unwrap_err(), "`missing` is not defined");
+    // Functions defined in the globals are sent, and see the globals of their receiver.
+    let make = sender.send("make")?;
+
+    let frozen = Transfer::frozen(data.into_value())?;
+    assert!(frozen.is_frozen());
+    let handles = (0..3).map(|i| {
+      let (frozen, make) = (frozen.clone(), make.clone());
+      std::thread::spawn(move || -> Result<String, String> {
+        let mut receiver = Interpreter::builder().build()?;
+        receiver.receive("data", frozen)?;
+        receiver.receive("make", make)?;
+        receiver.eval_str(&format!("(let add (make {}))", i))?;
+        Ok(receiver.eval_str("(add (len (get data \"xs\")))")?.to_string())
+      })
+    });
+    let results = handles.map(|handle| handle.join().unwrap()).collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(results, ["3", "4", "5"]);
+    assert_eq!(frozen.into_value().to_string(), sender.get("data").unwrap().to_string());
+
+    let mut receiver = Interpreter::builder().build()?;
+    receiver.receive("data", sender.send("data")?)?;
+    assert!(receiver.receive("data", sender.send("data")?).is_err());
+    assert!(receiver.eval_str("(set data 1)").is_err());
+
+    let frozen = Interpreter::builder()
+      .prelude_source("(let make (func (n) { (ret (func () { (ret n) })) })) (let one (make 1))")
+      .freeze();
+    assert_eq!(frozen.unwrap_err(), format!("{}: `one`", closure));
+    Ok(())
+  }
+
+  #[test]
+  fn recursive_functions() -> Result<(), String> {
+    let mut interpreter = Interpreter::builder().build()?;
+    let mut eval = |code: &str| interpreter.eval_str(code).map(|v| v.to_string());
+    eval("(let fact (func (n) { (if (< n 2) ((ret 1)) ((ret (* n (fact (- n 1)))))) }))")?;
+    assert_eq!(eval("(fact 10)")?, "3628800");
+    // A local function sees itself in the scope it is bound in, even once that scope is left.
+    eval(
+      "(let parity (func () {
+        (let even (func (n) { (if (= n 0) ((ret \"even\")) ((ret (odd (- n 1))))) }))
+        (let odd (func (n) { (if (= n 0) ((ret \"odd\")) ((ret (even (- n 1))))) }))
+        (ret even)
+      }))",
+    )?;
+    assert_eq!(eval("(let even (parity)) (list (even 10) (even 7))")?, "[even, odd]");
+    eval(
+      "(let count_down (func (k) {
+        (let down (func (n) { (if (< n 1) ((ret [])) ((ret (push (down (- n k)) n)))) }))
+        (ret (down 6))
+      }))",
+    )?;
+    assert_eq!(eval("(count_down 2)")?, "[2, 4, 6]");
+    // The name is looked up at each call: rebinding it changes what the body calls.
+    eval("(let count (func (n) { (if (< n 1) ((ret 0)) ((ret (+ 1 (count (- n 1)))))) }))")?;
+    eval("(let calls count) (set count (func (n) { (ret 100) }))")?;
+    assert_eq!(eval("(calls 5)")?, "101");
+    Ok(())
+  }
+
+  #[test]
+  fn host_resources() -> Result<(), String> {
+    use crate::utils::resource::{HostResource, Resource};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    struct File(Arc<AtomicUsize>);
+    impl HostResource for File {
+      fn kind(&self) -> &str {
+        "file"
+      }
+      fn close(&mut self) -> Result<(), String> {
+        match self.0.fetch_add(1, Ordering::SeqCst) {
+          0 => Ok(()),
+          _ => Err("closed twice".to_owned()),
+        }
+      }
+    }
+    struct Socket;
+    impl HostResource for Socket {
+      fn kind(&self) -> &str {
+        "socket"
+      }
+      fn close(&mut self) -> Result<(), String> {
+        Err("connection reset".to_owned())
+      }
+    }
+
+    let closed = Arc::new(AtomicUsize::new(0));
+    let file = Resource::new(File(closed.clone()));
+    let builder = Interpreter::builder().define("f", Value::Resource(file.clone()));
+    let mut interpreter = builder.build()?;
+    let mut eval = |code: &str| interpreter.eval_str(code).map(|v| v.to_string());
+    assert_eq!(eval("(type f)")?, "resource");
+    let compared = eval("(list f (= f f) (~ f f) (= f 1))")?;
+    assert_eq!(compared, "[@resource: file, true, false, false]");
+    assert_eq!(eval("(let g f) (close g)")?, "true");
+    assert_eq!(eval("(list (close f) f)")?, "[false, @resource: file (closed)]");
+    assert_eq!(closed.load(Ordering::SeqCst), 1);
+    assert!(file.is_closed());
+    let count = file.with(|file: &mut File| file.0.load(Ordering::SeqCst));
+    assert_eq!(count.unwrap_err(), "The file is closed");
+    let error = eval("(close 1)").unwrap_err();
+    assert_eq!(error, "builtin `close` expected resource at arg 0, got number");
+    assert!(interpreter.send("f").unwrap_err().starts_with("Can not transfer the file resource"));
+
+    // Dropping the last value holding a resource closes it.
+    let closed = Arc::new(AtomicUsize::new(0));
+    let file = Resource::new(File(closed.clone()));
+    let weak = file.downgrade();
+    let error = file.with(|_: &mut Socket| ()).unwrap_err();
+    assert_eq!(error, "The file is not the resource expected");
+    interpreter.define("open", Value::Resource(file))?;
+    interpreter.eval_str("(let keep (func (r) { (let copy [r]) (ret (len copy)) })) (keep open)")?;
+    assert_eq!(closed.load(Ordering::SeqCst), 0);
+    assert!(weak.upgrade().is_some_and(|file| !file.is_closed()));
+    interpreter.reset();
+    assert!(weak.upgrade().is_none());
+    assert_eq!(closed.load(Ordering::SeqCst), 1);
+
+    interpreter.define("socket", Value::Resource(Resource::new(Socket)))?;
+    let error = interpreter.eval_str("(close socket)").unwrap_err();
+    assert_eq!(error, "Could not close the socket: connection reset");
+    assert_eq!(interpreter.eval_str("(do socket)")?.to_string(), "@resource: socket (closed)");
+    Ok(())
+  }
 
   #[test]
   fn ackermann_bench() -> Result<(), String> {
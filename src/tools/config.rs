@@ -0,0 +1,196 @@
+use crate::core::interpreter::sandbox::SandboxPolicy;
+use crate::tools::lint::{LintConfig, Severity};
+use std::path::{Path, PathBuf};
+
+/// The name of the project configuration file.
+pub const FILE_NAME: &str = ".nixt.toml";
+
+/// The settings of a `.nixt.toml`, shared by the scripts of a project. Unset settings keep the
+/// defaults of the command line.
+///
+/// ```toml
+/// strict = true
+/// search_paths = ["lib"]
+///
+/// [lint]
+/// magic-number = "allow"
+/// max_function_lines = 60
+///
+/// [sandbox]
+/// deny = ["time:now"]
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProjectConfig {
+  pub strict: Option<bool>,
+  pub infix: Option<bool>,
+  pub lenient: Option<bool>,
+  pub const_exprs: Option<bool>,
+  /// The directories searched for the modules `import` does not find, relative to the file.
+  pub search_paths: Vec<PathBuf>,
+  /// The severity of lint rules, in the order they are set.
+  pub lint: Vec<(String, Severity)>,
+  pub max_function_lines: Option<usize>,
+  pub sandbox: Option<SandboxPolicy>,
+}
+
+impl ProjectConfig {
+  /// Reads the configuration in `source`, resolving its paths from `dir`.
+  ///
+  /// ```
+  /// use nixt::tools::config::ProjectConfig;
+  /// let source = "strict = true\n[lint]\nmagic-number = 'deny'";
+  /// let config = ProjectConfig::parse(source, ".".as_ref())?;
+  /// assert_eq!(config.strict, Some(true));
+  /// assert!(ProjectConfig::parse("stric = true", ".".as_ref()).is_err());
+  /// # Ok::<(), String>(())
+  /// ```
+  pub fn parse(source: &str, dir: &Path) -> Result<Self, String> {
+    let mut config = Self::default();
+    let mut table = String::new();
+    for (i, line) in source.lines().enumerate() {
+      let at = |e: String| format!("Line {} | {}", i + 1, e);
+      let line = strip_comment(line).trim();
+      if line.is_empty() {
+        continue;
+      }
+      if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+        table = name.trim().to_owned();
+        if !["lint", "sandbox"].contains(&table.as_str()) {
+          return Err(at(format!("Unknown table `[{}]`", table)));
+        }
+        continue;
+      }
+      let (key, value) = line
+        .split_once('=')
+        .ok_or_else(|| at("Expected `key = value`".to_owned()))?;
+      let value = value.trim();
+      if value.starts_with('[') && !value.ends_with(']') {
+        return Err(at("Unsupported syntax: arrays must fit on one line".to_owned()));
+      }
+      let value = parse_value(value).map_err(at)?;
+      config.set(&table, key.trim(), value, dir).map_err(at)?;
+    }
+    Ok(config)
+  }
+  fn set(&mut self, table: &str, key: &str, value: TomlValue, dir: &Path) -> Result<(), String> {
+    match (table, key, value) {
+      ("", "strict", TomlValue::Bool(b)) => self.strict = Some(b),
+      ("", "infix", TomlValue::Bool(b)) => self.infix = Some(b),
+      ("", "lenient", TomlValue::Bool(b)) => self.lenient = Some(b),
+      ("", "const_exprs", TomlValue::Bool(b)) => self.const_exprs = Some(b),
+      ("", "search_paths", TomlValue::Array(paths)) => {
+        self.search_paths = paths.iter().map(|path| dir.join(path)).collect();
+      }
+      ("lint", "max_function_lines", TomlValue::Integer(n)) => self.max_function_lines = Some(n),
+      ("lint", rule, TomlValue::Str(level)) => {
+        let severity = match level.as_str() {
+          "allow" => Severity::Allow,
+          "warn" => Severity::Warn,
+          "deny" => Severity::Deny,
+          _ => return Err(format!("`{}` must be \"allow\", \"warn\" or \"deny\"", rule)),
+        };
+        LintConfig::default().set(rule, severity)?;
+        self.lint.push((rule.to_owned(), severity));
+      }
+      ("sandbox", "allow", TomlValue::Array(names)) => {
+        self.sandbox = Some(SandboxPolicy::allow(names));
+      }
+      ("sandbox", "deny", TomlValue::Array(names)) => {
+        self.sandbox = Some(SandboxPolicy::deny(names));
+      }
+      (_, key, _) => {
+        let name = if table.is_empty() { key.to_owned() } else { format!("{}.{}", table, key) };
+        if is_known(table, key) {
+          return Err(format!("Invalid value for `{}`", name));
+        }
+        return Err(format!("Unknown setting `{}`", name));
+      }
+    }
+    Ok(())
+  }
+  /// The severities of `lint` applied to `config`.
+  pub fn lint_config(&self, mut config: LintConfig) -> LintConfig {
+    for (rule, severity) in &self.lint {
+      // The rules were checked when parsing.
+      let _ = config.set(rule, *severity);
+    }
+    if let Some(max) = self.max_function_lines {
+      config.max_function_lines = max;
+    }
+    config
+  }
+}
+
+fn is_known(table: &str, key: &str) -> bool {
+  match table {
+    "" => ["strict", "infix", "lenient", "const_exprs", "search_paths"].contains(&key),
+    "lint" => key == "max_function_lines",
+    _ => ["allow", "deny"].contains(&key),
+  }
+}
+
+/// The `.nixt.toml` of the closest directory from `start` up to the root, with its path.
+pub fn discover(start: &Path) -> Result<Option<(PathBuf, ProjectConfig)>, String> {
+  let start = std::fs::canonicalize(start).unwrap_or_else(|_| start.to_owned());
+  for dir in start.ancestors() {
+    let path = dir.join(FILE_NAME);
+    if path.is_file() {
+      let source = std::fs::read_to_string(&path).map_err(|e| e.to_string())?;
+      let config = ProjectConfig::parse(&source, dir)
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+      return Ok(Some((path, config)));
+    }
+  }
+  Ok(None)
+}
+
+/// The values of the TOML subset the configuration uses.
+enum TomlValue {
+  Bool(bool),
+  Integer(usize),
+  Str(String),
+  Array(Vec<String>),
+}
+
+fn parse_value(value: &str) -> Result<TomlValue, String> {
+  match value {
+    "true" => return Ok(TomlValue::Bool(true)),
+    "false" => return Ok(TomlValue::Bool(false)),
+    _ => {}
+  }
+  if let Ok(n) = value.parse() {
+    return Ok(TomlValue::Integer(n));
+  }
+  if let Some(items) = value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+    return items
+      .split(',')
+      .map(str::trim)
+      .filter(|item| !item.is_empty())
+      .map(parse_string)
+      .collect::<Result<_, _>>()
+      .map(TomlValue::Array);
+  }
+  parse_string(value).map(TomlValue::Str)
+}
+
+fn parse_string(value: &str) -> Result<String, String> {
+  ['"', '\'']
+    .iter()
+    .find_map(|quote| value.strip_prefix(*quote).and_then(|v| v.strip_suffix(*quote)))
+    .map(str::to_owned)
+    .ok_or_else(|| format!("Expected a boolean, a number, a string or a list: `{}`", value))
+}
+
+/// `line` without its `#` comment, if the `#` is not in a string.
+fn strip_comment(line: &str) -> &str {
+  let mut quote = None;
+  for (i, c) in line.char_indices() {
+    match (c, quote) {
+      ('"', None) | ('\'', None) => quote = Some(c),
+      (c, Some(q)) if c == q => quote = None,
+      ('#', None) => return &line[..i],
+      _ => {}
+    }
+  }
+  line
+}
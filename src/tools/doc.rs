@@ -0,0 +1,265 @@
+use crate::core::interpreter::builder::InterpreterBuilder;
+use crate::core::interpreter::interpreter::Capture;
+use crate::core::lexer::Lexer;
+use crate::tools::test::TestResult;
+use crate::utils::json::Json;
+use crate::utils::token::{Token, TokenType};
+use std::time::Instant;
+
+/// A top-level binding documented with `##` comments.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocEntry {
+  pub name: String,
+  pub line: usize,
+  pub is_const: bool,
+  /// The parameters when the binding is a `func`.
+  pub params: Option<Vec<String>>,
+  pub doc: String,
+}
+
+/// Collects the `##` doc comments preceding the top-level `let` and `const` definitions of
+/// `source`. Names starting with `_` are private and left out.
+///
+/// ```
+/// let docs = nixt::tools::doc::extract("## Adds one\n(let inc (func (n) {(ret (+ n 1))}))")?;
+/// assert_eq!(docs[0].name, "inc");
+/// assert_eq!(docs[0].doc, "Adds one");
+/// # Ok::<(), String>(())
+/// ```
+pub fn extract(source: &str) -> Result<Vec<DocEntry>, String> {
+  let mut lexer = Lexer::new(source);
+  lexer.set_trivia(true);
+  let tokens = lexer.scan_tokens();
+  if let Some(errors) = lexer.get_errors() {
+    return Err(errors.join("\n"));
+  }
+
+  let mut entries = vec![];
+  let mut depth = 0;
+  for (i, tok) in tokens.iter().enumerate() {
+    match tok.typ {
+      TokenType::LeftParen | TokenType::LeftBrace => depth += 1,
+      TokenType::RightParen | TokenType::RightBrace => depth -= 1,
+      _ => {}
+    }
+    if depth != 1 || tok.typ != TokenType::LeftParen {
+      continue;
+    }
+    let doc = doc_comment(tok);
+    if doc.is_empty() {
+      continue;
+    }
+    if let Some(entry) = definition(&tokens[i + 1..], doc) {
+      if !entry.name.starts_with('_') {
+        entries.push(entry);
+      }
+    }
+  }
+  Ok(entries)
+}
+
+/// The text of the `##` comments attached to `tok`, one line per comment.
+fn doc_comment(tok: &Token) -> String {
+  tok
+    .trivia
+    .iter()
+    .filter_map(|comment| match &comment.typ {
+      TokenType::Comment(text) => text.strip_prefix('#'),
+      _ => None,
+    })
+    .map(|line| line.strip_prefix(' ').unwrap_or(line))
+    .collect::<Vec<_>>()
+    .join("\n")
+}
+
+/// Reads `let name value` or `const name value`, optionally `pub`, from the tokens following a `(`.
+fn definition(tokens: &[Token], doc: String) -> Option<DocEntry> {
+  let tokens = match tokens.first()?.typ {
+    TokenType::Pub => &tokens[1..],
+    _ => tokens,
+  };
+  let is_const = match tokens.first()?.typ {
+    TokenType::Let => false,
+    TokenType::Const => true,
+    _ => return None,
+  };
+  let name = match &tokens.get(1)?.typ {
+    TokenType::Identifier(name) => name.to_owned(),
+    _ => return None,
+  };
+  let is_func = tokens.get(2).map(|t| &t.typ) == Some(&TokenType::LeftParen)
+    && tokens.get(3).map(|t| &t.typ) == Some(&TokenType::Func)
+    && tokens.get(4).map(|t| &t.typ) == Some(&TokenType::LeftParen);
+  let params = if is_func {
+    Some(
+      tokens[5..]
+        .iter()
+        .take_while(|t| t.typ != TokenType::RightParen)
+        .filter_map(|t| match &t.typ {
+          TokenType::Identifier(param) => Some(param.to_owned()),
+          _ => None,
+        })
+        .collect(),
+    )
+  } else {
+    None
+  };
+  Some(DocEntry {
+    name,
+    line: tokens[0].line,
+    is_const,
+    params,
+    doc,
+  })
+}
+
+/// Renders `entries` as a Markdown page titled `title`.
+pub fn to_markdown(title: &str, entries: &[DocEntry]) -> String {
+  let mut toret = format!("# {}\n", title);
+  for entry in entries {
+    let signature = match &entry.params {
+      Some(params) if params.is_empty() => format!("({})", entry.name),
+      Some(params) => format!("({} {})", entry.name, params.join(" ")),
+      None if entry.is_const => format!("const {}", entry.name),
+      None => entry.name.to_owned(),
+    };
+    toret.push_str(&format!("\n## `{}`\n\n{}\n", signature, entry.doc));
+  }
+  toret
+}
+
+/// Renders `entries` as a JSON array.
+pub fn to_json(entries: &[DocEntry]) -> Json {
+  Json::Array(
+    entries
+      .iter()
+      .map(|entry| {
+        let params = match &entry.params {
+          Some(params) => Json::Array(params.iter().map(|p| Json::Str(p.to_owned())).collect()),
+          None => Json::Null,
+        };
+        Json::Object(vec![
+          ("name".to_owned(), Json::Str(entry.name.to_owned())),
+          ("line".to_owned(), Json::Number(entry.line as f64)),
+          ("const".to_owned(), Json::Bool(entry.is_const)),
+          ("params".to_owned(), params),
+          ("doc".to_owned(), Json::Str(entry.doc.to_owned())),
+        ])
+      })
+      .collect(),
+  )
+}
+
+/// A fenced code block of a doc comment, run by `nixt doctest`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Example {
+  /// The documented binding, then the index of the example among its ones: `inc[0]`.
+  pub name: String,
+  pub code: String,
+  /// The lines the example should print, given by its `=> ` lines. Without any, the example
+  /// only has to run without error.
+  pub expected: Vec<String>,
+}
+
+/// The examples of the doc comments of `entries`: their code blocks fenced with ` ``` `, or
+/// ` ```nixt `. The other languages are not run.
+///
+/// ```
+/// let source = "## Adds one\n## ```\n## (print (inc 41))\n## => 42\n## ```
+///   (let inc (func (n) {(ret (+ n 1))}))";
+/// let docs = nixt::tools::doc::extract(source)?;
+/// let examples = nixt::tools::doc::examples(&docs);
+/// assert_eq!(examples[0].name, "inc[0]");
+/// assert_eq!((examples[0].code.as_str(), examples[0].expected.as_slice()), (
+///   "(print (inc 41))\n", ["42".to_owned()].as_slice()
+/// ));
+/// # Ok::<(), String>(())
+/// ```
+pub fn examples(entries: &[DocEntry]) -> Vec<Example> {
+  let mut examples = vec![];
+  for entry in entries {
+    let mut index = 0;
+    // The example being read, if in a block that is run.
+    let mut current: Option<Example> = None;
+    let mut fenced = false;
+    for line in entry.doc.lines() {
+      if let Some(language) = line.trim().strip_prefix("```") {
+        fenced = !fenced;
+        if let Some(example) = current.take() {
+          examples.push(example);
+        } else if fenced && matches!(language.trim(), "" | "nixt") {
+          current = Some(Example {
+            name: format!("{}[{}]", entry.name, index),
+            code: String::new(),
+            expected: vec![],
+          });
+          index += 1;
+        }
+      } else if let Some(example) = current.as_mut() {
+        match line.trim().strip_prefix("=>") {
+          Some(output) => example.expected.push(output.trim().to_owned()),
+          None => example.code.push_str(&format!("{}\n", line)),
+        }
+      }
+    }
+  }
+  examples
+}
+
+/// Runs the examples of the doc comments of `source`, each in a new interpreter a `builder`
+/// configures, after evaluating `source` in it. An example fails if it errors, or if it does not
+/// print the lines its `=> ` lines give.
+///
+/// ```
+/// let source = "## ```\n## (print (inc 1))\n## => 3\n## ```
+///   (let inc (func (n) {(ret (+ n 1))}))";
+/// let results = nixt::tools::doc::run_examples(source, nixt::Interpreter::builder)?;
+/// assert_eq!(results[0].failure.as_deref(), Some("Expected:\n  3\nPrinted:\n  2"));
+/// # Ok::<(), String>(())
+/// ```
+pub fn run_examples(
+  source: &str,
+  builder: impl Fn() -> InterpreterBuilder,
+) -> Result<Vec<TestResult>, String> {
+  let examples = examples(&extract(source)?);
+  let results = examples.into_iter().map(|example| {
+    let start = Instant::now();
+    let output = Capture::default();
+    let failure = run_example(source, &example, builder(), &output).err();
+    TestResult {
+      name: example.name,
+      failure,
+      output: output.take(),
+      duration: start.elapsed(),
+    }
+  });
+  Ok(results.collect())
+}
+
+fn run_example(
+  source: &str,
+  example: &Example,
+  builder: InterpreterBuilder,
+  output: &Capture,
+) -> Result<(), String> {
+  let mut interpreter = builder.stdout(output.clone()).stderr(output.clone()).build()?;
+  interpreter.eval_str(source).map_err(|e| format!("The script failed: {}", e))?;
+  // Only what the example prints is compared.
+  output.take();
+  interpreter.eval_str(&example.code)?;
+  if example.expected.is_empty() {
+    return Ok(());
+  }
+  let printed = output.contents();
+  let lines = printed.lines().map(str::trim_end).collect::<Vec<_>>();
+  if lines == example.expected {
+    return Ok(());
+  }
+  // The failure shows the output already.
+  output.take();
+  Err(format!(
+    "Expected:\n  {}\nPrinted:\n  {}",
+    example.expected.join("\n  "),
+    lines.join("\n  ")
+  ))
+}
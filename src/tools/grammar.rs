@@ -0,0 +1,45 @@
+use crate::core::grammar::{Grammar, Rule};
+
+/// The `grammar.js` of a tree-sitter parser for `grammar`, for editors to highlight and fold
+/// nixt code.
+///
+/// ```
+/// let js = nixt::tools::grammar::to_tree_sitter(&nixt::core::grammar::grammar());
+/// assert!(js.contains("name: 'nixt'"));
+/// assert!(js.contains("source_file: $ =>"));
+/// ```
+pub fn to_tree_sitter(grammar: &Grammar) -> String {
+  let mut js = String::from(
+    "// Generated by `nixt grammar --format tree-sitter` from the grammar of the nixt parser.\n",
+  );
+  js.push_str("module.exports = grammar({\n");
+  js.push_str(&format!("  name: {},\n", quote(grammar.name)));
+  js.push_str(&format!("  word: $ => $.{},\n", grammar.word));
+  let extras = grammar.extras.iter().map(rule).collect::<Vec<_>>();
+  js.push_str(&format!("  extras: $ => [{}],\n", extras.join(", ")));
+  js.push_str("  rules: {\n");
+  for (name, body) in &grammar.rules {
+    js.push_str(&format!("    {}: $ => {},\n", name, rule(body)));
+  }
+  js.push_str("  },\n});\n");
+  js
+}
+
+fn rule(rule: &Rule) -> String {
+  let list = |rules: &[Rule]| rules.iter().map(self::rule).collect::<Vec<_>>().join(", ");
+  match rule {
+    Rule::Literal(text) => quote(text),
+    Rule::Pattern(pattern) => format!("/{}/", pattern.replace('/', "\\/")),
+    Rule::Ref(name) => format!("$.{}", name),
+    Rule::Seq(rules) => format!("seq({})", list(rules)),
+    Rule::Choice(rules) => format!("choice({})", list(rules)),
+    Rule::Repeat(inner) => format!("repeat({})", self::rule(inner)),
+    Rule::Optional(inner) => format!("optional({})", self::rule(inner)),
+    Rule::Field(name, inner) => format!("field({}, {})", quote(name), self::rule(inner)),
+    Rule::Greedy(inner) => format!("prec.right({})", self::rule(inner)),
+  }
+}
+
+fn quote(text: &str) -> String {
+  format!("'{}'", text.replace('\\', "\\\\").replace('\'', "\\'"))
+}
@@ -0,0 +1,131 @@
+use crate::utils::element::format_number;
+use crate::utils::json::Json;
+use crate::utils::node::{AssignType, Node, NodeType};
+
+/// The version of the schema [`to_json`] writes, raised whenever a kind or a member changes.
+pub const AST_SCHEMA_VERSION: u32 = 1;
+
+/// A Graphviz digraph of `ast`, each node labelled with its type and, for literals, names and
+/// operators, its value. Children are ordered left to right.
+///
+/// ```
+/// use nixt::core::{lexer::Lexer, parser::Parser};
+/// let ast = Parser::new(Lexer::new("(let x 2)").scan_tokens()).parse();
+/// let dot = nixt::tools::graph::to_dot(&ast);
+/// assert!(dot.starts_with("digraph ast {\n"));
+/// assert!(dot.contains(r#"n2 [label="Assignement\nLet"];"#));
+/// assert!(dot.contains(r#"n4 [label="NodeNumber\n2"];"#));
+/// assert!(dot.contains("n2 -> n4;"));
+/// ```
+pub fn to_dot(ast: &Node) -> String {
+  let mut dot = String::from("digraph ast {\n  ordering=out;\n");
+  dot.push_str("  node [shape=box, fontname=\"monospace\"];\n");
+  let mut count = 0;
+  write_node(ast, &mut dot, &mut count);
+  dot.push_str("}\n");
+  dot
+}
+
+/// Writes `node` and its subtree, returning the id of `node`.
+fn write_node(node: &Node, dot: &mut String, count: &mut usize) -> usize {
+  let id = *count;
+  *count += 1;
+  dot.push_str(&format!("  n{} [label=\"{}\"];\n", id, escape(&label(&node.get_type()))));
+  for child in node.get_child() {
+    let child_id = write_node(child, dot, count);
+    dot.push_str(&format!("  n{} -> n{};\n", id, child_id));
+  }
+  id
+}
+
+/// The type of a node, then its value on a second line.
+fn label(node_type: &NodeType) -> String {
+  match node_type {
+    NodeType::NodeNumber(n) => format!("NodeNumber\n{}", format_number(*n)),
+    NodeType::NodeStr(s) => format!("NodeStr\n\"{}\"", s),
+    other => {
+      let debug = format!("{:?}", other);
+      match debug.split_once('(') {
+        Some((name, value)) => {
+          let value = value.strip_suffix(')').unwrap_or(value);
+          format!("{}\n{}", name, value.trim_matches('"'))
+        }
+        None => debug,
+      }
+    }
+  }
+}
+
+fn escape(label: &str) -> String {
+  label.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// The syntax tree `ast` as JSON, for tools that do not link nixt:
+/// `{"version": AST_SCHEMA_VERSION, "root": node}`, each node being
+/// `{"kind": k, "span": null, "children": [node...]}` plus the payload of its kind.
+///
+/// | kind | payload | node |
+/// |------|---------|------|
+/// | `block` | | A `(...)` form, or the whole program at the root |
+/// | `scope` | | A `{...}` body |
+/// | `let`, `const`, `set` | | An assignment: the name, then the value |
+/// | `call` | `name` | A call of `name` with its arguments |
+/// | `operator` | `operator`, as written: `+`, `<=`, `and`, ... | An operation on its operands |
+/// | `func` | | The parameters (identifiers in a `block`), then the body |
+/// | `if`, `while`, `cond`, `do`, `return`, `export` | | The forms of the same name |
+/// | `enum` | `name` | The variants, as identifiers |
+/// | `deprecated` | `hint` | The `# @deprecated` hint of a definition |
+/// | `identifier` | `name` | |
+/// | `number`, `string`, `bool` | `value` | A literal |
+/// | `none` | | A missing operand or branch |
+///
+/// `span` is `null` as the parser does not keep the positions of nodes yet.
+///
+/// ```
+/// use nixt::core::{lexer::Lexer, parser::Parser};
+/// let ast = Parser::new(Lexer::new("(let x 2)").scan_tokens()).parse();
+/// let json = nixt::tools::graph::to_json(&ast).to_string();
+/// assert!(json.starts_with(r#"{"version":1,"root":{"kind":"block","span":null,"children":"#));
+/// assert!(json.contains(r#"{"kind":"number","span":null,"children":[],"value":2}"#));
+/// ```
+pub fn to_json(ast: &Node) -> Json {
+  Json::Object(vec![
+    ("version".to_owned(), Json::Number(AST_SCHEMA_VERSION as f64)),
+    ("root".to_owned(), node_json(ast)),
+  ])
+}
+
+fn node_json(node: &Node) -> Json {
+  let (kind, payload) = match node.get_type() {
+    NodeType::Assignement(AssignType::Let) => ("let", None),
+    NodeType::Assignement(AssignType::Const) => ("const", None),
+    NodeType::Assignement(AssignType::Set) => ("set", None),
+    NodeType::Condition => ("if", None),
+    NodeType::Cond => ("cond", None),
+    NodeType::Operator(op) => ("operator", Some(("operator", Json::Str(op.to_string())))),
+    NodeType::Func => ("func", None),
+    NodeType::Loop => ("while", None),
+    NodeType::Return => ("return", None),
+    NodeType::Block => ("block", None),
+    NodeType::Do => ("do", None),
+    NodeType::None => ("none", None),
+    NodeType::Scope => ("scope", None),
+    NodeType::NodeBool(b) => ("bool", Some(("value", Json::Bool(b)))),
+    NodeType::NodeNumber(n) => ("number", Some(("value", Json::Number(n)))),
+    NodeType::NodeStr(s) => ("string", Some(("value", Json::Str(s)))),
+    NodeType::NodeIdentifier(name) => ("identifier", Some(("name", Json::Str(name)))),
+    NodeType::FunctionCall(name) => ("call", Some(("name", Json::Str(name)))),
+    NodeType::Enum(name) => ("enum", Some(("name", Json::Str(name)))),
+    NodeType::Export => ("export", None),
+    NodeType::Deprecated(hint) => ("deprecated", Some(("hint", Json::Str(hint)))),
+  };
+  let mut members = vec![
+    ("kind".to_owned(), Json::Str(kind.to_owned())),
+    ("span".to_owned(), Json::Null),
+    ("children".to_owned(), Json::Array(node.get_child().iter().map(node_json).collect())),
+  ];
+  if let Some((name, value)) = payload {
+    members.push((name.to_owned(), value));
+  }
+  Json::Object(members)
+}
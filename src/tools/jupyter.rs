@@ -0,0 +1,367 @@
+//! The Jupyter messaging protocol, used by the `nixt-kernel` binary to run nixt in notebooks.
+//!
+//! Only the messages a notebook needs to run cells are answered: `kernel_info`, `execute`,
+//! `is_complete`, `comm_info`, `history` and `shutdown`.
+
+use crate::utils::json::{self, Json};
+use crate::Interpreter;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const DELIMITER: &[u8] = b"<IDS|MSG>";
+const PROTOCOL_VERSION: &str = "5.3";
+
+/// The connection file Jupyter starts a kernel with.
+#[derive(Debug, Clone)]
+pub struct Connection {
+  pub transport: String,
+  pub ip: String,
+  pub shell_port: u16,
+  pub iopub_port: u16,
+  pub stdin_port: u16,
+  pub control_port: u16,
+  pub hb_port: u16,
+  pub key: String,
+}
+
+impl Connection {
+  pub fn parse(source: &str) -> Result<Self, String> {
+    let file = json::parse(source)?;
+    let text = |name: &str| match file.get(name) {
+      Some(Json::Str(s)) => Ok(s.to_owned()),
+      _ => Err(format!("The connection file needs a `{}` string", name)),
+    };
+    let port = |name: &str| match file.get(name) {
+      Some(Json::Number(n)) if n.fract() == 0. && (0. ..=65535.).contains(n) => Ok(*n as u16),
+      _ => Err(format!("The connection file needs a `{}` port", name)),
+    };
+    if let Some(scheme) = file.get("signature_scheme").and_then(Json::as_str) {
+      if !scheme.is_empty() && scheme != "hmac-sha256" {
+        return Err(format!("Unsupported signature scheme `{}`", scheme));
+      }
+    }
+    Ok(Self {
+      transport: text("transport")?,
+      ip: text("ip")?,
+      shell_port: port("shell_port")?,
+      iopub_port: port("iopub_port")?,
+      stdin_port: port("stdin_port")?,
+      control_port: port("control_port")?,
+      hb_port: port("hb_port")?,
+      key: text("key")?,
+    })
+  }
+  /// The address of one of the ports, such as `tcp://127.0.0.1:9001`.
+  pub fn endpoint(&self, port: u16) -> String {
+    format!("{}://{}:{}", self.transport, self.ip, port)
+  }
+}
+
+/// A message of the protocol, without its binary buffers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+  /// The routing prefix: the peer's identity on the shell socket, the topic on iopub.
+  pub identities: Vec<Vec<u8>>,
+  pub header: Json,
+  pub parent_header: Json,
+  pub metadata: Json,
+  pub content: Json,
+}
+
+impl Message {
+  /// Reads a message from the frames of a socket, checking its signature with `key`.
+  pub fn decode(frames: &[Vec<u8>], key: &[u8]) -> Result<Self, String> {
+    let start = frames
+      .iter()
+      .position(|frame| frame == DELIMITER)
+      .ok_or("A message needs the `<IDS|MSG>` delimiter")?;
+    let parts = frames
+      .get(start + 1..start + 6)
+      .ok_or("A message needs a signature, a header, a parent, metadata and content")?;
+    let expected = sign(key, &parts[1..]);
+    if !key.is_empty() && !same_signature(expected.as_bytes(), &parts[0]) {
+      return Err("Invalid message signature".to_owned());
+    }
+    let json = |frame: &Vec<u8>| json::parse(&String::from_utf8_lossy(frame));
+    Ok(Self {
+      identities: frames[..start].to_vec(),
+      header: json(&parts[1])?,
+      parent_header: json(&parts[2])?,
+      metadata: json(&parts[3])?,
+      content: json(&parts[4])?,
+    })
+  }
+  /// The frames to send this message as, signed with `key`.
+  pub fn encode(&self, key: &[u8]) -> Vec<Vec<u8>> {
+    let parts = [
+      format!("{}", self.header).into_bytes(),
+      format!("{}", self.parent_header).into_bytes(),
+      format!("{}", self.metadata).into_bytes(),
+      format!("{}", self.content).into_bytes(),
+    ];
+    let mut frames = self.identities.clone();
+    frames.push(DELIMITER.to_vec());
+    frames.push(sign(key, &parts).into_bytes());
+    frames.extend(parts);
+    frames
+  }
+  pub fn msg_type(&self) -> &str {
+    self.header.get("msg_type").and_then(Json::as_str).unwrap_or("")
+  }
+  /// A message of type `msg_type` answering this one, routed back to the same peer.
+  pub fn reply(&self, msg_type: &str, content: Json) -> Self {
+    Self {
+      identities: self.identities.clone(),
+      header: self.child_header(msg_type),
+      parent_header: self.header.clone(),
+      metadata: Json::Object(vec![]),
+      content,
+    }
+  }
+  /// A message of type `msg_type` to publish on iopub while handling this one.
+  pub fn broadcast(&self, msg_type: &str, content: Json) -> Self {
+    Self {
+      identities: vec![msg_type.as_bytes().to_vec()],
+      ..self.reply(msg_type, content)
+    }
+  }
+  fn child_header(&self, msg_type: &str) -> Json {
+    let session = self.header.get("session").cloned().unwrap_or(Json::Str(String::new()));
+    Json::Object(vec![
+      ("msg_id".to_owned(), Json::Str(message_id())),
+      ("session".to_owned(), session),
+      ("username".to_owned(), Json::Str("nixt".to_owned())),
+      ("date".to_owned(), Json::Str(now())),
+      ("msg_type".to_owned(), Json::Str(msg_type.to_owned())),
+      ("version".to_owned(), Json::Str(PROTOCOL_VERSION.to_owned())),
+    ])
+  }
+}
+
+/// The hex HMAC-SHA256 of `parts`, or an empty string when messages are not signed.
+pub fn sign(key: &[u8], parts: &[Vec<u8>]) -> String {
+  if key.is_empty() {
+    return String::new();
+  }
+  let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any size");
+  for part in parts {
+    mac.update(part);
+  }
+  let signature = mac.finalize().into_bytes();
+  signature.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Compares signatures in constant time.
+fn same_signature(expected: &[u8], given: &[u8]) -> bool {
+  expected.len() == given.len() && expected.iter().zip(given).fold(0, |d, (e, g)| d | (e ^ g)) == 0
+}
+
+fn message_id() -> String {
+  static COUNTER: AtomicUsize = AtomicUsize::new(0);
+  let nanos = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map_or(0, |d| d.as_nanos());
+  let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+  format!("{:x}-{:x}-{:x}", std::process::id(), nanos, count)
+}
+
+/// The current UTC time in ISO 8601, as message headers carry it.
+fn now() -> String {
+  let elapsed = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+  let (days, secs) = (elapsed.as_secs() / 86400, elapsed.as_secs() % 86400);
+  // Converts days since 1970-01-01 into a civil date.
+  let z = days as i64 + 719468;
+  let era = z.div_euclid(146097);
+  let doe = z - era * 146097;
+  let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+  let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+  let mp = (5 * doy + 2) / 153;
+  let day = doy - (153 * mp + 2) / 5 + 1;
+  let month = if mp < 10 { mp + 3 } else { mp - 9 };
+  let year = yoe + era * 400 + i64::from(month <= 2);
+  format!(
+    "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}Z",
+    year,
+    month,
+    day,
+    secs / 3600,
+    secs % 3600 / 60,
+    secs % 60,
+    elapsed.subsec_micros()
+  )
+}
+
+/// What handling a request produced.
+#[derive(Debug, Default)]
+pub struct Outgoing {
+  /// The reply, sent on the socket the request came from.
+  pub reply: Option<Message>,
+  /// The messages to publish on iopub, in order.
+  pub iopub: Vec<Message>,
+  /// Whether the kernel should stop.
+  pub shutdown: bool,
+}
+
+/// A persistent interpreter answering the requests of a notebook.
+pub struct Kernel {
+  interpreter: Interpreter,
+  execution_count: usize,
+}
+
+impl Kernel {
+  pub fn new(interpreter: Interpreter) -> Self {
+    Self {
+      interpreter,
+      execution_count: 0,
+    }
+  }
+  pub fn interpreter(&mut self) -> &mut Interpreter {
+    &mut self.interpreter
+  }
+  /// Answers a shell or control request, surrounded by the busy and idle statuses.
+  pub fn handle(&mut self, request: &Message) -> Outgoing {
+    let mut outgoing = Outgoing {
+      iopub: vec![request.broadcast("status", status("busy"))],
+      ..Outgoing::default()
+    };
+    let content = match request.msg_type() {
+      "kernel_info_request" => Some(kernel_info()),
+      "execute_request" => Some(self.execute(request, &mut outgoing.iopub)),
+      "is_complete_request" => {
+        let code = request.content.get("code").and_then(Json::as_str).unwrap_or("");
+        Some(object(vec![("status", text(completeness(code)))]))
+      }
+      "comm_info_request" => Some(object(vec![
+        ("status", text("ok")),
+        ("comms", Json::Object(vec![])),
+      ])),
+      "history_request" => Some(object(vec![
+        ("status", text("ok")),
+        ("history", Json::Array(vec![])),
+      ])),
+      "shutdown_request" => {
+        outgoing.shutdown = true;
+        let restart = request.content.get("restart").cloned().unwrap_or(Json::Bool(false));
+        Some(object(vec![("status", text("ok")), ("restart", restart)]))
+      }
+      _ => None,
+    };
+    outgoing.reply = content.map(|content| {
+      let reply_type = request.msg_type().replace("_request", "_reply");
+      request.reply(&reply_type, content)
+    });
+    outgoing.iopub.push(request.broadcast("status", status("idle")));
+    outgoing
+  }
+  /// Runs a cell, publishing its input, output and result or error.
+  fn execute(&mut self, request: &Message, iopub: &mut Vec<Message>) -> Json {
+    let code = request.content.get("code").and_then(Json::as_str).unwrap_or("");
+    let silent = request.content.get("silent") == Some(&Json::Bool(true));
+    if !silent {
+      self.execution_count += 1;
+    }
+    let count = Json::Number(self.execution_count as f64);
+    // An interrupt stops the cell it arrived during, not the ones after it.
+    self.interpreter.interrupt_flag().store(false, Ordering::Relaxed);
+    iopub.push(request.broadcast(
+      "execute_input",
+      object(vec![("code", text(code)), ("execution_count", count.clone())]),
+    ));
+    let output = self.interpreter.eval_capture(code);
+    let mut stderr = output.stderr;
+    for warning in output.diagnostics {
+      stderr.push_str(&format!("Warning: {}\n", warning));
+    }
+    for (name, stream) in [("stdout", output.stdout), ("stderr", stderr)] {
+      if !stream.is_empty() && !silent {
+        iopub.push(request.broadcast(
+          "stream",
+          object(vec![("name", text(name)), ("text", Json::Str(stream))]),
+        ));
+      }
+    }
+    match output.value {
+      Ok(value) => {
+        if !value.is_nil() && !silent {
+          let data = object(vec![("text/plain", Json::Str(format!("{}", value)))]);
+          iopub.push(request.broadcast(
+            "execute_result",
+            object(vec![
+              ("execution_count", count.clone()),
+              ("data", data),
+              ("metadata", Json::Object(vec![])),
+            ]),
+          ));
+        }
+        object(vec![
+          ("status", text("ok")),
+          ("execution_count", count),
+          ("user_expressions", Json::Object(vec![])),
+          ("payload", Json::Array(vec![])),
+        ])
+      }
+      Err(e) => {
+        let error = vec![
+          ("ename", text("Error")),
+          ("evalue", Json::Str(e.to_owned())),
+          ("traceback", Json::Array(vec![Json::Str(e)])),
+        ];
+        iopub.push(request.broadcast("error", object(error.clone())));
+        let mut content = vec![("status", text("error")), ("execution_count", count)];
+        content.extend(error);
+        object(content)
+      }
+    }
+  }
+}
+
+fn kernel_info() -> Json {
+  let version = env!("CARGO_PKG_VERSION");
+  object(vec![
+    ("status", text("ok")),
+    ("protocol_version", text(PROTOCOL_VERSION)),
+    ("implementation", text("nixt")),
+    ("implementation_version", text(version)),
+    (
+      "language_info",
+      object(vec![
+        ("name", text("nixt")),
+        ("version", text(version)),
+        ("mimetype", text("text/x-nixt")),
+        ("file_extension", text(".nxt")),
+      ]),
+    ),
+    ("banner", text(&format!("nixt {}", version))),
+  ])
+}
+
+/// Whether a cell can run: `incomplete` while brackets are left open.
+fn completeness(code: &str) -> &'static str {
+  let mut depth = 0;
+  for c in code.chars() {
+    match c {
+      '(' | '{' => depth += 1,
+      ')' | '}' => depth -= 1,
+      _ => {}
+    }
+  }
+  match depth {
+    0 => "complete",
+    d if d > 0 => "incomplete",
+    _ => "invalid",
+  }
+}
+
+fn status(state: &str) -> Json {
+  object(vec![("execution_state", text(state))])
+}
+
+fn text(s: &str) -> Json {
+  Json::Str(s.to_owned())
+}
+
+fn object(members: Vec<(&str, Json)>) -> Json {
+  Json::Object(members.into_iter().map(|(k, v)| (k.to_owned(), v)).collect())
+}
@@ -0,0 +1,288 @@
+use crate::core::lexer::Lexer;
+use crate::utils::token::{Token, TokenType};
+use std::collections::BTreeMap;
+
+/// The lint rules, with what they report.
+pub const RULES: &[(&str, &str)] = &[
+  ("shadowed-binding", "a local binding or parameter hides an outer binding of the same name"),
+  ("long-function", "a function body spans more lines than allowed"),
+  ("magic-number", "a number other than -1, 0, 1 or 2 is used without being named"),
+  ("unused-parameter", "a parameter not starting with `_` is never used"),
+  ("non-snake-case", "a binding or parameter name is not snake_case"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+  /// The rule is not checked.
+  Allow,
+  Warn,
+  /// The rule fails `nixt lint`.
+  Deny,
+}
+
+/// The severity of each rule, every rule warning by default.
+#[derive(Debug, Clone)]
+pub struct LintConfig {
+  severities: BTreeMap<&'static str, Severity>,
+  /// The most lines a function body may span before `long-function` reports it.
+  pub max_function_lines: usize,
+}
+
+impl Default for LintConfig {
+  fn default() -> Self {
+    Self {
+      severities: RULES.iter().map(|(rule, _)| (*rule, Severity::Warn)).collect(),
+      max_function_lines: 40,
+    }
+  }
+}
+
+impl LintConfig {
+  pub fn set(&mut self, rule: &str, severity: Severity) -> Result<(), String> {
+    let rule = known_rule(rule)?;
+    self.severities.insert(rule, severity);
+    Ok(())
+  }
+  pub fn severity(&self, rule: &str) -> Severity {
+    self.severities.get(rule).copied().unwrap_or(Severity::Allow)
+  }
+}
+
+fn known_rule(rule: &str) -> Result<&'static str, String> {
+  RULES
+    .iter()
+    .map(|(id, _)| *id)
+    .find(|id| *id == rule)
+    .ok_or_else(|| format!("Unknown lint rule `{}`", rule))
+}
+
+/// A finding of a lint rule.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lint {
+  pub rule: &'static str,
+  pub severity: Severity,
+  pub line: usize,
+  pub message: String,
+}
+
+/// `3: warning[magic-number]: ...`
+impl std::fmt::Display for Lint {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    let level = match self.severity {
+      Severity::Deny => "error",
+      _ => "warning",
+    };
+    write!(f, "{}: {}[{}]: {}", self.line, level, self.rule, self.message)
+  }
+}
+
+/// Checks `source` against the rules `config` does not allow, in the order of the lines.
+/// A `# nixt-lint: allow(rule, ...)` comment turns rules off for the whole file.
+///
+/// ```
+/// use nixt::tools::lint::{lint, LintConfig};
+/// let config = LintConfig::default();
+/// let lints = lint("(let area (func (r) {(ret (* r r 3.14))}))", &config)?;
+/// assert_eq!(lints[0].rule, "magic-number");
+/// assert!(lint("# nixt-lint: allow(magic-number)\n(print 42)", &config)?.is_empty());
+/// # Ok::<(), String>(())
+/// ```
+pub fn lint(source: &str, config: &LintConfig) -> Result<Vec<Lint>, String> {
+  let mut lexer = Lexer::new(source);
+  lexer.set_trivia(true);
+  let tokens = lexer.scan_tokens();
+  if let Some(errors) = lexer.get_errors() {
+    return Err(errors.join("\n"));
+  }
+  let mut config = config.clone();
+  for comment in tokens.iter().flat_map(|token| &token.trivia) {
+    if let TokenType::Comment(text) = &comment.typ {
+      for rule in suppressed(text) {
+        config
+          .set(rule, Severity::Allow)
+          .map_err(|e| format!("Line {} | {}", comment.line, e))?;
+      }
+    }
+  }
+  let mut linter = Linter {
+    tokens: &tokens,
+    config: &config,
+    frames: vec![Frame::default()],
+    function: None,
+    lints: vec![],
+  };
+  linter.run();
+  let mut lints = linter.lints;
+  lints.sort_by_key(|lint| lint.line);
+  Ok(lints)
+}
+
+/// The rules a `nixt-lint: allow(a, b)` comment names.
+fn suppressed(comment: &str) -> Vec<&str> {
+  comment
+    .trim()
+    .strip_prefix("nixt-lint:")
+    .and_then(|rest| rest.trim().strip_prefix("allow("))
+    .and_then(|rest| rest.strip_suffix(')'))
+    .map_or(vec![], |rules| rules.split(',').map(str::trim).collect())
+}
+
+#[derive(Default)]
+struct Frame {
+  /// The names bound in this scope so far.
+  bindings: Vec<String>,
+  /// The parameters of the function this scope is the body of, and whether they were used.
+  params: Vec<(String, bool)>,
+  /// The line of the `func` keyword, for function bodies.
+  func_line: Option<usize>,
+}
+
+struct Linter<'a> {
+  tokens: &'a [Token],
+  config: &'a LintConfig,
+  frames: Vec<Frame>,
+  /// The line of the function whose body is the next scope, and its parameters.
+  function: Option<(usize, Vec<(String, usize)>)>,
+  lints: Vec<Lint>,
+}
+
+impl<'a> Linter<'a> {
+  fn report(&mut self, rule: &'static str, line: usize, message: String) {
+    let severity = self.config.severity(rule);
+    if severity != Severity::Allow {
+      self.lints.push(Lint {
+        rule,
+        severity,
+        line,
+        message,
+      });
+    }
+  }
+  fn run(&mut self) {
+    let mut i = 0;
+    while i < self.tokens.len() {
+      let token = &self.tokens[i];
+      match &token.typ {
+        TokenType::Func => i = self.function(i),
+        TokenType::LeftBrace => self.open_scope(),
+        TokenType::RightBrace if self.frames.len() > 1 => self.close_scope(token.line),
+        TokenType::Let | TokenType::Const => {
+          if let Some(TokenType::Identifier(name)) = self.tokens.get(i + 1).map(|t| &t.typ) {
+            self.define(name, self.tokens[i + 1].line);
+            i += 1;
+          }
+        }
+        // Enums and their variants are named in PascalCase, and their names are not uses.
+        TokenType::Enum => {
+          while i < self.tokens.len() && self.tokens[i].typ != TokenType::RightParen {
+            i += 1;
+          }
+        }
+        TokenType::Identifier(name) => self.use_name(name),
+        TokenType::Number(n) => self.number(i, *n),
+        _ => {}
+      }
+      i += 1;
+    }
+  }
+  /// Reads the parameters following the `func` at `i`, returning the index of their closing
+  /// parenthesis.
+  fn function(&mut self, mut i: usize) -> usize {
+    let line = self.tokens[i].line;
+    if self.tokens.get(i + 1).map(|t| &t.typ) != Some(&TokenType::LeftParen) {
+      return i;
+    }
+    i += 2;
+    let mut params = vec![];
+    while i < self.tokens.len() {
+      match &self.tokens[i].typ {
+        TokenType::Identifier(name) => params.push((name.to_owned(), self.tokens[i].line)),
+        TokenType::RightParen | TokenType::Eof => break,
+        _ => {}
+      }
+      i += 1;
+    }
+    self.function = Some((line, params));
+    i
+  }
+  fn open_scope(&mut self) {
+    let (func_line, params) = match self.function.take() {
+      Some((line, params)) => (Some(line), params),
+      None => (None, vec![]),
+    };
+    self.frames.push(Frame {
+      func_line,
+      ..Frame::default()
+    });
+    for (name, line) in params {
+      self.define(&name, line);
+      self.frames.last_mut().unwrap().params.push((name, false));
+    }
+  }
+  fn close_scope(&mut self, end: usize) {
+    let frame = self.frames.pop().unwrap();
+    for (name, used) in &frame.params {
+      if !used && !name.starts_with('_') {
+        let line = frame.func_line.unwrap_or(end);
+        self.report("unused-parameter", line, format!("`{}` is never used", name));
+      }
+    }
+    if let Some(start) = frame.func_line {
+      let lines = end - start + 1;
+      if lines > self.config.max_function_lines {
+        let message = format!(
+          "the function spans {} lines, more than {}",
+          lines, self.config.max_function_lines
+        );
+        self.report("long-function", start, message);
+      }
+    }
+  }
+  fn define(&mut self, name: &str, line: usize) {
+    if !is_snake_case(name) {
+      self.report("non-snake-case", line, format!("`{}` is not snake_case", name));
+    }
+    let outer = self.frames.iter().any(|frame| frame.bindings.iter().any(|b| b == name));
+    if self.frames.len() > 1 && outer {
+      self.report("shadowed-binding", line, format!("`{}` shadows an outer binding", name));
+    }
+    self.frames.last_mut().unwrap().bindings.push(name.to_owned());
+  }
+  /// Marks the parameter `name` refers to as used.
+  fn use_name(&mut self, name: &str) {
+    for frame in self.frames.iter_mut().rev() {
+      if let Some(param) = frame.params.iter_mut().find(|(param, _)| param == name) {
+        param.1 = true;
+        return;
+      }
+      if frame.bindings.iter().any(|binding| binding == name) {
+        return;
+      }
+    }
+  }
+  fn number(&mut self, i: usize, n: f64) {
+    if [-1., 0., 1., 2.].contains(&n) {
+      return;
+    }
+    // The value of a definition names the number.
+    let named = i >= 2
+      && matches!(self.tokens[i - 2].typ, TokenType::Let | TokenType::Const)
+      && matches!(self.tokens[i - 1].typ, TokenType::Identifier(_));
+    if !named {
+      let token = &self.tokens[i];
+      let message = format!("`{}` could be a named constant", token.lexeme);
+      self.report("magic-number", token.line, message);
+    }
+  }
+}
+
+/// `snake_case`, `get?` and `ns:snake_case` names.
+fn is_snake_case(name: &str) -> bool {
+  name.split(':').all(|segment| {
+    let segment = segment.strip_suffix('?').unwrap_or(segment);
+    !segment.is_empty()
+      && segment
+        .chars()
+        .all(|c| c.is_lowercase() || c.is_ascii_digit() || c == '_' || !c.is_alphabetic())
+  })
+}
@@ -0,0 +1,143 @@
+use crate::utils::json::Json;
+use crate::utils::node::{Node, NodeType, OperatorType};
+
+/// The size and complexity of a function.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionMetrics {
+  /// The binding the function is assigned to, after the functions enclosing it:
+  /// `outer/inner`. Functions not assigned to a binding are `<anonymous>`.
+  pub name: String,
+  /// The nodes of its syntax tree, without those of the functions it defines.
+  pub nodes: usize,
+  /// How deeply `if`, `while` and `cond` forms nest in it.
+  pub depth: usize,
+  /// Its cyclomatic complexity: one, plus one per `if`, `while`, `cond` branch and extra
+  /// operand of `and`, `or` and `??`.
+  pub branches: usize,
+}
+
+/// The metrics of every function in `ast`, in the order they are defined, each enclosing
+/// function before the ones it defines.
+///
+/// ```
+/// use nixt::core::{lexer::Lexer, parser::Parser};
+/// let code = "(let abs (func (x) {(if (< x 0) ((ret (- 0 x)))) (ret x)}))";
+/// let ast = Parser::new(Lexer::new(code).scan_tokens()).parse();
+/// let abs = &nixt::tools::metrics::metrics(&ast)[0];
+/// assert_eq!((abs.name.as_str(), abs.depth, abs.branches), ("abs", 1, 2));
+/// ```
+pub fn metrics(ast: &Node) -> Vec<FunctionMetrics> {
+  let mut collector = Collector {
+    functions: vec![],
+    open: vec![],
+  };
+  collector.visit(ast, 0);
+  collector.functions
+}
+
+struct Collector {
+  functions: Vec<FunctionMetrics>,
+  /// The indices of the functions being measured, the innermost last.
+  open: Vec<usize>,
+}
+
+impl Collector {
+  fn visit(&mut self, node: &Node, nesting: usize) {
+    let children = node.get_child();
+    let node_type = node.get_type();
+    if let Some(&index) = self.open.last() {
+      let current = &mut self.functions[index];
+      current.nodes += 1;
+      current.depth = current.depth.max(nesting);
+      current.branches += match &node_type {
+        NodeType::Condition | NodeType::Loop => 1,
+        NodeType::Cond => children
+          .iter()
+          .filter(|clause| clause.get_child()[0].get_type() != NodeType::NodeBool(true))
+          .count(),
+        NodeType::Operator(OperatorType::And)
+        | NodeType::Operator(OperatorType::Or)
+        | NodeType::Operator(OperatorType::NilCoalesce) => children.len().saturating_sub(1),
+        _ => 0,
+      };
+    }
+    match (&node_type, children) {
+      (NodeType::Func, _) => self.function("<anonymous>", node),
+      (NodeType::Assignement(_), [name, value, ..]) if function_of(value).is_some() => {
+        let name = match name.get_type() {
+          NodeType::NodeIdentifier(name) => name,
+          _ => "<anonymous>".to_owned(),
+        };
+        self.function(&name, function_of(value).unwrap());
+      }
+      _ => {
+        let nesting = match node_type {
+          NodeType::Condition | NodeType::Loop | NodeType::Cond => nesting + 1,
+          _ => nesting,
+        };
+        for child in children {
+          self.visit(child, nesting);
+        }
+      }
+    }
+  }
+  fn function(&mut self, name: &str, func: &Node) {
+    let name = match self.open.last() {
+      Some(&parent) => format!("{}/{}", self.functions[parent].name, name),
+      None => name.to_owned(),
+    };
+    self.open.push(self.functions.len());
+    self.functions.push(FunctionMetrics {
+      name,
+      nodes: 0,
+      depth: 0,
+      branches: 1,
+    });
+    for child in func.get_child() {
+      self.visit(child, 0);
+    }
+    self.open.pop();
+  }
+}
+
+/// The function a binding is assigned, as in `(let f (func ...))`.
+fn function_of(value: &Node) -> Option<&Node> {
+  match (value.get_type(), value.get_child()) {
+    (NodeType::Func, _) => Some(value),
+    (NodeType::Block, [func]) if func.get_type() == NodeType::Func => Some(func),
+    _ => None,
+  }
+}
+
+/// The metrics as a table with a header.
+pub fn to_table(functions: &[FunctionMetrics]) -> String {
+  let width = functions.iter().map(|f| f.name.len()).max().unwrap_or(0).max(8);
+  let mut table = format!("{:<width$}  nodes  depth  branches\n", "function", width = width);
+  for f in functions {
+    table.push_str(&format!(
+      "{:<width$}  {:>5}  {:>5}  {:>8}\n",
+      f.name,
+      f.nodes,
+      f.depth,
+      f.branches,
+      width = width
+    ));
+  }
+  table
+}
+
+pub fn to_json(functions: &[FunctionMetrics]) -> Json {
+  Json::Array(
+    functions
+      .iter()
+      .map(|f| {
+        Json::Object(vec![
+          ("name".to_owned(), Json::Str(f.name.to_owned())),
+          ("nodes".to_owned(), Json::Number(f.nodes as f64)),
+          ("depth".to_owned(), Json::Number(f.depth as f64)),
+          ("branches".to_owned(), Json::Number(f.branches as f64)),
+        ])
+      })
+      .collect(),
+  )
+}
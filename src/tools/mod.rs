@@ -0,0 +1,12 @@
+pub mod config;
+pub mod doc;
+pub mod grammar;
+pub mod graph;
+pub mod lint;
+pub mod metrics;
+pub mod rename;
+pub mod repl;
+pub mod serve;
+pub mod test;
+#[cfg(feature = "jupyter")]
+pub mod jupyter;
@@ -0,0 +1,242 @@
+use crate::core::interpreter::functions::BUILTINS;
+use crate::core::lexer::Lexer;
+use crate::core::parser::validate_identifier;
+use crate::utils::token::{Token, TokenType};
+
+/// A replacement of the source between the byte offsets `start` and `end`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextEdit {
+  pub line: usize,
+  pub start: usize,
+  pub end: usize,
+  pub text: String,
+}
+
+/// The edits renaming the global binding `old` to `new` in each of `sources`, the files of a
+/// project: its definition and every reference not shadowed by a local binding or parameter.
+/// Enum variants (`Color:Red`) follow their enum.
+///
+/// Renaming is refused when no source defines `old`, when `new` is not a valid name, a keyword
+/// or a builtin, when a source already defines `new`, or when a local `new` would capture one
+/// of the references.
+///
+/// ```
+/// use nixt::tools::rename::{apply, rename};
+/// let source = "(let n 1) (let f (func (n) {(ret n)})) (print (+ n 1))";
+/// let edits = rename(&[source], "n", "count")?;
+/// assert_eq!(
+///   apply(source, &edits[0]),
+///   "(let count 1) (let f (func (n) {(ret n)})) (print (+ count 1))"
+/// );
+/// assert!(rename(&[source], "n", "f").is_err());
+/// # Ok::<(), String>(())
+/// ```
+pub fn rename(sources: &[&str], old: &str, new: &str) -> Result<Vec<Vec<TextEdit>>, String> {
+  check_name(new)?;
+  let renamings = sources
+    .iter()
+    .map(|source| Renaming::new(source, old, new))
+    .collect::<Result<Vec<_>, _>>()?;
+  // The variants of an enum follow it in every file, not only the one defining it.
+  let is_enum = renamings.iter().any(|renaming| {
+    renaming.tokens.windows(2).any(|pair| {
+      pair[0].typ == TokenType::Enum && pair[1].typ == TokenType::Identifier(old.to_owned())
+    })
+  });
+  let mut defined = false;
+  let mut edits = vec![];
+  for mut renaming in renamings {
+    renaming.is_enum = is_enum;
+    let renaming = renaming.run()?;
+    defined |= renaming.defined;
+    edits.push(renaming.edits);
+  }
+  if !defined {
+    return Err(format!("`{}` is not a global binding", old));
+  }
+  Ok(edits)
+}
+
+/// `source` with `edits` made, as [`rename`] returned them for it.
+pub fn apply(source: &str, edits: &[TextEdit]) -> String {
+  let mut source = source.to_owned();
+  let mut edits = edits.iter().collect::<Vec<_>>();
+  edits.sort_by_key(|edit| std::cmp::Reverse(edit.start));
+  for edit in edits {
+    source.replace_range(edit.start..edit.end, &edit.text);
+  }
+  source
+}
+
+/// Errors unless `name` lexes as a single identifier that can be bound.
+fn check_name(name: &str) -> Result<(), String> {
+  validate_identifier(name)?;
+  let tokens = Lexer::new(name).scan_tokens();
+  match &tokens[0].typ {
+    TokenType::Identifier(s) if s == name && tokens.len() == 2 => {}
+    typ if typ.is_keyword() => return Err(format!("`{}` is a keyword", name)),
+    _ => return Err(format!("`{}` is not a valid name", name)),
+  }
+  if BUILTINS.contains(&name) {
+    return Err(format!("`{}` is a builtin", name));
+  }
+  Ok(())
+}
+
+struct Renaming<'a> {
+  tokens: Vec<Token>,
+  starts: Vec<usize>,
+  old: &'a str,
+  new: &'a str,
+  /// The names bound by each enclosing scope so far, the globals first.
+  frames: Vec<Vec<String>>,
+  /// The parameters of the function whose body is the next scope.
+  params: Vec<String>,
+  defined: bool,
+  /// Whether `old` is an enum, whose variants are renamed with it.
+  is_enum: bool,
+  edits: Vec<TextEdit>,
+}
+
+impl<'a> Renaming<'a> {
+  fn new(source: &'a str, old: &'a str, new: &'a str) -> Result<Self, String> {
+    let mut lexer = Lexer::new(source);
+    lexer.set_trivia(true);
+    let tokens = lexer.scan_tokens();
+    if let Some(errors) = lexer.get_errors() {
+      return Err(errors.join("\n"));
+    }
+    let starts = locate(source, &tokens);
+    Ok(Self {
+      tokens,
+      starts,
+      old,
+      new,
+      frames: vec![vec![]],
+      params: vec![],
+      defined: false,
+      is_enum: false,
+      edits: vec![],
+    })
+  }
+  fn run(mut self) -> Result<Self, String> {
+    let mut i = 0;
+    while i < self.tokens.len() {
+      match self.tokens[i].typ.clone() {
+        TokenType::LeftBrace => {
+          let params = std::mem::take(&mut self.params);
+          self.frames.push(params);
+        }
+        TokenType::RightBrace if self.frames.len() > 1 => {
+          self.frames.pop();
+        }
+        TokenType::Let | TokenType::Const => {
+          if let Some(TokenType::Identifier(name)) = self.tokens.get(i + 1).map(|t| &t.typ) {
+            self.define(i + 1, &name.to_owned())?;
+            i += 1;
+          }
+        }
+        TokenType::Enum => {
+          if let Some(TokenType::Identifier(name)) = self.tokens.get(i + 1).map(|t| &t.typ) {
+            self.define(i + 1, &name.to_owned())?;
+            i += 1;
+          }
+          i = self.skip_list(i, |_| {});
+        }
+        TokenType::Func => {
+          let mut params = vec![];
+          i = self.skip_list(i, |name| params.push(name.to_owned()));
+          self.params = params;
+        }
+        TokenType::Identifier(name) => self.reference(i, &name)?,
+        _ => {}
+      }
+      i += 1;
+    }
+    Ok(self)
+  }
+  /// Skips a parenthesized list of names following the token at `i`, returning the index of
+  /// its closing parenthesis.
+  fn skip_list(&self, i: usize, mut each: impl FnMut(&str)) -> usize {
+    if self.tokens.get(i + 1).map(|t| &t.typ) != Some(&TokenType::LeftParen) {
+      return i;
+    }
+    let mut i = i + 2;
+    while i < self.tokens.len() {
+      match &self.tokens[i].typ {
+        TokenType::RightParen | TokenType::Eof => break,
+        TokenType::Identifier(name) => each(name),
+        _ => {}
+      }
+      i += 1;
+    }
+    i
+  }
+  fn define(&mut self, i: usize, name: &str) -> Result<(), String> {
+    let line = self.tokens[i].line;
+    if self.frames.len() == 1 {
+      if name == self.new {
+        return Err(format!("`{}` is already defined at line {}", self.new, line));
+      }
+      if name == self.old {
+        self.defined = true;
+        self.edit(i, self.new.to_owned());
+      }
+    }
+    self.frames.last_mut().unwrap().push(name.to_owned());
+    Ok(())
+  }
+  fn reference(&mut self, i: usize, name: &str) -> Result<(), String> {
+    let text = if name == self.old {
+      self.new.to_owned()
+    } else {
+      match name.split_once(':') {
+        Some((namespace, variant)) if namespace == self.old && self.is_enum => {
+          format!("{}:{}", self.new, variant)
+        }
+        _ => return Ok(()),
+      }
+    };
+    let locals = &self.frames[1..];
+    if locals.iter().any(|frame| frame.iter().any(|bound| bound == self.old)) {
+      return Ok(());
+    }
+    if locals.iter().any(|frame| frame.iter().any(|bound| bound == self.new)) {
+      return Err(format!(
+        "The local `{}` would capture the reference at line {}",
+        self.new, self.tokens[i].line
+      ));
+    }
+    self.edit(i, text);
+    Ok(())
+  }
+  fn edit(&mut self, i: usize, text: String) {
+    let start = self.starts[i];
+    self.edits.push(TextEdit {
+      line: self.tokens[i].line,
+      start,
+      end: start + self.tokens[i].lexeme.len(),
+      text,
+    });
+  }
+}
+
+/// The byte offset each token starts at. Only whitespace and the comments kept as trivia
+/// separate tokens, and lexemes are slices of the source.
+fn locate(source: &str, tokens: &[Token]) -> Vec<usize> {
+  let mut position = 0;
+  let mut find = |lexeme: &str| {
+    let start = source[position..].find(lexeme).map_or(source.len(), |i| position + i);
+    position = start + lexeme.len();
+    start
+  };
+  tokens
+    .iter()
+    .map(|token| {
+      for comment in &token.trivia {
+        find(&comment.lexeme);
+      }
+      find(&token.lexeme)
+    })
+    .collect()
+}
@@ -0,0 +1,115 @@
+use crate::core::lexer::Lexer;
+use crate::utils::token::TokenType;
+use crate::{Interpreter, Value};
+use std::io::{BufRead, Write};
+
+/// An interactive session: the interpreter keeps its globals from one input to the next, and
+/// lines are gathered until their brackets balance, so that a form can span several lines.
+///
+/// ```
+/// use nixt::tools::repl::Repl;
+/// let mut repl = Repl::new(nixt::Interpreter::builder().build()?);
+/// assert!(repl.eval_line("(let double (func (x) {").is_none());
+/// assert_eq!(repl.prompt(), ". ");
+/// assert!(repl.eval_line("  (ret (* x 2))").is_none());
+/// assert!(repl.eval_line("}))").unwrap().is_ok());
+/// assert_eq!(repl.eval_line("(double 21)").unwrap()?.to_string(), "42");
+/// # Ok::<(), String>(())
+/// ```
+pub struct Repl {
+  interpreter: Interpreter,
+  pending: String,
+}
+
+impl Repl {
+  pub fn new(interpreter: Interpreter) -> Self {
+    Self {
+      interpreter,
+      pending: String::new(),
+    }
+  }
+  pub fn interpreter(&mut self) -> &mut Interpreter {
+    &mut self.interpreter
+  }
+  /// Whether the lines read so far leave a form open, to be continued by the next ones.
+  pub fn continuing(&self) -> bool {
+    !self.pending.is_empty()
+  }
+  /// `> `, or `. ` while continuing a form.
+  pub fn prompt(&self) -> &'static str {
+    if self.continuing() {
+      ". "
+    } else {
+      "> "
+    }
+  }
+  /// Adds `line` to the input, returning the whole input once its brackets balance. Blank
+  /// lines outside a form are skipped.
+  pub fn feed(&mut self, line: &str) -> Option<String> {
+    if self.pending.is_empty() && line.trim().is_empty() {
+      return None;
+    }
+    self.pending.push_str(line.trim_end_matches(&['\n', '\r'][..]));
+    self.pending.push('\n');
+    match open_brackets(&self.pending) {
+      Some(open) if open > 0 => None,
+      _ => Some(std::mem::take(&mut self.pending)),
+    }
+  }
+  /// Feeds `line`, evaluating the input once it is complete. A failing input leaves the
+  /// session as it was before it: the interpreter unwinds its scopes, and the globals defined
+  /// so far stay available.
+  pub fn eval_line(&mut self, line: &str) -> Option<Result<Value, String>> {
+    let input = self.feed(line)?;
+    Some(self.interpreter.eval_str(input.trim()))
+  }
+  /// Reads lines from `input` until it ends, writing the prompts and the value of each input
+  /// to `output`, and its errors to `errors`. `nil` values are not written.
+  pub fn run(
+    &mut self,
+    input: impl BufRead,
+    mut output: impl Write,
+    mut errors: impl Write,
+  ) -> std::io::Result<()> {
+    let mut lines = input.lines();
+    loop {
+      write!(output, "{}", self.prompt())?;
+      output.flush()?;
+      let line = match lines.next() {
+        Some(line) => line?,
+        None => return writeln!(output),
+      };
+      match self.eval_line(&line) {
+        None | Some(Ok(Value::Nil)) => {}
+        Some(Ok(value)) => writeln!(output, "{}", value)?,
+        Some(Err(e)) => writeln!(errors, "Error: {}", e)?,
+      }
+    }
+  }
+}
+
+/// How many brackets `source` leaves open, `(`, `{` or `[`, or `None` if it closes one that is
+/// not open. An unterminated string or block comment counts as an open bracket.
+///
+/// ```
+/// use nixt::tools::repl::open_brackets;
+/// assert_eq!(open_brackets("(print (+ 1"), Some(2));
+/// assert_eq!(open_brackets("(print \")\")"), Some(0));
+/// assert_eq!(open_brackets("(print \"unterminated"), Some(2));
+/// assert_eq!(open_brackets("1)"), None);
+/// ```
+pub fn open_brackets(source: &str) -> Option<usize> {
+  let mut lexer = Lexer::new(source);
+  let mut open = 0usize;
+  for token in lexer.scan_tokens() {
+    match token.typ {
+      TokenType::LeftParen | TokenType::LeftBrace | TokenType::LeftBracket => open += 1,
+      TokenType::RightParen | TokenType::RightBrace | TokenType::RightBracket => {
+        open = open.checked_sub(1)?
+      }
+      _ => {}
+    }
+  }
+  let unterminated = lexer.diagnostics().iter().filter(|e| e.message.starts_with("Unterminated"));
+  Some(open + unterminated.count())
+}
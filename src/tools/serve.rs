@@ -0,0 +1,97 @@
+use crate::core::interpreter::interpreter::GLOBAL_SCOPE;
+use crate::utils::json::{self, Json};
+use crate::{EvalOutput, Interpreter};
+use std::io::{BufRead, Write};
+
+/// Answers one request of the `nixt serve` protocol: a JSON object with the `source` to
+/// evaluate and optional `inputs`, bound as global constants for this request only. Like
+/// [`Interpreter::define`], they can not rebind a builtin or a binding already defined.
+///
+/// The answer holds `ok`, then `value` or `error`, and what the script printed:
+///
+/// ```
+/// let mut interpreter = nixt::Interpreter::builder().build()?;
+/// let request = r#"{"source": "(print x) (* x 2)", "inputs": {"x": 21}}"#;
+/// assert_eq!(
+///   format!("{}", nixt::tools::serve::handle(&mut interpreter, request)),
+///   r#"{"ok":true,"value":42,"stdout":"21\n","stderr":"","diagnostics":[]}"#
+/// );
+/// # Ok::<(), String>(())
+/// ```
+pub fn handle(interpreter: &mut Interpreter, request: &str) -> Json {
+  let mut inputs = vec![];
+  let parsed = parse_request(interpreter, request, &mut inputs);
+  let output = match parsed {
+    Ok(source) => interpreter.eval_capture(&source),
+    Err(e) => EvalOutput {
+      value: Err(e),
+      stdout: String::new(),
+      stderr: String::new(),
+      diagnostics: vec![],
+    },
+  };
+  let globals = interpreter.env.scope(GLOBAL_SCOPE);
+  for name in inputs {
+    globals.bindings_mut().remove(&name);
+  }
+  let mut members = match &output.value {
+    Ok(value) => vec![
+      ("ok".to_owned(), Json::Bool(true)),
+      ("value".to_owned(), json::from_value(value)),
+    ],
+    Err(e) => vec![
+      ("ok".to_owned(), Json::Bool(false)),
+      ("error".to_owned(), Json::Str(e.to_owned())),
+    ],
+  };
+  members.push(("stdout".to_owned(), Json::Str(output.stdout)));
+  members.push(("stderr".to_owned(), Json::Str(output.stderr)));
+  let diagnostics = output.diagnostics.into_iter().map(Json::Str).collect();
+  members.push(("diagnostics".to_owned(), Json::Array(diagnostics)));
+  Json::Object(members)
+}
+
+/// Binds the request's inputs, adding their names to `inputs`, and returns its source.
+fn parse_request(
+  interpreter: &mut Interpreter,
+  request: &str,
+  inputs: &mut Vec<String>,
+) -> Result<String, String> {
+  let members = match json::parse(request)? {
+    Json::Object(members) => members,
+    _ => return Err("A request must be a JSON object".to_owned()),
+  };
+  let mut source = None;
+  for (key, value) in members {
+    match (key.as_str(), value) {
+      ("source", Json::Str(s)) => source = Some(s),
+      ("inputs", Json::Object(members)) => {
+        for (name, input) in members {
+          interpreter.define(&name, json::to_value(&input)?)?;
+          inputs.push(name);
+        }
+      }
+      ("source", _) => return Err("`source` must be a string".to_owned()),
+      ("inputs", _) => return Err("`inputs` must be an object".to_owned()),
+      (other, _) => return Err(format!("Unknown request member `{}`", other)),
+    }
+  }
+  source.ok_or_else(|| "A request needs a `source`".to_owned())
+}
+
+/// Answers the newline-delimited requests of `input` until it is closed, one line each.
+pub fn serve(
+  interpreter: &mut Interpreter,
+  input: impl BufRead,
+  mut output: impl Write,
+) -> std::io::Result<()> {
+  for line in input.lines() {
+    let line = line?;
+    if line.trim().is_empty() {
+      continue;
+    }
+    writeln!(output, "{}", handle(interpreter, &line))?;
+    output.flush()?;
+  }
+  Ok(())
+}
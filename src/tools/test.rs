@@ -0,0 +1,325 @@
+use crate::core::interpreter::builder::InterpreterBuilder;
+use crate::core::interpreter::harness::TestCase;
+use crate::core::interpreter::interpreter::Capture;
+use crate::utils::json::Json;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The outcome of a test `nixt test` ran.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestResult {
+  pub name: String,
+  /// Why the test failed, or `None` if it passed.
+  pub failure: Option<String>,
+  /// What the test printed, when [`run_parallel`] captured it.
+  pub output: String,
+  /// How long the test function ran.
+  pub duration: Duration,
+}
+
+impl TestResult {
+  pub fn passed(&self) -> bool {
+    self.failure.is_none()
+  }
+}
+
+/// Which tests to run.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Filter {
+  /// Only runs the tests whose name contains it.
+  pub name: Option<String>,
+  /// Skips the tests with one of these tags.
+  pub skip_tags: Vec<String>,
+}
+
+impl Filter {
+  /// Whether `test` is run.
+  pub fn matches(&self, test: &TestCase) -> bool {
+    let named = self.name.as_ref().is_none_or(|name| test.name.contains(name.as_str()));
+    named && !test.tags.iter().any(|tag| self.skip_tags.contains(tag))
+  }
+}
+
+/// Evaluates `source` with an interpreter `builder` configures, returning the tests it
+/// registered with `test` and `test_each` without running them.
+pub fn list(source: &str, builder: InterpreterBuilder) -> Result<Vec<TestCase>, String> {
+  let mut interpreter = builder.build()?;
+  interpreter.eval_str(source)?;
+  Ok(interpreter.take_tests())
+}
+
+/// Evaluates `source` with an interpreter `builder` configures, then runs the tests it
+/// registered that `filter` matches, in order. A failing test does not stop the next ones, but
+/// a failing script fails the whole run.
+///
+/// ```
+/// use nixt::tools::test::{self, Filter};
+/// let source = "(test \"adds\" (func () {(assert_eq (+ 1 1) 2)}))
+///   (test \"fails\" (func () {(assert false)}))
+///   (test \"slow: sorts\" (func () {}))";
+/// let filter = Filter { skip_tags: vec!["slow".to_owned()], ..Filter::default() };
+/// let results = test::run(source, nixt::Interpreter::builder(), &filter)?;
+/// assert!(results[0].passed());
+/// assert_eq!(results[1].failure.as_deref(), Some("Code panicked at assertion failed"));
+/// assert_eq!(results.len(), 2);
+/// # Ok::<(), String>(())
+/// ```
+pub fn run(
+  source: &str,
+  builder: InterpreterBuilder,
+  filter: &Filter,
+) -> Result<Vec<TestResult>, String> {
+  let mut interpreter = builder.build()?;
+  interpreter.eval_str(source)?;
+  let tests = interpreter.take_tests().into_iter().filter(|test| filter.matches(test));
+  let results = tests.map(|test| {
+    let start = Instant::now();
+    let failure = interpreter.run_test(&test).err();
+    TestResult {
+      name: test.name,
+      failure,
+      output: String::new(),
+      duration: start.elapsed(),
+    }
+  });
+  Ok(results.collect())
+}
+
+/// Runs the tests of `source` `filter` matches like [`run`], but each one in its own
+/// interpreter, on `jobs` threads. The interpreters are configured by the builders `builder`
+/// returns, and their output is captured in the results, which are in the order the tests were
+/// registered.
+///
+/// As the script is evaluated again for each test, fixtures are set up once per test.
+///
+/// ```
+/// let source = "(test \"a\" (func () {(print 1)})) (test \"b\" (func () {(assert false)}))";
+/// let filter = nixt::tools::test::Filter::default();
+/// let results = nixt::tools::test::run_parallel(source, 2, nixt::Interpreter::builder, &filter)?;
+/// assert_eq!((results[0].output.as_str(), results[1].passed()), ("1\n", false));
+/// # Ok::<(), String>(())
+/// ```
+pub fn run_parallel(
+  source: &str,
+  jobs: usize,
+  builder: impl Fn() -> InterpreterBuilder + Sync,
+  filter: &Filter,
+) -> Result<Vec<TestResult>, String> {
+  // The index of each test to run among all of them, with its name.
+  let selected = list(source, builder())?
+    .into_iter()
+    .enumerate()
+    .filter(|(_, test)| filter.matches(test))
+    .map(|(index, test)| (index, test.name))
+    .collect::<Vec<_>>();
+  let next = AtomicUsize::new(0);
+  let results = Mutex::new(vec![None; selected.len()]);
+  std::thread::scope(|scope| {
+    for _ in 0..jobs.clamp(1, selected.len().max(1)) {
+      scope.spawn(|| loop {
+        let position = next.fetch_add(1, Ordering::Relaxed);
+        let (index, name) = match selected.get(position) {
+          Some((index, name)) => (*index, name.to_owned()),
+          None => break,
+        };
+        let output = Capture::default();
+        let mut duration = Duration::ZERO;
+        let failure = run_isolated(source, index, &builder, &output, &mut duration).err();
+        let result = TestResult { name, failure, output: output.take(), duration };
+        results.lock().unwrap()[position] = Some(result);
+      });
+    }
+  });
+  Ok(results.into_inner().unwrap().into_iter().flatten().collect())
+}
+
+/// Evaluates `source` in a new interpreter writing to `output`, then runs its test `index`,
+/// timing it in `duration`.
+fn run_isolated(
+  source: &str,
+  index: usize,
+  builder: &impl Fn() -> InterpreterBuilder,
+  output: &Capture,
+  duration: &mut Duration,
+) -> Result<(), String> {
+  let mut interpreter = builder().stdout(output.clone()).stderr(output.clone()).build()?;
+  interpreter.eval_str(source)?;
+  let test = interpreter
+    .take_tests()
+    .into_iter()
+    .nth(index)
+    .ok_or("The script registered fewer tests when evaluated again")?;
+  let start = Instant::now();
+  let result = interpreter.run_test(&test);
+  *duration = start.elapsed();
+  result.map(|_| ())
+}
+
+/// The tests of a script that were run, or why the script failed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suite {
+  pub name: String,
+  pub results: Result<Vec<TestResult>, String>,
+}
+
+impl Suite {
+  fn counts(&self) -> (usize, usize, usize) {
+    match &self.results {
+      Ok(results) => {
+        let failed = results.iter().filter(|result| !result.passed()).count();
+        (results.len(), failed, 0)
+      }
+      Err(_) => (0, 0, 1),
+    }
+  }
+  fn duration(&self) -> Duration {
+    let results = self.results.as_deref().unwrap_or_default();
+    results.iter().map(|result| result.duration).sum()
+  }
+}
+
+/// A JUnit XML report of `suites`, a `testsuite` per script. A script that failed is reported
+/// as a test case named `(script)` with an `error`.
+///
+/// ```
+/// use nixt::tools::test::{Suite, to_junit};
+/// let suites = [Suite { name: "bad_test.nxt".to_owned(), results: Err("Oops <".to_owned()) }];
+/// assert!(to_junit(&suites).contains(r#"<error message="Oops &lt;">Oops &lt;</error>"#));
+/// ```
+pub fn to_junit(suites: &[Suite]) -> String {
+  let (mut tests, mut failures, mut errors, mut time) = (0, 0, 0, Duration::ZERO);
+  let mut body = String::new();
+  for suite in suites {
+    let (suite_tests, suite_failures, suite_errors) = suite.counts();
+    tests += suite_tests;
+    failures += suite_failures;
+    errors += suite_errors;
+    time += suite.duration();
+    body.push_str(&format!(
+      "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"{}\" time=\"{:.3}\">\n",
+      escape_xml(&suite.name),
+      suite_tests,
+      suite_failures,
+      suite_errors,
+      suite.duration().as_secs_f64()
+    ));
+    match &suite.results {
+      Ok(results) => {
+        for result in results {
+          body.push_str(&junit_case(&suite.name, result));
+        }
+      }
+      Err(error) => body.push_str(&format!(
+        "    <testcase name=\"(script)\" classname=\"{}\" time=\"0.000\">\n      \
+         <error message=\"{}\">{}</error>\n    </testcase>\n",
+        escape_xml(&suite.name),
+        escape_xml(error.lines().next().unwrap_or_default()),
+        escape_xml(error)
+      )),
+    }
+    body.push_str("  </testsuite>\n");
+  }
+  format!(
+    "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuites tests=\"{}\" failures=\"{}\" \
+     errors=\"{}\" time=\"{:.3}\">\n{}</testsuites>\n",
+    tests,
+    failures,
+    errors,
+    time.as_secs_f64(),
+    body
+  )
+}
+
+fn junit_case(suite: &str, result: &TestResult) -> String {
+  let open = format!(
+    "    <testcase name=\"{}\" classname=\"{}\" time=\"{:.3}\"",
+    escape_xml(&result.name),
+    escape_xml(suite),
+    result.duration.as_secs_f64()
+  );
+  let mut inner = String::new();
+  if let Some(failure) = &result.failure {
+    inner.push_str(&format!(
+      "      <failure message=\"{}\">{}</failure>\n",
+      escape_xml(failure.lines().next().unwrap_or_default()),
+      escape_xml(failure)
+    ));
+  }
+  if !result.output.is_empty() {
+    inner.push_str(&format!("      <system-out>{}</system-out>\n", escape_xml(&result.output)));
+  }
+  match inner.as_str() {
+    "" => format!("{}/>\n", open),
+    _ => format!("{}>\n{}    </testcase>\n", open, inner),
+  }
+}
+
+fn escape_xml(text: &str) -> String {
+  text
+    .replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+    .replace('\'', "&apos;")
+}
+
+/// A JSON report of `suites`: the number of tests that passed and failed, then each script with
+/// its tests, their outcome and duration in milliseconds, or the error of the script.
+pub fn to_json(suites: &[Suite]) -> Json {
+  let (mut passed, mut failed) = (0, 0);
+  let suites = suites.iter().map(|suite| {
+    let (tests, failures, errors) = suite.counts();
+    passed += tests - failures;
+    failed += failures + errors;
+    let mut members = vec![("name".to_owned(), Json::Str(suite.name.to_owned()))];
+    match &suite.results {
+      Ok(results) => {
+        members.push(("tests".to_owned(), Json::Array(results.iter().map(json_case).collect())))
+      }
+      Err(error) => members.push(("error".to_owned(), Json::Str(error.to_owned()))),
+    }
+    Json::Object(members)
+  });
+  let suites = Json::Array(suites.collect());
+  Json::Object(vec![
+    ("passed".to_owned(), Json::Number(passed as f64)),
+    ("failed".to_owned(), Json::Number(failed as f64)),
+    ("suites".to_owned(), suites),
+  ])
+}
+
+fn json_case(result: &TestResult) -> Json {
+  let failure = match &result.failure {
+    Some(failure) => Json::Str(failure.to_owned()),
+    None => Json::Null,
+  };
+  Json::Object(vec![
+    ("name".to_owned(), Json::Str(result.name.to_owned())),
+    ("passed".to_owned(), Json::Bool(result.passed())),
+    ("duration_ms".to_owned(), Json::Number(result.duration.as_micros() as f64 / 1000.)),
+    ("failure".to_owned(), failure),
+    ("output".to_owned(), Json::Str(result.output.to_owned())),
+  ])
+}
+
+/// The test scripts under `path`: the file itself, or the files of a directory and its
+/// subdirectories ending in `_test.nxt`, sorted.
+pub fn discover(path: &Path) -> Result<Vec<PathBuf>, String> {
+  if !path.is_dir() {
+    return Ok(vec![path.to_owned()]);
+  }
+  let mut scripts = vec![];
+  let entries = std::fs::read_dir(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+  for entry in entries {
+    let entry = entry.map_err(|e| e.to_string())?.path();
+    if entry.is_dir() {
+      scripts.extend(discover(&entry)?);
+    } else if entry.to_string_lossy().ends_with("_test.nxt") {
+      scripts.push(entry);
+    }
+  }
+  scripts.sort();
+  Ok(scripts)
+}
@@ -0,0 +1,123 @@
+//! The collections lists and maps keep their elements in.
+//!
+//! By default they are a `Vec` and an `IndexMap`, copied whole when a value is cloned, as when
+//! a list is passed to a function or `insert` returns a new map. With the `persistent` feature,
+//! they are persistent collections that share their elements between clones: cloning is O(1),
+//! and changing a clone copies O(log n) of it, so that scripts passing big collections around
+//! do not pay for their size. Both provide the methods the interpreter uses, so code written
+//! against `List` and `Map` builds either way.
+
+use crate::utils::element::Value;
+#[cfg(feature = "persistent")]
+use std::iter::FromIterator;
+
+/// The elements of a list.
+#[cfg(not(feature = "persistent"))]
+pub type List = Vec<Value>;
+/// The elements of a list.
+#[cfg(feature = "persistent")]
+pub type List = im::Vector<Value>;
+
+/// The elements of `list` in a `Vec`, for the code that needs them as a slice.
+#[cfg(not(feature = "persistent"))]
+pub fn to_vec(list: &List) -> Vec<Value> {
+  list.clone()
+}
+/// The elements of `list` in a `Vec`, for the code that needs them as a slice.
+#[cfg(feature = "persistent")]
+pub fn to_vec(list: &List) -> Vec<Value> {
+  list.iter().cloned().collect()
+}
+
+/// The entries of a map, in the order their keys were first inserted.
+#[cfg(not(feature = "persistent"))]
+pub type Map = indexmap::IndexMap<String, Value>;
+/// The entries of a map, in the order their keys were first inserted.
+#[cfg(feature = "persistent")]
+pub type Map = OrderedMap;
+
+/// A persistent map keeping its keys in the order they were first inserted, with the methods
+/// of `IndexMap` that the interpreter uses.
+///
+/// ```
+/// use nixt::utils::collections::OrderedMap;
+/// let mut map = OrderedMap::new();
+/// map.insert("b".to_owned(), nixt::Value::from(1.));
+/// map.insert("a".to_owned(), nixt::Value::from(2.));
+/// let copy = map.clone();
+/// map.shift_remove("b");
+/// assert_eq!(copy.keys().collect::<Vec<_>>(), ["b", "a"]);
+/// assert_eq!(map.keys().collect::<Vec<_>>(), ["a"]);
+/// ```
+#[cfg(feature = "persistent")]
+#[derive(Debug, Clone, Default)]
+pub struct OrderedMap {
+  /// The value of each key, with the position it was inserted at.
+  entries: im::OrdMap<String, (u64, Value)>,
+  /// The keys by the position they were inserted at.
+  order: im::OrdMap<u64, String>,
+  next: u64,
+}
+
+#[cfg(feature = "persistent")]
+impl OrderedMap {
+  pub fn new() -> Self {
+    Self::default()
+  }
+  pub fn len(&self) -> usize {
+    self.entries.len()
+  }
+  pub fn is_empty(&self) -> bool {
+    self.entries.is_empty()
+  }
+  pub fn get(&self, key: &str) -> Option<&Value> {
+    self.entries.get(key).map(|(_, value)| value)
+  }
+  pub fn get_mut(&mut self, key: &str) -> Option<&mut Value> {
+    self.entries.get_mut(key).map(|(_, value)| value)
+  }
+  pub fn contains_key(&self, key: &str) -> bool {
+    self.entries.contains_key(key)
+  }
+  /// Binds `key` to `value`, returning its previous value. A new key goes last, and a key
+  /// bound again keeps its place.
+  pub fn insert(&mut self, key: String, value: Value) -> Option<Value> {
+    if let Some((_, old)) = self.entries.get_mut(&key) {
+      return Some(std::mem::replace(old, value));
+    }
+    self.order.insert(self.next, key.clone());
+    self.entries.insert(key, (self.next, value));
+    self.next += 1;
+    None
+  }
+  /// Removes `key`, the other keys keeping their order.
+  pub fn shift_remove(&mut self, key: &str) -> Option<Value> {
+    let (position, value) = self.entries.remove(key)?;
+    self.order.remove(&position);
+    Some(value)
+  }
+  pub fn iter(&self) -> impl Iterator<Item = (&String, &Value)> {
+    self.order.values().map(move |key| (key, &self.entries[key].1))
+  }
+  pub fn keys(&self) -> impl Iterator<Item = &String> {
+    self.order.values()
+  }
+}
+
+#[cfg(feature = "persistent")]
+impl FromIterator<(String, Value)> for OrderedMap {
+  fn from_iter<I: IntoIterator<Item = (String, Value)>>(entries: I) -> Self {
+    let mut map = Self::new();
+    for (key, value) in entries {
+      map.insert(key, value);
+    }
+    map
+  }
+}
+
+#[cfg(feature = "persistent")]
+impl<const N: usize> From<[(String, Value); N]> for OrderedMap {
+  fn from(entries: [(String, Value); N]) -> Self {
+    IntoIterator::into_iter(entries).collect()
+  }
+}
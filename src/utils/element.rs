@@ -1,24 +1,291 @@
+use crate::core::interpreter::environment::Environment;
+use crate::utils::fingerprint::Fingerprinter;
 pub use crate::utils::node::Node;
+pub use crate::utils::collections::{List, Map};
+use crate::utils::resource::Resource;
+use std::cmp::Ordering;
 #[derive(Debug, Clone)]
 pub enum Value {
   String(String),
-  Number(f32),
-  List(Vec<Value>),
+  Number(f64),
+  List(List),
   Bool(bool),
-  Func(Func),
+  /// A function, boxed as its body is a whole syntax tree, to keep every value small.
+  Func(Box<Func>),
+  /// A builtin used as a value, called by name: `print` in `(sort_by len xs)` or `(let p print)`.
+  Native(String),
+  /// A variant of an `enum`: the enum's name and the variant's.
+  Enum(String, String),
+  /// Numbers from `start` up to, but excluding, `end` by `step`: `(range 0 10 2)` or `0..10`.
+  Range(f64, f64, f64),
+  /// A program `(parse "source")` read, which `eval` evaluates.
+  Ast(Node),
+  /// Values by key, in the order the keys were first inserted, which is how they print. Maps
+  /// with the same entries in another order are equal.
+  Map(Map),
+  /// A file, a connection or another resource of the host, shared by the copies of the value.
+  Resource(Resource),
   Nil,
 }
 #[derive(Debug, Clone)]
 pub struct Func {
   pub args: Vec<String>,
   pub body: Node,
+  /// The local scope the function was defined in, which its body sees even once that scope
+  /// is left. Functions defined in the globals or the prelude keep none: their body sees the
+  /// globals of the interpreter calling them.
+  pub env: Option<Environment>,
+}
+
+impl Value {
+  pub fn list<T: Into<Value>>(items: impl IntoIterator<Item = T>) -> Self {
+    Self::List(items.into_iter().map(Into::into).collect())
+  }
+  /// The map of `entries`, in their order. A key given twice keeps its first place and its last
+  /// value, as in `(map ...)`.
+  ///
+  /// ```
+  /// use nixt::Value;
+  /// let config = Value::map([("name", Value::from("nixt")), ("tags", Value::list(["a", "b"]))]);
+  /// assert_eq!(config.to_string(), "{name: nixt, tags: [a, b]}");
+  /// assert_eq!(config.get_path("tags.1").and_then(Value::as_str), Some("b"));
+  /// ```
+  pub fn map<K: Into<String>, T: Into<Value>>(entries: impl IntoIterator<Item = (K, T)>) -> Self {
+    Self::Map(entries.into_iter().map(|(key, value)| (key.into(), value.into())).collect())
+  }
+  pub fn as_number(&self) -> Option<f64> {
+    match self {
+      Self::Number(n) => Some(*n),
+      _ => None,
+    }
+  }
+  pub fn as_str(&self) -> Option<&str> {
+    match self {
+      Self::String(s) => Some(s),
+      _ => None,
+    }
+  }
+  pub fn as_bool(&self) -> Option<bool> {
+    match self {
+      Self::Bool(b) => Some(*b),
+      _ => None,
+    }
+  }
+  pub fn as_list(&self) -> Option<&List> {
+    match self {
+      Self::List(l) => Some(l),
+      _ => None,
+    }
+  }
+  pub fn as_map(&self) -> Option<&Map> {
+    match self {
+      Self::Map(m) => Some(m),
+      _ => None,
+    }
+  }
+  pub fn as_func(&self) -> Option<&Func> {
+    match self {
+      Self::Func(f) => Some(f),
+      _ => None,
+    }
+  }
+  pub fn as_resource(&self) -> Option<&Resource> {
+    match self {
+      Self::Resource(r) => Some(r),
+      _ => None,
+    }
+  }
+  pub fn is_nil(&self) -> bool {
+    matches!(self, Self::Nil)
+  }
+  /// The name `(type x)` gives: `number`, `string`, ..., or the name of an enum.
+  pub fn type_name(&self) -> &str {
+    match self {
+      Self::Nil => "nil",
+      Self::Bool(_) => "bool",
+      Self::Number(_) => "number",
+      Self::String(_) => "string",
+      Self::List(_) => "list",
+      Self::Func(_) | Self::Native(_) => "function",
+      Self::Enum(name, _) => name,
+      Self::Range(..) => "range",
+      Self::Ast(_) => "ast",
+      Self::Map(_) => "map",
+      Self::Resource(_) => "resource",
+    }
+  }
+  /// A total order over values: values of different types are ordered by type (nil, bools,
+  /// numbers, strings, lists, enums, ranges, functions, syntax trees, maps, resources), lists
+  /// and maps lexicographically.
+  pub fn compare(&self, other: &Value) -> Ordering {
+    match (self, other) {
+      (Self::Bool(l), Self::Bool(r)) => l.cmp(r),
+      (Self::Number(l), Self::Number(r)) => l.total_cmp(r),
+      (Self::String(l), Self::String(r)) => l.cmp(r),
+      (Self::List(l), Self::List(r)) => l
+        .iter()
+        .zip(r)
+        .map(|(l, r)| l.compare(r))
+        .find(|o| *o != Ordering::Equal)
+        .unwrap_or_else(|| l.len().cmp(&r.len())),
+      (Self::Enum(ln, lv), Self::Enum(rn, rv)) => (ln, lv).cmp(&(rn, rv)),
+      (Self::Range(ls, le, lst), Self::Range(rs, re, rst)) => ls
+        .total_cmp(rs)
+        .then(le.total_cmp(re))
+        .then(lst.total_cmp(rst)),
+      (Self::Native(l), Self::Native(r)) => l.cmp(r),
+      (Self::Resource(l), Self::Resource(r)) => l.kind().cmp(r.kind()),
+      (Self::Ast(_), Self::Ast(_)) => self.to_string().cmp(&other.to_string()),
+      (Self::Map(l), Self::Map(r)) => sorted_entries(l)
+        .into_iter()
+        .zip(sorted_entries(r))
+        .map(|((lk, lv), (rk, rv))| lk.cmp(rk).then_with(|| lv.compare(rv)))
+        .find(|o| *o != Ordering::Equal)
+        .unwrap_or_else(|| l.len().cmp(&r.len())),
+      _ => self.type_rank().cmp(&other.type_rank()),
+    }
+  }
+  fn type_rank(&self) -> u8 {
+    match self {
+      Self::Nil => 0,
+      Self::Bool(_) => 1,
+      Self::Number(_) => 2,
+      Self::String(_) => 3,
+      Self::List(_) => 4,
+      Self::Enum(..) => 5,
+      Self::Range(..) => 6,
+      Self::Func(_) | Self::Native(_) => 7,
+      Self::Ast(_) => 8,
+      Self::Map(_) => 9,
+      Self::Resource(_) => 10,
+    }
+  }
+  /// Follows a dot separated path of list indices and map keys, e.g. `"2.name.0"`.
+  pub fn get_path(&self, path: &str) -> Option<&Value> {
+    if path.is_empty() {
+      return Some(self);
+    }
+    path.split('.').try_fold(self, |current, segment| match current {
+      Self::Map(entries) => entries.get(segment),
+      _ => current.as_list()?.get(segment.parse::<usize>().ok()?),
+    })
+  }
+  /// A hash of the value that stays the same across runs, platforms and builds, to key the
+  /// caches of results computed from it. Values `=` finds equal have the same fingerprint.
+  ///
+  /// ```
+  /// use nixt::Value;
+  /// assert_eq!(Value::list([1, 2]).fingerprint(), Value::list([1., 2.]).fingerprint());
+  /// assert_ne!(Value::list(["ab"]).fingerprint(), Value::list(["a", "b"]).fingerprint());
+  /// assert_eq!(Value::from("nixt").fingerprint(), 0xf960_fea1_79ad_bfbf);
+  /// ```
+  pub fn fingerprint(&self) -> u64 {
+    let mut fingerprinter = Fingerprinter::new();
+    fingerprinter.value(self);
+    fingerprinter.finish()
+  }
+  /// This value as MessagePack, for another process or a later run to read back with
+  /// [`Value::from_bytes`]. Functions and syntax trees are not data and can not be encoded.
+  ///
+  /// ```
+  /// use nixt::Value;
+  /// let value = Value::list([Value::from("nixt"), Value::from(3.), Value::Nil]);
+  /// let bytes = value.to_bytes()?;
+  /// assert_eq!(bytes, [0x93, 0xa4, b'n', b'i', b'x', b't', 0x03, 0xc0]);
+  /// assert_eq!(Value::from_bytes(&bytes)?.to_string(), "[nixt, 3, nil]");
+  /// # Ok::<(), String>(())
+  /// ```
+  #[cfg(feature = "msgpack")]
+  pub fn to_bytes(&self) -> Result<Vec<u8>, String> {
+    crate::utils::msgpack::encode(self)
+  }
+  /// The value the MessagePack `bytes` encode, as written by [`Value::to_bytes`] or any other
+  /// MessagePack library.
+  #[cfg(feature = "msgpack")]
+  pub fn from_bytes(bytes: &[u8]) -> Result<Value, String> {
+    crate::utils::msgpack::decode(bytes)
+  }
+}
+
+impl From<f64> for Value {
+  fn from(n: f64) -> Self {
+    Self::Number(n)
+  }
+}
+
+/// The number of the shortest decimal that reads back as `n`, `0.1` for `0.1f32` rather than
+/// the `0.10000000149011612` of `n as f64`, so that a value handed over as an `f32` prints as
+/// the `f32` does.
+impl From<f32> for Value {
+  fn from(n: f32) -> Self {
+    Self::Number(n.to_string().parse().unwrap_or(n as f64))
+  }
+}
+
+impl From<i32> for Value {
+  fn from(n: i32) -> Self {
+    Self::Number(n as f64)
+  }
+}
+
+impl From<bool> for Value {
+  fn from(b: bool) -> Self {
+    Self::Bool(b)
+  }
+}
+
+impl From<&str> for Value {
+  fn from(s: &str) -> Self {
+    Self::String(s.to_owned())
+  }
+}
+
+impl From<String> for Value {
+  fn from(s: String) -> Self {
+    Self::String(s)
+  }
+}
+
+impl From<Vec<Value>> for Value {
+  fn from(l: Vec<Value>) -> Self {
+    Self::List(l.into_iter().collect())
+  }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+  fn from(o: Option<T>) -> Self {
+    o.map_or(Self::Nil, Into::into)
+  }
+}
+
+/// The entries of `map` sorted by key, in which maps compare and hash whatever their order.
+pub(crate) fn sorted_entries(map: &Map) -> Vec<(&String, &Value)> {
+  let mut entries = map.iter().collect::<Vec<_>>();
+  entries.sort_by_key(|(key, _)| *key);
+  entries
+}
+
+/// The shortest decimal that reads back as `n`, switching to exponent notation for very
+/// large or small magnitudes: `0.1`, `3`, `1e21`, `1.5e-8`.
+///
+/// Parsing the result gives back `n` bit for bit, for every finite `n` but `-0`, written `0`.
+/// This is what `(parse_num (num_to_str x))` relies on, and is tested over random bit patterns.
+pub fn format_number(n: f64) -> String {
+  if n == 0. {
+    return "0".to_owned(); // Also for -0
+  }
+  if n.is_finite() && (n.abs() >= 1e21 || n.abs() < 1e-7) {
+    format!("{:e}", n)
+  } else {
+    format!("{}", n)
+  }
 }
 
 impl std::fmt::Display for Value {
   fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
     match self {
       Self::String(s) => write!(f, "{}", s)?,
-      Self::Number(n) => write!(f, "{}", n)?,
+      Self::Number(n) => write!(f, "{}", format_number(*n))?,
       Self::List(l) => {
         write!(f, "[")?;
         for i in 0..l.len() {
@@ -32,6 +299,20 @@ impl std::fmt::Display for Value {
       }
       Self::Bool(b) => write!(f, "{}", b)?,
       Self::Func(fnc) => write!(f, "{}", fnc)?,
+      Self::Native(name) => write!(f, "@builtin: {}", name)?,
+      Self::Ast(ast) => {
+        let forms = ast.get_child().iter().map(|form| form.to_string()).collect::<Vec<_>>();
+        write!(f, "{}", forms.join(" "))?
+      }
+      Self::Map(m) => {
+        let entries = m.iter().map(|(k, v)| format!("{}: {}", k, v)).collect::<Vec<_>>();
+        write!(f, "{{{}}}", entries.join(", "))?
+      }
+      Self::Enum(name, variant) => write!(f, "{}:{}", name, variant)?,
+      Self::Range(start, end, step) if *step == 1. => write!(f, "{}..{}", start, end)?,
+      Self::Range(start, end, step) => write!(f, "(range {} {} {})", start, end, step)?,
+      Self::Resource(r) if r.is_closed() => write!(f, "@resource: {} (closed)", r.kind())?,
+      Self::Resource(r) => write!(f, "@resource: {}", r.kind())?,
       Self::Nil => write!(f, "nil")?,
     }
     Ok(())
@@ -0,0 +1,173 @@
+//! Stable 64-bit hashes of syntax trees and values, the same across runs, platforms and
+//! builds, to key caches that outlive the process. `std`'s hashers are randomly seeded and may
+//! change between Rust versions, so they can not be used for this.
+
+use crate::utils::element::{sorted_entries, Value};
+use crate::utils::node::{AssignType, Node, NodeType, OperatorType};
+
+/// A 64-bit FNV-1a hash fed with a tagged encoding of the hashed data, so that data of
+/// different shapes, such as `["ab"]` and `["a" "b"]`, do not write the same bytes.
+pub(crate) struct Fingerprinter(u64);
+
+impl Fingerprinter {
+  const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+  const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+  pub(crate) fn new() -> Self {
+    Self(Self::OFFSET)
+  }
+  pub(crate) fn finish(&self) -> u64 {
+    self.0
+  }
+  fn bytes(&mut self, bytes: &[u8]) {
+    for byte in bytes {
+      self.0 = (self.0 ^ *byte as u64).wrapping_mul(Self::PRIME);
+    }
+  }
+  fn tag(&mut self, tag: u8) {
+    self.bytes(&[tag]);
+  }
+  fn len(&mut self, len: usize) {
+    self.bytes(&(len as u64).to_le_bytes());
+  }
+  fn str(&mut self, s: &str) {
+    self.len(s.len());
+    self.bytes(s.as_bytes());
+  }
+  /// `n` by its bits, with `-0` hashed as `0` and every NaN alike, as `=` sees them.
+  fn number(&mut self, n: f64) {
+    let n = if n == 0. { 0. } else if n.is_nan() { f64::NAN } else { n };
+    self.bytes(&n.to_bits().to_le_bytes());
+  }
+
+  pub(crate) fn node(&mut self, node: &Node) {
+    match node.get_type() {
+      NodeType::Assignement(assign) => self.bytes(&[0, assign_tag(&assign)]),
+      NodeType::Condition => self.tag(1),
+      NodeType::Cond => self.tag(2),
+      NodeType::Operator(op) => self.bytes(&[3, operator_tag(&op)]),
+      NodeType::Func => self.tag(4),
+      NodeType::Loop => self.tag(5),
+      NodeType::Return => self.tag(6),
+      NodeType::Block => self.tag(7),
+      NodeType::Do => self.tag(8),
+      NodeType::None => self.tag(9),
+      NodeType::Scope => self.tag(10),
+      NodeType::NodeBool(b) => self.bytes(&[11, b as u8]),
+      NodeType::NodeNumber(n) => {
+        self.tag(12);
+        self.number(n);
+      }
+      NodeType::NodeStr(s) => {
+        self.tag(13);
+        self.str(&s);
+      }
+      NodeType::NodeIdentifier(name) => {
+        self.tag(14);
+        self.str(&name);
+      }
+      NodeType::FunctionCall(name) => {
+        self.tag(15);
+        self.str(&name);
+      }
+      NodeType::Enum(name) => {
+        self.tag(16);
+        self.str(&name);
+      }
+      NodeType::Export => self.tag(17),
+      NodeType::Deprecated(hint) => {
+        self.tag(18);
+        self.str(&hint);
+      }
+    }
+    self.len(node.get_child().len());
+    for child in node.get_child() {
+      self.node(child);
+    }
+  }
+  pub(crate) fn value(&mut self, value: &Value) {
+    match value {
+      Value::Nil => self.tag(0),
+      Value::Bool(b) => self.bytes(&[1, *b as u8]),
+      Value::Number(n) => {
+        self.tag(2);
+        self.number(*n);
+      }
+      Value::String(s) => {
+        self.tag(3);
+        self.str(s);
+      }
+      Value::List(items) => {
+        self.tag(4);
+        self.len(items.len());
+        items.iter().for_each(|item| self.value(item));
+      }
+      Value::Enum(name, variant) => {
+        self.tag(5);
+        self.str(name);
+        self.str(variant);
+      }
+      Value::Range(start, end, step) => {
+        self.tag(6);
+        for n in [start, end, step] {
+          self.number(*n);
+        }
+      }
+      Value::Func(func) => {
+        self.tag(7);
+        self.len(func.args.len());
+        func.args.iter().for_each(|arg| self.str(arg));
+        self.node(&func.body);
+      }
+      Value::Native(name) => {
+        self.tag(8);
+        self.str(name);
+      }
+      Value::Ast(ast) => {
+        self.tag(9);
+        self.node(ast);
+      }
+      Value::Resource(resource) => {
+        self.tag(11);
+        self.str(resource.kind());
+      }
+      Value::Map(entries) => {
+        self.tag(10);
+        self.len(entries.len());
+        for (key, value) in sorted_entries(entries) {
+          self.str(key);
+          self.value(value);
+        }
+      }
+    }
+  }
+}
+
+// The tags are spelled out rather than derived from the declaration order of the variants, so
+// that reordering them does not change the fingerprints.
+fn assign_tag(assign: &AssignType) -> u8 {
+  match assign {
+    AssignType::Let => 0,
+    AssignType::Const => 1,
+    AssignType::Set => 2,
+  }
+}
+
+fn operator_tag(op: &OperatorType) -> u8 {
+  match op {
+    OperatorType::Plus => 0,
+    OperatorType::Minus => 1,
+    OperatorType::Times => 2,
+    OperatorType::Div => 3,
+    OperatorType::Less => 4,
+    OperatorType::LessEqual => 5,
+    OperatorType::Greater => 6,
+    OperatorType::GreaterEqual => 7,
+    OperatorType::Equal => 8,
+    OperatorType::NotEqual => 9,
+    OperatorType::And => 10,
+    OperatorType::Or => 11,
+    OperatorType::Modulo => 12,
+    OperatorType::NilCoalesce => 13,
+  }
+}
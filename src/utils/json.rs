@@ -0,0 +1,293 @@
+use crate::utils::element::Value;
+
+/// A JSON document. Object members keep their source order, and are written back in that
+/// order unless reordered with [`Json::ordered`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+  Null,
+  Bool(bool),
+  Number(f64),
+  Str(String),
+  Array(Vec<Json>),
+  Object(Vec<(String, Json)>),
+}
+
+/// The order object members are iterated and written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KeyOrder {
+  /// The order the members were parsed or inserted in.
+  #[default]
+  Insertion,
+  /// Sorted by key. Members sharing a key keep their relative order.
+  Sorted,
+}
+
+impl Json {
+  /// The value of the first member named `key`, if this is an object.
+  pub fn get(&self, key: &str) -> Option<&Json> {
+    match self {
+      Json::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+      _ => None,
+    }
+  }
+  pub fn as_str(&self) -> Option<&str> {
+    match self {
+      Json::Str(s) => Some(s),
+      _ => None,
+    }
+  }
+  /// This document with the members of every object, nested ones included, in `order`.
+  pub fn ordered(self, order: KeyOrder) -> Json {
+    match self {
+      Json::Array(items) => Json::Array(items.into_iter().map(|i| i.ordered(order)).collect()),
+      Json::Object(members) => {
+        let mut members = members
+          .into_iter()
+          .map(|(key, value)| (key, value.ordered(order)))
+          .collect::<Vec<_>>();
+        if order == KeyOrder::Sorted {
+          members.sort_by(|(a, _), (b, _)| a.cmp(b));
+        }
+        Json::Object(members)
+      }
+      other => other,
+    }
+  }
+}
+
+pub fn parse(source: &str) -> Result<Json, String> {
+  let mut parser = JsonParser {
+    chars: source.chars().collect(),
+    current: 0,
+  };
+  let json = parser.value()?;
+  parser.skip_whitespace();
+  if parser.current < parser.chars.len() {
+    return Err(format!("Unexpected trailing JSON at {}", parser.current));
+  }
+  Ok(json)
+}
+
+/// Converts a JSON document into a nixt value. Objects become maps keeping the order of their
+/// members; a key given twice keeps its first place and its last value.
+pub fn to_value(json: &Json) -> Result<Value, String> {
+  Ok(match json {
+    Json::Null => Value::Nil,
+    Json::Bool(b) => Value::Bool(*b),
+    Json::Number(n) => Value::Number(*n),
+    Json::Str(s) => Value::String(s.to_owned()),
+    Json::Array(a) => Value::List(a.iter().map(to_value).collect::<Result<_, _>>()?),
+    Json::Object(members) => {
+      let entries = members.iter().map(|(key, value)| Ok((key.to_owned(), to_value(value)?)));
+      Value::Map(entries.collect::<Result<_, String>>()?)
+    }
+  })
+}
+
+/// Converts a nixt value into JSON, maps as objects with their keys in order. Values JSON can not
+/// represent, such as functions, are written as their display string.
+pub fn from_value(value: &Value) -> Json {
+  match value {
+    Value::Nil => Json::Null,
+    Value::Bool(b) => Json::Bool(*b),
+    Value::Number(n) => Json::Number(*n),
+    Value::String(s) => Json::Str(s.to_owned()),
+    Value::List(l) => Json::Array(l.iter().map(from_value).collect()),
+    Value::Map(m) => Json::Object(m.iter().map(|(k, v)| (k.to_owned(), from_value(v))).collect()),
+    other => Json::Str(format!("{}", other)),
+  }
+}
+
+struct JsonParser {
+  chars: Vec<char>,
+  current: usize,
+}
+
+impl JsonParser {
+  fn peek(&self) -> Option<char> {
+    self.chars.get(self.current).copied()
+  }
+  fn skip_whitespace(&mut self) {
+    while self.peek().is_some_and(|c| c.is_whitespace()) {
+      self.current += 1;
+    }
+  }
+  fn expect(&mut self, expected: char) -> Result<(), String> {
+    self.skip_whitespace();
+    if self.peek() == Some(expected) {
+      self.current += 1;
+      Ok(())
+    } else {
+      Err(format!("Expected `{}` in JSON at {}", expected, self.current))
+    }
+  }
+  fn keyword(&mut self, word: &str, json: Json) -> Result<Json, String> {
+    for expected in word.chars() {
+      if self.peek() != Some(expected) {
+        return Err(format!("Invalid JSON literal at {}", self.current));
+      }
+      self.current += 1;
+    }
+    Ok(json)
+  }
+  fn value(&mut self) -> Result<Json, String> {
+    self.skip_whitespace();
+    match self.peek() {
+      Some('n') => self.keyword("null", Json::Null),
+      Some('t') => self.keyword("true", Json::Bool(true)),
+      Some('f') => self.keyword("false", Json::Bool(false)),
+      Some('"') => Ok(Json::Str(self.string()?)),
+      Some('[') => {
+        self.current += 1;
+        let mut items = vec![];
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+          self.current += 1;
+          return Ok(Json::Array(items));
+        }
+        loop {
+          items.push(self.value()?);
+          self.skip_whitespace();
+          match self.peek() {
+            Some(',') => self.current += 1,
+            Some(']') => {
+              self.current += 1;
+              return Ok(Json::Array(items));
+            }
+            _ => return Err(format!("Expected `,` or `]` in JSON at {}", self.current)),
+          }
+        }
+      }
+      Some('{') => {
+        self.current += 1;
+        let mut members = vec![];
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+          self.current += 1;
+          return Ok(Json::Object(members));
+        }
+        loop {
+          self.skip_whitespace();
+          let key = self.string()?;
+          self.expect(':')?;
+          members.push((key, self.value()?));
+          self.skip_whitespace();
+          match self.peek() {
+            Some(',') => self.current += 1,
+            Some('}') => {
+              self.current += 1;
+              return Ok(Json::Object(members));
+            }
+            _ => return Err(format!("Expected `,` or `}}` in JSON at {}", self.current)),
+          }
+        }
+      }
+      Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+      _ => Err(format!("Invalid JSON value at {}", self.current)),
+    }
+  }
+  fn number(&mut self) -> Result<Json, String> {
+    let start = self.current;
+    while self
+      .peek()
+      .is_some_and(|c| c.is_ascii_digit() || "+-.eE".contains(c))
+    {
+      self.current += 1;
+    }
+    let text = self.chars[start..self.current].iter().collect::<String>();
+    match text.parse::<f64>() {
+      Ok(n) => Ok(Json::Number(n)),
+      Err(_) => Err(format!("Invalid JSON number `{}`", text)),
+    }
+  }
+  fn string(&mut self) -> Result<String, String> {
+    if self.peek() != Some('"') {
+      return Err(format!("Expected a JSON string at {}", self.current));
+    }
+    self.current += 1;
+    let mut toret = String::new();
+    loop {
+      let c = match self.peek() {
+        Some(c) => c,
+        None => return Err("Unterminated JSON string".to_owned()),
+      };
+      self.current += 1;
+      match c {
+        '"' => return Ok(toret),
+        '\\' => {
+          let escaped = self.peek().ok_or("Unterminated JSON string")?;
+          self.current += 1;
+          match escaped {
+            'n' => toret.push('\n'),
+            't' => toret.push('\t'),
+            'r' => toret.push('\r'),
+            'b' => toret.push('\u{8}'),
+            'f' => toret.push('\u{c}'),
+            'u' => {
+              let hex = self
+                .chars
+                .get(self.current..self.current + 4)
+                .ok_or("Invalid JSON unicode escape")?
+                .iter()
+                .collect::<String>();
+              self.current += 4;
+              let code = u32::from_str_radix(&hex, 16).map_err(|e| e.to_string())?;
+              toret.push(std::char::from_u32(code).unwrap_or('\u{fffd}'));
+            }
+            other => toret.push(other),
+          }
+        }
+        _ => toret.push(c),
+      }
+    }
+  }
+}
+
+impl std::fmt::Display for Json {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    match self {
+      Self::Null => write!(f, "null"),
+      Self::Bool(b) => write!(f, "{}", b),
+      Self::Number(n) if n.is_finite() => write!(f, "{}", n),
+      Self::Number(_) => write!(f, "null"),
+      Self::Str(s) => write_escaped(f, s),
+      Self::Array(items) => {
+        write!(f, "[")?;
+        for (i, item) in items.iter().enumerate() {
+          if i > 0 {
+            write!(f, ",")?;
+          }
+          write!(f, "{}", item)?;
+        }
+        write!(f, "]")
+      }
+      Self::Object(members) => {
+        write!(f, "{{")?;
+        for (i, (key, value)) in members.iter().enumerate() {
+          if i > 0 {
+            write!(f, ",")?;
+          }
+          write_escaped(f, key)?;
+          write!(f, ":{}", value)?;
+        }
+        write!(f, "}}")
+      }
+    }
+  }
+}
+
+fn write_escaped(f: &mut std::fmt::Formatter, s: &str) -> std::fmt::Result {
+  write!(f, "\"")?;
+  for c in s.chars() {
+    match c {
+      '"' => write!(f, "\\\"")?,
+      '\\' => write!(f, "\\\\")?,
+      '\n' => write!(f, "\\n")?,
+      '\r' => write!(f, "\\r")?,
+      '\t' => write!(f, "\\t")?,
+      c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+      c => write!(f, "{}", c)?,
+    }
+  }
+  write!(f, "\"")
+}
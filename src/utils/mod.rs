@@ -1,3 +1,10 @@
+pub mod collections;
 pub mod element;
+pub mod fingerprint;
+pub mod json;
+#[cfg(feature = "msgpack")]
+pub mod msgpack;
 pub mod node;
+pub mod resource;
 pub mod token;
+pub mod trace;
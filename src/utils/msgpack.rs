@@ -0,0 +1,238 @@
+//! Values as MessagePack, to pass the results of scripts between processes and store them.
+//!
+//! Nil, bools, numbers, strings, lists and maps are written as their MessagePack types, so that
+//! any MessagePack library reads them. Numbers holding an integer are written as integers, the
+//! others as 64-bit floats. Enums, ranges and builtins are written as extension types, while
+//! functions and syntax trees can not be written at all. Any integer, float, string, binary,
+//! array or map written by another library reads back, binary data as a list of bytes.
+
+use crate::utils::element::{Map, Value};
+use rmp::encode;
+use rmp::Marker;
+use std::convert::TryFrom;
+
+/// The extension type of an enum variant: its enum's name then its own, as two strings.
+const ENUM: i8 = 1;
+/// The extension type of a range: its start, end and step, as three numbers.
+const RANGE: i8 = 2;
+/// The extension type of a builtin: its name, as a string.
+const NATIVE: i8 = 3;
+
+/// The MessagePack encoding of `value`.
+pub fn encode(value: &Value) -> Result<Vec<u8>, String> {
+  let mut bytes = vec![];
+  write(&mut bytes, value)?;
+  Ok(bytes)
+}
+
+/// The value encoded by `bytes`, which must hold that one value and nothing after.
+pub fn decode(bytes: &[u8]) -> Result<Value, String> {
+  let mut reader = Reader { bytes, at: 0 };
+  let value = reader.value()?;
+  if reader.at < bytes.len() {
+    return Err(format!("Unexpected trailing MessagePack at byte {}", reader.at));
+  }
+  Ok(value)
+}
+
+fn write(out: &mut Vec<u8>, value: &Value) -> Result<(), String> {
+  match value {
+    Value::Nil => encode::write_nil(out).map_err(failed)?,
+    Value::Bool(b) => encode::write_bool(out, *b).map_err(failed)?,
+    Value::Number(n) => write_number(out, *n)?,
+    Value::String(s) => encode::write_str(out, s).map_err(failed)?,
+    Value::List(items) => {
+      encode::write_array_len(out, length(items.len())?).map_err(failed)?;
+      for item in items.iter() {
+        write(out, item)?;
+      }
+    }
+    Value::Map(entries) => {
+      encode::write_map_len(out, length(entries.len())?).map_err(failed)?;
+      for (key, value) in entries.iter() {
+        encode::write_str(out, key).map_err(failed)?;
+        write(out, value)?;
+      }
+    }
+    Value::Enum(name, variant) => {
+      let mut payload = vec![];
+      encode::write_str(&mut payload, name).map_err(failed)?;
+      encode::write_str(&mut payload, variant).map_err(failed)?;
+      write_ext(out, ENUM, &payload)?;
+    }
+    Value::Range(start, end, step) => {
+      let mut payload = vec![];
+      for n in [start, end, step] {
+        write_number(&mut payload, *n)?;
+      }
+      write_ext(out, RANGE, &payload)?;
+    }
+    Value::Native(name) => {
+      let mut payload = vec![];
+      encode::write_str(&mut payload, name).map_err(failed)?;
+      write_ext(out, NATIVE, &payload)?;
+    }
+    Value::Func(_) | Value::Ast(_) | Value::Resource(_) => {
+      return Err(format!("Can not encode a {}, only data", value.type_name()))
+    }
+  }
+  Ok(())
+}
+
+/// Writes `n` as an integer if it is one a float holds exactly, keeping `-0` a float.
+fn write_number(out: &mut Vec<u8>, n: f64) -> Result<(), String> {
+  const EXACT: f64 = 9_007_199_254_740_992.;
+  if n.fract() == 0. && n.abs() <= EXACT && !(n == 0. && n.is_sign_negative()) {
+    encode::write_sint(out, n as i64).map_err(failed)?;
+    Ok(())
+  } else {
+    encode::write_f64(out, n).map_err(failed)
+  }
+}
+
+fn write_ext(out: &mut Vec<u8>, ty: i8, payload: &[u8]) -> Result<(), String> {
+  encode::write_ext_meta(out, length(payload.len())?, ty).map_err(failed)?;
+  out.extend_from_slice(payload);
+  Ok(())
+}
+
+fn length(len: usize) -> Result<u32, String> {
+  let too_long = |_| format!("Can not encode {} elements, MessagePack holds at most 2^32", len);
+  u32::try_from(len).map_err(too_long)
+}
+
+/// Writing to a `Vec` can not fail, but the encoder's signatures allow it.
+fn failed(e: impl std::fmt::Display) -> String {
+  format!("Could not encode the value: {}", e)
+}
+
+struct Reader<'a> {
+  bytes: &'a [u8],
+  at: usize,
+}
+
+impl<'a> Reader<'a> {
+  fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+    let end = self.at.checked_add(n).filter(|end| *end <= self.bytes.len());
+    let end = end.ok_or_else(|| format!("Unexpected end of MessagePack at byte {}", self.at))?;
+    let taken = &self.bytes[self.at..end];
+    self.at = end;
+    Ok(taken)
+  }
+  fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+    let mut array = [0; N];
+    array.copy_from_slice(self.take(N)?);
+    Ok(array)
+  }
+  /// The length following `marker`, of a string, binary data, array, map or extension.
+  fn len(&mut self, marker: Marker) -> Result<usize, String> {
+    use Marker::*;
+    Ok(match marker {
+      Str8 | Bin8 | Ext8 => self.array::<1>()?[0] as usize,
+      Str16 | Bin16 | Ext16 | Array16 | Map16 => u16::from_be_bytes(self.array()?) as usize,
+      _ => u32::from_be_bytes(self.array()?) as usize,
+    })
+  }
+  fn str(&mut self, len: usize) -> Result<String, String> {
+    let at = self.at;
+    let bytes = self.take(len)?;
+    let invalid = |_| format!("Invalid UTF-8 in the string at byte {}", at);
+    String::from_utf8(bytes.to_vec()).map_err(invalid)
+  }
+  fn value(&mut self) -> Result<Value, String> {
+    let at = self.at;
+    let marker = Marker::from_u8(self.array::<1>()?[0]);
+    Ok(match marker {
+      Marker::Null => Value::Nil,
+      Marker::False => Value::Bool(false),
+      Marker::True => Value::Bool(true),
+      Marker::FixPos(n) => Value::Number(n as f64),
+      Marker::FixNeg(n) => Value::Number(n as f64),
+      Marker::U8 => Value::Number(self.array::<1>()?[0] as f64),
+      Marker::U16 => Value::Number(u16::from_be_bytes(self.array()?) as f64),
+      Marker::U32 => Value::Number(u32::from_be_bytes(self.array()?) as f64),
+      Marker::U64 => Value::Number(u64::from_be_bytes(self.array()?) as f64),
+      Marker::I8 => Value::Number(i8::from_be_bytes(self.array()?) as f64),
+      Marker::I16 => Value::Number(i16::from_be_bytes(self.array()?) as f64),
+      Marker::I32 => Value::Number(i32::from_be_bytes(self.array()?) as f64),
+      Marker::I64 => Value::Number(i64::from_be_bytes(self.array()?) as f64),
+      Marker::F32 => Value::Number(f32::from_be_bytes(self.array()?) as f64),
+      Marker::F64 => Value::Number(f64::from_be_bytes(self.array()?)),
+      Marker::FixStr(len) => Value::String(self.str(len as usize)?),
+      Marker::Str8 | Marker::Str16 | Marker::Str32 => {
+        let len = self.len(marker)?;
+        Value::String(self.str(len)?)
+      }
+      Marker::Bin8 | Marker::Bin16 | Marker::Bin32 => {
+        let len = self.len(marker)?;
+        Value::list(self.take(len)?.iter().map(|byte| *byte as f64))
+      }
+      Marker::FixArray(len) => self.list(len as usize)?,
+      Marker::Array16 | Marker::Array32 => {
+        let len = self.len(marker)?;
+        self.list(len)?
+      }
+      Marker::FixMap(len) => self.map(len as usize)?,
+      Marker::Map16 | Marker::Map32 => {
+        let len = self.len(marker)?;
+        self.map(len)?
+      }
+      Marker::FixExt1 => self.ext(1)?,
+      Marker::FixExt2 => self.ext(2)?,
+      Marker::FixExt4 => self.ext(4)?,
+      Marker::FixExt8 => self.ext(8)?,
+      Marker::FixExt16 => self.ext(16)?,
+      Marker::Ext8 | Marker::Ext16 | Marker::Ext32 => {
+        let len = self.len(marker)?;
+        self.ext(len)?
+      }
+      Marker::Reserved => return Err(format!("Invalid MessagePack marker at byte {}", at)),
+    })
+  }
+  fn list(&mut self, len: usize) -> Result<Value, String> {
+    // The length is not trusted to reserve memory: every element takes at least a byte.
+    let mut items = Vec::with_capacity(len.min(self.bytes.len() - self.at));
+    for _ in 0..len {
+      items.push(self.value()?);
+    }
+    Ok(Value::from(items))
+  }
+  fn map(&mut self, len: usize) -> Result<Value, String> {
+    let mut entries = Map::new();
+    for _ in 0..len {
+      let at = self.at;
+      match self.value()? {
+        Value::String(key) => entries.insert(key, self.value()?),
+        other => {
+          let found = other.type_name();
+          return Err(format!("Map keys must be strings, found a {} at byte {}", found, at));
+        }
+      };
+    }
+    Ok(Value::Map(entries))
+  }
+  fn ext(&mut self, len: usize) -> Result<Value, String> {
+    let at = self.at;
+    let ty = self.array::<1>()?[0] as i8;
+    let mut payload = Reader {
+      bytes: self.take(len)?,
+      at: 0,
+    };
+    let value = match (ty, payload.value()?) {
+      (ENUM, Value::String(name)) => match payload.value()? {
+        Value::String(variant) => Some(Value::Enum(name, variant)),
+        _ => None,
+      },
+      (RANGE, Value::Number(start)) => match (payload.value()?, payload.value()?) {
+        (Value::Number(end), Value::Number(step)) => Some(Value::Range(start, end, step)),
+        _ => None,
+      },
+      (NATIVE, Value::String(name)) => Some(Value::Native(name)),
+      _ => None,
+    };
+    match value {
+      Some(value) if payload.at == payload.bytes.len() => Ok(value),
+      _ => Err(format!("Invalid MessagePack extension of type {} at byte {}", ty, at)),
+    }
+  }
+}
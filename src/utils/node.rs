@@ -1,24 +1,45 @@
+use crate::utils::fingerprint::Fingerprinter;
+use crate::utils::token::Span;
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum NodeType {
   Assignement(AssignType),
   Condition,
+  Cond,
   Operator(OperatorType),
   Func,
   Loop,
   Return,
   Block,
+  Do,
   None,
   Scope,
   NodeBool(bool),
-  NodeNumber(f32),
+  NodeNumber(f64),
   NodeStr(String),
   NodeIdentifier(String),
   FunctionCall(String),
+  /// `(enum Color (Red Green Blue))`, with the variants as identifier children.
+  Enum(String),
+  /// A `pub let` or `pub const`, wrapping the assignment. Only these are importable.
+  Export,
+  /// Optional last child of a `let` or `const` annotated with `# @deprecated "hint"`.
+  Deprecated(String),
 }
-#[derive(Debug, PartialEq, Clone)]
+/// A node of the syntax tree. Nodes are equal if their types and children are, wherever they
+/// are in the source.
+#[derive(Debug, Clone)]
 pub struct Node {
   node_type: NodeType,
   child: Vec<Node>,
+  /// The span as 32-bit offsets, `u32::MAX` when unknown, to keep nodes small.
+  span: (u32, u32),
+}
+
+impl PartialEq for Node {
+  fn eq(&self, other: &Self) -> bool {
+    self.node_type == other.node_type && self.child == other.child
+  }
 }
 
 impl Node {
@@ -26,17 +47,159 @@ impl Node {
     Self {
       node_type: t,
       child: vec![],
+      span: (u32::MAX, u32::MAX),
     }
   }
+  /// Where the node was read from in the source, if it was parsed: the token of a literal or
+  /// a name, or a whole form from `(` to `)`.
+  pub fn span(&self) -> Option<Span> {
+    let (start, end) = self.span;
+    Some(Span {
+      start: start as usize,
+      end: end as usize,
+    })
+    .filter(|_| start != u32::MAX)
+  }
+  /// Sets the span of the node. Spans beyond 4 GiB of source are dropped.
+  pub fn set_span(&mut self, span: Option<Span>) {
+    self.span = match span {
+      Some(span) if span.end < u32::MAX as usize => (span.start as u32, span.end as u32),
+      _ => (u32::MAX, u32::MAX),
+    };
+  }
   pub fn add_children(&mut self, c: &Node) {
     self.child.push((*c).clone());
   }
-  pub fn get_child(&self) -> Vec<Node> {
-    self.child.clone()
+  pub fn get_child(&self) -> &[Node] {
+    &self.child
   }
   pub fn get_type(&self) -> NodeType {
     self.node_type.clone()
   }
+  /// A hash of the tree that stays the same across runs, platforms and builds, to tell if a
+  /// cached result of the program is still valid.
+  ///
+  /// ```
+  /// use nixt::core::{lexer::Lexer, parser::Parser};
+  /// let parse = |code| Parser::new(Lexer::new(code).scan_tokens()).parse();
+  /// assert_eq!(parse("(print 1)").fingerprint(), parse("(print  1) # one").fingerprint());
+  /// assert_ne!(parse("(print 1)").fingerprint(), parse("(print 2)").fingerprint());
+  /// assert_eq!(parse("(print 1)").fingerprint(), 0xfa87_4c09_b88c_a73c);
+  /// ```
+  pub fn fingerprint(&self) -> u64 {
+    let mut fingerprinter = Fingerprinter::new();
+    fingerprinter.node(self);
+    fingerprinter.finish()
+  }
+}
+
+/// One change of the edit script [`diff`] produces. Paths are the child indices leading from
+/// the root to the node, in the tree as left by the edits before.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AstEdit {
+  /// `node` becomes the child at `path`, shifting the next siblings.
+  Insert { path: Vec<usize>, node: Node },
+  /// The child at `path` is removed.
+  Delete { path: Vec<usize>, node: Node },
+  /// The node at `path` becomes `new`.
+  Replace { path: Vec<usize>, old: Node, new: Node },
+}
+
+/// The edits turning `old` into `new`, descending into the nodes both trees kept so that only
+/// the changed forms are touched.
+///
+/// ```
+/// use nixt::core::{lexer::Lexer, parser::Parser};
+/// use nixt::utils::node::{apply, diff, AstEdit};
+/// let parse = |code| Parser::new(Lexer::new(code).scan_tokens()).parse();
+/// let (old, new) = (parse("(let a 1) (let b 2)"), parse("(let a 1) (let b 3)"));
+/// let edits = diff(&old, &new);
+/// assert!(matches!(&edits[..], [AstEdit::Replace { path, .. }] if path == &[1, 0, 1]));
+/// assert_eq!(apply(&old, &edits), new);
+/// ```
+pub fn diff(old: &Node, new: &Node) -> Vec<AstEdit> {
+  let mut edits = vec![];
+  diff_at(old, new, &mut vec![], &mut edits);
+  edits
+}
+
+fn diff_at(old: &Node, new: &Node, path: &mut Vec<usize>, edits: &mut Vec<AstEdit>) {
+  if old == new {
+    return;
+  }
+  if old.node_type != new.node_type {
+    edits.push(AstEdit::Replace {
+      path: path.clone(),
+      old: old.clone(),
+      new: new.clone(),
+    });
+    return;
+  }
+  let (olds, news) = (&old.child, &new.child);
+  // The longest common subsequence of the children, kept untouched.
+  let mut common = vec![vec![0; news.len() + 1]; olds.len() + 1];
+  for i in (0..olds.len()).rev() {
+    for j in (0..news.len()).rev() {
+      common[i][j] = if olds[i] == news[j] {
+        common[i + 1][j + 1] + 1
+      } else {
+        common[i + 1][j].max(common[i][j + 1])
+      };
+    }
+  }
+  let (mut i, mut j, mut index) = (0, 0, 0);
+  while i < olds.len() || j < news.len() {
+    if i < olds.len() && j < news.len() && olds[i] == news[j] {
+      i += 1;
+      j += 1;
+      index += 1;
+    } else if i < olds.len() && j < news.len() && common[i + 1][j + 1] == common[i][j] {
+      // A node changed in place, without losing the kept ones: edit it rather than deleting
+      // and inserting.
+      path.push(index);
+      diff_at(&olds[i], &news[j], path, edits);
+      path.pop();
+      i += 1;
+      j += 1;
+      index += 1;
+    } else if j < news.len() && (i == olds.len() || common[i][j + 1] >= common[i + 1][j]) {
+      edits.push(AstEdit::Insert {
+        path: [&path[..], &[index]].concat(),
+        node: news[j].clone(),
+      });
+      j += 1;
+      index += 1;
+    } else {
+      edits.push(AstEdit::Delete {
+        path: [&path[..], &[index]].concat(),
+        node: olds[i].clone(),
+      });
+      i += 1;
+    }
+  }
+}
+
+/// `node` with `edits` applied in order, as [`diff`] produced them.
+pub fn apply(node: &Node, edits: &[AstEdit]) -> Node {
+  let mut node = node.clone();
+  for edit in edits {
+    match edit {
+      AstEdit::Insert { path, node: inserted } => {
+        let (last, parent) = path.split_last().expect("an insertion has a parent");
+        descend(&mut node, parent).child.insert(*last, inserted.clone());
+      }
+      AstEdit::Delete { path, .. } => {
+        let (last, parent) = path.split_last().expect("a deletion has a parent");
+        descend(&mut node, parent).child.remove(*last);
+      }
+      AstEdit::Replace { path, new, .. } => *descend(&mut node, path) = new.clone(),
+    }
+  }
+  node
+}
+
+fn descend<'a>(node: &'a mut Node, path: &[usize]) -> &'a mut Node {
+  path.iter().fold(node, |node, &index| &mut node.child[index])
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -59,7 +222,8 @@ pub enum OperatorType {
     NotEqual,
     And,
     Or,
-    Modulo
+    Modulo,
+    NilCoalesce,
 }
 
 pub fn stringify(node: &Node, indentations: usize) -> String {
@@ -69,7 +233,7 @@ pub fn stringify(node: &Node, indentations: usize) -> String {
     toret.push_str(&format!("{}@type : ", gen_indents(indentations)));
     toret.push_str(&format!("{:?}\n", children.get_type()));
     toret.push_str(&format!("{}@children : ", gen_indents(indentations)));
-    toret.push_str(&stringify(&children, indentations + 1));
+    toret.push_str(&stringify(children, indentations + 1));
   }
   toret.push_str(&format!("{}}}\n", gen_indents(indentations)));
   toret
@@ -83,9 +247,124 @@ fn gen_indents(amount: usize) -> String {
   toret
 }
 
+impl std::fmt::Display for OperatorType {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    let symbol = match self {
+      Self::Plus => "+",
+      Self::Minus => "-",
+      Self::Times => "*",
+      Self::Div => "/",
+      Self::Less => "<",
+      Self::LessEqual => "<=",
+      Self::Greater => ">",
+      Self::GreaterEqual => ">=",
+      Self::Equal => "=",
+      Self::NotEqual => "~",
+      Self::And => "and",
+      Self::Or => "or",
+      Self::Modulo => "%",
+      Self::NilCoalesce => "??",
+    };
+    write!(f, "{}", symbol)
+  }
+}
+
+/// Writes `nodes` separated by spaces, with a leading space when not empty.
+fn write_children(f: &mut std::fmt::Formatter, nodes: &[Node]) -> std::fmt::Result {
+  for node in nodes {
+    write!(f, " {}", node)?;
+  }
+  Ok(())
+}
+
+/// Renders the node back as nixt source, e.g. `(let x (+ 1 2))`.
 impl std::fmt::Display for Node {
   fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-    write!(f, "{}", stringify(self, 0))?;
-    Ok(())
+    let children = self.get_child();
+    match &self.node_type {
+      NodeType::NodeNumber(n) => write!(f, "{}", crate::utils::element::format_number(*n)),
+      NodeType::NodeStr(s) => write!(f, "{:?}", s),
+      NodeType::NodeBool(b) => write!(f, "{}", b),
+      NodeType::NodeIdentifier(s) => write!(f, "{}", s),
+      NodeType::None => write!(f, "nil"),
+      NodeType::Block => {
+        write!(f, "(")?;
+        for (i, child) in children.iter().enumerate() {
+          if i > 0 {
+            write!(f, " ")?;
+          }
+          write!(f, "{}", child)?;
+        }
+        write!(f, ")")
+      }
+      NodeType::Scope => {
+        write!(f, "{{")?;
+        write_children(f, children)?;
+        write!(f, " }}")
+      }
+      NodeType::Assignement(a) => {
+        let keyword = match a {
+          AssignType::Let => "let",
+          AssignType::Const => "const",
+          AssignType::Set => "set",
+        };
+        write!(f, "{}", keyword)?;
+        write_children(f, &children[..children.len().min(2)])
+      }
+      NodeType::Operator(op) => {
+        write!(f, "{}", op)?;
+        write_children(f, children)
+      }
+      NodeType::FunctionCall(name) => {
+        write!(f, "{}", name)?;
+        write_children(f, children)
+      }
+      NodeType::Func => {
+        write!(f, "func")?;
+        write_children(f, children)
+      }
+      NodeType::Loop => {
+        write!(f, "while")?;
+        write_children(f, children)
+      }
+      NodeType::Condition => {
+        write!(f, "if")?;
+        let has_else = children.get(2).is_some_and(|c| c.get_type() != NodeType::None);
+        write_children(f, &children[..if has_else { 3 } else { children.len().min(2) }])
+      }
+      NodeType::Enum(name) => {
+        write!(f, "enum {} (", name)?;
+        for (i, variant) in children.iter().enumerate() {
+          if i > 0 {
+            write!(f, " ")?;
+          }
+          write!(f, "{}", variant)?;
+        }
+        write!(f, ")")
+      }
+      NodeType::Export => {
+        write!(f, "pub")?;
+        write_children(f, children)
+      }
+      NodeType::Deprecated(hint) => write!(f, "# @deprecated {:?}", hint),
+      NodeType::Return => {
+        write!(f, "ret")?;
+        write_children(f, children)
+      }
+      NodeType::Do => {
+        write!(f, "do")?;
+        write_children(f, children)
+      }
+      NodeType::Cond => {
+        write!(f, "cond")?;
+        for clause in children {
+          let clause = clause.get_child();
+          write!(f, " ({}", clause[0])?;
+          write_children(f, clause[1].get_child())?;
+          write!(f, ")")?;
+        }
+        Ok(())
+      }
+    }
   }
 }
@@ -0,0 +1,151 @@
+//! Resources a host hands to scripts, such as open files or database connections, as values.
+//!
+//! A script holds a resource like any other value, and passes it back to the builtins of the
+//! host using it. Copies of the value share the resource: `(close r)` closes it for all of them,
+//! and it is closed anyway once the last of them is dropped, so that a script forgetting to close
+//! it, or failing before it does, does not leak it. The host can keep a [`WeakResource`] to find
+//! out whether scripts still hold it.
+
+use std::any::Any;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, Weak};
+
+/// What the host puts in a [`Resource`].
+pub trait HostResource: Any + Send {
+  /// What the resource is, shown when it prints: `file`, `connection`...
+  fn kind(&self) -> &str;
+  /// Releases what the resource holds. It is called once, by `(close r)`, [`Resource::close`] or
+  /// when the last value holding the resource is dropped, where its error is ignored.
+  fn close(&mut self) -> Result<(), String> {
+    Ok(())
+  }
+}
+
+struct Slot {
+  kind: String,
+  resource: Mutex<Option<Box<dyn HostResource>>>,
+}
+
+impl Slot {
+  fn resource(&self) -> MutexGuard<'_, Option<Box<dyn HostResource>>> {
+    self.resource.lock().unwrap_or_else(PoisonError::into_inner)
+  }
+}
+
+impl Drop for Slot {
+  fn drop(&mut self) {
+    if let Some(mut resource) = self.resource().take() {
+      resource.close().ok();
+    }
+  }
+}
+
+/// A [`HostResource`] shared by the values holding it, which `(close r)` and dropping the last
+/// of them close.
+///
+/// ```
+/// use nixt::error::{NixtError, RuntimeError};
+/// use nixt::utils::resource::{HostResource, Resource};
+/// use nixt::Value;
+///
+/// struct Connection(Vec<String>);
+/// impl HostResource for Connection {
+///   fn kind(&self) -> &str {
+///     "connection"
+///   }
+/// }
+///
+/// let connection = Resource::new(Connection(vec![]));
+/// let watcher = connection.downgrade();
+/// let mut interpreter = nixt::Interpreter::builder().build()?;
+/// let runtime = |e: String| NixtError::Runtime(RuntimeError::new(e));
+/// interpreter.register_native("db:query", move |args| match args {
+///   [Value::Resource(db), Value::String(query)] => {
+///     db.with(|db: &mut Connection| db.0.push(query.clone())).map_err(runtime)?;
+///     Ok(Value::Nil)
+///   }
+///   _ => Err(runtime("`db:query` takes a connection and a query".to_owned())),
+/// })?;
+/// interpreter.define("db", Value::Resource(connection))?;
+/// interpreter.eval_str("(db:query db \"select 1\")")?;
+/// assert_eq!(interpreter.eval_str("(do db)")?.to_string(), "@resource: connection");
+/// assert_eq!(interpreter.eval_str("(close db)")?.to_string(), "true");
+/// assert!(interpreter.eval_str("(db:query db \"select 2\")").is_err());
+/// drop(interpreter);
+/// assert!(watcher.upgrade().is_none());
+/// # Ok::<(), String>(())
+/// ```
+#[derive(Clone)]
+pub struct Resource(Arc<Slot>);
+
+impl Resource {
+  pub fn new(resource: impl HostResource) -> Self {
+    Self(Arc::new(Slot {
+      kind: resource.kind().to_owned(),
+      resource: Mutex::new(Some(Box::new(resource))),
+    }))
+  }
+  /// What the resource is, as [`HostResource::kind`] gave it, even once closed.
+  pub fn kind(&self) -> &str {
+    &self.0.kind
+  }
+  pub fn is_closed(&self) -> bool {
+    self.0.resource().is_none()
+  }
+  /// Closes the resource for every value holding it. Returns whether it was open.
+  pub fn close(&self) -> Result<bool, String> {
+    let resource = self.0.resource().take();
+    match resource {
+      Some(mut resource) => {
+        let failed = |e| format!("Could not close the {}: {}", self.kind(), e);
+        resource.close().map_err(failed)?;
+        Ok(true)
+      }
+      None => Ok(false),
+    }
+  }
+  /// Calls `f` with the resource, if it is still open and a `T`.
+  pub fn with<T: HostResource, R>(&self, f: impl FnOnce(&mut T) -> R) -> Result<R, String> {
+    let mut resource = self.0.resource();
+    let resource: &mut dyn Any = match resource.as_mut() {
+      Some(resource) => resource.as_mut(),
+      None => return Err(format!("The {} is closed", self.kind())),
+    };
+    match resource.downcast_mut::<T>() {
+      Some(resource) => Ok(f(resource)),
+      None => Err(format!("The {} is not the resource expected", self.kind())),
+    }
+  }
+  /// A reference to the resource that does not keep it open.
+  pub fn downgrade(&self) -> WeakResource {
+    WeakResource(Arc::downgrade(&self.0))
+  }
+  /// Whether both hold the same resource.
+  pub fn ptr_eq(&self, other: &Resource) -> bool {
+    Arc::ptr_eq(&self.0, &other.0)
+  }
+}
+
+impl fmt::Debug for Resource {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let mut resource = f.debug_struct("Resource");
+    resource.field("kind", &self.kind()).field("closed", &self.is_closed()).finish()
+  }
+}
+
+/// A [`Resource`] that does not keep it alive: once no value holds it, it is closed and
+/// [`WeakResource::upgrade`] returns `None`.
+#[derive(Clone)]
+pub struct WeakResource(Weak<Slot>);
+
+impl WeakResource {
+  pub fn upgrade(&self) -> Option<Resource> {
+    self.0.upgrade().map(Resource)
+  }
+}
+
+impl fmt::Debug for WeakResource {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("WeakResource").field("alive", &(self.0.strong_count() > 0)).finish()
+  }
+}
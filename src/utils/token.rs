@@ -4,11 +4,14 @@ pub enum TokenType {
     LeftBrace,
     RightBrace,
     RightParen,
+    LeftBracket,
+    RightBracket,
     Str(String),
     Identifier(String),
-    Number(f32),
+    Number(f64),
     Equal,
     Dot,
+    DotDot,
     Comma,
     Percent,
     Plus,
@@ -20,6 +23,7 @@ pub enum TokenType {
     Greater,
     GreaterEqual,
     Tilde,
+    QuestionQuestion,
 
     And,
     Func,
@@ -33,18 +37,105 @@ pub enum TokenType {
     Const,
     While,
     Set,
+    Do,
+    Cond,
+    Else,
+    Pub,
+    Enum,
+
+    /// A comment, only produced in the lexer's trivia mode and never seen by the parser.
+    Comment(String),
 
     Eof,
 }
+/// Where something is in the source: the bytes from `start` up to, but excluding, `end`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    /// The span from the start of `self` to the end of `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            start: self.start,
+            end: other.end,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Token {
     pub typ: TokenType,
     pub lexeme: String,
     pub line: usize,
+    /// Where the lexeme is in the source.
+    pub span: Span,
+    /// The comments right before this token, in trivia mode.
+    pub trivia: Vec<Token>,
 }
 
 impl Token {
     pub fn new(typ: TokenType, lexeme: String, line: usize) -> Self {
-        Self { typ, lexeme, line }
+        Self {
+            typ,
+            lexeme,
+            line,
+            span: Span::default(),
+            trivia: vec![],
+        }
+    }
+}
+
+impl TokenType {
+    /// Whether this token type can be produced by a word of the keyword table.
+    pub fn is_keyword(&self) -> bool {
+        matches!(
+            self,
+            Self::And
+                | Self::Func
+                | Self::If
+                | Self::Nil
+                | Self::Or
+                | Self::Return
+                | Self::True
+                | Self::False
+                | Self::Let
+                | Self::Const
+                | Self::While
+                | Self::Set
+                | Self::Do
+                | Self::Cond
+                | Self::Else
+                | Self::Pub
+                | Self::Enum
+        )
+    }
+}
+
+impl std::fmt::Display for TokenType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let kind = match self {
+            Self::Str(_) => "string",
+            Self::Identifier(_) => "identifier",
+            Self::Number(_) => "number",
+            Self::Eof => "end of file",
+            Self::Comment(_) => "comment",
+            typ if typ.is_keyword() => "keyword",
+            _ => "symbol",
+        };
+        write!(f, "{}", kind)
+    }
+}
+
+/// `identifier `foo` at line 3`
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.typ == TokenType::Eof {
+            write!(f, "end of file at line {}", self.line)
+        } else {
+            write!(f, "{} `{}` at line {}", self.typ, self.lexeme, self.line)
+        }
     }
 }
@@ -0,0 +1,31 @@
+//! Instrumentation of the lexer, parser and interpreter, compiled to nothing unless the
+//! `tracing` feature is enabled.
+
+/// Enters a debug span until the end of the enclosing block, timing a phase such as parsing.
+#[cfg(feature = "tracing")]
+macro_rules! trace_phase {
+  ($($arg:tt)*) => {
+    let _phase = tracing::debug_span!($($arg)*).entered();
+  };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! trace_phase {
+  ($($arg:tt)*) => {};
+}
+
+/// Emits a trace level event, such as the evaluation of a node.
+#[cfg(feature = "tracing")]
+macro_rules! trace_event {
+  ($($arg:tt)*) => {
+    tracing::trace!($($arg)*)
+  };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! trace_event {
+  ($($arg:tt)*) => {};
+}
+
+pub(crate) use trace_event;
+pub(crate) use trace_phase;